@@ -0,0 +1,142 @@
+//! End-to-end throughput scenarios driven by [oha](https://github.com/hatoo/oha).
+//!
+//! Every scenario in `benches/load-scenarios.json` (or the file named by
+//! `LOAD_SCENARIOS`) is run against an in-process server with `oha --json`.
+//! The JSON report is read back and compared with the scenario's thresholds;
+//! any breach makes `cargo bench --bench load` exit nonzero. When `oha` is not
+//! on `PATH` the scenarios are skipped.
+//!
+//! The server prints a table per connection to stdout, so run this as
+//! `cargo bench --bench load > /dev/null` to keep only the summary on stderr.
+
+use std::net::TcpListener;
+use std::process::{self, Command};
+use std::thread;
+
+use nu_table::{NuTable, NuTableConfig, TableTheme};
+use serde_json::Value;
+
+const DEFAULT_SCENARIOS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/benches/load-scenarios.json");
+
+struct Outcome {
+    name: String,
+    requests_per_sec: f64,
+    p99_ms: f64,
+    success_rate: f64,
+    failures: Vec<String>,
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`; plain `cargo test --benches` does not,
+    // and a multi-second load run has no place in the test suite.
+    if !std::env::args().any(|arg| arg == "--bench") {
+        return;
+    }
+    if Command::new("oha").arg("--version").output().is_err() {
+        eprintln!("oha not found on PATH, skipping load scenarios");
+        return;
+    }
+
+    let path = std::env::var("LOAD_SCENARIOS").unwrap_or_else(|_| DEFAULT_SCENARIOS.to_string());
+    let scenarios: Vec<Value> = std::fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| serde_json::from_str(&text).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| {
+            eprintln!("cannot load scenarios from {}: {}", path, e);
+            process::exit(2);
+        });
+
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind ephemeral port");
+    let addr = listener.local_addr().expect("local addr");
+    thread::spawn(move || rusty_server::serve(listener));
+
+    let outcomes: Vec<Outcome> = scenarios
+        .iter()
+        .map(|scenario| run_scenario(scenario, &format!("http://{}", addr)))
+        .collect();
+
+    print_summary(&outcomes);
+    let failures: Vec<&String> = outcomes.iter().flat_map(|o| &o.failures).collect();
+    if !failures.is_empty() {
+        for failure in failures {
+            eprintln!("regression: {}", failure);
+        }
+        process::exit(1);
+    }
+}
+
+fn run_scenario(scenario: &Value, base_url: &str) -> Outcome {
+    let name = scenario["name"].as_str().unwrap_or("unnamed").to_string();
+    let mut command = Command::new("oha");
+    command
+        .args(["--no-tui", "--json"])
+        .args(["-z", &format!("{}s", scenario["duration_secs"].as_u64().unwrap_or(10))])
+        .args(["-c", &scenario["connections"].as_u64().unwrap_or(16).to_string()]);
+    for header in scenario["headers"].as_array().into_iter().flatten() {
+        command.args(["-H", header.as_str().unwrap_or_default()]);
+    }
+    command.arg(format!("{}{}", base_url, scenario["path"].as_str().unwrap_or("/")));
+
+    let report: Value = match command.output() {
+        Ok(output) if output.status.success() => serde_json::from_slice(&output.stdout).unwrap_or(Value::Null),
+        Ok(output) => {
+            eprintln!("{}: oha failed: {}", name, String::from_utf8_lossy(&output.stderr));
+            Value::Null
+        }
+        Err(e) => {
+            eprintln!("{}: cannot run oha: {}", name, e);
+            Value::Null
+        }
+    };
+
+    let requests_per_sec = report["summary"]["requestsPerSec"].as_f64().unwrap_or(0.0);
+    let success_rate = report["summary"]["successRate"].as_f64().unwrap_or(0.0);
+    let p99_ms = report["latencyPercentiles"]["p99"].as_f64().unwrap_or(f64::INFINITY) * 1000.0;
+
+    let mut failures = Vec::new();
+    if let Some(min) = scenario["min_requests_per_sec"].as_f64() {
+        if requests_per_sec < min {
+            failures.push(format!("{}: {:.0} req/s is below {:.0}", name, requests_per_sec, min));
+        }
+    }
+    if let Some(max) = scenario["max_p99_ms"].as_f64() {
+        if p99_ms > max {
+            failures.push(format!("{}: p99 {:.2} ms is above {:.2} ms", name, p99_ms, max));
+        }
+    }
+    if success_rate < 1.0 {
+        failures.push(format!("{}: success rate {:.3}", name, success_rate));
+    }
+
+    Outcome {
+        name,
+        requests_per_sec,
+        p99_ms,
+        success_rate,
+        failures,
+    }
+}
+
+fn print_summary(outcomes: &[Outcome]) {
+    let mut table = NuTable::new(outcomes.len() + 1, 5);
+    for (col, title) in ["Scenario", "Req/s", "p99 (ms)", "Success", "Result"].iter().enumerate() {
+        table.insert((0, col), title.to_string());
+    }
+    for (i, outcome) in outcomes.iter().enumerate() {
+        let row = i + 1;
+        table.insert((row, 0), outcome.name.clone());
+        table.insert((row, 1), format!("{:.0}", outcome.requests_per_sec));
+        table.insert((row, 2), format!("{:.2}", outcome.p99_ms));
+        table.insert((row, 3), format!("{:.1}%", outcome.success_rate * 100.0));
+        table.insert((row, 4), if outcome.failures.is_empty() { "✓".to_string() } else { "✗".to_string() });
+    }
+
+    let config = NuTableConfig {
+        theme: TableTheme::rounded(),
+        with_header: true,
+        ..NuTableConfig::default()
+    };
+    if let Some(output) = table.draw(config, 100) {
+        eprintln!("{}", output);
+    }
+}
@@ -0,0 +1,115 @@
+//! Micro-benchmarks for the request hot path: parsing a head, building and
+//! serializing a response, and producing a full response over an in-memory
+//! stream.
+
+use std::io::{self, Cursor, Read, Write};
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use rusty_server::handle_connection;
+use rusty_server::http::{parse_request, Response};
+
+const CURL_REQUEST: &[u8] = b"GET /version HTTP/1.1\r\nHost: 127.0.0.1:3000\r\nUser-Agent: curl/8.5.0\r\nAccept: */*\r\n\r\n";
+
+const BROWSER_REQUEST: &[u8] = b"GET /version HTTP/1.1\r\n\
+Host: 127.0.0.1:3000\r\n\
+User-Agent: Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0\r\n\
+Accept: text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8\r\n\
+Accept-Language: en-US,en;q=0.5\r\n\
+Accept-Encoding: gzip, deflate, br, zstd\r\n\
+Connection: keep-alive\r\n\
+Cookie: session=0123456789abcdef0123456789abcdef; theme=dark\r\n\
+Upgrade-Insecure-Requests: 1\r\n\
+Sec-Fetch-Dest: document\r\n\
+Sec-Fetch-Mode: navigate\r\n\
+Sec-Fetch-Site: none\r\n\
+Priority: u=0, i\r\n\r\n";
+
+/// Replays one request and counts the response bytes without storing them.
+struct Replay {
+    input: Cursor<&'static [u8]>,
+    written: usize,
+}
+
+impl Replay {
+    fn new(request: &'static [u8]) -> Self {
+        Replay {
+            input: Cursor::new(request),
+            written: 0,
+        }
+    }
+}
+
+impl Read for Replay {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for Replay {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_request");
+    for (name, request) in [("curl", CURL_REQUEST), ("browser", BROWSER_REQUEST)] {
+        group.throughput(Throughput::Bytes(request.len() as u64));
+        group.bench_function(name, |b| b.iter(|| parse_request(black_box(request))));
+    }
+    group.finish();
+}
+
+fn respond(c: &mut Criterion) {
+    let mut group = c.benchmark_group("handle_connection");
+    let cases: [(&str, &'static [u8]); 3] = [
+        ("version_html", CURL_REQUEST),
+        ("version_json", b"GET /version HTTP/1.1\r\nHost: a\r\nAccept: application/json\r\n\r\n"),
+        ("not_found", b"GET /missing HTTP/1.1\r\nHost: a\r\n\r\n"),
+    ];
+    for (name, request) in cases {
+        let mut probe = Replay::new(request);
+        handle_connection(&mut probe).unwrap();
+        group.throughput(Throughput::Bytes(probe.written as u64));
+        group.bench_function(name, |b| {
+            b.iter(|| handle_connection(Replay::new(black_box(request))).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn build(c: &mut Criterion) {
+    let mut group = c.benchmark_group("build_response");
+    let cases: [(&str, &'static [u8]); 3] = [
+        ("empty", b""),
+        ("small_text", b"Hello, world!\n"),
+        ("small_json", br#"{"name":"rusty-server","version":"0.1.0","uptime_secs":12345}"#),
+    ];
+    for (name, body) in cases {
+        let response = || {
+            Response::with_body(200, "application/json", black_box(body))
+                .header("Cache-Control", "no-store")
+                .header("X-Request-Id", "0123456789abcdef")
+        };
+        let mut probe = Vec::new();
+        response().write_to(&mut probe).unwrap();
+        group.throughput(Throughput::Bytes(probe.len() as u64));
+        let mut out = Vec::with_capacity(probe.len());
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                out.clear();
+                response().write_to(&mut out).unwrap();
+                black_box(out.len())
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse, build, respond);
+criterion_main!(benches);
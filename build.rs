@@ -0,0 +1,17 @@
+//! Compiles the gRPC service definitions when the `grpc` feature is on.
+//!
+//! `protox` parses the `.proto` files in pure Rust, so building does not need
+//! `protoc` installed.
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto");
+        let descriptors =
+            protox::compile(["proto/stats.proto"], ["proto"]).expect("proto/stats.proto compiles");
+        tonic_build::configure()
+            .compile_fds(descriptors)
+            .expect("gRPC code generation");
+    }
+}
@@ -0,0 +1,8 @@
+#!/bin/sh
+# A CGI script: served at /cgi-bin/hello.sh when the server runs with
+# CGI_DIR=cgi-bin. The request arrives in environment variables, the body
+# on standard input.
+printf 'Content-Type: text/plain\r\n\r\n'
+echo "Hello from CGI!"
+echo "You asked for $REQUEST_METHOD $SCRIPT_NAME$PATH_INFO${QUERY_STRING:+?$QUERY_STRING}"
+echo "Your browser is ${HTTP_USER_AGENT:-unknown}"
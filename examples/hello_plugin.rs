@@ -0,0 +1,88 @@
+//! A handler plugin: a `cdylib` the server loads from its plugins directory
+//! at startup, adding routes without recompiling the server.
+//!
+//! ```text
+//! cargo build --example hello_plugin
+//! mkdir -p plugins && cp target/debug/examples/libhello_plugin.so plugins/
+//! cargo run --features plugins
+//! curl http://127.0.0.1:3000/plugin/hello
+//! ```
+
+use std::ffi::c_void;
+use std::ptr;
+
+use rusty_server::plugin::{Host, PluginRequest, ResponseHandle, ABI_VERSION};
+
+const GREETING: &str = "Hello from a plugin!\n";
+
+/// Registers the plugin's routes. Exported under the name the server looks
+/// up (`rusty_server::plugin::REGISTER_SYMBOL`).
+///
+/// # Safety
+///
+/// Called by the server with a valid `Host` table.
+#[no_mangle]
+pub unsafe extern "C" fn rusty_server_plugin_register(host: *const Host) -> i32 {
+    let host = &*host;
+    if host.abi_version != ABI_VERSION {
+        return 1;
+    }
+    // `user_data` lets one handler serve several routes with different data.
+    let greeting = GREETING as *const str as *const u8 as *mut c_void;
+    let added = (host.add_route)(
+        host.context,
+        "GET".into(),
+        "/plugin/hello".into(),
+        greet,
+        greeting,
+    ) && (host.add_route)(
+        host.context,
+        "GET".into(),
+        "/plugin/whoami".into(),
+        whoami,
+        ptr::null_mut(),
+    );
+    if added {
+        0
+    } else {
+        2
+    }
+}
+
+unsafe extern "C" fn greet(
+    user_data: *mut c_void,
+    _request: *const PluginRequest,
+    response: *mut ResponseHandle,
+    host: *const Host,
+) {
+    let host = &*host;
+    (host.add_header)(
+        response,
+        "Content-Type".into(),
+        "text/plain; charset=utf-8".into(),
+    );
+    (host.set_body)(response, user_data as *const u8, GREETING.len());
+}
+
+/// Answers with the client's `User-Agent`.
+unsafe extern "C" fn whoami(
+    _user_data: *mut c_void,
+    request: *const PluginRequest,
+    response: *mut ResponseHandle,
+    host: *const Host,
+) {
+    let (request, host) = (&*request, &*host);
+    let headers = std::slice::from_raw_parts(request.headers, request.header_count);
+    let user_agent = headers
+        .iter()
+        .find(|header| header.name.as_str().eq_ignore_ascii_case("User-Agent"))
+        .map_or("unknown", |header| header.value.as_str());
+
+    let body = format!("You are {}\n", user_agent);
+    (host.add_header)(
+        response,
+        "Content-Type".into(),
+        "text/plain; charset=utf-8".into(),
+    );
+    (host.set_body)(response, body.as_ptr(), body.len());
+}
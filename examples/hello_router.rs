@@ -0,0 +1,57 @@
+//! The smallest useful server: one custom route, driven through
+//! `serve_connection` from a hand-written accept loop.
+//!
+//! ```text
+//! cargo run --example hello_router
+//! curl http://127.0.0.1:3000/hello
+//! ```
+
+use std::net::TcpListener;
+
+use rusty_server::http::Response;
+use rusty_server::{serve_connection, Config, Router};
+
+fn router() -> Router {
+    let mut router = Router::new();
+    router.get("/hello", |_| Response::text(200, "Hello from Rust!\n"));
+    router
+}
+
+fn main() -> std::io::Result<()> {
+    let router = router();
+    let config = Config::default();
+    let listener = TcpListener::bind("127.0.0.1:3000")?;
+    println!("Listening on http://{}", listener.local_addr()?);
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => match serve_connection(stream, &router, &config) {
+                Ok(exchange) => println!("{} {}", exchange.status_text(), exchange.path),
+                Err(e) => eprintln!("connection failed: {}", e),
+            },
+            Err(e) => eprintln!("accept failed: {}", e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rusty_server::http::parse_request;
+
+    use super::router;
+
+    #[test]
+    fn hello_route_answers() {
+        let request = parse_request(b"GET /hello HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+        let response = router().handle(&request);
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"Hello from Rust!\n");
+    }
+
+    #[test]
+    fn other_paths_are_not_found() {
+        let request = parse_request(b"GET /version HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+        assert_eq!(router().handle(&request).status, 404);
+    }
+}
@@ -0,0 +1,76 @@
+//! A tiny JSON API with shared state: a hit counter and the server clock.
+//!
+//! ```text
+//! cargo run --example json_api
+//! curl http://127.0.0.1:3000/api/hits
+//! curl http://127.0.0.1:3000/api/time
+//! ```
+
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusty_server::http::Response;
+use serde_json::json;
+use rusty_server::{serve_connection, Config, Router};
+
+fn router() -> Router {
+    let hits = Arc::new(AtomicU64::new(0));
+    let mut router = Router::new();
+    router
+        .get("/api/hits", move |_| {
+            let count = hits.fetch_add(1, Ordering::Relaxed) + 1;
+            Response::json(200, &json!({ "hits": count }))
+        })
+        .get("/api/time", |_| {
+            let unix = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or(0);
+            Response::json(200, &json!({ "unix": unix }))
+        });
+    router
+}
+
+fn main() -> std::io::Result<()> {
+    let router = router();
+    let config = Config::default();
+    let listener = TcpListener::bind("127.0.0.1:3000")?;
+    println!("Listening on http://{}", listener.local_addr()?);
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if let Err(e) = serve_connection(stream, &router, &config) {
+                    eprintln!("connection failed: {}", e);
+                }
+            }
+            Err(e) => eprintln!("accept failed: {}", e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rusty_server::http::parse_request;
+
+    use super::router;
+
+    #[test]
+    fn hits_count_up_per_request() {
+        let router = router();
+        let request = parse_request(b"GET /api/hits HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+        assert_eq!(router.handle(&request).body, br#"{"hits":1}"#);
+        assert_eq!(router.handle(&request).body, br#"{"hits":2}"#);
+    }
+
+    #[test]
+    fn time_is_json() {
+        let request = parse_request(b"GET /api/time HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+        let response = router().handle(&request);
+        assert_eq!(response.header_value("Content-Type"), Some("application/json"));
+        assert!(response.body.starts_with(br#"{"unix":"#));
+    }
+}
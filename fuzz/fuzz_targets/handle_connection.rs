@@ -0,0 +1,45 @@
+#![no_main]
+
+use std::io::{self, Cursor, Read, Write};
+
+use libfuzzer_sys::fuzz_target;
+use rusty_server::http::{parse_request, MAX_HEAD_SIZE};
+
+/// Socket stand-in that replays the fuzz input and keeps the response.
+struct Replay<'a> {
+    input: Cursor<&'a [u8]>,
+    written: Vec<u8>,
+}
+
+impl Read for Replay<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for Replay<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut stream = Replay {
+        input: Cursor::new(data),
+        written: Vec::new(),
+    };
+    // Every input, however malformed, must produce a response...
+    let (_, status, _) =
+        rusty_server::handle_connection(&mut stream).expect("in-memory I/O cannot fail");
+    assert!(stream.written.starts_with(b"HTTP/1.1 "));
+    // ...and one rejecting what the parser rejects.
+    if data.len() < MAX_HEAD_SIZE && parse_request(data).is_err() {
+        let code: u16 = status[..3].parse().unwrap();
+        assert!(code >= 400, "{} for a malformed request", status);
+    }
+});
@@ -0,0 +1,40 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rusty_server::http::{parse_request, Version, MAX_HEAD_SIZE};
+
+/// The token characters of RFC 9110, allowed in methods and header names.
+fn is_token(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+fuzz_target!(|data: &[u8]| {
+    // The server never hands the parser more than one buffer's worth.
+    let data = &data[..data.len().min(MAX_HEAD_SIZE)];
+    let Ok(request) = parse_request(data) else {
+        return;
+    };
+
+    // Whatever is accepted was well formed...
+    assert!(is_token(&request.method));
+    assert!(request.target.bytes().all(|b| b.is_ascii_graphic()));
+    assert!(request.path.starts_with('/') || request.path == "*");
+    for (name, value) in &request.headers {
+        assert!(is_token(name));
+        assert!(!value.contains(['\r', '\n', '\0']));
+        assert_eq!(value.trim_matches([' ', '\t']), value);
+    }
+    assert!(request.header_all("Host").count() <= 1);
+    assert!(request.header("Host").is_some() || request.version == Version::Http10);
+
+    // ...and nothing grew beyond the input.
+    let head = data.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+    assert!(request.target.len() < head);
+    assert!(request.headers.len() < head);
+    assert!(request.body.len() <= data.len() - head - 4);
+    assert!(request.body.len() as u64 <= request.content_length());
+    assert!(request.params.is_empty() && request.id.is_empty());
+});
@@ -0,0 +1,172 @@
+//! A Nushell plugin that shows a running rusty-server's stats, open
+//! connections, and routes as tables, from the server's admin endpoints
+//! (`ADMIN_API=1`):
+//!
+//! ```nu
+//! > plugin add target/release/nu_plugin_rusty_server
+//! > plugin use rusty_server
+//! > server stats
+//! > server connections | where age > 1min
+//! > server routes --url http://10.0.0.5:3000 | group-by kind
+//! ```
+//!
+//! The server is `--url`, or `$env.RUSTY_SERVER_URL`, or
+//! `http://127.0.0.1:3000`.
+
+use std::time::Duration;
+
+use chrono::DateTime;
+use nu_plugin::{
+    serve_plugin, EngineInterface, EvaluatedCall, MsgPackSerializer, Plugin, PluginCommand,
+    SimplePluginCommand,
+};
+use nu_protocol::{Category, LabeledError, Record, Signature, Span, SyntaxShape, Type, Value};
+use rusty_server::admin::{CONNECTIONS_PATH, ROUTES_PATH, STATS_PATH};
+use rusty_server::client::Client;
+
+/// The server asked unless `--url` or `RUSTY_SERVER_URL` names another.
+const DEFAULT_URL: &str = "http://127.0.0.1:3000";
+
+/// How long to wait for the server.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+struct ServerPlugin;
+
+impl Plugin for ServerPlugin {
+    fn version(&self) -> String {
+        env!("CARGO_PKG_VERSION").into()
+    }
+
+    fn commands(&self) -> Vec<Box<dyn PluginCommand<Plugin = Self>>> {
+        vec![
+            Box::new(Admin {
+                name: "server stats",
+                description: "Uptime and connection counts of a running rusty-server.",
+                path: STATS_PATH,
+                output: Type::record(),
+            }),
+            Box::new(Admin {
+                name: "server connections",
+                description: "The connections open on a running rusty-server, oldest first.",
+                path: CONNECTIONS_PATH,
+                output: Type::table(),
+            }),
+            Box::new(Admin {
+                name: "server routes",
+                description: "The method, path, and kind of every route of a running rusty-server.",
+                path: ROUTES_PATH,
+                output: Type::table(),
+            }),
+        ]
+    }
+}
+
+/// A command that shows one admin endpoint.
+struct Admin {
+    name: &'static str,
+    description: &'static str,
+    path: &'static str,
+    output: Type,
+}
+
+impl SimplePluginCommand for Admin {
+    type Plugin = ServerPlugin;
+
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name)
+            .named(
+                "url",
+                SyntaxShape::String,
+                "the server, if not $env.RUSTY_SERVER_URL or http://127.0.0.1:3000",
+                Some('u'),
+            )
+            .input_output_type(Type::Nothing, self.output.clone())
+            .category(Category::Network)
+    }
+
+    fn description(&self) -> &str {
+        self.description
+    }
+
+    fn run(
+        &self,
+        _plugin: &ServerPlugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: &Value,
+    ) -> Result<Value, LabeledError> {
+        let url = match call.get_flag::<String>("url")? {
+            Some(url) => url,
+            None => match engine.get_env_var("RUSTY_SERVER_URL")? {
+                Some(url) => url.coerce_into_string()?,
+                None => DEFAULT_URL.to_string(),
+            },
+        };
+        let url = format!("{}{}", url.trim_end_matches('/'), self.path);
+        let fail = |message: String| {
+            LabeledError::new(format!("cannot get {}", url)).with_label(message, call.head)
+        };
+
+        let response = Client::new()
+            .timeout(TIMEOUT)
+            .get(&url)
+            .map_err(|e| fail(e.to_string()))?;
+        match response.status {
+            200 => {}
+            404 => {
+                return Err(fail("404 Not Found".to_string())
+                    .with_help("start the server with ADMIN_API=1"))
+            }
+            status => return Err(fail(format!("status {}", status))),
+        }
+        let json: serde_json::Value =
+            serde_json::from_slice(&response.body).map_err(|e| fail(e.to_string()))?;
+        Ok(to_value(None, json, call.head))
+    }
+}
+
+/// `json` as a Nushell value. Numbers under a `*_secs` key become durations
+/// and those under `time` or `opened` dates; the `_secs` suffix is dropped.
+fn to_value(key: Option<&str>, json: serde_json::Value, span: Span) -> Value {
+    use serde_json::Value as Json;
+
+    match json {
+        Json::Null => Value::nothing(span),
+        Json::Bool(value) => Value::bool(value, span),
+        Json::Number(number) => match (key, number.as_i64()) {
+            (Some(key), Some(secs)) if key.ends_with("_secs") => {
+                Value::duration(secs.saturating_mul(1_000_000_000), span)
+            }
+            (Some("time" | "opened"), Some(secs)) => match DateTime::from_timestamp(secs, 0) {
+                Some(time) => Value::date(time.fixed_offset(), span),
+                None => Value::int(secs, span),
+            },
+            (_, Some(value)) => Value::int(value, span),
+            (_, None) => Value::float(number.as_f64().unwrap_or(f64::NAN), span),
+        },
+        Json::String(value) => Value::string(value, span),
+        Json::Array(items) => Value::list(
+            items
+                .into_iter()
+                .map(|item| to_value(None, item, span))
+                .collect(),
+            span,
+        ),
+        Json::Object(members) => {
+            let mut record = Record::new();
+            for (key, value) in members {
+                let value = to_value(Some(&key), value, span);
+                record.push(key.strip_suffix("_secs").unwrap_or(&key), value);
+            }
+            Value::record(record, span)
+        }
+    }
+}
+
+fn main() {
+    serve_plugin(&ServerPlugin, MsgPackSerializer)
+}
@@ -0,0 +1,105 @@
+//! An access log in the Combined Log Format.
+//!
+//! [`open`] starts writing one line per answered request, the way Apache's
+//! and nginx's `combined` logs do, so that analyzers such as GoAccess or
+//! AWStats can read the server's traffic:
+//!
+//! ```text
+//! 127.0.0.1 - - [15/Oct/2026:15:03:02 +0000] "GET /version HTTP/1.1" 200 2287 "-" "curl/8.5.0"
+//! ```
+//!
+//! The size is the bytes sent for the response, headers included. Requests
+//! that could not be parsed are logged with `"-"` for the request line;
+//! connections that failed before a response was sent are not logged.
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+
+use crate::server::Exchange;
+use crate::{console, unix_timestamp, ServerError};
+
+/// Where access log lines go, once [`open`] is called.
+static LOG: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+
+/// Writes an access log line for every request answered from now on: to
+/// stdout if `path` is `-`, and otherwise appended to the file at `path`,
+/// which is created if needed.
+pub fn open(path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+    let writer: Box<dyn Write + Send> = if path == Path::new("-") {
+        Box::new(io::stdout())
+    } else {
+        Box::new(OpenOptions::new().create(true).append(true).open(path)?)
+    };
+    *LOG.lock().unwrap_or_else(PoisonError::into_inner) = Some(writer);
+    Ok(())
+}
+
+/// Logs a finished request whose response took `sent` bytes, if the access
+/// log is open. The log is closed after a failed write, with an error on
+/// stderr.
+pub(crate) fn record(peer: &str, result: &Result<Exchange, ServerError>, sent: u64) {
+    let mut log = LOG.lock().unwrap_or_else(PoisonError::into_inner);
+    let (Some(writer), Ok(exchange)) = (log.as_mut(), result) else {
+        return;
+    };
+    let line = line(peer, exchange, sent, unix_timestamp());
+    if let Err(e) = writeln!(writer, "{}", line).and_then(|()| writer.flush()) {
+        eprintln!("Error: cannot write the access log: {}", e);
+        *log = None;
+    }
+}
+
+/// The Combined Log Format line for `exchange` with `peer`, answered with
+/// `sent` bytes at `time`, in seconds since the Unix epoch.
+pub fn line(peer: &str, exchange: &Exchange, sent: u64, time: u64) -> String {
+    // `127.0.0.1:52814` and `::1:52814` alike end in the port.
+    let host = peer.rsplit_once(':').map_or(peer, |(host, _)| host);
+    let request = exchange.request.as_ref();
+    let request_line = request.map_or("-".to_string(), |request| {
+        format!("{} {} {}", request.method, request.target, request.version)
+    });
+    let header = |name| request.and_then(|request| request.header(name)).unwrap_or("-");
+    format!(
+        r#"{} - - [{}] "{}" {} {} "{}" "{}""#,
+        host,
+        timestamp(time),
+        escape(&request_line),
+        exchange.status,
+        if sent == 0 { "-".to_string() } else { sent.to_string() },
+        escape(header("Referer")),
+        escape(header("User-Agent"))
+    )
+}
+
+/// `time` as `15/Oct/2026:15:03:02 +0000`, in UTC.
+fn timestamp(time: u64) -> String {
+    let (year, month, day) = console::civil_date(time / 86_400);
+    let seconds = time % 86_400;
+    format!(
+        "{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+        day,
+        console::MONTHS[month as usize - 1],
+        year,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// `text` with quotes, backslashes, and control characters escaped as
+/// Apache escapes them, so that a field cannot end early or forge a line.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\x{:02x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
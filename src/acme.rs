@@ -0,0 +1,610 @@
+//! Certificates from an ACME certificate authority such as Let's Encrypt
+//! (with the `acme` feature).
+//!
+//! An [`Acme`] orders one certificate for all of its domains, proving control
+//! of each with a TLS-ALPN-01 challenge (RFC 8737) answered by the server's
+//! own HTTPS listeners, so nothing has to listen on port 80. The certificate,
+//! its key, and the account key are stored in a directory and loaded from it
+//! on the next start. [`Acme::keep_renewed`] renews the certificate in the
+//! background once it is within [`RENEW_BEFORE`] of expiring; new
+//! connections get the renewed one straight away.
+//!
+//! The certificate authority validates each domain on port 443, so the
+//! server must be reachable there.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL;
+use base64::Engine;
+use rcgen::{CertificateParams, CustomExtension, KeyPair};
+use ring::digest::{digest, SHA256};
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, KeyPair as _, ECDSA_P256_SHA256_FIXED_SIGNING};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::ServerConfig;
+use serde_json::{json, Value};
+
+use crate::client::{Client, ClientError};
+use crate::console;
+use crate::http::Response;
+use crate::tls::{self, Acceptor};
+
+/// The Let's Encrypt production directory, used unless set with
+/// [`Acme::directory`].
+pub const LETS_ENCRYPT: &str = "https://acme-v02.api.letsencrypt.org/directory";
+
+/// The Let's Encrypt staging directory, for trying a setup out without
+/// running into the production rate limits.
+pub const LETS_ENCRYPT_STAGING: &str = "https://acme-staging-v02.api.letsencrypt.org/directory";
+
+/// How long before it expires a certificate is renewed.
+pub const RENEW_BEFORE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// How often [`Acme::keep_renewed`] checks whether renewal is due.
+const CHECK_INTERVAL: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// How long [`Acme::keep_renewed`] waits after a failed attempt.
+const RETRY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How often a pending authorization or order is checked, and how many times.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const POLL_ATTEMPTS: u32 = 30;
+
+/// Why a certificate could not be obtained, stored, or loaded.
+#[derive(Debug, thiserror::Error)]
+pub enum AcmeError {
+    /// The certificate authority could not be reached.
+    #[error(transparent)]
+    Client(#[from] ClientError),
+    /// The certificate authority answered with an error.
+    #[error("certificate authority answered {status}: {detail}")]
+    Rejected { status: u16, detail: String },
+    /// The certificate authority's answer lacked what was asked for.
+    #[error("unexpected answer from certificate authority: {0}")]
+    InvalidResponse(String),
+    /// The certificate authority could not validate control of a domain.
+    #[error("validation of {domain} failed: {detail}")]
+    Validation { domain: String, detail: String },
+    /// An authorization or order was still pending after the last check.
+    #[error("timed out waiting for the {0}")]
+    Timeout(&'static str),
+    /// A key or certificate could not be generated or read.
+    #[error("key or certificate error: {0}")]
+    Crypto(String),
+    /// The storage directory could not be read or written.
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+}
+
+fn crypto_error(e: impl std::fmt::Display) -> AcmeError {
+    AcmeError::Crypto(e.to_string())
+}
+
+/// The certificates the HTTPS listeners present: the current one, and one
+/// per domain being validated.
+#[derive(Debug, Default)]
+struct Certificates {
+    current: RwLock<Option<Arc<CertifiedKey>>>,
+    challenges: Mutex<HashMap<String, Arc<CertifiedKey>>>,
+}
+
+impl ResolvesServerCert for Certificates {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let validating = client_hello
+            .alpn()
+            .is_some_and(|mut protocols| protocols.any(|protocol| protocol == tls::ACME_TLS_ALPN));
+        if validating {
+            let challenges = self
+                .challenges
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            return challenges.get(client_hello.server_name()?).cloned();
+        }
+        self.current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+/// Certificates for a set of domains from an ACME certificate authority;
+/// see the [module documentation](self).
+pub struct Acme {
+    domains: Vec<String>,
+    directory: String,
+    contact: Option<String>,
+    dir: PathBuf,
+    client: Client,
+    certificates: Arc<Certificates>,
+}
+
+impl Acme {
+    /// Certificates for `domains`, the first of which names the files,
+    /// stored in `dir`.
+    pub fn new(
+        domains: impl IntoIterator<Item = impl Into<String>>,
+        dir: impl Into<PathBuf>,
+    ) -> Self {
+        Acme {
+            domains: domains.into_iter().map(Into::into).collect(),
+            directory: LETS_ENCRYPT.to_string(),
+            contact: None,
+            dir: dir.into(),
+            client: Client::new(),
+            certificates: Arc::default(),
+        }
+    }
+
+    /// Sets the directory URL of the certificate authority; [`LETS_ENCRYPT`]
+    /// by default.
+    pub fn directory(mut self, url: impl Into<String>) -> Self {
+        self.directory = url.into();
+        self
+    }
+
+    /// Sets the email address the certificate authority may write to about
+    /// the account, such as before a certificate expires.
+    pub fn contact(mut self, email: impl Into<String>) -> Self {
+        self.contact = Some(email.into());
+        self
+    }
+
+    /// Sets the client the certificate authority is reached with, for one
+    /// whose certificate comes from a private CA.
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// The TLS side of the listeners: presents the current certificate,
+    /// and answers validations while a certificate is being obtained. Until
+    /// there is a certificate, other clients' handshakes fail.
+    pub fn acceptor(&self) -> Acceptor {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .expect("the ring provider supports the default protocol versions")
+            .with_no_client_auth()
+            .with_cert_resolver(self.certificates.clone());
+        config.alpn_protocols = vec![b"http/1.1".to_vec(), tls::ACME_TLS_ALPN.to_vec()];
+        Acceptor::new(Arc::new(config))
+    }
+
+    /// Loads the stored certificate, if there is one, and returns when it
+    /// expires, in seconds since the Unix epoch.
+    pub fn load(&self) -> Result<Option<u64>, AcmeError> {
+        let (cert, key) = (self.file("crt"), self.file("key"));
+        if !cert.exists() {
+            return Ok(None);
+        }
+        let (chain, key) = tls::read_pem_files(&cert, &key).map_err(crypto_error)?;
+        self.install(chain, &key).map(Some)
+    }
+
+    /// When the current certificate expires, in seconds since the Unix epoch.
+    pub fn expires(&self) -> Option<u64> {
+        let current = self
+            .certificates
+            .current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        tls::not_after(current.as_ref()?.end_entity_cert().ok()?)
+    }
+
+    /// Whether there is no certificate yet, or the current one expires
+    /// within [`RENEW_BEFORE`].
+    pub fn renewal_due(&self) -> bool {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        self.expires()
+            .is_none_or(|expires| expires < (now + RENEW_BEFORE).as_secs())
+    }
+
+    /// Orders a certificate for the domains, stores it, and presents it
+    /// from then on. Returns when it expires, in seconds since the Unix
+    /// epoch. The listeners serving [`acceptor`](Self::acceptor) must be
+    /// running, as they answer the validations.
+    pub fn obtain(&self) -> Result<u64, AcmeError> {
+        fs::create_dir_all(&self.dir)?;
+        let mut session = Session::start(self)?;
+        let (order_url, order) = session.order()?;
+        for authorization in strings(&order, "authorizations")? {
+            session.authorize(&authorization)?;
+        }
+
+        let key = KeyPair::generate().map_err(crypto_error)?;
+        let csr = CertificateParams::new(self.domains.clone())
+            .and_then(|params| params.serialize_request(&key))
+            .map_err(crypto_error)?;
+        let finalize = string(&order, "finalize")?;
+        session.post(
+            &finalize,
+            Some(json!({ "csr": BASE64URL.encode(csr.der()) })),
+        )?;
+        let order = session.poll(&order_url, "order", &["pending", "ready", "processing"])?;
+        if order["status"] != "valid" {
+            return Err(AcmeError::InvalidResponse(format!(
+                "order is {}",
+                order["status"]
+            )));
+        }
+        let pem = session.post(&string(&order, "certificate")?, None)?.body;
+
+        let chain = CertificateDer::pem_slice_iter(&pem)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(crypto_error)?;
+        let key_pem = key.serialize_pem();
+        let expires = self.install(chain, &PrivateKeyDer::Pkcs8(key.serialize_der().into()))?;
+        write_private(&self.file("key"), key_pem.as_bytes())?;
+        fs::write(self.file("crt"), &pem)?;
+        Ok(expires)
+    }
+
+    /// Starts a thread that obtains a certificate if renewal is due, and
+    /// checks again every 12 hours, reporting each attempt on the console.
+    pub fn keep_renewed(self: Arc<Self>) -> JoinHandle<()> {
+        thread::spawn(move || loop {
+            let wait = if !self.renewal_due() {
+                CHECK_INTERVAL
+            } else {
+                match self.obtain() {
+                    Ok(expires) => {
+                        console::print_table(&[
+                            (
+                                "ACME",
+                                format!("✓ certificate for {}", self.domains.join(", ")),
+                            ),
+                            ("Expires", expires.to_string()),
+                        ]);
+                        CHECK_INTERVAL
+                    }
+                    Err(e) => {
+                        console::print_table(&[(
+                            "ACME",
+                            format!("✗ cannot obtain a certificate: {}", e),
+                        )]);
+                        RETRY_INTERVAL
+                    }
+                }
+            };
+            thread::sleep(wait);
+        })
+    }
+
+    /// Presents `chain` and `key` from now on, and returns when the
+    /// certificate expires.
+    fn install(
+        &self,
+        chain: Vec<CertificateDer<'static>>,
+        key: &PrivateKeyDer<'_>,
+    ) -> Result<u64, AcmeError> {
+        let signing_key =
+            rustls::crypto::ring::sign::any_supported_type(key).map_err(crypto_error)?;
+        let expires = chain
+            .first()
+            .and_then(|cert| tls::not_after(cert))
+            .ok_or_else(|| AcmeError::Crypto("no valid certificate in the chain".to_string()))?;
+        let certified = CertifiedKey::new(chain, signing_key);
+        *self
+            .certificates
+            .current
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(certified));
+        Ok(expires)
+    }
+
+    /// The file with `extension` named after the first domain.
+    fn file(&self, extension: &str) -> PathBuf {
+        let name = self.domains.first().map_or("certificate", String::as_str);
+        self.dir.join(format!("{}.{}", name, extension))
+    }
+}
+
+/// An account's conversation with the certificate authority: its key, the
+/// next nonce, and the account URL once registered.
+struct Session<'a> {
+    acme: &'a Acme,
+    key: EcdsaKeyPair,
+    rng: SystemRandom,
+    /// The account key as a JWK, with its members in the order of its
+    /// thumbprint (RFC 7638).
+    jwk: String,
+    new_nonce: String,
+    new_order: String,
+    nonce: Option<String>,
+    account: Option<String>,
+}
+
+impl<'a> Session<'a> {
+    /// Reads the directory, and registers the account, or finds it if the
+    /// key was registered before.
+    fn start(acme: &'a Acme) -> Result<Self, AcmeError> {
+        let directory = json_body(&checked(acme.client.get(&acme.directory)?)?)?;
+        let rng = SystemRandom::new();
+        let key = account_key(&acme.dir.join("account.key"), &rng)?;
+        let point = key.public_key().as_ref();
+        let (x, y) = point[1..].split_at(32);
+        let jwk = format!(
+            r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#,
+            BASE64URL.encode(x),
+            BASE64URL.encode(y)
+        );
+        let mut session = Session {
+            acme,
+            key,
+            rng,
+            jwk,
+            new_nonce: string(&directory, "newNonce")?,
+            new_order: string(&directory, "newOrder")?,
+            nonce: None,
+            account: None,
+        };
+
+        let mut account = json!({ "termsOfServiceAgreed": true });
+        if let Some(email) = &acme.contact {
+            account["contact"] = json!([format!("mailto:{}", email)]);
+        }
+        let response = session.post(&string(&directory, "newAccount")?, Some(account))?;
+        session.account = Some(location(&response)?);
+        Ok(session)
+    }
+
+    /// Places an order for the domains, and returns its URL and itself.
+    fn order(&mut self) -> Result<(String, Value), AcmeError> {
+        let identifiers: Vec<Value> = self
+            .acme
+            .domains
+            .iter()
+            .map(|domain| json!({ "type": "dns", "value": domain }))
+            .collect();
+        let url = self.new_order.clone();
+        let response = self.post(&url, Some(json!({ "identifiers": identifiers })))?;
+        Ok((location(&response)?, json_body(&response)?))
+    }
+
+    /// Proves control of the domain of the authorization at `url` with its
+    /// TLS-ALPN-01 challenge, unless it is already valid.
+    fn authorize(&mut self, url: &str) -> Result<(), AcmeError> {
+        let authorization = json_body(&self.post(url, None)?)?;
+        if authorization["status"] == "valid" {
+            return Ok(());
+        }
+        let domain = authorization["identifier"]["value"]
+            .as_str()
+            .ok_or_else(|| missing("identifier"))?
+            .to_string();
+        let challenge = authorization["challenges"]
+            .as_array()
+            .and_then(|challenges| {
+                challenges
+                    .iter()
+                    .find(|challenge| challenge["type"] == "tls-alpn-01")
+            })
+            .ok_or_else(|| {
+                AcmeError::InvalidResponse(format!("no tls-alpn-01 challenge for {}", domain))
+            })?;
+        let key_authorization = format!("{}.{}", string(challenge, "token")?, self.thumbprint());
+
+        let certificate = challenge_certificate(&domain, &key_authorization)?;
+        let acme = self.acme;
+        let challenges = &acme.certificates.challenges;
+        challenges
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(domain.clone(), Arc::new(certificate));
+        let result = self
+            .post(&string(challenge, "url")?, Some(json!({})))
+            .and_then(|_| self.poll(url, "authorization", &["pending"]));
+        challenges
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&domain);
+
+        let authorization = result?;
+        if authorization["status"] == "valid" {
+            return Ok(());
+        }
+        let detail = authorization["challenges"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find_map(|challenge| challenge["error"]["detail"].as_str())
+            .unwrap_or("no reason given");
+        Err(AcmeError::Validation {
+            domain,
+            detail: detail.to_string(),
+        })
+    }
+
+    /// Fetches the object at `url` until its status is no longer one of
+    /// `waiting`, and returns it.
+    fn poll(
+        &mut self,
+        url: &str,
+        what: &'static str,
+        waiting: &[&str],
+    ) -> Result<Value, AcmeError> {
+        for _ in 0..POLL_ATTEMPTS {
+            let object = json_body(&self.post(url, None)?)?;
+            if !waiting.iter().any(|status| object["status"] == *status) {
+                return Ok(object);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        Err(AcmeError::Timeout(what))
+    }
+
+    /// Sends `payload` to `url` signed with the account key, or an empty
+    /// payload to fetch what is there without one. A request turned down
+    /// for its nonce is sent once more with a fresh one.
+    fn post(&mut self, url: &str, payload: Option<Value>) -> Result<Response, AcmeError> {
+        let payload =
+            payload.map_or_else(String::new, |payload| BASE64URL.encode(payload.to_string()));
+        let mut retried = false;
+        loop {
+            let nonce = match self.nonce.take() {
+                Some(nonce) => nonce,
+                None => self.fresh_nonce()?,
+            };
+            let mut protected = json!({ "alg": "ES256", "nonce": nonce, "url": url });
+            match &self.account {
+                Some(account) => protected["kid"] = json!(account),
+                None => {
+                    protected["jwk"] =
+                        serde_json::from_str(&self.jwk).expect("the JWK is valid JSON")
+                }
+            }
+            let protected = BASE64URL.encode(protected.to_string());
+            let signing_input = format!("{}.{}", protected, payload);
+            let signature = self
+                .key
+                .sign(&self.rng, signing_input.as_bytes())
+                .map_err(|_| AcmeError::Crypto("cannot sign the request".to_string()))?;
+            let body = json!({
+                "protected": protected,
+                "payload": payload,
+                "signature": BASE64URL.encode(signature.as_ref()),
+            });
+
+            let response = self.acme.client.post(
+                url,
+                &[("Content-Type", "application/jose+json")],
+                body.to_string().as_bytes(),
+            )?;
+            self.nonce = response.header_value("Replay-Nonce").map(String::from);
+            let bad_nonce = response.status == 400
+                && json_body(&response)
+                    .is_ok_and(|problem| problem["type"] == "urn:ietf:params:acme:error:badNonce");
+            if bad_nonce && !retried {
+                retried = true;
+                continue;
+            }
+            return checked(response);
+        }
+    }
+
+    fn fresh_nonce(&self) -> Result<String, AcmeError> {
+        let response = self
+            .acme
+            .client
+            .request("HEAD", &self.new_nonce, &[], &[])?;
+        response
+            .header_value("Replay-Nonce")
+            .map(String::from)
+            .ok_or_else(|| missing("Replay-Nonce"))
+    }
+
+    /// The account key's JWK thumbprint (RFC 7638), for key authorizations.
+    fn thumbprint(&self) -> String {
+        BASE64URL.encode(digest(&SHA256, self.jwk.as_bytes()))
+    }
+}
+
+/// The account key stored at `path`, generated and stored first if there is
+/// none.
+fn account_key(path: &Path, rng: &SystemRandom) -> Result<EcdsaKeyPair, AcmeError> {
+    let pkcs8 = if path.exists() {
+        match PrivateKeyDer::from_pem_file(path).map_err(crypto_error)? {
+            PrivateKeyDer::Pkcs8(key) => key.secret_pkcs8_der().to_vec(),
+            _ => {
+                return Err(AcmeError::Crypto(format!(
+                    "{} is not a PKCS #8 key",
+                    path.display()
+                )))
+            }
+        }
+    } else {
+        let key = KeyPair::generate().map_err(crypto_error)?;
+        write_private(path, key.serialize_pem().as_bytes())?;
+        key.serialize_der()
+    };
+    EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &pkcs8, rng).map_err(crypto_error)
+}
+
+/// A self-signed certificate for `domain` carrying the digest of
+/// `key_authorization`, which answers its TLS-ALPN-01 challenge.
+fn challenge_certificate(domain: &str, key_authorization: &str) -> Result<CertifiedKey, AcmeError> {
+    let key = KeyPair::generate().map_err(crypto_error)?;
+    let mut params = CertificateParams::new(vec![domain.to_string()]).map_err(crypto_error)?;
+    let digest = digest(&SHA256, key_authorization.as_bytes());
+    params.custom_extensions = vec![CustomExtension::new_acme_identifier(digest.as_ref())];
+    let certificate = params.self_signed(&key).map_err(crypto_error)?;
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.serialize_der()));
+    let signing_key = rustls::crypto::ring::sign::any_supported_type(&key).map_err(crypto_error)?;
+    Ok(CertifiedKey::new(
+        vec![certificate.der().clone()],
+        signing_key,
+    ))
+}
+
+/// Writes `contents` to `path`, readable by the owner only where that can
+/// be set.
+fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    io::Write::write_all(&mut options.open(path)?, contents)
+}
+
+/// `response` if its status is a success, or else the problem it
+/// describes.
+fn checked(response: Response) -> Result<Response, AcmeError> {
+    if (200..300).contains(&response.status) {
+        return Ok(response);
+    }
+    let detail = json_body(&response)
+        .ok()
+        .and_then(|problem| problem["detail"].as_str().map(String::from))
+        .unwrap_or_else(|| String::from_utf8_lossy(&response.body).into_owned());
+    Err(AcmeError::Rejected {
+        status: response.status,
+        detail,
+    })
+}
+
+fn json_body(response: &Response) -> Result<Value, AcmeError> {
+    serde_json::from_slice(&response.body).map_err(|e| AcmeError::InvalidResponse(e.to_string()))
+}
+
+fn location(response: &Response) -> Result<String, AcmeError> {
+    response
+        .header_value("Location")
+        .map(String::from)
+        .ok_or_else(|| missing("Location"))
+}
+
+fn missing(name: &str) -> AcmeError {
+    AcmeError::InvalidResponse(format!("no {}", name))
+}
+
+/// The string member `name` of `object`.
+fn string(object: &Value, name: &str) -> Result<String, AcmeError> {
+    object[name]
+        .as_str()
+        .map(String::from)
+        .ok_or_else(|| missing(name))
+}
+
+/// The strings in the array member `name` of `object`.
+fn strings(object: &Value, name: &str) -> Result<Vec<String>, AcmeError> {
+    object[name]
+        .as_array()
+        .and_then(|items| {
+            items
+                .iter()
+                .map(|item| item.as_str().map(String::from))
+                .collect()
+        })
+        .ok_or_else(|| missing(name))
+}
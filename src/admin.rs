@@ -0,0 +1,91 @@
+//! JSON endpoints for tools that watch a running server, such as the
+//! `nu_plugin_rusty_server` Nushell plugin.
+//!
+//! [`Router::admin`] serves them:
+//!
+//! - [`STATS_PATH`]: uptime and connection counts, the same object the
+//!   `/events` stream sends
+//! - [`CONNECTIONS_PATH`]: every open connection, with its peer, when it
+//!   was accepted, the requests answered on it, and how long it has been
+//!   idle, which shows keep-alive connections that are never closed
+//! - [`ROUTES_PATH`]: the method, path, and kind of every route registered
+//!   before them
+//!
+//! They tell anyone who can reach the server who else is connected, so they
+//! are off unless asked for.
+
+use serde::Serialize;
+
+use crate::http::Response;
+use crate::openapi::Kind;
+use crate::router::Router;
+use crate::{stats, unix_timestamp};
+
+/// Where [`Router::admin`] serves the server stats.
+pub const STATS_PATH: &str = "/admin/stats";
+
+/// Where [`Router::admin`] serves the open connections.
+pub const CONNECTIONS_PATH: &str = "/admin/connections";
+
+/// Where [`Router::admin`] serves the routes.
+pub const ROUTES_PATH: &str = "/admin/routes";
+
+/// `GET /admin/stats`: the server stats, as a JSON object.
+pub(crate) fn stats() -> Response {
+    Response::json(200, &stats::snapshot())
+}
+
+/// An open connection, as `GET /admin/connections` lists it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct ConnectionInfo {
+    peer: String,
+    opened: u64,
+    age_secs: u64,
+    requests: u64,
+    /// How long it has been waiting for another request, if it is.
+    idle_secs: Option<u64>,
+}
+
+/// `GET /admin/connections`: the open connections, oldest first, as a JSON
+/// array.
+pub(crate) fn connections() -> Response {
+    let now = unix_timestamp();
+    let connections: Vec<ConnectionInfo> = stats::connections()
+        .into_iter()
+        .map(|connection| ConnectionInfo {
+            age_secs: now.saturating_sub(connection.opened),
+            idle_secs: connection.idle_since.map(|since| now.saturating_sub(since)),
+            peer: connection.peer,
+            opened: connection.opened,
+            requests: connection.requests,
+        })
+        .collect();
+    Response::json(200, &connections)
+}
+
+/// A route, as `GET /admin/routes` lists it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct RouteInfo {
+    method: String,
+    path: String,
+    /// `route`, `events`, or `websocket`.
+    kind: &'static str,
+}
+
+/// The routes of `router`, as `GET /admin/routes` answers with them.
+pub(crate) fn routes(router: &Router) -> Vec<RouteInfo> {
+    router
+        .operations()
+        .into_iter()
+        .map(|(method, path, kind)| RouteInfo {
+            method: method.to_string(),
+            path: path.to_string(),
+            kind: match kind {
+                Kind::Route => "route",
+                Kind::Events => "events",
+                #[cfg(feature = "websocket")]
+                Kind::WebSocket => "websocket",
+            },
+        })
+        .collect()
+}
@@ -0,0 +1,280 @@
+//! HTTP authentication for protected paths (with the `auth` feature).
+//!
+//! [`BasicAuth`] asks for a user name and password (RFC 7617) on the path
+//! prefixes it protects, checking them against users given in code, in the
+//! config file, or in an htpasswd file, and [`BearerAuth`] for one of a set
+//! of static tokens (RFC 6750), as scrapers and other programs send them:
+//!
+//! ```no_run
+//! use rusty_server::auth::{BasicAuth, BearerAuth};
+//!
+//! let auth = BasicAuth::new("rusty-server")
+//!     .protect("/admin")
+//!     .user("ops", "correct horse battery staple")
+//!     .htpasswd_file(".htpasswd")?;
+//! let mut router = rusty_server::Router::default();
+//! router.middleware(auth);
+//! router.middleware(BearerAuth::new().protect("/metrics").token("s3cr3t-scraper-token"));
+//! # Ok::<(), rusty_server::auth::HtpasswdError>(())
+//! ```
+//!
+//! A request under a protected prefix without valid credentials gets `401
+//! Unauthorized` with a `WWW-Authenticate` challenge, which makes browsers
+//! ask for a password. Passwords and tokens are compared in constant time,
+//! so response times do not give them away. A request carries a single
+//! `Authorization` header, so give each its own prefixes: a path protected
+//! by both could never be reached. Like other middleware, it covers routed
+//! requests, not event streams, streaming handlers such as CGI, or
+//! WebSockets.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use sha1::{Digest, Sha1};
+use subtle::{Choice, ConstantTimeEq};
+
+use crate::http::{Request, Response};
+use crate::router::{covers, Middleware};
+
+/// A line of an htpasswd file that cannot be used.
+#[derive(Debug, thiserror::Error)]
+pub enum HtpasswdError {
+    /// The file could not be read.
+    #[error("cannot read the htpasswd file: {0}")]
+    Io(#[from] io::Error),
+    /// A line is not `user:password`.
+    #[error("line {0} of the htpasswd file is not `user:password`")]
+    Malformed(usize),
+    /// A password is hashed with a scheme other than `{SHA}`, such as bcrypt
+    /// or `$apr1$` MD5.
+    #[error("the password of `{0}` is hashed with a scheme other than {{SHA}}; use `htpasswd -s` to hash it")]
+    UnsupportedHash(String),
+}
+
+/// A user's password, as stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Password {
+    Plain(String),
+    /// `{SHA}` and the Base64 SHA-1 digest of the password, as `htpasswd -s`
+    /// writes it.
+    Sha1(String),
+}
+
+impl Password {
+    /// `stored`, as it appears after the user name in an htpasswd file.
+    fn parse(user: &str, stored: &str) -> Result<Password, HtpasswdError> {
+        if let Some(digest) = stored.strip_prefix("{SHA}") {
+            return Ok(Password::Sha1(digest.to_string()));
+        }
+        if stored.starts_with('$') {
+            return Err(HtpasswdError::UnsupportedHash(user.to_string()));
+        }
+        Ok(Password::Plain(stored.to_string()))
+    }
+
+    /// Whether `password` is this one.
+    fn matches(&self, password: &str) -> bool {
+        match self {
+            Password::Plain(stored) => constant_time_eq(stored.as_bytes(), password.as_bytes()),
+            Password::Sha1(digest) => {
+                let hashed = BASE64.encode(Sha1::digest(password.as_bytes()));
+                constant_time_eq(digest.as_bytes(), hashed.as_bytes())
+            }
+        }
+    }
+}
+
+/// Middleware asking for a user name and password on protected paths.
+#[derive(Debug, Clone)]
+pub struct BasicAuth {
+    realm: String,
+    prefixes: Vec<String>,
+    users: HashMap<String, Password>,
+}
+
+impl BasicAuth {
+    /// Protects nothing yet, and names `realm` in its challenges, which
+    /// browsers show when asking for credentials.
+    pub fn new(realm: &str) -> Self {
+        BasicAuth {
+            realm: realm.to_string(),
+            prefixes: Vec::new(),
+            users: HashMap::new(),
+        }
+    }
+
+    /// Asks for credentials on `prefix` and the paths below it: `/admin`
+    /// covers `/admin` and `/admin/stats`, but not `/administrator`.
+    pub fn protect(mut self, prefix: &str) -> Self {
+        self.prefixes.push(prefix.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Lets `user` in with `password`, which may also be hashed as in an
+    /// htpasswd file (`{SHA}` and its Base64 SHA-1 digest).
+    pub fn user(mut self, user: &str, password: &str) -> Self {
+        let password = match password.strip_prefix("{SHA}") {
+            Some(digest) => Password::Sha1(digest.to_string()),
+            None => Password::Plain(password.to_string()),
+        };
+        self.users.insert(user.to_string(), password);
+        self
+    }
+
+    /// Lets in the users of the htpasswd file at `path`: `user:password`
+    /// lines, with passwords in plain text or hashed with `htpasswd -s`.
+    /// Blank lines and lines starting with `#` are skipped.
+    pub fn htpasswd_file(self, path: impl AsRef<Path>) -> Result<Self, HtpasswdError> {
+        self.htpasswd(&fs::read_to_string(path)?)
+    }
+
+    /// Lets in the users of `htpasswd`, the contents of an htpasswd file;
+    /// see [`htpasswd_file`](BasicAuth::htpasswd_file).
+    pub fn htpasswd(mut self, htpasswd: &str) -> Result<Self, HtpasswdError> {
+        for (index, line) in htpasswd.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (user, stored) = line
+                .split_once(':')
+                .filter(|(user, _)| !user.is_empty())
+                .ok_or(HtpasswdError::Malformed(index + 1))?;
+            let password = Password::parse(user, stored)?;
+            self.users.insert(user.to_string(), password);
+        }
+        Ok(self)
+    }
+
+    /// Whether `request` needs credentials.
+    pub fn protects(&self, request: &Request) -> bool {
+        self.prefixes.iter().any(|prefix| covers(prefix, &request.path))
+    }
+
+    /// Whether `request` carries the credentials of a user let in.
+    pub fn authorized(&self, request: &Request) -> bool {
+        let Some((user, password)) = request.header("Authorization").and_then(basic_credentials)
+        else {
+            return false;
+        };
+        // Unknown users are checked against a dummy password all the same,
+        // so that the time taken does not tell which users exist.
+        let dummy = Password::Plain(String::new());
+        let stored = self.users.get(&user);
+        stored.unwrap_or(&dummy).matches(&password) && stored.is_some()
+    }
+}
+
+impl Middleware for BasicAuth {
+    fn before(&self, request: &Request) -> Option<Response> {
+        if !self.protects(request) || self.authorized(request) {
+            return None;
+        }
+        let challenge = format!(
+            "Basic realm=\"{}\", charset=\"UTF-8\"",
+            self.realm.replace(['"', '\\'], "")
+        );
+        Some(unauthorized(request, "This path needs a user name and password.", challenge))
+    }
+}
+
+/// Middleware asking for a bearer token on protected paths.
+#[derive(Debug, Clone, Default)]
+pub struct BearerAuth {
+    prefixes: Vec<String>,
+    tokens: Vec<String>,
+}
+
+impl BearerAuth {
+    /// Protects nothing yet, and accepts no tokens.
+    pub fn new() -> Self {
+        BearerAuth::default()
+    }
+
+    /// Asks for a token on `prefix` and the paths below it, as
+    /// [`BasicAuth::protect`] does.
+    pub fn protect(mut self, prefix: &str) -> Self {
+        self.prefixes.push(prefix.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Accepts `token`; call it once for each token, such as one per
+    /// scraper, so that each can be revoked alone. Surrounding whitespace is
+    /// ignored, and an empty token is never accepted.
+    pub fn token(mut self, token: &str) -> Self {
+        let token = token.trim();
+        if !token.is_empty() {
+            self.tokens.push(token.to_string());
+        }
+        self
+    }
+
+    /// Whether `request` needs a token.
+    pub fn protects(&self, request: &Request) -> bool {
+        self.prefixes.iter().any(|prefix| covers(prefix, &request.path))
+    }
+
+    /// Whether `request` carries one of the tokens accepted.
+    pub fn authorized(&self, request: &Request) -> bool {
+        let Some(token) = request.header("Authorization").and_then(bearer_token) else {
+            return false;
+        };
+        // Every token is compared, so that the time taken does not tell
+        // which one matched.
+        let found = self.tokens.iter().fold(Choice::from(0), |found, accepted| {
+            found | accepted.as_bytes().ct_eq(token.as_bytes())
+        });
+        found.into()
+    }
+}
+
+impl Middleware for BearerAuth {
+    fn before(&self, request: &Request) -> Option<Response> {
+        if !self.protects(request) || self.authorized(request) {
+            return None;
+        }
+        // RFC 6750 tells a client whose token was refused apart from one
+        // that sent none.
+        let challenge = match request.header("Authorization").and_then(bearer_token) {
+            Some(_) => "Bearer error=\"invalid_token\"",
+            None => "Bearer",
+        };
+        Some(unauthorized(request, "This path needs a bearer token.", challenge.to_string()))
+    }
+}
+
+/// The `401 Unauthorized` page for `request`, saying `message`, with the
+/// `WWW-Authenticate` header `challenge`.
+fn unauthorized(request: &Request, message: &str, challenge: String) -> Response {
+    crate::pages::error_for(request, 401, "Unauthorized", message)
+        .header("WWW-Authenticate", challenge)
+}
+
+/// The token of a `Bearer` `Authorization` header.
+fn bearer_token(authorization: &str) -> Option<&str> {
+    let (scheme, token) = authorization.trim().split_once(' ')?;
+    let token = token.trim();
+    (scheme.eq_ignore_ascii_case("Bearer") && !token.is_empty()).then_some(token)
+}
+
+/// The user name and password of a `Basic` `Authorization` header.
+fn basic_credentials(authorization: &str) -> Option<(String, String)> {
+    let (scheme, encoded) = authorization.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("Basic") {
+        return None;
+    }
+    let decoded = BASE64.decode(encoded.trim()).ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (user, password) = decoded.split_once(':')?;
+    Some((user.to_string(), password.to_string()))
+}
+
+/// Whether `a` and `b` are equal, in time that depends on their lengths
+/// only, not on where they differ.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
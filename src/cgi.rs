@@ -0,0 +1,467 @@
+//! Old-school CGI: executing scripts from a `cgi-bin` directory.
+//!
+//! Mount a [`Cgi`] on a router as a streaming handler:
+//!
+//! ```no_run
+//! use rusty_server::cgi::Cgi;
+//!
+//! let cgi = Cgi::new("/cgi-bin", "cgi-bin");
+//! let mut router = rusty_server::Router::default();
+//! router.stream("/cgi-bin", move |request, connection| cgi.serve(request, connection));
+//! ```
+//!
+//! `/cgi-bin/hello.sh/extra` runs `cgi-bin/hello.sh` with `PATH_INFO` set to
+//! `/extra`. The script gets the RFC 3875 meta-variables in an otherwise
+//! empty environment, the request body on its standard input, and its
+//! standard output, a CGI response, is streamed to the client. Anything it
+//! writes to standard error is printed to the console.
+//!
+//! Each script is a process with the server's privileges: only point this
+//! at a directory of scripts you trust.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use crate::console;
+use crate::http::{Request, Response};
+use crate::pages;
+use crate::plugin::is_valid_header;
+use crate::stream::Connection;
+
+/// How long a script may run before it is killed.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How many scripts may run at once; requests beyond that get a 503.
+pub const DEFAULT_MAX_CONCURRENT: usize = 16;
+
+/// `PATH` for scripts, whose environment is otherwise cleared.
+const SCRIPT_PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+
+/// Largest response head accepted from a script.
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+/// Scripts in one directory, served under one URL prefix.
+#[derive(Debug, Clone)]
+pub struct Cgi {
+    prefix: String,
+    dir: PathBuf,
+    timeout: Duration,
+    max_concurrent: usize,
+    running: Arc<AtomicUsize>,
+}
+
+impl Cgi {
+    /// Runs scripts from `dir` for requests under `prefix`, the prefix this
+    /// is mounted at with [`Router::stream`](crate::Router::stream).
+    pub fn new(prefix: &str, dir: impl Into<PathBuf>) -> Self {
+        Cgi {
+            prefix: prefix.trim_end_matches('/').to_string(),
+            dir: dir.into(),
+            timeout: DEFAULT_TIMEOUT,
+            max_concurrent: DEFAULT_MAX_CONCURRENT,
+            running: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Sets how long a script may run; see [`DEFAULT_TIMEOUT`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets how many scripts may run at once; see
+    /// [`DEFAULT_MAX_CONCURRENT`].
+    pub fn max_concurrent(mut self, max_concurrent: usize) -> Self {
+        self.max_concurrent = max_concurrent;
+        self
+    }
+
+    /// The script for `request`, and the path info after it, if the request
+    /// names one inside the directory.
+    fn script(&self, request: &Request) -> Option<(String, PathBuf, String)> {
+        let rest = request.path.strip_prefix(&self.prefix)?.strip_prefix('/')?;
+        let (name, path_info) = match rest.find('/') {
+            Some(slash) => (&rest[..slash], &rest[slash..]),
+            None => (rest, ""),
+        };
+        if escapes_root(name) || name.is_empty() {
+            return None;
+        }
+        let script_name = format!("{}/{}", self.prefix, name);
+        Some((script_name, self.dir.join(name), path_info.to_string()))
+    }
+
+    /// Runs the script `request` names and streams its response back on
+    /// `connection`. Returns the status sent.
+    ///
+    /// Missing scripts get a 404 page, scripts that are not executable a
+    /// 403. When too many scripts are running already the client gets a
+    /// 503. A script that fails before its response head is complete gets
+    /// the client a 500 page, or a 504 if it timed out; later failures end
+    /// the connection.
+    pub fn serve(&self, request: &Request, connection: &mut Connection<'_>) -> io::Result<u16> {
+        let Some((script_name, script, path_info)) = self.script(request) else {
+            return send(connection, pages::not_found_for(request));
+        };
+        if !script.is_file() {
+            return send(connection, pages::not_found_for(request));
+        }
+        if !is_executable(&script) {
+            return send(
+                connection,
+                pages::error_for(request, 403, "Forbidden", "The script is not executable."),
+            );
+        }
+        let Some(_slot) = Slot::take(&self.running, self.max_concurrent) else {
+            return send(
+                connection,
+                pages::error_for(
+                    request,
+                    503,
+                    "Service Unavailable",
+                    "Too many scripts are running; try again shortly.",
+                )
+                .header("Retry-After", "1"),
+            );
+        };
+
+        let variables = meta_variables(request, &script_name, &script, &path_info, &self.dir);
+        // The script runs inside its directory, so a relative path to it would
+        // no longer resolve.
+        let program = script.canonicalize().unwrap_or(script.clone());
+        let child = Command::new(program)
+            .current_dir(&self.dir)
+            .env_clear()
+            .env("PATH", SCRIPT_PATH)
+            .envs(variables)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) => return send(connection, script_error(&e.to_string())),
+        };
+        let (stdin, stdout, stderr) =
+            (child.stdin.take(), child.stdout.take(), child.stderr.take());
+        let child = Arc::new(Mutex::new(child));
+        let watchdog = Watchdog::start(Arc::clone(&child), self.timeout);
+        if let Some(stderr) = stderr {
+            thread::spawn(move || log_stderr(stderr));
+        }
+
+        let result = relay(connection, stdin, stdout, &watchdog);
+        watchdog.stop();
+        let _ = child.lock().unwrap_or_else(PoisonError::into_inner).wait();
+        match result {
+            Ok(status) => Ok(status),
+            Err(Failure::BeforeHead(_)) if watchdog.fired() => send(
+                connection,
+                pages::error(504, "Gateway Timeout", "The script did not finish in time."),
+            ),
+            Err(Failure::BeforeHead(message)) => send(connection, script_error(&message)),
+            Err(Failure::AfterHead(e)) => Err(e),
+        }
+    }
+}
+
+/// Where running a script went wrong.
+enum Failure {
+    /// Nothing was sent to the client yet, so it can still get an error page.
+    BeforeHead(String),
+    AfterHead(io::Error),
+}
+
+/// Feeds the request body to the script, then streams its response to the
+/// client.
+fn relay(
+    connection: &mut Connection<'_>,
+    stdin: Option<impl Write>,
+    stdout: Option<impl Read>,
+    watchdog: &Watchdog,
+) -> Result<u16, Failure> {
+    if let Some(mut stdin) = stdin {
+        // A script that does not read its input closes the pipe early; the
+        // rest of the body is of no interest then.
+        let _ = io::copy(connection, &mut stdin);
+    }
+    let mut stdout = stdout.ok_or_else(|| Failure::BeforeHead("no standard output".to_string()))?;
+
+    let mut output = Vec::new();
+    let mut chunk = vec![0; 16 * 1024];
+    let (response, head_len) = loop {
+        let n = stdout
+            .read(&mut chunk)
+            .map_err(|e| Failure::BeforeHead(e.to_string()))?;
+        if n == 0 {
+            let reason = if watchdog.fired() {
+                "timed out"
+            } else {
+                "exited without a response head"
+            };
+            return Err(Failure::BeforeHead(reason.to_string()));
+        }
+        output.extend_from_slice(&chunk[..n]);
+        if let Some(head) = parse_head(&output).map_err(|e| Failure::BeforeHead(e.to_string()))? {
+            break head;
+        }
+        if output.len() > MAX_HEAD_SIZE {
+            return Err(Failure::BeforeHead("response head too large".to_string()));
+        }
+    };
+
+    send_response(connection, &response, &output[head_len..], &mut stdout)
+        .map_err(Failure::AfterHead)?;
+    Ok(response.status)
+}
+
+/// Sends the head of `response` and `body`, then copies the rest of the body
+/// from `stdout` as it is written.
+fn send_response(
+    connection: &mut Connection<'_>,
+    response: &Response,
+    body: &[u8],
+    stdout: &mut impl Read,
+) -> io::Result<()> {
+    connection.send_head(response)?;
+    connection.write_all(body)?;
+    connection.flush()?;
+    let mut chunk = vec![0; 16 * 1024];
+    loop {
+        let n = stdout.read(&mut chunk)?;
+        if n == 0 {
+            return Ok(());
+        }
+        connection.write_all(&chunk[..n])?;
+        connection.flush()?;
+    }
+}
+
+/// A place among the scripts allowed to run at once, given back on drop.
+struct Slot<'a>(&'a AtomicUsize);
+
+impl<'a> Slot<'a> {
+    fn take(running: &'a AtomicUsize, max: usize) -> Option<Self> {
+        running
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                (count < max).then_some(count + 1)
+            })
+            .ok()
+            .map(|_| Slot(running))
+    }
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Kills a script that outlives its timeout.
+struct Watchdog {
+    done: mpsc::Sender<()>,
+    fired: Arc<AtomicBool>,
+}
+
+impl Watchdog {
+    fn start(child: Arc<Mutex<Child>>, timeout: Duration) -> Self {
+        let (done, finished) = mpsc::channel();
+        let fired = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&fired);
+        thread::spawn(move || {
+            if let Err(RecvTimeoutError::Timeout) = finished.recv_timeout(timeout) {
+                flag.store(true, Ordering::Release);
+                let _ = child.lock().unwrap_or_else(PoisonError::into_inner).kill();
+            }
+        });
+        Watchdog { done, fired }
+    }
+
+    fn fired(&self) -> bool {
+        self.fired.load(Ordering::Acquire)
+    }
+
+    fn stop(&self) {
+        let _ = self.done.send(());
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|meta| meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_path: &Path) -> bool {
+    true
+}
+
+fn log_stderr(stderr: impl Read) {
+    for line in BufReader::new(stderr).lines() {
+        match line {
+            Ok(line) => console::print_table(&[("CGI", line)]),
+            Err(_) => break,
+        }
+    }
+}
+
+fn script_error(message: &str) -> Response {
+    console::print_table(&[("CGI", format!("✗ {}", message))]);
+    pages::error(
+        500,
+        "Internal Server Error",
+        "The script failed to produce a response.",
+    )
+}
+
+fn send(connection: &mut Connection<'_>, response: Response) -> io::Result<u16> {
+    response.write_to(connection)?;
+    Ok(response.status)
+}
+
+/// Whether `path` contains a `..` component, which could leave the
+/// directory it is resolved in.
+pub(crate) fn escapes_root(path: &str) -> bool {
+    Path::new(path)
+        .components()
+        .any(|component| matches!(component, Component::ParentDir))
+}
+
+/// The RFC 3875 meta-variables for running `script_filename` for `request`.
+pub(crate) fn meta_variables(
+    request: &Request,
+    script_name: &str,
+    script_filename: &Path,
+    path_info: &str,
+    document_root: &Path,
+) -> Vec<(String, String)> {
+    let query = request
+        .target
+        .split_once('?')
+        .map_or("", |(_, query)| query);
+    let mut variables = vec![
+        ("GATEWAY_INTERFACE", "CGI/1.1".to_string()),
+        (
+            "SERVER_SOFTWARE",
+            format!("rusty-server/{}", env!("CARGO_PKG_VERSION")),
+        ),
+        ("SERVER_PROTOCOL", request.version.to_string()),
+        ("REQUEST_METHOD", request.method.clone()),
+        ("REQUEST_URI", request.target.clone()),
+        ("SCRIPT_NAME", script_name.to_string()),
+        (
+            "SCRIPT_FILENAME",
+            script_filename.to_string_lossy().into_owned(),
+        ),
+        ("PATH_INFO", path_info.to_string()),
+        ("QUERY_STRING", query.to_string()),
+        (
+            "DOCUMENT_ROOT",
+            document_root.to_string_lossy().into_owned(),
+        ),
+    ];
+    if let Some(host) = request.header("Host") {
+        // The port follows the last colon, unless that colon is inside an
+        // IPv6 literal such as `[::1]`.
+        match host.rsplit_once(':') {
+            Some((name, port)) if !port.contains(']') => {
+                variables.push(("SERVER_NAME", name.to_string()));
+                variables.push(("SERVER_PORT", port.to_string()));
+            }
+            _ => variables.push(("SERVER_NAME", host.to_string())),
+        }
+    }
+    if request.header("Content-Length").is_some() {
+        variables.push(("CONTENT_LENGTH", request.content_length().to_string()));
+    }
+    if let Some(content_type) = request.header("Content-Type") {
+        variables.push(("CONTENT_TYPE", content_type.to_string()));
+    }
+    let mut variables: Vec<(String, String)> = variables
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect();
+    for (name, value) in &request.headers {
+        if name.eq_ignore_ascii_case("Content-Length") || name.eq_ignore_ascii_case("Content-Type")
+        {
+            continue;
+        }
+        // `Proxy` would end up as HTTP_PROXY, which many programs read as
+        // their outgoing proxy ("httpoxy").
+        if name.eq_ignore_ascii_case("Proxy") {
+            continue;
+        }
+        let name = format!("HTTP_{}", name.to_ascii_uppercase().replace('-', "_"));
+        match variables.iter_mut().find(|(existing, _)| *existing == name) {
+            Some((_, existing)) => {
+                existing.push_str(", ");
+                existing.push_str(value);
+            }
+            None => variables.push((name, value.clone())),
+        }
+    }
+    variables
+}
+
+/// Parses a CGI response head (RFC 3875 section 6) at the start of `output`,
+/// returning the response it describes and the head's length, or `None` if
+/// the head is not complete yet.
+pub(crate) fn parse_head(output: &[u8]) -> io::Result<Option<(Response, usize)>> {
+    let (head, head_len) = match find_blank_line(output) {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+    let head = std::str::from_utf8(head).map_err(|_| invalid("response head is not UTF-8"))?;
+    let mut response = Response::new(200);
+    let mut status = None;
+    for line in head.lines() {
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| invalid("malformed response header"))?;
+        let (name, value) = (name.trim(), value.trim());
+        if name.eq_ignore_ascii_case("Status") {
+            let code = value.split(' ').next().and_then(|code| code.parse().ok());
+            status = Some(
+                code.filter(|code| (100..=599).contains(code))
+                    .ok_or_else(|| invalid("invalid Status header"))?,
+            );
+        } else if is_valid_header(name, value) {
+            response.headers.push((name.to_string(), value.to_string()));
+        }
+    }
+    response.status = match status {
+        Some(status) => status,
+        None if response.header_value("Location").is_some() => 302,
+        None => 200,
+    };
+    response
+        .headers
+        .push(("Connection".to_string(), "close".to_string()));
+    Ok(Some((response, head_len)))
+}
+
+/// The head before the first blank line (`\r\n\r\n` or `\n\n`), and the
+/// length including that line.
+fn find_blank_line(bytes: &[u8]) -> Option<(&[u8], usize)> {
+    for (i, window) in bytes.windows(2).enumerate() {
+        if window == b"\n\n" {
+            return Some((&bytes[..i], i + 2));
+        }
+        if window == b"\n\r" && bytes.get(i + 2) == Some(&b'\n') {
+            return Some((&bytes[..i], i + 3));
+        }
+    }
+    None
+}
+
+pub(crate) fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
@@ -0,0 +1,180 @@
+//! Probing a running server's endpoints, as `rusty-server check` does.
+//!
+//! A [`Check`] requests each endpoint under a base URL and compares the
+//! answer with what is expected: a 2xx status (or exactly the one set with
+//! [`Check::status`]) and, optionally, a content type. [`print`] shows the
+//! outcome as a table, and the binary exits non-zero if any probe failed,
+//! which makes the subcommand usable as a Docker `HEALTHCHECK`.
+
+use std::time::{Duration, Instant};
+
+use crate::client::Client;
+use crate::console;
+
+/// Endpoints probed unless set with [`Check::endpoints`].
+pub const DEFAULT_ENDPOINTS: [&str; 1] = ["/version"];
+
+/// Timeout for each probe, unless set with [`Check::timeout`].
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What to probe, and what to expect.
+#[derive(Debug, Clone)]
+pub struct Check {
+    url: String,
+    endpoints: Vec<String>,
+    status: Option<u16>,
+    content_type: Option<String>,
+    timeout: Duration,
+}
+
+/// The outcome of probing one endpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Probe {
+    pub endpoint: String,
+    /// The response status, if a response arrived.
+    pub status: Option<u16>,
+    pub content_type: Option<String>,
+    pub elapsed: Duration,
+    /// Why the probe failed; `None` if it passed.
+    pub failure: Option<String>,
+}
+
+impl Probe {
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+impl Check {
+    /// Probes [`DEFAULT_ENDPOINTS`] under `url`, such as
+    /// `http://127.0.0.1:3000`, expecting a 2xx status.
+    pub fn new(url: impl Into<String>) -> Self {
+        Check {
+            url: url.into().trim_end_matches('/').to_string(),
+            endpoints: DEFAULT_ENDPOINTS.iter().map(|e| e.to_string()).collect(),
+            status: None,
+            content_type: None,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Replaces the endpoints to probe: paths, with or without a query.
+    pub fn endpoints<I, S>(mut self, endpoints: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.endpoints = endpoints.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Expects exactly `status` rather than any 2xx status.
+    pub fn status(mut self, status: u16) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    /// Expects the media type `content_type`, such as `application/json`;
+    /// parameters such as `charset` are ignored on both sides.
+    pub fn content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    /// Sets the timeout for each connect, read, and write of a probe.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Probes every endpoint in turn.
+    pub fn run(&self) -> Vec<Probe> {
+        let client = Client::new().timeout(self.timeout);
+        self.endpoints
+            .iter()
+            .map(|endpoint| self.probe(&client, endpoint))
+            .collect()
+    }
+
+    fn probe(&self, client: &Client, endpoint: &str) -> Probe {
+        let separator = if endpoint.starts_with('/') { "" } else { "/" };
+        let url = format!("{}{}{}", self.url, separator, endpoint);
+        let started = Instant::now();
+        let result = client.get(&url);
+        let elapsed = started.elapsed();
+
+        let mut probe = Probe {
+            endpoint: endpoint.to_string(),
+            status: None,
+            content_type: None,
+            elapsed,
+            failure: None,
+        };
+        let response = match result {
+            Ok(response) => response,
+            Err(e) => {
+                probe.failure = Some(e.to_string());
+                return probe;
+            }
+        };
+        probe.status = Some(response.status);
+        probe.content_type = response.header_value("Content-Type").map(str::to_string);
+
+        let status_ok = match self.status {
+            Some(expected) => response.status == expected,
+            None => (200..300).contains(&response.status),
+        };
+        if !status_ok {
+            probe.failure = Some(match self.status {
+                Some(expected) => format!("expected status {}", expected),
+                None => "expected a 2xx status".to_string(),
+            });
+        } else if let Some(expected) = &self.content_type {
+            let actual = probe.content_type.as_deref().map(media_type);
+            if actual != Some(media_type(expected)) {
+                probe.failure = Some(format!("expected {}", expected));
+            }
+        }
+        probe
+    }
+}
+
+/// The media type of a `Content-Type` value, without parameters, in lower
+/// case.
+fn media_type(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+/// Prints `probes` as a table: endpoint, status, content type, elapsed
+/// time, and result.
+pub fn print(probes: &[Probe]) {
+    let rows: Vec<Vec<String>> = probes
+        .iter()
+        .map(|probe| {
+            vec![
+                probe.endpoint.clone(),
+                probe
+                    .status
+                    .map_or("-".to_string(), |status| status.to_string()),
+                probe
+                    .content_type
+                    .clone()
+                    .unwrap_or_else(|| "-".to_string()),
+                format!("{} ms", probe.elapsed.as_millis()),
+                match &probe.failure {
+                    None => "✓".to_string(),
+                    Some(failure) => format!("✗ {}", failure),
+                },
+            ]
+        })
+        .collect();
+    console::print_grid(
+        &["Endpoint", "Status", "Content-Type", "Elapsed", "Result"],
+        &rows,
+    );
+}
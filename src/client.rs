@@ -0,0 +1,639 @@
+//! A small blocking HTTP/1.1 client for the server's own outgoing requests,
+//! such as webhook deliveries and probes of other servers.
+//!
+//! A [`Client`] keeps idle connections to each origin and reuses them, so
+//! repeated requests to one host skip the TCP (and TLS) handshake. A request
+//! sent on a pooled connection that the server has meanwhile closed is sent
+//! again on a new one. Every connect, read, and write is bounded by the
+//! client's [`timeout`](Client::timeout).
+//!
+//! `https://` URLs need the `tls` feature. Certificates are checked against
+//! the Mozilla roots from `webpki-roots`, plus any added with
+//! [`Client::root_certificate`].
+//!
+//! Responses are returned as a [`Response`] with the body read in full,
+//! decoded from chunked transfer coding where needed. Bodies larger than
+//! [`MAX_RESPONSE_BODY`] are refused.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::http::Response;
+use crate::plugin::is_valid_header;
+
+/// Timeout for each connect, read, and write, unless set with
+/// [`Client::timeout`].
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest response body accepted, in bytes.
+pub const MAX_RESPONSE_BODY: usize = 16 * 1024 * 1024;
+
+/// Longest status or header line accepted, in bytes.
+const MAX_LINE: usize = 8 * 1024;
+
+/// Most header fields accepted in one response.
+const MAX_HEADERS: usize = 100;
+
+/// How long an idle connection is kept for reuse.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Most idle connections kept per origin.
+const MAX_IDLE_PER_ORIGIN: usize = 4;
+
+/// Header fields the client sets itself.
+const RESERVED_HEADERS: [&str; 4] = ["Host", "Content-Length", "Transfer-Encoding", "Connection"];
+
+/// Why a request failed.
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    /// The URL could not be parsed.
+    #[error("invalid URL: {0}")]
+    InvalidUrl(String),
+    /// The URL's scheme is neither `http` nor `https`, or is `https` without
+    /// the `tls` feature.
+    #[error("unsupported URL scheme: {0}")]
+    UnsupportedScheme(String),
+    /// The method or a header field cannot be sent.
+    #[error("invalid request: {0}")]
+    InvalidRequest(String),
+    /// A connect, read, or write did not complete within the timeout.
+    #[error("timed out while {0}")]
+    Timeout(&'static str),
+    /// The TLS handshake failed, for instance on an untrusted certificate.
+    #[error("TLS error: {0}")]
+    Tls(String),
+    /// The server's answer is not a valid HTTP/1.x response.
+    #[error("invalid response: {0}")]
+    InvalidResponse(&'static str),
+    /// Any other I/O failure.
+    #[error("I/O error: {0}")]
+    Io(#[source] io::Error),
+}
+
+fn io_error(e: io::Error, doing: &'static str) -> ClientError {
+    match e.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => ClientError::Timeout(doing),
+        _ => ClientError::Io(e),
+    }
+}
+
+/// The parts of a URL the client needs.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Url {
+    https: bool,
+    /// Host name or IP address, without the brackets of an IPv6 literal.
+    host: String,
+    port: u16,
+    /// `host[:port]` as written in the URL, for the `Host` header.
+    authority: String,
+    /// Path and query, for the request line.
+    target: String,
+}
+
+impl Url {
+    fn parse(url: &str) -> Result<Url, ClientError> {
+        let invalid = || ClientError::InvalidUrl(url.to_string());
+        let (scheme, rest) = url.split_once("://").ok_or_else(invalid)?;
+        let https = match scheme.to_ascii_lowercase().as_str() {
+            "http" => false,
+            "https" if cfg!(feature = "tls") => true,
+            _ => return Err(ClientError::UnsupportedScheme(scheme.to_string())),
+        };
+        let rest = rest.split('#').next().unwrap_or_default();
+        let (authority, target) = match rest.find(['/', '?']) {
+            Some(at) if rest[at..].starts_with('?') => (&rest[..at], format!("/{}", &rest[at..])),
+            Some(at) => (&rest[..at], rest[at..].to_string()),
+            None => (rest, "/".to_string()),
+        };
+        if authority.is_empty()
+            || authority.contains('@')
+            || target.bytes().any(|b| b.is_ascii_control() || b == b' ')
+        {
+            return Err(invalid());
+        }
+
+        let (host, port) = if let Some(bracketed) = authority.strip_prefix('[') {
+            let (host, after) = bracketed.split_once(']').ok_or_else(invalid)?;
+            (host, after.strip_prefix(':'))
+        } else {
+            match authority.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            }
+        };
+        let port = match port {
+            Some(port) => port.parse().map_err(|_| invalid())?,
+            None if https => 443,
+            None => 80,
+        };
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Url {
+            https,
+            host: host.to_string(),
+            port,
+            authority: authority.to_string(),
+            target,
+        })
+    }
+}
+
+/// Where a connection goes; idle connections are pooled per origin.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Origin {
+    https: bool,
+    host: String,
+    port: u16,
+}
+
+enum Transport {
+    Plain(TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(Box<rustls::StreamOwned<rustls::ClientConnection, TcpStream>>),
+}
+
+impl Read for Transport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Transport::Plain(stream) => stream.read(buf),
+            #[cfg(feature = "tls")]
+            Transport::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Transport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Transport::Plain(stream) => stream.write(buf),
+            #[cfg(feature = "tls")]
+            Transport::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Transport::Plain(stream) => stream.flush(),
+            #[cfg(feature = "tls")]
+            Transport::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+struct Connection {
+    reader: BufReader<Transport>,
+    idle_since: Instant,
+}
+
+/// How one attempt at a request failed.
+enum Failure {
+    /// A pooled connection turned out to be closed before anything was
+    /// received, so the request can be sent again on a new one.
+    Stale(ClientError),
+    Failed(ClientError),
+}
+
+/// A blocking HTTP/1.1 client with a connection pool; see the
+/// [module documentation](self). Share one client between threads to share
+/// its pool.
+pub struct Client {
+    timeout: Duration,
+    user_agent: String,
+    idle: Mutex<HashMap<Origin, Vec<Connection>>>,
+    #[cfg(feature = "tls")]
+    roots: rustls::RootCertStore,
+    #[cfg(feature = "tls")]
+    tls: std::sync::Arc<rustls::ClientConfig>,
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Client::new()
+    }
+}
+
+impl Client {
+    /// A client with [`DEFAULT_TIMEOUT`] and an empty pool.
+    pub fn new() -> Self {
+        #[cfg(feature = "tls")]
+        let roots = rustls::RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        Client {
+            timeout: DEFAULT_TIMEOUT,
+            user_agent: format!("rusty-server/{}", env!("CARGO_PKG_VERSION")),
+            idle: Mutex::new(HashMap::new()),
+            #[cfg(feature = "tls")]
+            tls: tls_config(&roots),
+            #[cfg(feature = "tls")]
+            roots,
+        }
+    }
+
+    /// Sets the timeout for each connect, read, and write.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the `User-Agent` sent with every request.
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Also trusts the DER-encoded CA certificate `der`, for servers whose
+    /// certificates come from a private CA.
+    #[cfg(feature = "tls")]
+    pub fn root_certificate(mut self, der: impl Into<Vec<u8>>) -> Result<Self, ClientError> {
+        self.roots
+            .add(rustls::pki_types::CertificateDer::from(der.into()))
+            .map_err(|e| ClientError::Tls(e.to_string()))?;
+        self.tls = tls_config(&self.roots);
+        Ok(self)
+    }
+
+    /// Sends a `GET` request.
+    pub fn get(&self, url: &str) -> Result<Response, ClientError> {
+        self.request("GET", url, &[], &[])
+    }
+
+    /// Sends a `POST` request with `body` and the extra header fields
+    /// `headers`, such as `Content-Type`.
+    pub fn post(
+        &self,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<Response, ClientError> {
+        self.request("POST", url, headers, body)
+    }
+
+    /// Sends a `method` request to `url` with the extra header fields
+    /// `headers` and `body`, and returns the response. `Host`,
+    /// `Content-Length`, `Transfer-Encoding`, and `Connection` are set by
+    /// the client and cannot be passed in `headers`.
+    pub fn request(
+        &self,
+        method: &str,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<Response, ClientError> {
+        let url = Url::parse(url)?;
+        let message = self.message(method, &url, headers, body)?;
+        let origin = Origin {
+            https: url.https,
+            host: url.host.clone(),
+            port: url.port,
+        };
+
+        if let Some(mut connection) = self.checkout(&origin) {
+            match self.exchange(&mut connection, method, &message, true) {
+                Ok((response, reusable)) => {
+                    if reusable {
+                        self.checkin(origin, connection);
+                    }
+                    return Ok(response);
+                }
+                Err(Failure::Stale(_)) => {}
+                Err(Failure::Failed(e)) => return Err(e),
+            }
+        }
+
+        let mut connection = self.connect(&url)?;
+        match self.exchange(&mut connection, method, &message, false) {
+            Ok((response, reusable)) => {
+                if reusable {
+                    self.checkin(origin, connection);
+                }
+                Ok(response)
+            }
+            Err(Failure::Stale(e) | Failure::Failed(e)) => Err(e),
+        }
+    }
+
+    /// The request head and body, ready to be written.
+    fn message(
+        &self,
+        method: &str,
+        url: &Url,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<Vec<u8>, ClientError> {
+        if method.is_empty()
+            || !method
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        {
+            return Err(ClientError::InvalidRequest(format!(
+                "invalid method {:?}",
+                method
+            )));
+        }
+        let mut head = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: {}\r\n",
+            method, url.target, url.authority, self.user_agent
+        );
+        if !body.is_empty() || matches!(method, "POST" | "PUT" | "PATCH") {
+            head.push_str(&format!("Content-Length: {}\r\n", body.len()));
+        }
+        for (name, value) in headers {
+            if !is_valid_header(name, value)
+                || RESERVED_HEADERS
+                    .iter()
+                    .any(|reserved| reserved.eq_ignore_ascii_case(name))
+            {
+                return Err(ClientError::InvalidRequest(format!(
+                    "cannot send header {:?}",
+                    name
+                )));
+            }
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+
+        let mut message = head.into_bytes();
+        message.extend_from_slice(body);
+        Ok(message)
+    }
+
+    /// An idle connection to `origin` that has not been idle for too long.
+    fn checkout(&self, origin: &Origin) -> Option<Connection> {
+        let mut idle = self.idle.lock().unwrap_or_else(PoisonError::into_inner);
+        let connections = idle.get_mut(origin)?;
+        connections.retain(|connection| connection.idle_since.elapsed() < IDLE_TIMEOUT);
+        connections.pop()
+    }
+
+    fn checkin(&self, origin: Origin, mut connection: Connection) {
+        let mut idle = self.idle.lock().unwrap_or_else(PoisonError::into_inner);
+        let connections = idle.entry(origin).or_default();
+        if connections.len() < MAX_IDLE_PER_ORIGIN {
+            connection.idle_since = Instant::now();
+            connections.push(connection);
+        }
+    }
+
+    fn connect(&self, url: &Url) -> Result<Connection, ClientError> {
+        let mut last_error = None;
+        let addrs = (url.host.as_str(), url.port)
+            .to_socket_addrs()
+            .map_err(|e| io_error(e, "resolving the host"))?;
+        let mut stream = None;
+        for addr in addrs {
+            match TcpStream::connect_timeout(&addr, self.timeout) {
+                Ok(connected) => {
+                    stream = Some(connected);
+                    break;
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        let stream = match (stream, last_error) {
+            (Some(stream), _) => stream,
+            (None, Some(e)) => return Err(io_error(e, "connecting")),
+            (None, None) => {
+                return Err(ClientError::Io(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "host has no address",
+                )))
+            }
+        };
+        stream
+            .set_read_timeout(Some(self.timeout))
+            .and_then(|()| stream.set_write_timeout(Some(self.timeout)))
+            .and_then(|()| stream.set_nodelay(true))
+            .map_err(ClientError::Io)?;
+
+        let transport = if url.https {
+            self.handshake(url, stream)?
+        } else {
+            Transport::Plain(stream)
+        };
+        Ok(Connection {
+            reader: BufReader::new(transport),
+            idle_since: Instant::now(),
+        })
+    }
+
+    #[cfg(feature = "tls")]
+    fn handshake(&self, url: &Url, mut stream: TcpStream) -> Result<Transport, ClientError> {
+        let name = rustls::pki_types::ServerName::try_from(url.host.clone())
+            .map_err(|e| ClientError::Tls(e.to_string()))?;
+        let mut connection = rustls::ClientConnection::new(std::sync::Arc::clone(&self.tls), name)
+            .map_err(|e| ClientError::Tls(e.to_string()))?;
+        while connection.is_handshaking() {
+            connection
+                .complete_io(&mut stream)
+                .map_err(|e| match e.kind() {
+                    io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => {
+                        ClientError::Timeout("negotiating TLS")
+                    }
+                    _ => ClientError::Tls(e.to_string()),
+                })?;
+        }
+        Ok(Transport::Tls(Box::new(rustls::StreamOwned::new(
+            connection, stream,
+        ))))
+    }
+
+    #[cfg(not(feature = "tls"))]
+    fn handshake(&self, _url: &Url, _stream: TcpStream) -> Result<Transport, ClientError> {
+        Err(ClientError::UnsupportedScheme("https".to_string()))
+    }
+
+    /// Sends `message` on `connection` and reads the response. Returns it
+    /// along with whether the connection can be reused.
+    fn exchange(
+        &self,
+        connection: &mut Connection,
+        method: &str,
+        message: &[u8],
+        reused: bool,
+    ) -> Result<(Response, bool), Failure> {
+        let stale = |e: ClientError| {
+            if reused {
+                Failure::Stale(e)
+            } else {
+                Failure::Failed(e)
+            }
+        };
+        let transport = connection.reader.get_mut();
+        transport
+            .write_all(message)
+            .and_then(|()| transport.flush())
+            .map_err(|e| stale(io_error(e, "sending the request")))?;
+
+        // A closed pooled connection shows up as an empty or failed read
+        // before the first byte of the response.
+        match connection.reader.fill_buf() {
+            Ok([]) => {
+                return Err(stale(ClientError::InvalidResponse(
+                    "connection closed before the response",
+                )))
+            }
+            Ok(_) => {}
+            Err(e)
+                if e.kind() == io::ErrorKind::TimedOut || e.kind() == io::ErrorKind::WouldBlock =>
+            {
+                return Err(Failure::Failed(ClientError::Timeout(
+                    "waiting for the response",
+                )))
+            }
+            Err(e) => return Err(stale(ClientError::Io(e))),
+        }
+        read_response(&mut connection.reader, method).map_err(Failure::Failed)
+    }
+}
+
+#[cfg(feature = "tls")]
+fn tls_config(roots: &rustls::RootCertStore) -> std::sync::Arc<rustls::ClientConfig> {
+    let provider = std::sync::Arc::new(rustls::crypto::ring::default_provider());
+    let mut config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .expect("the ring provider supports the default protocol versions")
+        .with_root_certificates(roots.clone())
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    std::sync::Arc::new(config)
+}
+
+/// Reads one line, without its line ending.
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String, ClientError> {
+    let mut line = Vec::new();
+    reader
+        .take(MAX_LINE as u64)
+        .read_until(b'\n', &mut line)
+        .map_err(|e| io_error(e, "reading the response"))?;
+    if line.last() != Some(&b'\n') {
+        return Err(ClientError::InvalidResponse(if line.len() == MAX_LINE {
+            "line too long"
+        } else {
+            "connection closed in the middle of the head"
+        }));
+    }
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    Ok(String::from_utf8_lossy(&line).into_owned())
+}
+
+/// Reads a response to a `method` request, skipping interim 1xx responses.
+/// Returns it along with whether the connection can be reused.
+fn read_response<R: BufRead>(
+    reader: &mut R,
+    method: &str,
+) -> Result<(Response, bool), ClientError> {
+    loop {
+        let status_line = read_line(reader)?;
+        let mut parts = status_line.splitn(3, ' ');
+        let version = parts.next().unwrap_or_default();
+        let status: u16 = parts
+            .next()
+            .filter(|status| status.len() == 3)
+            .and_then(|status| status.parse().ok())
+            .filter(|status| (100..600).contains(status))
+            .ok_or(ClientError::InvalidResponse("malformed status line"))?;
+        if version != "HTTP/1.1" && version != "HTTP/1.0" {
+            return Err(ClientError::InvalidResponse("unsupported HTTP version"));
+        }
+
+        let mut headers = Vec::new();
+        loop {
+            let line = read_line(reader)?;
+            if line.is_empty() {
+                break;
+            }
+            if headers.len() == MAX_HEADERS {
+                return Err(ClientError::InvalidResponse("too many header fields"));
+            }
+            let (name, value) = line
+                .split_once(':')
+                .ok_or(ClientError::InvalidResponse("malformed header field"))?;
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+        if (100..200).contains(&status) && status != 101 {
+            continue;
+        }
+
+        let mut response = Response::new(status);
+        response.headers = headers;
+        let mut reusable = version == "HTTP/1.1"
+            && !response
+                .header_value("Connection")
+                .is_some_and(|value| value.eq_ignore_ascii_case("close"));
+        let chunked = response
+            .header_value("Transfer-Encoding")
+            .and_then(|codings| codings.rsplit(',').next())
+            .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked"));
+        let length = response.header_value("Content-Length").map(str::to_string);
+
+        if method == "HEAD" || status == 204 || status == 304 || status < 200 {
+            // No body.
+        } else if chunked {
+            response.body = read_chunked(reader)?;
+        } else if let Some(length) = length {
+            let length: usize = length
+                .parse()
+                .map_err(|_| ClientError::InvalidResponse("malformed Content-Length"))?;
+            if length > MAX_RESPONSE_BODY {
+                return Err(ClientError::InvalidResponse("body too large"));
+            }
+            response.body = vec![0; length];
+            reader
+                .read_exact(&mut response.body)
+                .map_err(|e| io_error(e, "reading the body"))?;
+        } else {
+            // The body runs until the server closes the connection.
+            reusable = false;
+            reader
+                .take(MAX_RESPONSE_BODY as u64 + 1)
+                .read_to_end(&mut response.body)
+                .or_else(|e| match e.kind() {
+                    // TLS servers often close without a close_notify alert.
+                    io::ErrorKind::UnexpectedEof => Ok(0),
+                    _ => Err(io_error(e, "reading the body")),
+                })?;
+            if response.body.len() > MAX_RESPONSE_BODY {
+                return Err(ClientError::InvalidResponse("body too large"));
+            }
+        }
+        response.headers.retain(|(name, _)| {
+            !name.eq_ignore_ascii_case("Content-Length")
+                && !name.eq_ignore_ascii_case("Transfer-Encoding")
+        });
+        return Ok((response, reusable));
+    }
+}
+
+/// Reads a chunked body, discarding chunk extensions and trailer fields.
+fn read_chunked<R: BufRead>(reader: &mut R) -> Result<Vec<u8>, ClientError> {
+    let mut body = Vec::new();
+    loop {
+        let line = read_line(reader)?;
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16)
+            .map_err(|_| ClientError::InvalidResponse("malformed chunk size"))?;
+        if size == 0 {
+            while !read_line(reader)?.is_empty() {}
+            return Ok(body);
+        }
+        if size > MAX_RESPONSE_BODY - body.len() {
+            return Err(ClientError::InvalidResponse("body too large"));
+        }
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader
+            .read_exact(&mut body[start..])
+            .map_err(|e| io_error(e, "reading the body"))?;
+        if !read_line(reader)?.is_empty() {
+            return Err(ClientError::InvalidResponse("malformed chunk"));
+        }
+    }
+}
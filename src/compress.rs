@@ -0,0 +1,224 @@
+//! Compression of response bodies: gzip, and with the `brotli` and `zstd`
+//! features Brotli and Zstandard.
+//!
+//! Register [`Compress`] as middleware, and responses are compressed with
+//! the encoding the client prefers of those it lists in `Accept-Encoding`:
+//!
+//! ```no_run
+//! use rusty_server::compress::Compress;
+//!
+//! let mut router = rusty_server::Router::default();
+//! router.middleware(Compress::new());
+//! ```
+//!
+//! Bodies of at least [`DEFAULT_MIN_SIZE`] bytes are compressed, error
+//! pages included, but only text-like ones: HTML, CSS, JavaScript, JSON,
+//! SVG, and so on, as images, video, and archives are compressed already.
+//! Partial content is sent as it is, since its `Content-Range` counts bytes
+//! of the uncompressed body. Each response that could be compressed says
+//! `Vary: Accept-Encoding`, so that caches keep the versions apart, and a
+//! compressed one's strong `ETag` is made weak, as it no longer names the
+//! same bytes.
+
+use std::fmt;
+use std::io::{self, Write};
+
+use flate2::write::GzEncoder;
+
+use crate::http::{vary, Request, Response};
+use crate::router::Middleware;
+
+/// Bodies smaller than this are sent as they are: the compressed format's
+/// framing would outweigh the savings.
+pub const DEFAULT_MIN_SIZE: usize = 1024;
+
+/// A content coding responses can be compressed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// `br`, with the `brotli` feature.
+    #[cfg(feature = "brotli")]
+    Brotli,
+    /// `zstd`, with the `zstd` feature.
+    #[cfg(feature = "zstd")]
+    Zstd,
+    /// `gzip`.
+    Gzip,
+}
+
+impl Encoding {
+    /// The encodings this build supports, the server's preference first:
+    /// Brotli compresses text best, then Zstandard, then gzip.
+    pub const ALL: &'static [Encoding] = &[
+        #[cfg(feature = "brotli")]
+        Encoding::Brotli,
+        #[cfg(feature = "zstd")]
+        Encoding::Zstd,
+        Encoding::Gzip,
+    ];
+
+    /// The coding's name in `Accept-Encoding` and `Content-Encoding`.
+    pub fn name(self) -> &'static str {
+        match self {
+            #[cfg(feature = "brotli")]
+            Encoding::Brotli => "br",
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// Whether `coding`, from `Accept-Encoding`, names this encoding.
+    fn is_named(self, coding: &str) -> bool {
+        coding.eq_ignore_ascii_case(self.name())
+            || (self == Encoding::Gzip && coding.eq_ignore_ascii_case("x-gzip"))
+    }
+
+    /// `body` compressed with this encoding, at its default level.
+    fn encode(self, body: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            #[cfg(feature = "brotli")]
+            Encoding::Brotli => {
+                // Quality 5 of 11 compresses well while staying fast enough
+                // for responses made per request.
+                let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+                encoder.write_all(body)?;
+                Ok(encoder.into_inner())
+            }
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd => zstd::encode_all(body, zstd::DEFAULT_COMPRESSION_LEVEL),
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Middleware compressing response bodies for clients that accept it.
+#[derive(Debug, Clone)]
+pub struct Compress {
+    min_size: usize,
+    encodings: Vec<Encoding>,
+}
+
+impl Compress {
+    /// Compresses bodies of at least [`DEFAULT_MIN_SIZE`] bytes with any of
+    /// [`Encoding::ALL`].
+    pub fn new() -> Self {
+        Compress {
+            min_size: DEFAULT_MIN_SIZE,
+            encodings: Encoding::ALL.to_vec(),
+        }
+    }
+
+    /// Sets the smallest body compressed; see [`DEFAULT_MIN_SIZE`].
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// Sets the encodings offered, the preferred first for clients that
+    /// accept several equally.
+    pub fn encodings(mut self, encodings: &[Encoding]) -> Self {
+        self.encodings = encodings.to_vec();
+        self
+    }
+}
+
+impl Default for Compress {
+    fn default() -> Self {
+        Compress::new()
+    }
+}
+
+impl Middleware for Compress {
+    fn after(&self, request: &Request, response: &mut Response) {
+        if response.body.len() < self.min_size
+            || response.status == 206
+            || response.header_value("Content-Encoding").is_some()
+            || !response.header_value("Content-Type").is_some_and(compressible)
+        {
+            return;
+        }
+        vary(response, "Accept-Encoding");
+        let Some(encoding) = negotiate(request, &self.encodings) else {
+            return;
+        };
+        let Ok(body) = encoding.encode(&response.body) else {
+            return;
+        };
+        if body.len() >= response.body.len() {
+            return;
+        }
+        response.body = body;
+        response
+            .headers
+            .push(("Content-Encoding".to_string(), encoding.name().to_string()));
+        for (name, value) in &mut response.headers {
+            if name.eq_ignore_ascii_case("ETag") && value.starts_with('"') {
+                *value = format!("W/{}", value);
+            }
+        }
+    }
+}
+
+/// The one of `encodings` that `request` prefers by the weights in its
+/// `Accept-Encoding`, the earliest in `encodings` among equals; `None` if it
+/// accepts none of them. A coding without a weight has weight 1, `*` stands
+/// for the codings not listed, and weight 0 refuses a coding.
+pub fn negotiate(request: &Request, encodings: &[Encoding]) -> Option<Encoding> {
+    let mut weights: Vec<(&str, f32)> = Vec::new();
+    for coding in request.header_all("Accept-Encoding").flat_map(|value| value.split(',')) {
+        let mut params = coding.split(';');
+        let name = params.next().unwrap_or_default().trim();
+        let weight = params
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        if !name.is_empty() {
+            weights.push((name, weight));
+        }
+    }
+    let wildcard = weights.iter().find(|(name, _)| *name == "*").map(|&(_, q)| q);
+    let weight = |encoding: Encoding| {
+        weights
+            .iter()
+            .find(|(name, _)| encoding.is_named(name))
+            .map(|&(_, q)| q)
+            .or(wildcard)
+            .unwrap_or(0.0)
+    };
+    let mut best: Option<(Encoding, f32)> = None;
+    for &encoding in encodings {
+        let q = weight(encoding);
+        if q > 0.0 && best.is_none_or(|(_, best)| q > best) {
+            best = Some((encoding, q));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+/// Whether bodies of `content_type` shrink under compression.
+fn compressible(content_type: &str) -> bool {
+    let media_type = content_type.split(';').next().unwrap_or_default().trim();
+    let media_type = media_type.to_ascii_lowercase();
+    media_type.starts_with("text/")
+        || media_type.ends_with("+json")
+        || media_type.ends_with("+xml")
+        || matches!(
+            media_type.as_str(),
+            "application/json"
+                | "application/javascript"
+                | "application/xml"
+                | "application/wasm"
+                | "image/svg+xml"
+                | "image/x-icon"
+        )
+}
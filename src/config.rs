@@ -0,0 +1,151 @@
+//! Settings applied to every connection.
+
+use std::num::NonZeroUsize;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::http::{MAX_BODY_SIZE, MAX_HEAD_SIZE};
+
+/// How long an idle connection is kept open for another request by default.
+const KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How many requests a connection carries by default.
+const MAX_REQUESTS: NonZeroUsize = NonZeroUsize::new(1000).unwrap();
+
+/// How long a client has to send a request head by default.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a client may stop reading a response by default.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Connection-handling settings.
+///
+/// Start from [`Config::default`] and adjust individual fields; new fields
+/// may be added without a breaking change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Config {
+    /// Largest request head (request line plus headers) accepted, in bytes.
+    /// Longer heads are answered with 414 or 431.
+    pub max_head_size: usize,
+    /// Largest request body read for a routed handler, in bytes. Larger
+    /// bodies are answered with 413. Streaming handlers read their bodies
+    /// themselves and are not limited.
+    pub max_body_size: usize,
+    /// How long the listener keeps an idle connection open for another
+    /// request, or `None` to close every connection after one response.
+    pub keep_alive_timeout: Option<Duration>,
+    /// How many requests the listener answers on one connection before
+    /// closing it, or `None` for no limit. Kept-alive responses advertise
+    /// what is left, with the idle timeout, in a `Keep-Alive` header.
+    pub max_requests: Option<NonZeroUsize>,
+    /// How long the listener gives a client to send a complete request head,
+    /// or `None` to wait as long as it takes. Clients slower than that, such
+    /// as ones dribbling a byte at a time to hold the connection, are
+    /// answered with 408 and disconnected. Bodies are not covered.
+    pub request_timeout: Option<Duration>,
+    /// How long the listener waits for a client to take more of a response,
+    /// or `None` to wait as long as it takes. A client that stops reading
+    /// for that long has its connection aborted. The deadline is per write,
+    /// so slow but steady clients can take as long as they need. It is set
+    /// as a connection opens.
+    pub write_timeout: Option<Duration>,
+    /// How many worker threads the listener serves connections on, or `None`
+    /// for a thread per connection. Every open connection, WebSocket and
+    /// event-stream ones included, occupies a worker; the others wait.
+    pub workers: Option<NonZeroUsize>,
+    /// What accepts connections and runs the threads serving them.
+    pub runtime: Runtime,
+    /// How many connections may be open at once across the listeners, or
+    /// `None` for no limit. Connections accepted beyond it are shed as
+    /// [`shedding`](Config::shedding) says, without reaching a worker.
+    pub max_connections: Option<NonZeroUsize>,
+    /// What happens to connections accepted beyond
+    /// [`max_connections`](Config::max_connections).
+    pub shedding: Shedding,
+    /// The certificate and key to serve HTTPS with, or `None` for plain
+    /// HTTP.
+    #[cfg(feature = "tls")]
+    pub tls: Option<crate::tls::Acceptor>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            max_head_size: MAX_HEAD_SIZE,
+            max_body_size: MAX_BODY_SIZE,
+            keep_alive_timeout: Some(KEEP_ALIVE_TIMEOUT),
+            max_requests: Some(MAX_REQUESTS),
+            request_timeout: Some(REQUEST_TIMEOUT),
+            write_timeout: Some(WRITE_TIMEOUT),
+            workers: None,
+            runtime: Runtime::Sync,
+            max_connections: None,
+            shedding: Shedding::Unavailable,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+}
+
+/// What the listener runs on. Either way, every connection is served by the
+/// same blocking request cycle, so routes, streaming handlers, and reports
+/// behave alike.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Runtime {
+    /// A blocking accept loop, with a thread per connection or a pool of
+    /// [`Config::workers`].
+    #[default]
+    Sync,
+    /// An accept task on a tokio runtime, with connections served on its
+    /// blocking threads; at most [`Config::workers`] of them, if set.
+    #[cfg(feature = "tokio")]
+    Tokio,
+}
+
+/// A [`Runtime`] name other than `sync` and `tokio`.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("unknown runtime `{0}` (expected `sync`, or `tokio` with the `tokio` feature)")]
+pub struct UnknownRuntime(pub String);
+
+impl FromStr for Runtime {
+    type Err = UnknownRuntime;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "sync" => Ok(Runtime::Sync),
+            #[cfg(feature = "tokio")]
+            "tokio" => Ok(Runtime::Tokio),
+            _ => Err(UnknownRuntime(name.to_string())),
+        }
+    }
+}
+
+/// How the listener sheds connections beyond [`Config::max_connections`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Shedding {
+    /// Answers `503 Service Unavailable` with `Retry-After`, without
+    /// reading the request, and closes the connection. Clients and load
+    /// balancers can tell an overloaded server from a dead one.
+    #[default]
+    Unavailable,
+    /// Closes the connection at once, which is cheaper under a flood.
+    Close,
+}
+
+/// A [`Shedding`] name other than `503` and `close`.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("unknown shedding `{0}` (expected `503` or `close`)")]
+pub struct UnknownShedding(pub String);
+
+impl FromStr for Shedding {
+    type Err = UnknownShedding;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "503" => Ok(Shedding::Unavailable),
+            "close" => Ok(Shedding::Close),
+            _ => Err(UnknownShedding(name.to_string())),
+        }
+    }
+}
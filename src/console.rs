@@ -0,0 +1,1004 @@
+//! Console reporting.
+//!
+//! Status information is printed as two-column key/value tables, and lists
+//! of results as grids under a header row: drawn with nu_table when the
+//! `console` feature is enabled, as aligned plain-text columns otherwise.
+//!
+//! Drawn tables fit the terminal, wrapping long cells, and are otherwise
+//! as wide as `COLUMNS` says, or 80 columns; [`set_width`] overrides both. The width is read for every
+//! table, so a resized terminal gets wider or narrower tables from the next
+//! one on. A table too wide to wrap into the width is printed as plain
+//! columns instead.
+//!
+//! Drawn tables use the border [`Theme`] set with [`set_theme`], or are
+//! printed as plain columns under [`Theme::Plain`], and color
+//! outcomes when stdout is a terminal and `NO_COLOR` is not set: a `✓`
+//! followed by a status code, or a number in a `Status` column, is green
+//! for 1xx to 3xx, yellow for 4xx, and red for 5xx; a bare `✓` is green and
+//! a `✗` red.
+//!
+//! With [`Format::Ndjson`], each table, grid row, and status line is instead
+//! printed as one JSON object per line, stamped with the Unix `time`, for
+//! piping into `jq` or Nushell's `from json --objects`, or shipping to Loki
+//! or Elasticsearch. Each connection is an object with its `peer`, `method`,
+//! `path`, `status`, `bytes_read`, and `elapsed_ms`. With the `tracing`
+//! feature, [`Format::Tracing`] and [`Format::Pretty`] hand the reports to
+//! a tracing subscriber instead; see [`crate::trace`].
+//!
+//! Every connection gets a report of its own, unless [`set_summary`] batches
+//! them into one summary per interval; [`set_debug`] keeps the
+//! per-connection reports as well. [`set_verbosity`] drops the reports, or
+//! adds the request and response details to them.
+//!
+//! [`set_log_dir`] mirrors everything printed to a file named for the day,
+//! without colors, so the record of a session outlives the terminal's
+//! scrollback. [`set_log_file`] mirrors it to a [`LogFile`] instead, which
+//! rotates by size or time and keeps a bounded number of old files.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use crate::logfile::LogFile;
+use crate::server::Exchange;
+use crate::{json_escape, unix_timestamp, ServerError};
+
+static FORMAT: AtomicU8 = AtomicU8::new(Format::Table as u8);
+
+static THEME: AtomicU8 = AtomicU8::new(Theme::Rounded as u8);
+
+/// The width set with [`set_width`], or 0 to measure it.
+static WIDTH: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether to color tables: `COLOR_AUTO`, `COLOR_ON`, or `COLOR_OFF`.
+static COLOR: AtomicU8 = AtomicU8::new(COLOR_AUTO);
+
+const COLOR_AUTO: u8 = 0;
+const COLOR_ON: u8 = 1;
+const COLOR_OFF: u8 = 2;
+
+/// Set once something else, such as the dashboard, owns the terminal.
+static SILENT: AtomicBool = AtomicBool::new(false);
+
+static DEBUG: AtomicBool = AtomicBool::new(false);
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+/// The file console output is mirrored to, if any.
+static MIRROR: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+
+/// The connections of the current summary interval, once summaries are on.
+static SUMMARY: OnceLock<Mutex<Summary>> = OnceLock::new();
+
+/// Most distinct paths counted per summary; the rest are counted together,
+/// so scans for random paths cannot grow the summary without bound.
+const SUMMARY_PATHS: usize = 1000;
+
+/// Paths listed in a summary.
+const TOP_PATHS: usize = 5;
+
+/// Table width when stdout is not a terminal and `COLUMNS` is not set.
+#[cfg(feature = "console")]
+const DEFAULT_WIDTH: usize = 80;
+
+/// How console output is written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// Tables, for reading.
+    #[default]
+    Table,
+    /// Newline-delimited JSON, for processing and for log shippers; named
+    /// `ndjson` or `json`.
+    Ndjson,
+    /// Tracing events, written one per line by the subscriber's formatter.
+    #[cfg(feature = "tracing")]
+    Tracing,
+    /// Tracing events, drawn as tables by [`crate::trace::Pretty`]; other
+    /// output is printed as with [`Format::Table`].
+    #[cfg(feature = "tracing")]
+    Pretty,
+}
+
+/// A [`Format`] name other than `table`, `ndjson`, and `json` (and `tracing`
+/// and `pretty` with the `tracing` feature).
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[cfg_attr(
+    not(feature = "tracing"),
+    error("unknown log format `{0}` (expected `table`, `ndjson`, or `json`)")
+)]
+#[cfg_attr(
+    feature = "tracing",
+    error("unknown log format `{0}` (expected `table`, `ndjson`, `json`, `tracing`, or `pretty`)")
+)]
+pub struct UnknownFormat(pub String);
+
+impl FromStr for Format {
+    type Err = UnknownFormat;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "table" => Ok(Format::Table),
+            "ndjson" | "json" => Ok(Format::Ndjson),
+            #[cfg(feature = "tracing")]
+            "tracing" => Ok(Format::Tracing),
+            #[cfg(feature = "tracing")]
+            "pretty" => Ok(Format::Pretty),
+            _ => Err(UnknownFormat(name.to_string())),
+        }
+    }
+}
+
+/// The borders drawn around tables.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Theme {
+    /// Rounded corners: `╭─╮`.
+    #[default]
+    Rounded,
+    /// Thick lines: `┏━┓`.
+    Heavy,
+    /// A line under the header, and no outer border.
+    Light,
+    /// ASCII only, for terminals without box-drawing characters: `+-+`.
+    Basic,
+    /// No lines at all.
+    None,
+    /// No drawn tables: aligned plain-text columns, as without the
+    /// `console` feature.
+    Plain,
+}
+
+/// A [`Theme`] name other than `rounded`, `heavy`, `light`, `basic`,
+/// `none`, and `plain`.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("unknown table theme `{0}` (expected `rounded`, `heavy`, `light`, `basic`, `none`, or `plain`)")]
+pub struct UnknownTheme(pub String);
+
+impl FromStr for Theme {
+    type Err = UnknownTheme;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "rounded" => Ok(Theme::Rounded),
+            "heavy" => Ok(Theme::Heavy),
+            "light" => Ok(Theme::Light),
+            "basic" => Ok(Theme::Basic),
+            "none" => Ok(Theme::None),
+            "plain" => Ok(Theme::Plain),
+            _ => Err(UnknownTheme(name.to_string())),
+        }
+    }
+}
+
+/// How much is reported about each connection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Nothing; startup, errors, and summaries are still printed.
+    Quiet,
+    /// The peer, path, and response status.
+    #[default]
+    Normal,
+    /// The request line, the response's content type, and the time taken
+    /// as well, even under summaries.
+    Verbose,
+    /// Every request and response header as well.
+    VeryVerbose,
+}
+
+/// A [`Verbosity`] name other than `quiet`, `normal`, `verbose`, and
+/// `debug`.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("unknown log level `{0}` (expected `quiet`, `normal`, `verbose`, or `debug`)")]
+pub struct UnknownVerbosity(pub String);
+
+impl FromStr for Verbosity {
+    type Err = UnknownVerbosity;
+
+    /// Parses the level names, with `debug` for [`Verbosity::VeryVerbose`].
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "quiet" => Ok(Verbosity::Quiet),
+            "normal" => Ok(Verbosity::Normal),
+            "verbose" => Ok(Verbosity::Verbose),
+            "debug" => Ok(Verbosity::VeryVerbose),
+            _ => Err(UnknownVerbosity(name.to_string())),
+        }
+    }
+}
+
+/// Draws all further tables `width` columns wide, whatever the terminal's
+/// width, or measures it again for each table with `None`.
+pub fn set_width(width: Option<NonZeroUsize>) {
+    WIDTH.store(width.map_or(0, NonZeroUsize::get), Ordering::Relaxed);
+}
+
+/// Draws all further tables with `theme`.
+pub fn set_theme(theme: Theme) {
+    THEME.store(theme as u8, Ordering::Relaxed);
+}
+
+/// The theme tables are drawn with.
+pub fn theme() -> Theme {
+    match THEME.load(Ordering::Relaxed) {
+        0 => Theme::Rounded,
+        1 => Theme::Heavy,
+        2 => Theme::Light,
+        3 => Theme::Basic,
+        4 => Theme::None,
+        _ => Theme::Plain,
+    }
+}
+
+/// Colors tables, or stops coloring them, regardless of the terminal and
+/// `NO_COLOR`.
+pub fn set_color(color: bool) {
+    COLOR.store(if color { COLOR_ON } else { COLOR_OFF }, Ordering::Relaxed);
+}
+
+/// Whether tables are colored: as set with [`set_color`], or else when
+/// stdout is a terminal and `NO_COLOR` is unset or empty.
+pub fn color() -> bool {
+    match COLOR.load(Ordering::Relaxed) {
+        COLOR_ON => true,
+        COLOR_OFF => false,
+        _ => {
+            use std::io::IsTerminal;
+
+            let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+            !no_color && std::io::stdout().is_terminal()
+        }
+    }
+}
+
+/// How a cell reports an outcome, by the rules in the module documentation.
+#[cfg_attr(not(feature = "console"), allow(dead_code))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Success,
+    Warning,
+    Failure,
+}
+
+#[cfg_attr(not(feature = "console"), allow(dead_code))]
+impl Outcome {
+    /// The outcome `cell` reports, if any; `status_column` says whether it
+    /// is in a `Status` column, where bare numbers are status codes.
+    fn of(cell: &str, status_column: bool) -> Option<Outcome> {
+        if cell.starts_with('✗') {
+            return Some(Outcome::Failure);
+        }
+        let status = match cell.strip_prefix('✓') {
+            Some(rest) => rest.trim_start(),
+            None if status_column => cell,
+            None => return None,
+        };
+        match status.get(..3).and_then(|code| code.parse::<u16>().ok()) {
+            Some(100..=399) => Some(Outcome::Success),
+            Some(400..=499) => Some(Outcome::Warning),
+            Some(500..=599) => Some(Outcome::Failure),
+            _ if cell.starts_with('✓') => Some(Outcome::Success),
+            _ => None,
+        }
+    }
+}
+
+/// Switches all further console output to `format`.
+pub fn set_format(format: Format) {
+    FORMAT.store(format as u8, Ordering::Relaxed);
+}
+
+/// The format console output is written in.
+pub fn format() -> Format {
+    match FORMAT.load(Ordering::Relaxed) {
+        0 => Format::Table,
+        #[cfg(feature = "tracing")]
+        2 => Format::Tracing,
+        #[cfg(feature = "tracing")]
+        3 => Format::Pretty,
+        _ => Format::Ndjson,
+    }
+}
+
+/// Reports connections at `verbosity` from now on.
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+/// Removes the verbosity flags from `args` and returns the level they ask
+/// for: `-q` or `--quiet` for [`Verbosity::Quiet`], `-v` or `--verbose` for
+/// [`Verbosity::Verbose`], and `-vv` or a second `-v` for
+/// [`Verbosity::VeryVerbose`]. Fails if both quiet and verbose were asked
+/// for.
+pub fn take_verbosity(args: &mut Vec<String>) -> Result<Verbosity, ConflictingVerbosity> {
+    let (mut quiet, mut verbose) = (false, 0);
+    args.retain(|arg| {
+        match arg.as_str() {
+            "-q" | "--quiet" => quiet = true,
+            "-v" | "--verbose" => verbose += 1,
+            "-vv" => verbose += 2,
+            _ => return true,
+        }
+        false
+    });
+    match (quiet, verbose) {
+        (true, 0) => Ok(Verbosity::Quiet),
+        (true, _) => Err(ConflictingVerbosity),
+        (false, 0) => Ok(Verbosity::Normal),
+        (false, 1) => Ok(Verbosity::Verbose),
+        (false, _) => Ok(Verbosity::VeryVerbose),
+    }
+}
+
+/// Both `--quiet` and `--verbose` given.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("--quiet and --verbose cannot be combined")]
+pub struct ConflictingVerbosity;
+
+/// How much is reported about each connection.
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        2 => Verbosity::Verbose,
+        _ => Verbosity::VeryVerbose,
+    }
+}
+
+/// Keeps the per-connection reports when summaries are on.
+pub fn set_debug(debug: bool) {
+    DEBUG.store(debug, Ordering::Relaxed);
+}
+
+/// Whether [`set_debug`] keeps the per-connection reports.
+pub fn debug() -> bool {
+    DEBUG.load(Ordering::Relaxed)
+}
+
+/// Replaces the per-connection reports with a summary of the connections
+/// in every `interval`: request and error counts, statuses and status
+/// classes, methods, top paths, mean latency, and bytes read and sent.
+/// Intervals without connections are skipped. Only the first call has an
+/// effect.
+pub fn set_summary(interval: Duration) {
+    let mut started = false;
+    let summary = SUMMARY.get_or_init(|| {
+        started = true;
+        Mutex::new(Summary::default())
+    });
+    if !started {
+        return;
+    }
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        let window = std::mem::take(&mut *summary.lock().unwrap_or_else(PoisonError::into_inner));
+        if window.requests > 0 {
+            window.print(interval);
+        }
+    });
+}
+
+/// Reports a finished connection from `peer` that sent `sent` bytes and
+/// took `elapsed`, as the verbosity and summaries call for.
+pub(crate) fn report(peer: &str, result: &Result<Exchange, ServerError>, sent: u64, elapsed: Duration) {
+    let verbosity = verbosity();
+    // Under summaries, connections are only counted unless debugging or
+    // verbose.
+    let wanted = tally(result, sent, elapsed) || verbosity >= Verbosity::Verbose;
+    if !wanted || verbosity == Verbosity::Quiet {
+        return;
+    }
+
+    #[cfg(feature = "tracing")]
+    if matches!(format(), Format::Tracing | Format::Pretty) {
+        return crate::trace::report(result, elapsed);
+    }
+    if format() == Format::Ndjson {
+        let mut members = format!(r#""peer":"{}""#, peer);
+        match result {
+            Ok(exchange) => {
+                if let Some(client) = exchange.request.as_ref().and_then(|request| request.client_cn.as_ref()) {
+                    members.push_str(&format!(r#","client":"{}""#, json_escape(client)));
+                }
+                if let Some(request) = &exchange.request {
+                    members.push_str(&format!(r#","method":"{}""#, json_escape(&request.method)));
+                }
+                members.push_str(&format!(
+                    r#","path":"{}","status":{},"bytes_read":{},"request_id":"{}""#,
+                    json_escape(&exchange.path),
+                    exchange.status,
+                    exchange.bytes_read,
+                    json_escape(&exchange.request_id)
+                ));
+                if verbosity >= Verbosity::Verbose {
+                    if let Some(request) = &exchange.request {
+                        members.push_str(&format!(r#","target":"{}""#, json_escape(&request.target)));
+                    }
+                    if let Some(content_type) = content_type(exchange) {
+                        members.push_str(&format!(r#","content_type":"{}""#, json_escape(content_type)));
+                    }
+                }
+                if verbosity == Verbosity::VeryVerbose {
+                    let request_headers = exchange.request.as_ref().map_or(&[][..], |request| &request.headers);
+                    members.push_str(&format!(
+                        r#","request_headers":{{{}}},"response_headers":{{{}}}"#,
+                        json_headers(request_headers),
+                        json_headers(&exchange.response_headers)
+                    ));
+                }
+            }
+            Err(e) => members.push_str(&format!(r#","error":"{}""#, json_escape(&e.to_string()))),
+        }
+        members.push_str(&format!(r#","elapsed_ms":{}"#, elapsed.as_secs_f64() * 1000.0));
+        return print_json(&members);
+    }
+
+    let mut rows = vec![("Connection", peer.to_string()), ("Time", unix_timestamp().to_string())];
+    match result {
+        Ok(exchange) => {
+            if let Some(client) = exchange.request.as_ref().and_then(|request| request.client_cn.as_ref()) {
+                rows.push(("Client", client.clone()));
+            }
+            let request = match (&exchange.request, verbosity) {
+                (Some(request), Verbosity::Verbose | Verbosity::VeryVerbose) => {
+                    format!("{} {} {}", request.method, request.target, request.version)
+                }
+                _ => exchange.path.clone(),
+            };
+            rows.push(("Request", request));
+            rows.push(("Request ID", exchange.request_id.clone()));
+            if verbosity == Verbosity::VeryVerbose {
+                if let Some(request) = &exchange.request {
+                    rows.push(("Request headers", text_headers(&request.headers)));
+                }
+            }
+            rows.push(("Response", format!("✓ {} ({} bytes)", exchange.status_text(), exchange.bytes_read)));
+            if verbosity == Verbosity::Verbose {
+                if let Some(content_type) = content_type(exchange) {
+                    rows.push(("Content-Type", content_type.to_string()));
+                }
+            }
+            if verbosity == Verbosity::VeryVerbose && !exchange.response_headers.is_empty() {
+                rows.push(("Response headers", text_headers(&exchange.response_headers)));
+            }
+        }
+        Err(e) => rows.push(("Status", format!("✗ Failed: {}", e))),
+    }
+    if verbosity >= Verbosity::Verbose {
+        rows.push(("Elapsed", format!("{:.1} ms", elapsed.as_secs_f64() * 1000.0)));
+    }
+    print_table(&rows);
+}
+
+/// The `Content-Type` of the response in `exchange`, if the router set one.
+fn content_type(exchange: &Exchange) -> Option<&str> {
+    exchange
+        .response_headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("Content-Type"))
+        .map(|(_, value)| value.as_str())
+}
+
+/// `headers` as `Name: value` lines.
+pub(crate) fn text_headers(headers: &[(String, String)]) -> String {
+    headers
+        .iter()
+        .map(|(name, value)| format!("{}: {}", name, value))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// `headers` as JSON object members, without braces.
+fn json_headers(headers: &[(String, String)]) -> String {
+    headers
+        .iter()
+        .map(|(name, value)| format!(r#""{}":"{}""#, json_escape(name), json_escape(value)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Counts a finished connection into the current summary, if summaries are
+/// on; returns whether it still needs a report of its own.
+fn tally(result: &Result<Exchange, ServerError>, sent: u64, elapsed: Duration) -> bool {
+    let Some(summary) = SUMMARY.get() else {
+        return true;
+    };
+    let mut summary = summary.lock().unwrap_or_else(PoisonError::into_inner);
+    summary.requests += 1;
+    summary.elapsed += elapsed;
+    summary.bytes_sent += sent;
+    match result {
+        Ok(exchange) => {
+            if exchange.status >= 500 {
+                summary.errors += 1;
+            }
+            *summary.statuses.entry(exchange.status).or_default() += 1;
+            *summary.classes.entry(crate::metrics::class(exchange.status)).or_default() += 1;
+            if let Some(request) = &exchange.request {
+                *summary.methods.entry(request.method.clone()).or_default() += 1;
+            }
+            summary.bytes_read += exchange.bytes_read as u64;
+            if summary.paths.len() < SUMMARY_PATHS || summary.paths.contains_key(&exchange.path) {
+                *summary.paths.entry(exchange.path.clone()).or_default() += 1;
+            } else {
+                summary.other_paths += 1;
+            }
+        }
+        Err(_) => {
+            summary.failed += 1;
+            summary.errors += 1;
+        }
+    }
+    DEBUG.load(Ordering::Relaxed)
+}
+
+/// The connections of one summary interval.
+#[derive(Debug, Default)]
+struct Summary {
+    requests: u64,
+    failed: u64,
+    /// Failed connections and 5xx responses.
+    errors: u64,
+    /// Time taken by all the connections together.
+    elapsed: Duration,
+    statuses: HashMap<u16, u64>,
+    classes: BTreeMap<String, u64>,
+    methods: BTreeMap<String, u64>,
+    paths: HashMap<String, u64>,
+    /// Connections to paths beyond [`SUMMARY_PATHS`].
+    other_paths: u64,
+    bytes_read: u64,
+    bytes_sent: u64,
+}
+
+impl Summary {
+    fn print(&self, interval: Duration) {
+        let mean_latency_ms = self.elapsed.as_secs_f64() * 1000.0 / self.requests.max(1) as f64;
+        let mut statuses: Vec<(&u16, &u64)> = self.statuses.iter().collect();
+        statuses.sort();
+        let mut paths: Vec<(&String, &u64)> = self.paths.iter().collect();
+        paths.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        paths.truncate(TOP_PATHS);
+
+        if format() == Format::Ndjson {
+            let statuses: Vec<String> = statuses
+                .iter()
+                .map(|(status, count)| format!(r#""{}":{}"#, status, count))
+                .collect();
+            let counts = |counts: &BTreeMap<String, u64>| {
+                counts
+                    .iter()
+                    .map(|(key, count)| format!(r#""{}":{}"#, json_escape(key), count))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            };
+            let paths: Vec<String> = paths
+                .iter()
+                .map(|(path, count)| format!(r#""{}":{}"#, json_escape(path), count))
+                .collect();
+            return print_json(&format!(
+                r#""interval_secs":{},"requests":{},"failed":{},"errors":{},"statuses":{{{}}},"classes":{{{}}},"methods":{{{}}},"top_paths":{{{}}},"mean_latency_ms":{:.3},"bytes_read":{},"bytes_sent":{}"#,
+                interval.as_secs_f64(),
+                self.requests,
+                self.failed,
+                self.errors,
+                statuses.join(","),
+                counts(&self.classes),
+                counts(&self.methods),
+                paths.join(","),
+                mean_latency_ms,
+                self.bytes_read,
+                self.bytes_sent
+            ));
+        }
+
+        let mut status_text: Vec<String> = statuses
+            .iter()
+            .map(|(status, count)| format!("{} ×{}", status, count))
+            .collect();
+        if self.failed > 0 {
+            status_text.push(format!("failed ×{}", self.failed));
+        }
+        let counts = |counts: &BTreeMap<String, u64>| {
+            counts
+                .iter()
+                .map(|(key, count)| format!("{} ×{}", key, count))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let path_text: Vec<String> = paths
+            .iter()
+            .map(|(path, count)| format!("{} ×{}", path, count))
+            .collect();
+        print_table(&[
+            ("Summary", format!("last {} s", interval.as_secs_f64())),
+            (
+                "Requests",
+                format!(
+                    "{} ({:.1}/s)",
+                    self.requests,
+                    self.requests as f64 / interval.as_secs_f64()
+                ),
+            ),
+            ("Errors", self.errors.to_string()),
+            ("Statuses", status_text.join(", ")),
+            ("Classes", counts(&self.classes)),
+            ("Methods", counts(&self.methods)),
+            ("Top paths", path_text.join(", ")),
+            ("Mean latency", format!("{:.1} ms", mean_latency_ms)),
+            ("Bytes read", self.bytes_read.to_string()),
+            ("Bytes sent", self.bytes_sent.to_string()),
+        ]);
+    }
+}
+
+/// Stops all further console output.
+#[cfg_attr(not(feature = "tui"), allow(dead_code))]
+pub(crate) fn silence() {
+    SILENT.store(true, Ordering::Relaxed);
+}
+
+/// The format to print in, or `None` while output is silenced.
+fn output() -> Option<Format> {
+    (!SILENT.load(Ordering::Relaxed)).then(format)
+}
+
+/// `rows` as a key/value table, the way [`print_table`] draws it.
+pub fn render_table(rows: &[(&str, String)]) -> String {
+    match theme() {
+        Theme::Plain => plain_table(rows),
+        _ => draw_table(rows),
+    }
+}
+
+/// `rows` under the column titles `header`, the way [`print_grid`] draws
+/// them.
+pub fn render_grid(header: &[&str], rows: &[Vec<String>]) -> String {
+    match theme() {
+        Theme::Plain => plain_grid(header, rows),
+        _ => draw_grid(header, rows),
+    }
+}
+
+/// Prints `rows` as a key/value table on stdout.
+pub fn print_table(rows: &[(&str, String)]) {
+    match output() {
+        Some(Format::Table) => emit(&render_table(rows)),
+        #[cfg(feature = "tracing")]
+        Some(Format::Pretty) => emit(&render_table(rows)),
+        Some(Format::Ndjson) => print_object(rows.iter().map(|(key, value)| (*key, value.as_str()))),
+        #[cfg(feature = "tracing")]
+        Some(Format::Tracing) => print_event(rows.iter().map(|(key, value)| (*key, value.as_str()))),
+        None => {}
+    }
+}
+
+/// Prints `rows` under the column titles `header` as a table on stdout.
+pub fn print_grid(header: &[&str], rows: &[Vec<String>]) {
+    match output() {
+        Some(Format::Table) => emit(&render_grid(header, rows)),
+        #[cfg(feature = "tracing")]
+        Some(Format::Pretty) => emit(&render_grid(header, rows)),
+        Some(Format::Ndjson) => {
+            for cells in rows {
+                print_object(header.iter().copied().zip(cells.iter().map(String::as_str)));
+            }
+        }
+        #[cfg(feature = "tracing")]
+        Some(Format::Tracing) => {
+            for cells in rows {
+                print_event(header.iter().copied().zip(cells.iter().map(String::as_str)));
+            }
+        }
+        None => {}
+    }
+}
+
+/// Prints a one-line status message on stdout.
+pub fn print_line(message: &str) {
+    match output() {
+        Some(Format::Table) => emit(message),
+        #[cfg(feature = "tracing")]
+        Some(Format::Pretty) => emit(message),
+        Some(Format::Ndjson) => print_object([("message", message)]),
+        #[cfg(feature = "tracing")]
+        Some(Format::Tracing) => tracing::info!(target: "rusty_server::console", "{}", message),
+        None => {}
+    }
+}
+
+/// Prints the JSON object members `members` (without braces) as one line,
+/// after the `time`, in NDJSON format; callers print a table otherwise.
+pub(crate) fn print_json(members: &str) {
+    if output() == Some(Format::Ndjson) {
+        emit(&format!(r#"{{"time":{},{}}}"#, unix_timestamp(), members));
+    }
+}
+
+/// Draws `rows` as a table on stdout whatever the format, unless output is
+/// silenced: the tables of [`crate::trace::Pretty`].
+#[cfg(feature = "tracing")]
+pub(crate) fn print_drawn(rows: &[(&str, String)]) {
+    if output().is_some() {
+        emit(&render_table(rows));
+    }
+}
+
+/// Also writes all further console output, without colors, to
+/// `rusty-server-YYYY-MM-DD.log` in `dir`, named for today's date in UTC and
+/// appended to if it exists. Creates `dir` if needed and returns the file's
+/// path.
+pub fn set_log_dir(dir: impl AsRef<Path>) -> io::Result<PathBuf> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)?;
+    let (year, month, day) = civil_date(unix_timestamp() / 86_400);
+    let path = dir.join(format!(
+        "rusty-server-{:04}-{:02}-{:02}.log",
+        year, month, day
+    ));
+    let file = OpenOptions::new().create(true).append(true).open(&path)?;
+    *MIRROR.lock().unwrap_or_else(PoisonError::into_inner) = Some(Box::new(file));
+    Ok(path)
+}
+
+/// Also writes all further console output, without colors, to `log`,
+/// which rotates as it was opened to, in place of any [`set_log_dir`] file.
+pub fn set_log_file(log: LogFile) {
+    *MIRROR.lock().unwrap_or_else(PoisonError::into_inner) = Some(Box::new(log));
+}
+
+/// Prints `text` on stdout and writes it to the mirror file, if there is
+/// one. The mirror is dropped after a failed write, with an error on
+/// stderr.
+fn emit(text: &str) {
+    // Held while printing too, so that reports from connections served at
+    // the same time come out whole and in the same order in both places.
+    let mut mirror = MIRROR.lock().unwrap_or_else(PoisonError::into_inner);
+    println!("{}", text);
+    if let Some(file) = mirror.as_mut() {
+        // In one write, so that a rotating file never splits a report.
+        let line = format!("{}\n", strip_colors(text));
+        if let Err(e) = file.write_all(line.as_bytes()).and_then(|()| file.flush()) {
+            eprintln!("Error: cannot write the log file: {}", e);
+            *mirror = None;
+        }
+    }
+}
+
+/// `text` without ANSI escape sequences.
+fn strip_colors(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // CSI sequences end with a byte in `@`..=`~`.
+            if chars.next() == Some('[') {
+                chars.find(|c| ('@'..='~').contains(c));
+            }
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+/// The English month abbreviations, as access logs and HTTP dates use them.
+pub(crate) const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// The proleptic Gregorian (year, month, day) `days` after 1970-01-01.
+pub(crate) fn civil_date(days: u64) -> (u64, u64, u64) {
+    // Howard Hinnant's `civil_from_days`, with eras starting on March 1st.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// The days from 1970-01-01 to the proleptic Gregorian `year`, `month`, and
+/// `day`, the inverse of [`civil_date`]; `None` before 1970.
+pub(crate) fn days_from_civil(year: u64, month: u64, day: u64) -> Option<u64> {
+    // Howard Hinnant's `days_from_civil`, with eras starting on March 1st.
+    let year = year.checked_sub(u64::from(month <= 2))?;
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * mp + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    (era * 146_097 + day_of_era).checked_sub(719_468)
+}
+
+/// Prints `fields` as a JSON object of strings, with snake_case keys.
+fn print_object<'a>(fields: impl IntoIterator<Item = (&'a str, &'a str)>) {
+    let members: Vec<String> = fields
+        .into_iter()
+        .map(|(key, value)| format!(r#""{}":"{}""#, snake_case(key), json_escape(value)))
+        .collect();
+    print_json(&members.join(","))
+}
+
+/// Logs `fields` as one tracing event, as `Key: value` pairs.
+#[cfg(feature = "tracing")]
+fn print_event<'a>(fields: impl IntoIterator<Item = (&'a str, &'a str)>) {
+    let pairs: Vec<String> = fields
+        .into_iter()
+        .map(|(key, value)| format!("{}: {}", key, value))
+        .collect();
+    tracing::info!(target: "rusty_server::console", "{}", pairs.join(", "))
+}
+
+/// `Content-Type` as `content_type`.
+fn snake_case(key: &str) -> String {
+    key.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(feature = "console")]
+fn draw_table(rows: &[(&str, String)]) -> String {
+    use nu_protocol::TrimStrategy;
+    use nu_table::{NuTable, NuTableConfig};
+
+    let mut table = NuTable::new(rows.len(), 2);
+    for (row, (key, value)) in rows.iter().enumerate() {
+        table.insert((row, 0), key.to_string());
+        table.insert((row, 1), value.clone());
+        if let Some(style) = style(value, false) {
+            table.insert_style((row, 1), style);
+        }
+    }
+
+    let config = NuTableConfig {
+        theme: table_theme(),
+        trim: TrimStrategy::wrap(true),
+        ..NuTableConfig::default()
+    };
+    table.draw(config, width()).unwrap_or_else(|| plain_table(rows))
+}
+
+#[cfg(not(feature = "console"))]
+fn draw_table(rows: &[(&str, String)]) -> String {
+    plain_table(rows)
+}
+
+/// `rows` as aligned plain-text columns.
+fn plain_table(rows: &[(&str, String)]) -> String {
+    let width = rows.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+    rows.iter()
+        .map(|(key, value)| format!("{:width$}  {}", key, value, width = width))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(feature = "console")]
+fn draw_grid(header: &[&str], rows: &[Vec<String>]) -> String {
+    use nu_protocol::TrimStrategy;
+    use nu_table::{NuTable, NuTableConfig};
+
+    let mut table = NuTable::new(rows.len() + 1, header.len());
+    for (column, title) in header.iter().enumerate() {
+        table.insert((0, column), title.to_string());
+    }
+    for (row, cells) in rows.iter().enumerate() {
+        for (column, cell) in cells.iter().enumerate().take(header.len()) {
+            table.insert((row + 1, column), cell.clone());
+            if let Some(style) = style(cell, header[column] == "Status") {
+                table.insert_style((row + 1, column), style);
+            }
+        }
+    }
+
+    let config = NuTableConfig {
+        theme: table_theme(),
+        trim: TrimStrategy::wrap(true),
+        with_header: true,
+        ..NuTableConfig::default()
+    };
+    table.draw(config, width()).unwrap_or_else(|| plain_grid(header, rows))
+}
+
+#[cfg(not(feature = "console"))]
+fn draw_grid(header: &[&str], rows: &[Vec<String>]) -> String {
+    plain_grid(header, rows)
+}
+
+/// `rows` under `header` as aligned plain-text columns.
+fn plain_grid(header: &[&str], rows: &[Vec<String>]) -> String {
+    let widths: Vec<usize> = header
+        .iter()
+        .enumerate()
+        .map(|(column, title)| {
+            rows.iter()
+                .filter_map(|cells| cells.get(column))
+                .map(|cell| cell.chars().count())
+                .chain([title.len()])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let line = |cells: Vec<&str>| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+    std::iter::once(line(header.to_vec()))
+        .chain(
+            rows.iter()
+                .map(|cells| line(cells.iter().map(String::as_str).collect())),
+        )
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The width to draw tables in: as set with [`set_width`], else the
+/// terminal's if stdout is one, else `COLUMNS`, else [`DEFAULT_WIDTH`].
+#[cfg(feature = "console")]
+fn width() -> usize {
+    use std::io::IsTerminal;
+
+    match WIDTH.load(Ordering::Relaxed) {
+        0 => {}
+        width => return width,
+    }
+    if io::stdout().is_terminal() {
+        if let Some((terminal_size::Width(width), _)) = terminal_size::terminal_size() {
+            return width.into();
+        }
+    }
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .filter(|&columns| columns > 0)
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+#[cfg(feature = "console")]
+fn table_theme() -> nu_table::TableTheme {
+    use nu_table::TableTheme;
+
+    match theme() {
+        Theme::Rounded => TableTheme::rounded(),
+        Theme::Heavy => TableTheme::heavy(),
+        Theme::Light => TableTheme::light(),
+        Theme::Basic => TableTheme::basic(),
+        Theme::None | Theme::Plain => TableTheme::none(),
+    }
+}
+
+/// The color of `cell`, if tables are colored and it reports an outcome.
+#[cfg(feature = "console")]
+fn style(cell: &str, status_column: bool) -> Option<nu_table::TextStyle> {
+    use nu_ansi_term::Color;
+
+    if !color() {
+        return None;
+    }
+    let color = match Outcome::of(cell, status_column)? {
+        Outcome::Success => Color::Green,
+        Outcome::Warning => Color::Yellow,
+        Outcome::Failure => Color::Red,
+    };
+    Some(nu_table::TextStyle::new().fg(color))
+}
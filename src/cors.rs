@@ -0,0 +1,206 @@
+//! Cross-origin resource sharing (CORS), so that pages served from another
+//! origin, such as a frontend's dev server on another port, can call the
+//! server from the browser.
+//!
+//! Register [`Cors`] as middleware with the origins to let in:
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use rusty_server::cors::Cors;
+//!
+//! let mut router = rusty_server::Router::default();
+//! router.middleware(
+//!     Cors::new()
+//!         .allow_origin("http://localhost:5173")
+//!         .allow_credentials(true)
+//!         .max_age(Duration::from_secs(600)),
+//! );
+//! ```
+//!
+//! Responses to requests from an allowed `Origin` then carry
+//! `Access-Control-Allow-Origin`, and preflight `OPTIONS` requests, which
+//! browsers send before requests with other methods or headers, are
+//! answered with `204 No Content` and the methods and headers allowed,
+//! without reaching the routes. Requests from other origins are served as
+//! before, without the headers, so the browser keeps the response from the
+//! page.
+
+use std::time::Duration;
+
+use crate::http::{vary, Request, Response};
+use crate::router::Middleware;
+
+/// The methods allowed unless [`Cors::allow_methods`] says otherwise.
+pub const DEFAULT_METHODS: &[&str] = &["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE"];
+
+/// Middleware answering preflight requests and adding the CORS headers.
+#[derive(Debug, Clone)]
+pub struct Cors {
+    /// The allowed origins; `None` for any.
+    origins: Option<Vec<String>>,
+    methods: Vec<String>,
+    /// The allowed request headers; `None` for whichever a preflight asks
+    /// for.
+    headers: Option<Vec<String>>,
+    expose: Vec<String>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl Cors {
+    /// Allows any origin to make requests with the [`DEFAULT_METHODS`] and
+    /// any headers, without credentials.
+    pub fn new() -> Self {
+        Cors {
+            origins: None,
+            methods: DEFAULT_METHODS.iter().map(|method| method.to_string()).collect(),
+            headers: None,
+            expose: Vec::new(),
+            credentials: false,
+            max_age: None,
+        }
+    }
+
+    /// Allows `origin`, such as `https://app.example.com`, in place of any
+    /// origin; call it once for each origin to allow. `*` allows any.
+    pub fn allow_origin(mut self, origin: &str) -> Self {
+        let origin = origin.trim().trim_end_matches('/');
+        if origin == "*" {
+            self.origins = None;
+        } else {
+            self.origins.get_or_insert_with(Vec::new).push(origin.to_string());
+        }
+        self
+    }
+
+    /// Allows `methods` in place of the [`DEFAULT_METHODS`].
+    pub fn allow_methods(mut self, methods: &[&str]) -> Self {
+        self.methods = methods.iter().map(|method| method.to_ascii_uppercase()).collect();
+        self
+    }
+
+    /// Allows the request headers `headers` in place of any a preflight
+    /// asks for.
+    pub fn allow_headers(mut self, headers: &[&str]) -> Self {
+        self.headers = Some(headers.iter().map(|header| header.to_string()).collect());
+        self
+    }
+
+    /// Lets pages read the response headers `headers`, beyond the few that
+    /// browsers always show them.
+    pub fn expose_headers(mut self, headers: &[&str]) -> Self {
+        self.expose = headers.iter().map(|header| header.to_string()).collect();
+        self
+    }
+
+    /// Allows requests with cookies or HTTP authentication from the origins
+    /// named with [`Cors::allow_origin`]. The allowed origin is then named in
+    /// every response rather than sent as `*`, as browsers require. With any
+    /// origin allowed, credentials stay refused: echoing every origin back
+    /// with them would let any site read responses as the user.
+    pub fn allow_credentials(mut self, credentials: bool) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    /// Lets browsers cache the answer to a preflight for `max_age`.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Whether requests from `origin` are allowed.
+    pub fn allows(&self, origin: &str) -> bool {
+        self.origins.as_ref().is_none_or(|origins| {
+            origins.iter().any(|allowed| allowed.eq_ignore_ascii_case(origin))
+        })
+    }
+
+    /// Whether requests with credentials are allowed: only from origins
+    /// named one by one.
+    fn credentials(&self) -> bool {
+        self.credentials && self.origins.is_some()
+    }
+
+    /// The `Access-Control-Allow-Origin` for an allowed `origin`.
+    fn allow_origin_value<'a>(&self, origin: &'a str) -> &'a str {
+        if self.origins.is_none() {
+            "*"
+        } else {
+            origin
+        }
+    }
+
+    /// Adds the headers every response to a request from the allowed
+    /// `origin` carries.
+    fn add_headers(&self, origin: &str, response: &mut Response) {
+        let allow_origin = self.allow_origin_value(origin);
+        response
+            .headers
+            .push(("Access-Control-Allow-Origin".to_string(), allow_origin.to_string()));
+        if allow_origin != "*" {
+            vary(response, "Origin");
+        }
+        if self.credentials() {
+            response
+                .headers
+                .push(("Access-Control-Allow-Credentials".to_string(), "true".to_string()));
+        }
+    }
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Cors::new()
+    }
+}
+
+impl Middleware for Cors {
+    fn before(&self, request: &Request) -> Option<Response> {
+        if request.method != "OPTIONS" {
+            return None;
+        }
+        let origin = request.header("Origin").filter(|origin| self.allows(origin))?;
+        request.header("Access-Control-Request-Method")?;
+        let mut response = Response::new(204)
+            .header("Access-Control-Allow-Methods", self.methods.join(", "));
+        let headers = match &self.headers {
+            Some(headers) => headers.join(", "),
+            None => request
+                .header("Access-Control-Request-Headers")
+                .unwrap_or_default()
+                .to_string(),
+        };
+        if !headers.is_empty() {
+            response = response.header("Access-Control-Allow-Headers", headers);
+        }
+        if let Some(max_age) = self.max_age {
+            response = response.header("Access-Control-Max-Age", max_age.as_secs().to_string());
+        }
+        self.add_headers(origin, &mut response);
+        vary(&mut response, "Access-Control-Request-Method");
+        vary(&mut response, "Access-Control-Request-Headers");
+        Some(response)
+    }
+
+    fn after(&self, request: &Request, response: &mut Response) {
+        let Some(origin) = request.header("Origin").filter(|origin| self.allows(origin)) else {
+            // The answer depends on the origin even when it is not allowed.
+            if self.origins.is_some() {
+                vary(response, "Origin");
+            }
+            return;
+        };
+        // A preflight answered by `before` has its headers already.
+        if response.header_value("Access-Control-Allow-Origin").is_some() {
+            return;
+        }
+        self.add_headers(origin, response);
+        if !self.expose.is_empty() {
+            response
+                .headers
+                .push(("Access-Control-Expose-Headers".to_string(), self.expose.join(", ")));
+        }
+    }
+}
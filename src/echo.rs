@@ -0,0 +1,120 @@
+//! A raw TCP echo listener, for looking beneath HTTP.
+//!
+//! Every byte a client sends is written straight back, and each chunk read is
+//! printed as a hex dump table, so a workshop can watch what a browser or
+//! `curl` actually puts on the wire: the request line, the headers, the blank
+//! line, and nothing more magical than that. At [`Verbosity::Quiet`] the
+//! connections are echoed without being printed.
+
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use crate::console::{self, Verbosity};
+use crate::error::ServerError;
+
+/// Bytes shown per hex dump line; eight keep a line within the width of a
+/// console table.
+const BYTES_PER_LINE: usize = 8;
+
+/// Accepts connections on `listener` and echoes each on its own thread.
+pub fn serve(listener: TcpListener) -> Result<(), ServerError> {
+    let local_addr = listener.local_addr()?;
+    console::print_table(&[
+        ("Status", "Echo Started".to_string()),
+        ("Address", format!("tcp://{}", local_addr)),
+    ]);
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let spawned = thread::Builder::new().spawn(move || process_connection(stream));
+                if let Err(e) = spawned {
+                    console::print_table(&[("Error", ServerError::from(e).to_string())]);
+                }
+            }
+            Err(e) => console::print_table(&[("Error", ServerError::from(e).to_string())]),
+        }
+    }
+    Ok(())
+}
+
+fn process_connection(mut stream: TcpStream) {
+    let peer = stream
+        .peer_addr()
+        .map_or_else(|_| "unknown".to_string(), |addr| addr.to_string());
+    let quiet = console::verbosity() == Verbosity::Quiet;
+    if !quiet {
+        console::print_table(&[("Status", "Connected".to_string()), ("Peer", peer.clone())]);
+    }
+    let (status, total) = match echo(&mut stream, &peer) {
+        Ok(total) => ("Closed".to_string(), total),
+        Err(e) => (format!("Failed: {}", ServerError::from(e)), 0),
+    };
+    if quiet {
+        return;
+    }
+    console::print_table(&[
+        ("Status", status),
+        ("Peer", peer),
+        ("Bytes", total.to_string()),
+    ]);
+}
+
+/// Writes everything read from `stream` back to it, printing a hex dump of
+/// each chunk labelled with `peer`, until the client closes its side.
+/// Returns the number of bytes echoed.
+pub fn echo<S: Read + Write>(stream: &mut S, peer: &str) -> io::Result<u64> {
+    let mut buf = [0; 4096];
+    let mut total = 0;
+    loop {
+        let n = match stream.read(&mut buf) {
+            Ok(0) => return Ok(total),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if console::verbosity() > Verbosity::Quiet {
+            console::print_table(&[
+                ("Peer", peer.to_string()),
+                ("Offset", total.to_string()),
+                ("Bytes", n.to_string()),
+                ("Data", hex_dump(&buf[..n], total)),
+            ]);
+        }
+        stream.write_all(&buf[..n])?;
+        stream.flush()?;
+        total += n as u64;
+    }
+}
+
+/// Formats `bytes` as `hexdump -C` style lines: the offset (counting from
+/// `offset`), eight bytes in hex, and the printable ASCII characters.
+pub fn hex_dump(bytes: &[u8], offset: u64) -> String {
+    let mut dump = String::new();
+    for (index, line) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        if index > 0 {
+            dump.push('\n');
+        }
+        let _ = write!(dump, "{:08x} ", offset + (index * BYTES_PER_LINE) as u64);
+        for column in 0..BYTES_PER_LINE {
+            match line.get(column) {
+                Some(byte) => {
+                    let _ = write!(dump, " {:02x}", byte);
+                }
+                None => dump.push_str("   "),
+            }
+        }
+        dump.push_str("  |");
+        dump.extend(line.iter().map(|&byte| {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            }
+        }));
+        dump.push('|');
+    }
+    dump
+}
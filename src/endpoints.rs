@@ -0,0 +1,181 @@
+//! Handlers for the built-in endpoints.
+
+use std::thread;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::http::{Request, Response};
+use crate::negotiate::Format;
+use crate::sse::{Event, EventStream};
+use crate::{http, metrics, pages, server, stats, unix_timestamp};
+
+/// How often `/events` sends a stats event.
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reconnect delay suggested to `/events` clients, in milliseconds.
+const STATS_RETRY_MS: u64 = 3000;
+
+/// The formats `/version` answers in, the default first.
+const VERSION_FORMATS: &[Format] = &[
+    #[cfg(feature = "html")]
+    Format::Html,
+    Format::Json,
+    Format::Text,
+];
+
+/// The build and platform information `/version` answers with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VersionInfo {
+    pub version: &'static str,
+    pub commit: &'static str,
+    pub branch: &'static str,
+    /// When the response was made, in seconds since the Unix epoch, as a
+    /// string, as it has always been sent.
+    pub built_at: String,
+    pub rust_version: String,
+    pub platform: &'static str,
+    pub arch: &'static str,
+    /// `https` for requests that arrived over TLS, `http` otherwise.
+    pub scheme: &'static str,
+    pub started_at: u64,
+    pub uptime_secs: u64,
+}
+
+impl VersionInfo {
+    /// The information for a request over `scheme`, as of `built_at`.
+    fn new(built_at: u64, scheme: &'static str) -> Self {
+        VersionInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            commit: "unknown",
+            branch: "main",
+            built_at: built_at.to_string(),
+            rust_version: rustc_version_runtime::version().to_string(),
+            platform: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            scheme,
+            started_at: stats::started_at(),
+            uptime_secs: stats::uptime().as_secs(),
+        }
+    }
+
+    /// A weak `ETag` for the information in `format`. It leaves out
+    /// `built_at` and `uptime_secs`, which change every second, so that a
+    /// client polling `/version` gets `304 Not Modified` until the server
+    /// restarts or is upgraded.
+    fn etag(&self, format: Format) -> String {
+        let stable = VersionInfo {
+            built_at: String::new(),
+            uptime_secs: 0,
+            ..self.clone()
+        };
+        let key = format!("{:?} {:?}", format, stable);
+        format!("W/\"{:016x}\"", http::fnv1a(key.as_bytes()))
+    }
+
+    /// The information as `key: value` lines.
+    fn to_text(&self) -> String {
+        format!(
+            "version: {}\ncommit: {}\nbranch: {}\nbuilt_at: {}\nrust_version: {}\nplatform: {}\narch: {}\nscheme: {}\nstarted_at: {}\nuptime_secs: {}\n",
+            self.version,
+            self.commit,
+            self.branch,
+            self.built_at,
+            self.rust_version,
+            self.platform,
+            self.arch,
+            self.scheme,
+            self.started_at,
+            self.uptime_secs
+        )
+    }
+}
+
+/// `GET /version`: build and platform information, as a page, JSON, or
+/// `key: value` lines, whichever the client's `Accept` prefers.
+pub fn version(request: &Request) -> Response {
+    let scheme = if request.secure { "https" } else { "http" };
+    let info = VersionInfo::new(unix_timestamp(), scheme);
+    let format = Format::negotiate(request, VERSION_FORMATS);
+    let mut response = match format {
+        Format::Html => pages::version(&info),
+        Format::Json => Response::json(200, &info),
+        Format::Text => Response::text(200, info.to_text()),
+    };
+    http::vary(&mut response, "Accept");
+    response.header("ETag", info.etag(format))
+}
+
+/// The body of `/healthz`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HealthStatus {
+    /// `ok`, or `draining` once the server is shutting down.
+    pub status: &'static str,
+    pub started_at: u64,
+    pub uptime_secs: u64,
+    pub timestamp: u64,
+}
+
+/// `GET /healthz`: whether the server is taking connections, as JSON with
+/// its start time and uptime, or as the bare status for clients preferring
+/// plain text. Answers 200 while serving and 503 once it is draining, so
+/// load balancers stop sending it traffic.
+pub fn healthz(request: &Request) -> Response {
+    let (status, code) = if server::draining() {
+        ("draining", 503)
+    } else {
+        ("ok", 200)
+    };
+    let mut response = if Format::negotiate(request, &[Format::Json, Format::Text]) == Format::Text {
+        Response::text(code, format!("{}\n", status))
+    } else {
+        let health = HealthStatus {
+            status,
+            started_at: stats::started_at(),
+            uptime_secs: stats::snapshot().uptime_secs,
+            timestamp: unix_timestamp(),
+        };
+        Response::json(code, &health)
+    };
+    http::vary(&mut response, "Accept");
+    response
+}
+
+/// `GET /metrics`: request, byte, and connection counters in the Prometheus
+/// text exposition format.
+pub fn metrics(_request: &Request) -> Response {
+    Response::with_body(
+        200,
+        "text/plain; version=0.0.4; charset=utf-8",
+        metrics::render(),
+    )
+}
+
+/// `GET /events`: server stats as a `stats` event every second.
+///
+/// Event IDs count up from 0, or from one past `Last-Event-ID` when a client
+/// reconnects, so clients can tell how many updates they missed.
+pub fn events(request: &Request, events: &mut EventStream<'_>) {
+    let mut id = request
+        .header("Last-Event-ID")
+        .and_then(|id| id.parse::<u64>().ok())
+        .map_or(0, |last| last.saturating_add(1));
+    // An opening comment gets the head through buffering proxies at once.
+    if events.comment("server stats every second").is_err() {
+        return;
+    }
+    let mut retry = Some(STATS_RETRY_MS);
+    loop {
+        let mut event = Event::new(stats::snapshot().to_json())
+            .id(id.to_string())
+            .event("stats");
+        if let Some(millis) = retry.take() {
+            event = event.retry(millis);
+        }
+        if events.send(&event).is_err() {
+            return;
+        }
+        id = id.saturating_add(1);
+        thread::sleep(STATS_INTERVAL);
+    }
+}
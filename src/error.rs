@@ -0,0 +1,79 @@
+//! The server's error type.
+//!
+//! Every failure the server can run into is a [`ServerError`] variant, and each
+//! variant knows which HTTP status it is answered with (when a client is still
+//! there to answer) and which process exit code it maps to (when it ends the
+//! process).
+
+use std::io;
+use std::process::ExitCode;
+
+use crate::http::ParseError;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ServerError {
+    /// The listening socket could not be created.
+    #[error("cannot bind {addr}: {source}")]
+    Bind {
+        addr: String,
+        #[source]
+        source: io::Error,
+    },
+    /// The client sent a request that could not be parsed.
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    /// A socket operation did not complete within its deadline.
+    #[error("timed out while {0}")]
+    Timeout(&'static str),
+    /// A request handler could not produce a response.
+    #[error("handler failed: {0}")]
+    Handler(String),
+    /// TLS configuration or handshake failure.
+    #[error("TLS error: {0}")]
+    Tls(String),
+    /// Any other I/O failure on a connection or the listener.
+    #[error("I/O error: {0}")]
+    Io(#[source] io::Error),
+}
+
+impl ServerError {
+    /// Status code and reason phrase a client receives for this error.
+    pub fn status(&self) -> (u16, &'static str) {
+        match self {
+            ServerError::Parse(e) => e.status(),
+            ServerError::Timeout(_) => (408, "Request Timeout"),
+            ServerError::Bind { .. }
+            | ServerError::Handler(_)
+            | ServerError::Tls(_)
+            | ServerError::Io(_) => (500, "Internal Server Error"),
+        }
+    }
+
+    /// Process exit code used when this error terminates the server.
+    ///
+    /// Values follow `sysexits.h` so supervisors can tell configuration
+    /// problems from runtime failures.
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            // EX_UNAVAILABLE: the address is in use or not available.
+            ServerError::Bind { .. } => ExitCode::from(69),
+            // EX_CONFIG: certificates or keys are unusable.
+            ServerError::Tls(_) => ExitCode::from(78),
+            // EX_IOERR
+            ServerError::Io(_) | ServerError::Timeout(_) => ExitCode::from(74),
+            // EX_SOFTWARE
+            ServerError::Parse(_) | ServerError::Handler(_) => ExitCode::from(70),
+        }
+    }
+}
+
+impl From<io::Error> for ServerError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => {
+                ServerError::Timeout("waiting on the socket")
+            }
+            _ => ServerError::Io(e),
+        }
+    }
+}
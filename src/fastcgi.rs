@@ -0,0 +1,343 @@
+//! A FastCGI client, relaying requests to backends such as PHP-FPM.
+//!
+//! Mount a [`FastCgi`] on a router as a streaming handler:
+//!
+//! ```no_run
+//! use rusty_server::fastcgi::FastCgi;
+//!
+//! let php = FastCgi::new("127.0.0.1:9000", "/var/www/html");
+//! let mut router = rusty_server::Router::default();
+//! router.stream("/app", move |request, connection| php.serve(request, connection));
+//! ```
+//!
+//! Each request opens a new connection to the backend. The request body is
+//! forwarded as `FCGI_STDIN` records while it is read from the client, and
+//! the backend's `FCGI_STDOUT` is passed on to the client as it arrives.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::cgi::{escapes_root, invalid, meta_variables, parse_head};
+use crate::console;
+use crate::http::{Request, Response};
+use crate::pages;
+use crate::stream::Connection;
+
+const VERSION: u8 = 1;
+
+const BEGIN_REQUEST: u8 = 1;
+const END_REQUEST: u8 = 3;
+const PARAMS: u8 = 4;
+const STDIN: u8 = 5;
+const STDOUT: u8 = 6;
+const STDERR: u8 = 7;
+
+const RESPONDER: u16 = 1;
+
+/// The only request on each backend connection.
+const REQUEST_ID: u16 = 1;
+
+/// Largest record body the protocol allows.
+const MAX_RECORD: usize = 0xffff;
+
+/// Largest response head accepted from the backend.
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+/// How long to wait for the backend to accept a connection, or for any one
+/// read or write on it.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where the backend listens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Address {
+    Tcp(String),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+/// A FastCGI backend, and how to map request paths to its scripts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FastCgi {
+    address: Address,
+    document_root: PathBuf,
+    front_controller: Option<String>,
+    timeout: Duration,
+}
+
+impl FastCgi {
+    /// A backend at `address`, either `host:port` or `unix:/path/to/socket`,
+    /// serving scripts from `document_root` on its filesystem.
+    ///
+    /// The script for a request is the file at its path under
+    /// `document_root`, as with nginx's `$document_root$fastcgi_script_name`.
+    pub fn new(address: &str, document_root: impl Into<PathBuf>) -> Self {
+        let address = match address.strip_prefix("unix:") {
+            #[cfg(unix)]
+            Some(path) => Address::Unix(PathBuf::from(path)),
+            _ => Address::Tcp(address.to_string()),
+        };
+        FastCgi {
+            address,
+            document_root: document_root.into(),
+            front_controller: None,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Runs every request through `script` (such as `/index.php`) instead,
+    /// with the request path passed on as `PATH_INFO`.
+    pub fn front_controller(mut self, script: &str) -> Self {
+        self.front_controller = Some(format!("/{}", script.trim_start_matches('/')));
+        self
+    }
+
+    /// Sets the connect, read, and write timeout; see [`DEFAULT_TIMEOUT`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The CGI parameters sent for `request`, or `None` if its path tries to
+    /// leave the document root.
+    pub fn params(&self, request: &Request) -> Option<Vec<(String, String)>> {
+        if escapes_root(&request.path) {
+            return None;
+        }
+        let (script_name, path_info) = match &self.front_controller {
+            Some(script) => (script.as_str(), request.path.as_str()),
+            None => (request.path.as_str(), ""),
+        };
+        let script_filename = self.document_root.join(script_name.trim_start_matches('/'));
+        Some(meta_variables(
+            request,
+            script_name,
+            &script_filename,
+            path_info,
+            &self.document_root,
+        ))
+    }
+
+    /// Relays `request` to the backend and streams its answer back on
+    /// `connection`. Returns the status sent.
+    ///
+    /// A backend that cannot be reached or answers with garbage gets the
+    /// client a 502 page, one that times out a 504. Once the response head
+    /// has been sent, failures can only end the connection, and are
+    /// returned.
+    pub fn serve(&self, request: &Request, connection: &mut Connection<'_>) -> io::Result<u16> {
+        let Some(params) = self.params(request) else {
+            return send(connection, pages::not_found_for(request));
+        };
+        let mut backend = match self.connect() {
+            Ok(backend) => backend,
+            Err(e) => return send(connection, gateway_error(&e)),
+        };
+        let (response, body) = match self.begin(&mut *backend, &params, connection) {
+            Ok(head) => head,
+            Err(e) => return send(connection, gateway_error(&e)),
+        };
+        connection.send_head(&response)?;
+        connection.write_all(&body)?;
+        connection.flush()?;
+        let mut records = Records::new(&mut *backend);
+        while let Some((kind, content)) = records.next()? {
+            match kind {
+                STDOUT => {
+                    connection.write_all(&content)?;
+                    connection.flush()?;
+                }
+                STDERR => log_stderr(&content),
+                END_REQUEST => break,
+                _ => {}
+            }
+        }
+        Ok(response.status)
+    }
+
+    fn connect(&self) -> io::Result<Box<dyn Backend>> {
+        match &self.address {
+            Address::Tcp(address) => {
+                let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no address");
+                for addr in address.to_socket_addrs()? {
+                    match TcpStream::connect_timeout(&addr, self.timeout) {
+                        Ok(stream) => {
+                            stream.set_read_timeout(Some(self.timeout))?;
+                            stream.set_write_timeout(Some(self.timeout))?;
+                            return Ok(Box::new(stream));
+                        }
+                        Err(e) => last_error = e,
+                    }
+                }
+                Err(last_error)
+            }
+            #[cfg(unix)]
+            Address::Unix(path) => {
+                let stream = std::os::unix::net::UnixStream::connect(path)?;
+                stream.set_read_timeout(Some(self.timeout))?;
+                stream.set_write_timeout(Some(self.timeout))?;
+                Ok(Box::new(stream))
+            }
+        }
+    }
+
+    /// Sends the request, body included, and reads until the end of the
+    /// response head. Returns the head and any body bytes read with it.
+    fn begin(
+        &self,
+        backend: &mut dyn Backend,
+        params: &[(String, String)],
+        body: &mut Connection<'_>,
+    ) -> io::Result<(Response, Vec<u8>)> {
+        let mut begin = [0; 8];
+        begin[..2].copy_from_slice(&RESPONDER.to_be_bytes());
+        write_record(backend, BEGIN_REQUEST, &begin)?;
+
+        let mut encoded = Vec::new();
+        for (name, value) in params {
+            encode_length(&mut encoded, name.len());
+            encode_length(&mut encoded, value.len());
+            encoded.extend_from_slice(name.as_bytes());
+            encoded.extend_from_slice(value.as_bytes());
+        }
+        write_stream(backend, PARAMS, &encoded)?;
+        write_record(backend, PARAMS, &[])?;
+
+        let mut chunk = vec![0; 16 * 1024];
+        loop {
+            let n = body.read(&mut chunk)?;
+            if n == 0 {
+                break;
+            }
+            write_record(backend, STDIN, &chunk[..n])?;
+        }
+        write_record(backend, STDIN, &[])?;
+        backend.flush()?;
+
+        let mut stdout = Vec::new();
+        let mut records = Records::new(backend);
+        loop {
+            match records.next()? {
+                Some((STDOUT, content)) => {
+                    stdout.extend_from_slice(&content);
+                    if let Some((response, head_len)) = parse_head(&stdout)? {
+                        return Ok((response, stdout.split_off(head_len)));
+                    }
+                    if stdout.len() > MAX_HEAD_SIZE {
+                        return Err(invalid("response head too large"));
+                    }
+                }
+                Some((STDERR, content)) => log_stderr(&content),
+                Some((END_REQUEST, _)) | None => {
+                    return Err(invalid("backend ended the request without a response"))
+                }
+                Some(_) => {}
+            }
+        }
+    }
+}
+
+/// A connection to the backend.
+trait Backend: Read + Write {}
+
+impl<T: Read + Write> Backend for T {}
+
+/// Reads records addressed to [`REQUEST_ID`].
+struct Records<'a> {
+    backend: &'a mut dyn Backend,
+}
+
+impl<'a> Records<'a> {
+    fn new(backend: &'a mut dyn Backend) -> Self {
+        Records { backend }
+    }
+
+    /// The next record's type and content, or `None` at the end of the
+    /// connection.
+    fn next(&mut self) -> io::Result<Option<(u8, Vec<u8>)>> {
+        loop {
+            let mut header = [0; 8];
+            match self.backend.read_exact(&mut header) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e),
+            }
+            if header[0] != VERSION {
+                return Err(invalid("unsupported FastCGI version"));
+            }
+            let id = u16::from_be_bytes([header[2], header[3]]);
+            let length = u16::from_be_bytes([header[4], header[5]]) as usize;
+            let padding = header[6] as usize;
+            let mut content = vec![0; length + padding];
+            self.backend.read_exact(&mut content)?;
+            content.truncate(length);
+            if id == REQUEST_ID {
+                return Ok(Some((header[1], content)));
+            }
+        }
+    }
+}
+
+fn write_record(backend: &mut dyn Backend, kind: u8, content: &[u8]) -> io::Result<()> {
+    let length = content.len() as u16;
+    let padding = (8 - content.len() % 8) % 8;
+    let [id_hi, id_lo] = REQUEST_ID.to_be_bytes();
+    let [length_hi, length_lo] = length.to_be_bytes();
+    let header = [
+        VERSION,
+        kind,
+        id_hi,
+        id_lo,
+        length_hi,
+        length_lo,
+        padding as u8,
+        0,
+    ];
+    backend.write_all(&header)?;
+    backend.write_all(content)?;
+    backend.write_all(&[0; 8][..padding])
+}
+
+/// Writes `content` as records of `kind`, split at the record size limit.
+fn write_stream(backend: &mut dyn Backend, kind: u8, content: &[u8]) -> io::Result<()> {
+    for chunk in content.chunks(MAX_RECORD) {
+        write_record(backend, kind, chunk)?;
+    }
+    Ok(())
+}
+
+/// Appends a name-value pair length: one byte below 128, four otherwise.
+fn encode_length(out: &mut Vec<u8>, length: usize) {
+    if length < 0x80 {
+        out.push(length as u8);
+    } else {
+        out.extend_from_slice(&(length as u32 | 0x8000_0000).to_be_bytes());
+    }
+}
+
+fn log_stderr(content: &[u8]) {
+    let text = String::from_utf8_lossy(content);
+    console::print_table(&[("FastCGI", text.trim_end().to_string())]);
+}
+
+/// The page for a backend failure before the response head was sent.
+fn gateway_error(e: &io::Error) -> Response {
+    match e.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => pages::error(
+            504,
+            "Gateway Timeout",
+            "The FastCGI backend did not answer in time.",
+        ),
+        _ => pages::error(
+            502,
+            "Bad Gateway",
+            "The FastCGI backend could not be reached or sent an invalid response.",
+        ),
+    }
+}
+
+fn send(connection: &mut Connection<'_>, response: Response) -> io::Result<u16> {
+    response.write_to(connection)?;
+    Ok(response.status)
+}
@@ -0,0 +1,216 @@
+//! Static files: serving a directory under a URL prefix.
+//!
+//! Mount [`StaticFiles`] on a router as a fallback:
+//!
+//! ```no_run
+//! use rusty_server::files::StaticFiles;
+//!
+//! let files = StaticFiles::new("/static", "public");
+//! let mut router = rusty_server::Router::default();
+//! router.fallback(move |request| files.serve(request));
+//! ```
+//!
+//! `/static/css/site.css` is then `public/css/site.css`, and `/static/docs/`
+//! is `public/docs/index.html`. A path that does not name a file inside the
+//! directory, whether it is missing or would leave the directory through
+//! `..` or a symbolic link, is left to the rest of the router, which answers
+//! with the 404 page, unless [`StaticFiles::spa`] has the directory's own
+//! `index.html` answer instead, for a single-page app's client-side routes.
+//!
+//! A `Range` header gets part of a file, so that video players can seek and
+//! downloads can resume.
+//!
+//! Each file has an `ETag` made of its size and modification time, and a
+//! client sending it back in `If-None-Match` gets `304 Not Modified` until
+//! the file changes. So does a client sending the file's `Last-Modified`
+//! back in `If-Modified-Since`.
+//!
+//! The `Content-Type` of a file follows its extension, as [`content_type`]
+//! lists them, unless [`StaticFiles::mime_type`] says otherwise:
+//!
+//! ```no_run
+//! # use rusty_server::files::StaticFiles;
+//! let files = StaticFiles::new("/static", "public")
+//!     .mime_type("md", "text/markdown; charset=utf-8")
+//!     .mime_type("log", "text/plain; charset=utf-8");
+//! ```
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::cgi::escapes_root;
+use crate::http::{http_date, percent_encode_path, Request, Response};
+
+/// The file served for a path naming a directory.
+pub const INDEX: &str = "index.html";
+
+/// A directory of files, served under one URL prefix.
+#[derive(Debug, Clone)]
+pub struct StaticFiles {
+    prefix: String,
+    dir: PathBuf,
+    /// Content types by lowercase extension, in place of [`content_type`].
+    types: HashMap<String, String>,
+    /// Whether paths without a file get the root `index.html`.
+    spa: bool,
+}
+
+impl StaticFiles {
+    /// Serves files from `dir` for requests under `prefix`.
+    pub fn new(prefix: &str, dir: impl Into<PathBuf>) -> Self {
+        StaticFiles {
+            prefix: prefix.trim_end_matches('/').to_string(),
+            dir: dir.into(),
+            types: HashMap::new(),
+            spa: false,
+        }
+    }
+
+    /// Serves files with the extension `extension` (with or without its
+    /// dot, in any case) as `content_type`, rather than as [`content_type`]
+    /// would have it.
+    pub fn mime_type(mut self, extension: &str, content_type: &str) -> Self {
+        let extension = extension.trim_start_matches('.').to_ascii_lowercase();
+        self.types.insert(extension, content_type.to_string());
+        self
+    }
+
+    /// Serves the directory's `index.html`, with a 200, for `GET` requests
+    /// under the prefix that name no file, so that a single-page app can
+    /// route them on the client.
+    pub fn spa(mut self, spa: bool) -> Self {
+        self.spa = spa;
+        self
+    }
+
+    /// The URL prefix files are served under, without a trailing slash.
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// The directory files are served from.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The file `request` names, or `None` for requests other than `GET`,
+    /// outside the prefix, or not naming a readable file inside the
+    /// directory, unless [`spa`](StaticFiles::spa) serves the root index
+    /// for the last. A directory without a trailing slash is redirected to
+    /// the path with one, so that links in its index resolve inside it.
+    /// Files are tagged with an `ETag` from their size and modification
+    /// time and with their `Last-Modified` time, answered with `304 Not
+    /// Modified` for an `If-None-Match` or `If-Modified-Since` they pass,
+    /// and served in part for a `Range` header, as
+    /// [`Response::conditional`] and [`Response::ranged`] have it.
+    pub fn serve(&self, request: &Request) -> Option<Response> {
+        if request.method != "GET" {
+            return None;
+        }
+        let rest = request.path.strip_prefix(&self.prefix)?;
+        if !(rest.is_empty() || rest.starts_with('/')) {
+            return None;
+        }
+        match self.file(request, rest) {
+            None if self.spa => self.file(request, "/"),
+            file => file,
+        }
+    }
+
+    /// The response to `request` for `rest`, the part of its path after the
+    /// prefix, if it names a file or directory. Only the part of the file
+    /// that is sent is read.
+    fn file(&self, request: &Request, rest: &str) -> Option<Response> {
+        let path = &request.path;
+        let relative = rest.trim_start_matches('/');
+        if escapes_root(relative) {
+            return None;
+        }
+        let mut file = self.resolve(relative)?;
+        if file.is_dir() {
+            if !rest.ends_with('/') {
+                let location = format!("{}/", percent_encode_path(path));
+                return Some(Response::new(301).header("Location", location));
+            }
+            file = self.resolve(Path::new(relative).join(INDEX))?;
+        }
+        let mut opened = File::open(&file).ok()?;
+        let metadata = opened.metadata().ok()?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        let etag = format!("\"{:x}-{:x}\"", modified.as_nanos(), metadata.len());
+        let response = Response::with_body(200, self.type_of(&file), Vec::new())
+            .header("ETag", etag)
+            .header("Last-Modified", http_date(modified.as_secs()))
+            .conditional(request);
+        if response.status != 200 {
+            return Some(response);
+        }
+        response
+            .ranged_from(request, metadata.len(), |start, count| {
+                let mut body = Vec::new();
+                opened.seek(SeekFrom::Start(start))?;
+                (&mut opened).take(count).read_to_end(&mut body)?;
+                Ok::<_, std::io::Error>(body)
+            })
+            .ok()
+    }
+
+    /// The content type `path` is served as.
+    fn type_of(&self, path: &Path) -> &str {
+        extension(path)
+            .and_then(|extension| self.types.get(&extension))
+            .map_or_else(|| content_type(path), String::as_str)
+    }
+
+    /// The canonical path of `relative` in the directory, if it exists and
+    /// is still inside the directory once symbolic links are followed.
+    fn resolve(&self, relative: impl AsRef<Path>) -> Option<PathBuf> {
+        let root = self.dir.canonicalize().ok()?;
+        let path = root.join(relative).canonicalize().ok()?;
+        path.starts_with(&root).then_some(path)
+    }
+}
+
+/// The media type for `path`, by its extension; `application/octet-stream`
+/// for extensions not listed.
+pub fn content_type(path: &Path) -> &'static str {
+    match extension(path).as_deref() {
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js" | "mjs") => "text/javascript; charset=utf-8",
+        Some("json" | "map") => "application/json",
+        Some("txt" | "md") => "text/plain; charset=utf-8",
+        Some("csv") => "text/csv; charset=utf-8",
+        Some("xml") => "application/xml",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("ico") => "image/x-icon",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("pdf") => "application/pdf",
+        Some("wasm") => "application/wasm",
+        Some("mp3") => "audio/mpeg",
+        Some("ogg") => "audio/ogg",
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        Some("zip") => "application/zip",
+        Some("gz") => "application/gzip",
+        _ => "application/octet-stream",
+    }
+}
+
+/// The extension of `path`, in lowercase.
+fn extension(path: &Path) -> Option<String> {
+    let extension = path.extension()?.to_str()?;
+    Some(extension.to_ascii_lowercase())
+}
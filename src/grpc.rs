@@ -0,0 +1,69 @@
+//! The gRPC server: the standard `grpc.health.v1.Health` service and a
+//! `rusty_server.v1.Stats` service reporting the same counters as the HTTP
+//! `/events` stream.
+//!
+//! It runs on its own listener next to the HTTP one, sharing the process-wide
+//! stats.
+
+use std::net::TcpListener;
+
+use tonic::{Request, Response, Status};
+
+use crate::{console, stats, ServerError};
+
+/// Generated messages, server, and client for `proto/stats.proto`.
+pub mod proto {
+    tonic::include_proto!("rusty_server.v1");
+}
+
+use proto::stats_server::{Stats, StatsServer};
+use proto::{GetStatsRequest, StatsReply};
+
+/// The `Stats` service.
+#[derive(Debug, Default)]
+pub struct StatsService;
+
+#[tonic::async_trait]
+impl Stats for StatsService {
+    async fn get_stats(
+        &self,
+        _request: Request<GetStatsRequest>,
+    ) -> Result<Response<StatsReply>, Status> {
+        let snapshot = stats::snapshot();
+        Ok(Response::new(StatsReply {
+            time: snapshot.time,
+            uptime_secs: snapshot.uptime_secs,
+            connections_total: snapshot.connections_total,
+            connections_active: snapshot.connections_active,
+        }))
+    }
+}
+
+/// Prints the startup table for `listener` and serves the gRPC services on it
+/// until it fails. Blocks the calling thread on its own Tokio runtime.
+pub fn serve(listener: TcpListener) -> Result<(), ServerError> {
+    let local_addr = listener.local_addr()?;
+    listener.set_nonblocking(true)?;
+    stats::start();
+
+    console::print_table(&[
+        ("Status", "gRPC Server Started".to_string()),
+        ("Address", format!("http://{}", local_addr)),
+    ]);
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::from_std(listener)?;
+        let (mut health, health_service) = tonic_health::server::health_reporter();
+        health.set_serving::<StatsServer<StatsService>>().await;
+
+        tonic::transport::Server::builder()
+            .add_service(health_service)
+            .add_service(StatsServer::new(StatsService))
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener))
+            .await
+            .map_err(|e| ServerError::Io(std::io::Error::other(e)))
+    })
+}
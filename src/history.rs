@@ -0,0 +1,247 @@
+//! A bounded history of the requests served, exported to CSV and JSON.
+//!
+//! [`start`] turns recording on; every finished connection is then kept as
+//! a [`Record`], up to the newest [`CAPACITY`]. [`export`] writes them to
+//! `history-<time>.csv` and `history-<time>.json` in the directory given to
+//! [`start`] and prints a summary table of the session.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::Duration;
+
+use crate::server::Exchange;
+use crate::{console, json_escape, stats, unix_timestamp, ServerError};
+
+/// Most requests kept; older ones are dropped as new ones come in.
+pub const CAPACITY: usize = 10_000;
+
+static HISTORY: OnceLock<Mutex<History>> = OnceLock::new();
+
+/// One finished connection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    /// Seconds since the Unix epoch.
+    pub time: u64,
+    pub peer: String,
+    /// Empty if the request could not be parsed.
+    pub method: String,
+    pub path: String,
+    /// The response status, or `None` if the connection failed.
+    pub status: Option<u16>,
+    pub bytes_read: usize,
+    pub elapsed: Duration,
+}
+
+#[derive(Debug)]
+struct History {
+    dir: PathBuf,
+    started: u64,
+    records: VecDeque<Record>,
+    /// Records dropped to stay within [`CAPACITY`].
+    dropped: u64,
+}
+
+/// The files written by [`export`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Export {
+    pub csv: PathBuf,
+    pub json: PathBuf,
+}
+
+/// Starts recording requests, for [`export`] to write to `dir`. Only the
+/// first call has an effect.
+pub fn start(dir: impl Into<PathBuf>) {
+    HISTORY.get_or_init(|| {
+        Mutex::new(History {
+            dir: dir.into(),
+            started: unix_timestamp(),
+            records: VecDeque::new(),
+            dropped: 0,
+        })
+    });
+}
+
+/// Keeps a finished connection, if recording is on.
+pub(crate) fn record(peer: &str, result: &Result<Exchange, ServerError>, elapsed: Duration) {
+    let Some(history) = HISTORY.get() else {
+        return;
+    };
+    let record = match result {
+        Ok(exchange) => Record {
+            time: unix_timestamp(),
+            peer: peer.to_string(),
+            method: exchange
+                .request
+                .as_ref()
+                .map(|request| request.method.clone())
+                .unwrap_or_default(),
+            path: exchange.path.clone(),
+            status: Some(exchange.status),
+            bytes_read: exchange.bytes_read,
+            elapsed,
+        },
+        Err(e) => Record {
+            time: unix_timestamp(),
+            peer: peer.to_string(),
+            method: String::new(),
+            path: e.to_string(),
+            status: None,
+            bytes_read: 0,
+            elapsed,
+        },
+    };
+    let mut history = history.lock().unwrap_or_else(PoisonError::into_inner);
+    if history.records.len() == CAPACITY {
+        history.records.pop_front();
+        history.dropped += 1;
+    }
+    history.records.push_back(record);
+}
+
+/// The requests recorded so far, oldest first.
+pub fn records() -> Vec<Record> {
+    HISTORY.get().map_or_else(Vec::new, |history| {
+        let history = history.lock().unwrap_or_else(PoisonError::into_inner);
+        history.records.iter().cloned().collect()
+    })
+}
+
+/// Writes the recorded requests to a CSV and a JSON file and prints a
+/// summary of them. Fails if recording was never started.
+pub fn export() -> io::Result<Export> {
+    let Some(history) = HISTORY.get() else {
+        return Err(io::Error::other(
+            "the request history is not being recorded",
+        ));
+    };
+    let (dir, started, records, dropped) = {
+        let history = history.lock().unwrap_or_else(PoisonError::into_inner);
+        let records: Vec<Record> = history.records.iter().cloned().collect();
+        (
+            history.dir.clone(),
+            history.started,
+            records,
+            history.dropped,
+        )
+    };
+
+    fs::create_dir_all(&dir)?;
+    let name = format!("history-{}", unix_timestamp());
+    let export = Export {
+        csv: dir.join(format!("{}.csv", name)),
+        json: dir.join(format!("{}.json", name)),
+    };
+    fs::write(&export.csv, to_csv(&records))?;
+    fs::write(&export.json, to_json(&records))?;
+    print_summary(started, &records, dropped, &export);
+    Ok(export)
+}
+
+/// `records` as CSV, under a header row.
+pub fn to_csv(records: &[Record]) -> String {
+    let mut csv = String::from("time,peer,method,path,status,bytes_read,latency_ms\n");
+    for record in records {
+        let status = record
+            .status
+            .map(|status| status.to_string())
+            .unwrap_or_default();
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{:.3}\n",
+            record.time,
+            csv_field(&record.peer),
+            csv_field(&record.method),
+            csv_field(&record.path),
+            status,
+            record.bytes_read,
+            record.elapsed.as_secs_f64() * 1000.0
+        ));
+    }
+    csv
+}
+
+/// `records` as a JSON array of objects; `status` is `null` for failed
+/// connections.
+pub fn to_json(records: &[Record]) -> String {
+    let objects: Vec<String> = records
+        .iter()
+        .map(|record| {
+            let status = record
+                .status
+                .map_or_else(|| "null".to_string(), |status| status.to_string());
+            format!(
+                r#"{{"time":{},"peer":"{}","method":"{}","path":"{}","status":{},"bytes_read":{},"latency_ms":{:.3}}}"#,
+                record.time,
+                json_escape(&record.peer),
+                json_escape(&record.method),
+                json_escape(&record.path),
+                status,
+                record.bytes_read,
+                record.elapsed.as_secs_f64() * 1000.0
+            )
+        })
+        .collect();
+    format!("[{}]\n", objects.join(",\n"))
+}
+
+/// `field`, quoted if it holds a comma, a quote, or a line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn print_summary(started: u64, records: &[Record], dropped: u64, export: &Export) {
+    let mut statuses: BTreeMap<String, u64> = BTreeMap::new();
+    for record in records {
+        let status = record
+            .status
+            .map_or_else(|| "failed".to_string(), |status| status.to_string());
+        *statuses.entry(status).or_default() += 1;
+    }
+    let statuses: Vec<String> = statuses
+        .iter()
+        .map(|(status, count)| format!("{} ×{}", status, count))
+        .collect();
+    let latency = match records.len() {
+        0 => Duration::ZERO,
+        n => {
+            records
+                .iter()
+                .map(|record| record.elapsed)
+                .sum::<Duration>()
+                / n as u32
+        }
+    };
+    let mut requests = records.len().to_string();
+    if dropped > 0 {
+        requests.push_str(&format!(" (and {} older ones dropped)", dropped));
+    }
+    console::print_table(&[
+        (
+            "History",
+            format!("{} s", unix_timestamp().saturating_sub(started)),
+        ),
+        ("Uptime", format!("{} s", stats::uptime().as_secs())),
+        ("Requests", requests),
+        ("Statuses", statuses.join(", ")),
+        (
+            "Mean latency",
+            format!("{:.1} ms", latency.as_secs_f64() * 1000.0),
+        ),
+        (
+            "Bytes read",
+            records
+                .iter()
+                .map(|record| record.bytes_read)
+                .sum::<usize>()
+                .to_string(),
+        ),
+        ("CSV", export.csv.display().to_string()),
+        ("JSON", export.json.display().to_string()),
+    ]);
+}
@@ -0,0 +1,766 @@
+//! HTTP/1.1 messages: request-head parsing following the message syntax of
+//! RFC 9112, and the response type handlers produce.
+//!
+//! The parser is deliberately strict: anything the RFC allows a server to
+//! reject (obsolete line folding, bare LF line endings, conflicting framing
+//! headers) is rejected with the status code the RFC recommends, so that the
+//! server never guesses where one request ends and the next begins.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::net::IpAddr;
+
+use serde::Serialize;
+
+/// Largest request head (request line plus headers) the server will read:
+/// enough for browsers sending large cookies.
+pub const MAX_HEAD_SIZE: usize = 8 * 1024;
+
+/// Largest request body the server will read for a routed handler.
+pub const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// Protocol versions the server speaks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Version::Http10 => f.write_str("HTTP/1.0"),
+            Version::Http11 => f.write_str("HTTP/1.1"),
+        }
+    }
+}
+
+/// A parsed request: its head, and as much of the body as has been read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    /// The request target exactly as it appeared on the request line.
+    pub target: String,
+    /// The percent-decoded path component of the target, without the query
+    /// string.
+    pub path: String,
+    pub version: Version,
+    /// Header fields in the order received, names as sent by the client.
+    pub headers: Vec<(String, String)>,
+    /// The body, up to `Content-Length`. Routed handlers get all of it;
+    /// streaming handlers only get what arrived along with the head, and read
+    /// the rest from their connection.
+    pub body: Vec<u8>,
+    /// Whether the request arrived over TLS.
+    pub secure: bool,
+    /// The common name in the certificate the client presented, when the
+    /// listener requires one.
+    pub client_cn: Option<String>,
+    /// The client's IP address, when the request arrived on a listener;
+    /// `None` for requests served over other streams or parsed directly.
+    pub peer: Option<IpAddr>,
+    /// What the route's pattern captured from `path`, by parameter name, in
+    /// pattern order: `code` for `/status/:code`. Empty for exact routes.
+    pub params: Vec<(String, String)>,
+    /// The ID the request is logged under and answered with in
+    /// `X-Request-Id`: the client's own, if it sent a usable one, and
+    /// otherwise one the server generated. Empty until the server assigns it.
+    pub id: String,
+}
+
+impl Request {
+    /// Returns the value the route's pattern captured for the parameter
+    /// `name`.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the first value of the header `name`, compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The body length declared by `Content-Length`, or 0 without one.
+    ///
+    /// Parsing already rejected invalid and conflicting values.
+    pub fn content_length(&self) -> u64 {
+        self.header("Content-Length")
+            .and_then(|value| value.split(',').next())
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(0)
+    }
+
+    /// Whether the client wants the connection kept open after the response:
+    /// by default on HTTP/1.1 unless it sends `Connection: close`, and on
+    /// HTTP/1.0 only if it sends `Connection: keep-alive`.
+    pub fn keeps_alive(&self) -> bool {
+        let has_option = |option: &str| {
+            self.header_all("Connection")
+                .flat_map(|value| value.split(','))
+                .any(|token| token.trim().eq_ignore_ascii_case(option))
+        };
+        match self.version {
+            Version::Http11 => !has_option("close"),
+            Version::Http10 => has_option("keep-alive"),
+        }
+    }
+
+    /// Whether the body is sent with the chunked transfer coding.
+    pub fn is_chunked(&self) -> bool {
+        self.header("Transfer-Encoding")
+            .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked"))
+    }
+
+    /// Returns every value of the header `name`, compared case-insensitively.
+    pub fn header_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.headers
+            .iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Reasons a request head is rejected, each tied to a response status.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The head is syntactically invalid (400).
+    BadRequest(&'static str),
+    /// The request line alone does not fit in the head size limit (414).
+    UriTooLong,
+    /// A request to a route taking a body has no `Content-Length` (411).
+    LengthRequired,
+    /// The body is larger than the server accepts (413).
+    ContentTooLarge,
+    /// The header section does not fit in the head size limit (431).
+    HeaderFieldsTooLarge,
+    /// The request uses a feature the server does not implement (501).
+    NotImplemented(&'static str),
+    /// The request uses an HTTP major version other than 1 (505).
+    VersionNotSupported,
+}
+
+impl ParseError {
+    /// Status code and reason phrase to answer this error with.
+    pub fn status(&self) -> (u16, &'static str) {
+        match self {
+            ParseError::BadRequest(_) => (400, "Bad Request"),
+            ParseError::UriTooLong => (414, "URI Too Long"),
+            ParseError::LengthRequired => (411, "Length Required"),
+            ParseError::ContentTooLarge => (413, "Content Too Large"),
+            ParseError::HeaderFieldsTooLarge => (431, "Request Header Fields Too Large"),
+            ParseError::NotImplemented(_) => (501, "Not Implemented"),
+            ParseError::VersionNotSupported => (505, "HTTP Version Not Supported"),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::BadRequest(reason) => write!(f, "malformed request: {}", reason),
+            ParseError::UriTooLong => f.write_str("request line exceeds the size limit"),
+            ParseError::LengthRequired => f.write_str("request body has no Content-Length"),
+            ParseError::ContentTooLarge => f.write_str("request body exceeds the size limit"),
+            ParseError::HeaderFieldsTooLarge => {
+                f.write_str("request headers exceed the size limit")
+            }
+            ParseError::NotImplemented(what) => write!(f, "not implemented: {}", what),
+            ParseError::VersionNotSupported => f.write_str("unsupported HTTP version"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A response produced by a handler, serialized by [`Response::write_to`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    /// Header fields other than `Content-Length`, which is derived from the
+    /// body (and omitted for 1xx, 204, and 304 responses, which cannot have
+    /// one).
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// An empty response with the given status.
+    pub fn new(status: u16) -> Self {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// A response with `body` served as `content_type`.
+    pub fn with_body(status: u16, content_type: &str, body: impl Into<Vec<u8>>) -> Self {
+        Response::new(status)
+            .header("Content-Type", content_type)
+            .body(body)
+    }
+
+    pub fn html(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        Response::with_body(status, "text/html", body)
+    }
+
+    pub fn text(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        Response::with_body(status, "text/plain; charset=utf-8", body)
+    }
+
+    /// A response with `value` serialized as its JSON body; the 500 page if
+    /// `value` cannot be, such as a map with keys that are not strings.
+    /// Use [`with_body`](Response::with_body) for JSON that is already
+    /// serialized.
+    pub fn json<T: Serialize + ?Sized>(status: u16, value: &T) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(body) => Response::with_body(status, "application/json", body),
+            Err(e) => crate::pages::error(500, "Internal Server Error", &e.to_string()),
+        }
+    }
+
+    /// Adds a header field.
+    pub fn header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.headers.push((name.to_string(), value.into()));
+        self
+    }
+
+    /// Replaces the body.
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Returns the first value of the header `name`, compared case-insensitively.
+    pub fn header_value(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Tags a `200` response to `GET` `request` with an `ETag`, a hash of
+    /// its body unless it has one already, and turns it into `304 Not
+    /// Modified`, without the body, if the request's `If-None-Match` names
+    /// that tag, or, for a request without one, if the response's
+    /// `Last-Modified` is no later than its `If-Modified-Since` (RFC 9110
+    /// section 13.2.2). Other responses are left alone.
+    pub fn conditional(self, request: &Request) -> Response {
+        if self.status != 200 || request.method != "GET" {
+            return self;
+        }
+        let response = match self.header_value("ETag") {
+            Some(_) => self,
+            None => {
+                let etag = format!("\"{:016x}\"", fnv1a(&self.body));
+                self.header("ETag", etag)
+            }
+        };
+        let etag = response.header_value("ETag").unwrap_or_default();
+        let unchanged = match request.header("If-None-Match") {
+            Some(tags) => none_match(tags, etag),
+            None => request
+                .header("If-Modified-Since")
+                .and_then(parse_http_date)
+                .zip(response.header_value("Last-Modified").and_then(parse_http_date))
+                .is_some_and(|(since, modified)| modified <= since),
+        };
+        if !unchanged {
+            return response;
+        }
+        let mut unmodified = Response::new(304);
+        unmodified.headers = response
+            .headers
+            .into_iter()
+            .filter(|(name, _)| !name.eq_ignore_ascii_case("Content-Type"))
+            .collect();
+        unmodified
+    }
+
+    /// Narrows a `200` response to `GET` `request` to the byte range its
+    /// `Range` header asks for: `206 Partial Content` with a
+    /// `Content-Range`, or `416 Range Not Satisfiable` if the range lies
+    /// past the end of the body. Other responses, requests without a
+    /// `Range`, and ranges this does not handle (several at once, or units
+    /// other than bytes) get the whole body, as RFC 9110 allows. Either way
+    /// the response says it accepts ranges.
+    pub fn ranged(mut self, request: &Request) -> Response {
+        if self.status != 200 || request.method != "GET" {
+            return self;
+        }
+        let body = std::mem::take(&mut self.body);
+        let len = body.len() as u64;
+        self.ranged_from(request, len, |start, count| {
+            Ok::<_, std::convert::Infallible>(body[start as usize..(start + count) as usize].to_vec())
+        })
+        .unwrap_or_else(|never| match never {})
+    }
+
+    /// [`ranged`](Self::ranged) for a `200` response to `GET` `request`
+    /// whose body of `len` bytes has not been read yet: `read` is given the
+    /// offset and length of the bytes to send, and reads only those, so
+    /// that part of a large file costs no more than that part.
+    pub(crate) fn ranged_from<E>(
+        self,
+        request: &Request,
+        len: u64,
+        read: impl FnOnce(u64, u64) -> Result<Vec<u8>, E>,
+    ) -> Result<Response, E> {
+        let response = self.header("Accept-Ranges", "bytes");
+        Ok(match request.header("Range").and_then(|range| byte_range(range, len)) {
+            None => response.body(read(0, len)?),
+            Some(Some((first, last))) => {
+                let mut partial = Response::new(206);
+                partial.headers = response.headers;
+                partial.body = read(first, last - first + 1)?;
+                partial.header("Content-Range", format!("bytes {}-{}/{}", first, last, len))
+            }
+            Some(None) => {
+                let message = format!("The range is past the end of the {} bytes.", len);
+                crate::pages::error_for(request, 416, "Range Not Satisfiable", &message)
+                    .header("Accept-Ranges", "bytes")
+                    .header("Content-Range", format!("bytes */{}", len))
+            }
+        })
+    }
+
+    /// Reason phrase for the status code.
+    pub fn reason(&self) -> &'static str {
+        reason_phrase(self.status)
+    }
+
+    /// Writes the status line, headers, and body to `writer`.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut head = self.head();
+        if self.status >= 200 && self.status != 204 && self.status != 304 {
+            head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        }
+        head.push_str("\r\n");
+
+        // One write, so the head and a small body leave in a single segment.
+        let mut message = head.into_bytes();
+        message.extend_from_slice(&self.body);
+        writer.write_all(&message)
+    }
+
+    /// Writes the status line and headers, without `Content-Length`, for a
+    /// body that is streamed afterwards and ends when the connection closes.
+    pub fn write_head_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut head = self.head();
+        head.push_str("\r\n");
+        writer.write_all(head.as_bytes())
+    }
+
+    fn head(&self) -> String {
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, self.reason().to_uppercase());
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head
+    }
+}
+
+/// Standard reason phrase for `status` (RFC 9110 section 15).
+pub fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        100 => "Continue",
+        101 => "Switching Protocols",
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        304 => "Not Modified",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        406 => "Not Acceptable",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        411 => "Length Required",
+        413 => "Content Too Large",
+        414 => "URI Too Long",
+        415 => "Unsupported Media Type",
+        416 => "Range Not Satisfiable",
+        426 => "Upgrade Required",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        505 => "HTTP Version Not Supported",
+        _ => "Unknown",
+    }
+}
+
+/// Parses the request head at the start of `buf`, allowing heads of up to
+/// [`MAX_HEAD_SIZE`] bytes.
+pub fn parse_request(buf: &[u8]) -> Result<Request, ParseError> {
+    parse_request_with_limit(buf, MAX_HEAD_SIZE)
+}
+
+/// Parses the request head at the start of `buf`.
+///
+/// `buf` holds everything read from the connection so far. A buffer that does
+/// not contain the blank line terminating the head is rejected: as 414 or 431
+/// when it has already reached `max_head_size`, as 400 otherwise.
+pub fn parse_request_with_limit(buf: &[u8], max_head_size: usize) -> Result<Request, ParseError> {
+    let head_end = match find(buf, b"\r\n\r\n") {
+        Some(end) => end,
+        None if buf.len() >= max_head_size => {
+            return Err(match find(buf, b"\r\n") {
+                Some(_) => ParseError::HeaderFieldsTooLarge,
+                None => ParseError::UriTooLong,
+            });
+        }
+        None => return Err(ParseError::BadRequest("incomplete request head")),
+    };
+    let head = &buf[..head_end];
+    if head.iter().enumerate().any(|(i, &b)| {
+        (b == b'\r' && head.get(i + 1) != Some(&b'\n'))
+            || (b == b'\n' && (i == 0 || head[i - 1] != b'\r'))
+    }) {
+        return Err(ParseError::BadRequest("line not terminated by CRLF"));
+    }
+
+    let mut lines = head
+        .split(|&b| b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line));
+    let request_line = lines.next().unwrap_or_default();
+    let (method, target, version) = parse_request_line(request_line)?;
+
+    let mut headers = Vec::new();
+    for line in lines {
+        headers.push(parse_header_line(line)?);
+    }
+
+    let path = origin_path(&method, &target)?;
+    let mut request = Request {
+        method,
+        target,
+        path,
+        version,
+        headers,
+        body: Vec::new(),
+        secure: false,
+        client_cn: None,
+        peer: None,
+        params: Vec::new(),
+        id: String::new(),
+    };
+    check_host(&request)?;
+    check_framing(&request)?;
+    let body = &buf[head_end + 4..];
+    let length = body.len().min(request.content_length().try_into().unwrap_or(usize::MAX));
+    request.body = body[..length].to_vec();
+    Ok(request)
+}
+
+fn parse_request_line(line: &[u8]) -> Result<(String, String, Version), ParseError> {
+    let line = std::str::from_utf8(line)
+        .map_err(|_| ParseError::BadRequest("request line is not ASCII"))?;
+    let mut parts = line.split(' ');
+    let (Some(method), Some(target), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(ParseError::BadRequest(
+            "request line is not `method target version`",
+        ));
+    };
+
+    if method.is_empty() || !method.bytes().all(is_token_byte) {
+        return Err(ParseError::BadRequest("invalid method"));
+    }
+    if target.is_empty() || !target.bytes().all(is_target_byte) {
+        return Err(ParseError::BadRequest("invalid request target"));
+    }
+    let version = match version {
+        "HTTP/1.1" => Version::Http11,
+        "HTTP/1.0" => Version::Http10,
+        // Later 1.x minor versions are backwards compatible with 1.1.
+        v if v.starts_with("HTTP/1.") && is_http_version(v) => Version::Http11,
+        v if is_http_version(v) => return Err(ParseError::VersionNotSupported),
+        _ => return Err(ParseError::BadRequest("invalid HTTP version")),
+    };
+    Ok((method.to_string(), target.to_string(), version))
+}
+
+fn parse_header_line(line: &[u8]) -> Result<(String, String), ParseError> {
+    if line.first().is_some_and(|&b| b == b' ' || b == b'\t') {
+        return Err(ParseError::BadRequest("obsolete line folding"));
+    }
+    let colon = line
+        .iter()
+        .position(|&b| b == b':')
+        .ok_or(ParseError::BadRequest("header line without colon"))?;
+    let (name, value) = (&line[..colon], &line[colon + 1..]);
+    if name.is_empty() || !name.iter().copied().all(is_token_byte) {
+        return Err(ParseError::BadRequest("invalid header name"));
+    }
+    if value.iter().any(|&b| (b < 0x20 && b != b'\t') || b == 0x7f) {
+        return Err(ParseError::BadRequest("control character in header value"));
+    }
+    let value = String::from_utf8_lossy(value)
+        .trim_matches([' ', '\t'])
+        .to_string();
+    Ok((String::from_utf8_lossy(name).into_owned(), value))
+}
+
+/// Derives the path from the request target (RFC 9112 section 3.2).
+fn origin_path(method: &str, target: &str) -> Result<String, ParseError> {
+    let path_and_query = if target.starts_with('/') {
+        target
+    } else if target == "*" && method == "OPTIONS" {
+        return Ok("*".to_string());
+    } else if let Some(rest) = target
+        .strip_prefix("http://")
+        .or_else(|| target.strip_prefix("https://"))
+    {
+        match rest.find('/') {
+            Some(slash) => &rest[slash..],
+            None => "/",
+        }
+    } else {
+        return Err(ParseError::BadRequest(
+            "request target is not origin- or absolute-form",
+        ));
+    };
+    let path = path_and_query.split(['?', '#']).next().unwrap_or("/");
+    percent_decode(path).ok_or(ParseError::BadRequest("invalid percent-encoding in path"))
+}
+
+/// Adds `field` to the `Vary` header of `response`, unless it is listed
+/// there already or the header is `*`.
+pub(crate) fn vary(response: &mut Response, field: &str) {
+    let existing = response
+        .headers
+        .iter_mut()
+        .find(|(name, _)| name.eq_ignore_ascii_case("Vary"));
+    match existing {
+        Some((_, value)) => {
+            let listed = value
+                .split(',')
+                .map(str::trim)
+                .any(|listed| listed == "*" || listed.eq_ignore_ascii_case(field));
+            if !listed {
+                value.push_str(", ");
+                value.push_str(field);
+            }
+        }
+        None => response.headers.push(("Vary".to_string(), field.to_string())),
+    }
+}
+
+/// `time`, in seconds since the Unix epoch, as an HTTP date such as
+/// `Thu, 15 Oct 2026 15:03:02 GMT` (RFC 9110 section 5.6.7).
+pub fn http_date(time: u64) -> String {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    let days = time / 86_400;
+    let (year, month, day) = crate::console::civil_date(days);
+    let seconds = time % 86_400;
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[(days % 7) as usize],
+        day,
+        crate::console::MONTHS[month as usize - 1],
+        year,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// The seconds since the Unix epoch of the HTTP date `date`, in the
+/// preferred format [`http_date`] writes; `None` for the obsolete formats
+/// and anything else.
+pub fn parse_http_date(date: &str) -> Option<u64> {
+    let fields: Vec<&str> = date.trim().split(' ').collect();
+    let [_weekday, day, month, year, time, "GMT"] = fields[..] else {
+        return None;
+    };
+    let number = |s: &str, len: usize| {
+        if s.len() == len && s.bytes().all(|b| b.is_ascii_digit()) {
+            s.parse::<u64>().ok()
+        } else {
+            None
+        }
+    };
+    let month = crate::console::MONTHS.iter().position(|name| *name == month)? as u64 + 1;
+    let (day, year) = (number(day, 2)?, number(year, 4)?);
+    let mut clock = time.split(':').map(|field| number(field, 2));
+    let (hours, minutes, seconds) = (clock.next()??, clock.next()??, clock.next()??);
+    if clock.next().is_some() || day == 0 || day > 31 || hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+    let days = crate::console::days_from_civil(year, month, day)?;
+    Some(days * 86_400 + hours * 3600 + minutes * 60 + seconds)
+}
+
+/// Whether the `If-None-Match` header value `tags` names `etag`, by the weak
+/// comparison: `W/"a"` matches `"a"`.
+fn none_match(tags: &str, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    tags.trim() == "*" || tags.split(',').any(|tag| opaque(tag) == opaque(etag))
+}
+
+/// The 64-bit FNV-1a hash of `bytes`, which stays the same from one build
+/// to the next, unlike the standard library's hasher.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// The first and last byte that the `Range` header value `range` asks for
+/// of a body of `len` bytes: `None` if it is not a single byte range,
+/// `Some(None)` if that range is not satisfiable (RFC 9110 section 14.1.2).
+fn byte_range(range: &str, len: u64) -> Option<Option<(u64, u64)>> {
+    let spec = range.trim().strip_prefix("bytes=")?.trim();
+    if spec.contains(',') {
+        return None;
+    }
+    let (first, last) = spec.split_once('-')?;
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if first.is_empty() {
+        // A suffix: the last `last` bytes.
+        let suffix: u64 = last.parse().ok().filter(|_| digits(last))?;
+        return Some((suffix > 0 && len > 0).then(|| (len.saturating_sub(suffix), len - 1)));
+    }
+    let first: u64 = first.parse().ok().filter(|_| digits(first))?;
+    let last = match last {
+        "" => u64::MAX,
+        last => last.parse().ok().filter(|_| digits(last))?,
+    };
+    if last < first {
+        return None;
+    }
+    Some((first < len).then(|| (first, last.min(len - 1))))
+}
+
+/// Decodes `%XX` escapes in `input`.
+///
+/// Returns `None` when an escape is truncated or not hexadecimal, or when the
+/// decoded bytes are not valid UTF-8.
+pub fn percent_decode(input: &str) -> Option<String> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3)?;
+            let high = (hex[0] as char).to_digit(16)?;
+            let low = (hex[1] as char).to_digit(16)?;
+            decoded.push((high * 16 + low) as u8);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Percent-encodes every byte of `input` outside the RFC 3986 unreserved set,
+/// leaving `/` as-is so the path segments stay intact.
+pub fn percent_encode_path(input: &str) -> String {
+    let mut encoded = String::with_capacity(input.len());
+    for b in input.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~/".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+fn check_host(request: &Request) -> Result<(), ParseError> {
+    match request.header_all("Host").count() {
+        0 if request.version == Version::Http11 => {
+            Err(ParseError::BadRequest("missing Host header"))
+        }
+        0 | 1 => Ok(()),
+        _ => Err(ParseError::BadRequest("multiple Host headers")),
+    }
+}
+
+/// Rejects requests whose body length cannot be determined unambiguously
+/// (RFC 9112 section 6.3).
+fn check_framing(request: &Request) -> Result<(), ParseError> {
+    let mut content_length = None;
+    for value in request.header_all("Content-Length") {
+        for item in value.split(',') {
+            let item = item.trim();
+            if item.is_empty() || !item.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(ParseError::BadRequest("invalid Content-Length"));
+            }
+            let length: u64 = item
+                .parse()
+                .map_err(|_| ParseError::BadRequest("invalid Content-Length"))?;
+            if content_length.is_some_and(|previous| previous != length) {
+                return Err(ParseError::BadRequest("conflicting Content-Length values"));
+            }
+            content_length = Some(length);
+        }
+    }
+
+    if request.header("Transfer-Encoding").is_some() {
+        if content_length.is_some() {
+            return Err(ParseError::BadRequest(
+                "both Transfer-Encoding and Content-Length",
+            ));
+        }
+        // Only chunked alone is decoded; other codings (gzip, and chunked
+        // layered over them) are not.
+        if request.header_all("Transfer-Encoding").count() > 1 || !request.is_chunked() {
+            return Err(ParseError::NotImplemented("Transfer-Encoding"));
+        }
+    }
+    Ok(())
+}
+
+/// Length of the request head at the start of `buf`, including the blank
+/// line that ends it, or `None` if the head is incomplete.
+pub fn head_len(buf: &[u8]) -> Option<usize> {
+    find(buf, b"\r\n\r\n").map(|end| end + 4)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// `tchar` from RFC 9110 section 5.6.2.
+fn is_token_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+/// Characters permitted in a request target by RFC 3986.
+fn is_target_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"-._~:/?#[]@!$&'()*+,;=%".contains(&b)
+}
+
+fn is_http_version(version: &str) -> bool {
+    let bytes = version.as_bytes();
+    bytes.len() == 8
+        && version.starts_with("HTTP/")
+        && bytes[5].is_ascii_digit()
+        && bytes[6] == b'.'
+        && bytes[7].is_ascii_digit()
+}
@@ -0,0 +1,170 @@
+//! A small HTTP/1.1 status server.
+//!
+//! The public API is organised as:
+//!
+//! - [`http`]: request parsing and the [`Response`] type
+//! - [`router`]: mapping methods and paths to handlers
+//! - [`server`]: the listener and the transport-independent connection cycle
+//! - [`openapi`]: the OpenAPI document of a router, and Swagger UI for it
+//! - [`admin`]: JSON endpoints with the server's stats, connections, and
+//!   routes
+//! - [`plugin`]: the C ABI for handler plugins, and loading them from shared
+//!   libraries (with the `plugins` feature)
+//! - [`sse`]: Server-Sent Events framing and event streams
+//! - [`stream`]: streaming handlers, which read the request body and write
+//!   the response as it is produced
+//! - [`cgi`]: executing CGI scripts from a directory
+//! - [`fastcgi`]: relaying requests to FastCGI backends such as PHP-FPM
+//! - [`files`]: serving static files from a directory
+//! - [`pages`]: the built-in pages and error pages, and operators' own error
+//!   pages in their place
+//! - [`negotiate`]: picking the response format a request's `Accept` header
+//!   prefers
+//! - [`cors`]: cross-origin resource sharing, as middleware
+//! - [`ratelimit`]: per-client rate limiting with token buckets, as
+//!   middleware
+//! - [`console`]: the console reports, as tables or as NDJSON
+//! - [`access`]: an access log in the Combined Log Format
+//! - [`logfile`]: log files rotated by size or time, keeping a number of
+//!   old ones
+//! - [`metrics`]: the counters and latency histograms served at `/metrics`
+//! - [`check`]: probing a running server's endpoints
+//! - [`repl`]: an interactive admin console on stdin
+//! - [`echo`]: a raw TCP echo listener that hex-dumps what clients send
+//! - [`client`]: a pooled HTTP/1.1 client for the server's own outgoing
+//!   requests (`https://` with the `tls` feature)
+//! - `script`: routes written in Rhai scripts, reloadable at run time (with
+//!   the `scripting` feature)
+//! - `tui`: a live terminal dashboard of the server's traffic (with the `tui`
+//!   feature)
+//! - `websocket`: the WebSocket handshake, frame codec, and sessions (with the
+//!   `websocket` feature)
+//! - `mdns`: advertising the server on the local network (with the `mdns`
+//!   feature)
+//! - `registry`: registering with Consul or etcd (with the `registry`
+//!   feature)
+//! - `webhook`: signed JSON event notifications to configured URLs (with the
+//!   `webhooks` feature)
+//! - `grpc`: the gRPC Health and Stats services (with the `grpc` feature)
+//! - `tls`: serving HTTPS with a certificate and key (with the `tls`
+//!   feature)
+//! - `acme`: certificates from Let's Encrypt or another ACME certificate
+//!   authority, renewed in the background (with the `acme` feature)
+//! - `history`: a bounded history of the requests served, exported to CSV
+//!   and JSON (with the `history` feature)
+//! - `otel`: OpenTelemetry request spans, exported over OTLP/HTTP (with the
+//!   `otel` feature)
+//! - `compress`: gzip, Brotli, and zstd compression of response bodies, as
+//!   middleware (with the `compression`, `brotli`, and `zstd` features)
+//! - `auth`: HTTP Basic authentication for path prefixes, as middleware
+//!   (with the `auth` feature)
+//! - `trace`: connection and request spans, and reporting through a tracing
+//!   subscriber (with the `tracing` feature)
+//!
+//! The most used items are re-exported at the crate root. The binary in
+//! `main.rs` only binds the listener and hands it to [`serve`], which lets the
+//! integration tests run the exact same code on an ephemeral port.
+
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod access;
+#[cfg(feature = "acme")]
+pub mod acme;
+pub mod admin;
+#[cfg(feature = "auth")]
+pub mod auth;
+pub mod cgi;
+pub mod check;
+pub mod client;
+#[cfg(feature = "compression")]
+pub mod compress;
+pub mod console;
+pub mod cors;
+pub mod echo;
+pub mod fastcgi;
+pub mod files;
+#[cfg(feature = "history")]
+pub mod history;
+pub mod http;
+pub mod logfile;
+#[cfg(feature = "mdns")]
+pub mod mdns;
+pub mod metrics;
+pub mod negotiate;
+pub mod openapi;
+#[cfg(feature = "otel")]
+pub mod otel;
+pub mod pages;
+pub mod plugin;
+pub mod ratelimit;
+#[cfg(feature = "registry")]
+pub mod registry;
+pub mod repl;
+pub mod router;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod server;
+pub mod sse;
+pub mod stream;
+#[cfg(feature = "tls")]
+pub mod tls;
+#[cfg(feature = "tracing")]
+pub mod trace;
+#[cfg(feature = "tui")]
+pub mod tui;
+#[cfg(feature = "webhooks")]
+pub mod webhook;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+
+mod config;
+mod endpoints;
+mod error;
+mod pool;
+mod stats;
+
+pub use error::ServerError;
+pub use http::{Request, Response};
+pub use router::{Fallback, Handler, Middleware, Router};
+pub use server::{
+    handle_connection, serve, serve_all, serve_connection, serve_with, Config, Exchange, Runtime,
+    Server, ServerBuilder, Shedding,
+};
+#[cfg(feature = "tokio")]
+pub use server::{handle_connection_async, serve_connection_async};
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Escapes `value` for use inside a JSON string literal.
+pub(crate) fn json_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// 64 bits that neither repeat within the process nor reveal how many were
+/// drawn before: a counter hashed under a key chosen at random at startup.
+pub(crate) fn unique_u64() -> u64 {
+    static KEY: OnceLock<RandomState> = OnceLock::new();
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    KEY.get_or_init(RandomState::new).hash_one(NEXT.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Seconds since the Unix epoch, or 0 if the system clock is set before it.
+pub(crate) fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,205 @@
+//! Log files that rotate by size or time.
+//!
+//! A [`LogFile`] appends to one path, such as `logs/server.log`, and once
+//! it is due for [`Rotation`] renames it to `server.log.1`, shifting older
+//! files to `server.log.2` and so on, and starts afresh. Only the newest
+//! `keep` rotated files are kept, so a long-running server cannot fill the
+//! disk with its logs.
+
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::UNIX_EPOCH;
+
+use crate::unix_timestamp;
+
+/// When a [`LogFile`] starts a new file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Rotation {
+    /// Never: the file grows for as long as the server runs.
+    #[default]
+    Never,
+    /// Before a write would take the file past this many bytes.
+    Size(u64),
+    /// At the first write of every day, in UTC.
+    Daily,
+    /// At the first write of every hour.
+    Hourly,
+}
+
+/// A [`Rotation`] other than `never`, `daily`, `hourly`, or a size such as
+/// `10M`.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("unknown log rotation `{0}` (expected `never`, `daily`, `hourly`, or a size such as `512K`, `10M`, or `1G`)")]
+pub struct UnknownRotation(pub String);
+
+impl FromStr for Rotation {
+    type Err = UnknownRotation;
+
+    /// Parses `never`, `daily`, `hourly`, or a size in bytes with an
+    /// optional `K`, `M`, or `G` suffix for KiB, MiB, or GiB.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let unknown = || UnknownRotation(name.to_string());
+        match name {
+            "never" => return Ok(Rotation::Never),
+            "daily" => return Ok(Rotation::Daily),
+            "hourly" => return Ok(Rotation::Hourly),
+            _ => {}
+        }
+        let (digits, unit) = match name.char_indices().last() {
+            Some((i, 'K' | 'k')) => (&name[..i], 1 << 10),
+            Some((i, 'M' | 'm')) => (&name[..i], 1 << 20),
+            Some((i, 'G' | 'g')) => (&name[..i], 1 << 30),
+            _ => (name, 1),
+        };
+        let size: u64 = digits.parse().map_err(|_| unknown())?;
+        match size.checked_mul(unit) {
+            Some(size) if size > 0 => Ok(Rotation::Size(size)),
+            _ => Err(unknown()),
+        }
+    }
+}
+
+impl fmt::Display for Rotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rotation::Never => f.write_str("never"),
+            Rotation::Size(size) => write!(f, "{} bytes", size),
+            Rotation::Daily => f.write_str("daily"),
+            Rotation::Hourly => f.write_str("hourly"),
+        }
+    }
+}
+
+impl Rotation {
+    /// The period `time`, in seconds since the Unix epoch, falls in; files
+    /// are rotated when it changes.
+    fn period(self, time: u64) -> u64 {
+        match self {
+            Rotation::Daily => time / 86_400,
+            Rotation::Hourly => time / 3600,
+            Rotation::Never | Rotation::Size(_) => 0,
+        }
+    }
+}
+
+/// A log file, rotated as its [`Rotation`] says.
+///
+/// Each write goes whole into one file, so writing a line at a time keeps
+/// lines from being split across files.
+#[derive(Debug)]
+pub struct LogFile {
+    path: PathBuf,
+    rotation: Rotation,
+    keep: usize,
+    file: File,
+    /// Bytes in the current file.
+    size: u64,
+    /// The [`Rotation::period`] the current file was started in.
+    period: u64,
+}
+
+impl LogFile {
+    /// Appends to the file at `path`, which is created with its directory
+    /// if needed, and keeps the newest `keep` files rotated out of it. A
+    /// file left from an earlier day or hour is rotated at once under
+    /// [`Rotation::Daily`] or [`Rotation::Hourly`].
+    pub fn open(path: impl Into<PathBuf>, rotation: Rotation, keep: usize) -> io::Result<LogFile> {
+        let path = path.into();
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let metadata = file.metadata()?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or_else(unix_timestamp, |modified| modified.as_secs());
+        let mut log = LogFile {
+            path,
+            rotation,
+            keep,
+            file,
+            size: metadata.len(),
+            period: rotation.period(modified),
+        };
+        if log.size > 0 && log.period != rotation.period(unix_timestamp()) {
+            log.rotate()?;
+        }
+        Ok(log)
+    }
+
+    /// The path written to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The path of the `n`th newest rotated file.
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        PathBuf::from(path)
+    }
+
+    /// Whether writing `len` more bytes needs a new file first.
+    fn due(&self, len: usize) -> bool {
+        match self.rotation {
+            Rotation::Never => false,
+            Rotation::Size(max) => self.size > 0 && self.size + len as u64 > max,
+            Rotation::Daily | Rotation::Hourly => {
+                self.period != self.rotation.period(unix_timestamp())
+            }
+        }
+    }
+
+    /// Shifts the rotated files up by one, dropping the oldest, moves the
+    /// current file to `.1`, and starts a new one.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        remove(&self.rotated(self.keep.max(1)))?;
+        for n in (1..self.keep).rev() {
+            match fs::rename(self.rotated(n), self.rotated(n + 1)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        if self.keep > 0 {
+            fs::rename(&self.path, self.rotated(1))?;
+        } else {
+            remove(&self.path)?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        self.period = self.rotation.period(unix_timestamp());
+        Ok(())
+    }
+}
+
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.due(buf.len()) {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Removes the file at `path`, if there is one.
+fn remove(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
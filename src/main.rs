@@ -1,397 +1,1907 @@
-// Server endpoints can be checked using Nushell:
+// Server endpoints can be checked with the built-in probe, which exits
+// non-zero if any of them fails:
 //
-// # Define the check command
-// def check-endpoints [] {
-//     ["/version" "/healthz" "/metrics"] | each { |ep| 
-//         try { 
-//             http get $"http://127.0.0.1:3000($ep)"
-//             {endpoint: $ep, status: "OK"}
-//         } catch {
-//             {endpoint: $ep, status: "Error"}
-//         }
-//     }
-// }
+//     rusty-server check --url http://127.0.0.1:3000 --endpoints /version,/healthz
 //
-// # Check all endpoints
-// check-endpoints | table
+// ╭──────────┬────────┬──────────────────┬─────────┬────────╮
+// │ Endpoint │ Status │ Content-Type     │ Elapsed │ Result │
+// ├──────────┼────────┼──────────────────┼─────────┼────────┤
+// │ /version │ 200    │ text/html        │ 1 ms    │ ✓      │
+// │ /healthz │ 200    │ application/json │ 0 ms    │ ✓      │
+// ╰──────────┴────────┴──────────────────┴─────────┴────────╯
 //
-// # Check failed endpoints
-// $endpoint_status | where status == "Error" | table
+// With ADMIN_API=1, the Nushell plugin in `nu_plugin_rusty_server/` shows the
+// running server's state as tables:
 //
-// # Check working endpoints
-// $endpoint_status | where status == "OK" | table
-//
-// Example output:
-// ╭───┬──────────┬────────╮
-// │ # │ endpoint │ status │
-// ├───┼──────────┼────────┤
-// │ 0 │ /version │ OK     │
-// │ 1 │ /healthz │ Error  │
-// │ 2 │ /metrics │ Error  │
-// ╰───┴──────────┴────────╯
-
-
-use std::io::{self, Read, Write};
-use std::net::{TcpListener, TcpStream};
-use std::time::{SystemTime, UNIX_EPOCH};
-use nu_table::{NuTable, NuTableConfig, TableTheme};
-
-fn main() -> io::Result<()> {
-    let listener = TcpListener::bind("127.0.0.1:3000")?;
-    
-    // Initial server status
-    let mut table = NuTable::new(2, 2);
-    table.insert((0, 0).into(), "Status".to_string());
-    table.insert((0, 1).into(), "Server Started".to_string());
-    table.insert((1, 0).into(), "Address".to_string());
-    table.insert((1, 1).into(), "http://127.0.0.1:3000".to_string());
-    
-    let config = NuTableConfig {
-        theme: TableTheme::rounded(),
-        ..NuTableConfig::default()
-    };
-    
-    if let Some(output) = table.draw(config.clone(), 80) {
-        println!("{}", output);
-    }
-
-    for stream in listener.incoming() {
-        match stream {
-            Ok(stream) => {
-                let peer_addr = stream.peer_addr()?;
-                let timestamp = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs();
-                
-                // Connection info table
-                let mut status_table = NuTable::new(4, 2);
-                status_table.insert((0, 0).into(), "Connection".to_string());
-                status_table.insert((0, 1).into(), format!("{}:{}", peer_addr.ip(), peer_addr.port()));
-                status_table.insert((1, 0).into(), "Time".to_string());
-                status_table.insert((1, 1).into(), timestamp.to_string());
-                
-                match handle_connection(stream) {
-                    Ok((bytes, status, path)) => {
-                        status_table.insert((2, 0).into(), "Request".to_string());
-                        status_table.insert((2, 1).into(), path);
-                        status_table.insert((3, 0).into(), "Response".to_string());
-                        status_table.insert((3, 1).into(), format!("✓ {} ({} bytes)", status, bytes));
-                    }
-                    Err(e) => {
-                        status_table.insert((2, 0).into(), "Status".to_string());
-                        status_table.insert((2, 1).into(), format!("✗ Failed: {}", e));
-                    }
-                }
-                
-                if let Some(output) = status_table.draw(config.clone(), 80) {
-                    println!("{}", output);
-                }
+//     server stats
+//     server connections | sort-by age --reverse
+//     server routes | where kind == websocket
+
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener};
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock, PoisonError, RwLock};
+use std::time::Duration;
+
+use clap::{ArgAction, Args, CommandFactory, Parser, Subcommand};
+use rusty_server::console;
+use rusty_server::logfile::Rotation;
+use rusty_server::ServerError;
+
+/// Where the server listens unless `LISTEN_ADDRESS` or the address options
+/// say otherwise.
+const ADDRESS: &str = "127.0.0.1:3000";
+
+/// The host and port of [`ADDRESS`], for `--port` without `--host` and the
+/// other way around.
+const HOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+const PORT: u16 = 3000;
+
+/// How often the certificate and key files for HTTPS are checked for
+/// changes.
+#[cfg(feature = "tls")]
+const CERTIFICATE_RELOAD_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Where `tcp-echo` listens unless given an address or port.
+const ECHO_ADDRESS: &str = "127.0.0.1:7007";
+
+
+/// Shared libraries (with `plugins`) and WebAssembly components (with
+/// `wasm`) in this directory are loaded as handler plugins.
+#[cfg(any(feature = "plugins", feature = "wasm"))]
+const PLUGIN_DIR: &str = "plugins";
+
+/// Rhai scripts in this directory define routes (with `scripting`).
+#[cfg(feature = "scripting")]
+const SCRIPT_DIR: &str = "scripts";
+
+/// How often the scripts directory is checked for changes.
+#[cfg(feature = "scripting")]
+const SCRIPT_RELOAD_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Requests under this prefix are relayed to the FastCGI backend named by the
+/// `FASTCGI_ADDRESS` environment variable (`host:port` or `unix:/path`), with
+/// scripts resolved under `FASTCGI_ROOT`.
+const FASTCGI_PREFIX: &str = "/php";
+
+/// Requests under this prefix run CGI scripts from the directory named by
+/// the `CGI_DIR` environment variable.
+const CGI_PREFIX: &str = "/cgi-bin";
+
+/// How long a shutdown waits for queued webhooks to be delivered.
+#[cfg(feature = "webhooks")]
+const WEBHOOK_FLUSH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// How long a shutdown waits for the last spans to be exported.
+#[cfg(feature = "otel")]
+const OTEL_FLUSH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+#[cfg(feature = "grpc")]
+const GRPC_ADDRESS: &str = "127.0.0.1:50051";
+
+/// Where the admin endpoints reload the config file.
+const RELOAD_PATH: &str = "/admin/reload";
+
+/// Where ACME certificates and keys are stored unless `ACME_DIR` says
+/// otherwise.
+#[cfg(feature = "acme")]
+const ACME_DIR: &str = "acme";
+
+/// The paths `ADMIN_CLIENTS` restricts.
+const ADMIN_PREFIX: &str = "/admin";
+
+/// Where `--static-dir` is served unless `--static-prefix` says otherwise.
+const STATIC_PREFIX: &str = "/static";
+
+/// How many rotated log files are kept unless `--log-keep` says otherwise.
+const LOG_KEEP: usize = 7;
+
+/// The settings in the config file, by variable name; they take precedence
+/// over the environment.
+static FILE_SETTINGS: RwLock<BTreeMap<String, String>> = RwLock::new(BTreeMap::new());
+
+/// The config file given with `--config`, read again on reload.
+static CONFIG_FILE: OnceLock<String> = OnceLock::new();
+
+/// The config the server runs with, for reloads to change and compare with.
+static RUNNING: Mutex<Option<rusty_server::Config>> = Mutex::new(None);
+
+/// The command line, for reloads to read the options again.
+static CLI: OnceLock<Cli> = OnceLock::new();
+
+/// The static files served and the settings they come from, which a reload
+/// can change.
+static STATIC_FILES: RwLock<Option<StaticSettings>> = RwLock::new(None);
+
+/// Set if `--debug` was given, which a reload leaves in force.
+static DEBUG_FLAG: AtomicBool = AtomicBool::new(false);
+
+/// The connection limit `--max-connections` sets, if given.
+static MAX_CONNECTIONS: OnceLock<NonZeroUsize> = OnceLock::new();
+
+/// The origins `--cors` allows, if given.
+static CORS_ORIGINS: OnceLock<String> = OnceLock::new();
+
+/// The Basic authentication `--basic-auth` asks for, if given.
+#[cfg(feature = "auth")]
+static BASIC_AUTH: OnceLock<rusty_server::auth::BasicAuth> = OnceLock::new();
+
+/// The bearer tokens `--bearer-auth` asks for, if given.
+#[cfg(feature = "auth")]
+static BEARER_AUTH: OnceLock<rusty_server::auth::BearerAuth> = OnceLock::new();
+
+/// The requests a second and the burst `--rate-limit` allows each client,
+/// if given.
+static RATE_LIMIT: OnceLock<(f64, Option<u32>)> = OnceLock::new();
+
+/// Set if responses are compressed, by `--compress` or `COMPRESS`.
+static COMPRESS: AtomicBool = AtomicBool::new(false);
+
+/// The command line. Each option but `--max-connections` has an environment
+/// variable standing in for it, which the option takes precedence over.
+#[derive(Debug, Parser)]
+#[command(
+    name = "rusty-server",
+    version,
+    about = "A status server that reports its traffic as console tables",
+    after_help = "Every environment variable above can also be given with a SERVER_ prefix, \
+                  which takes precedence, as in SERVER_LOG_FORMAT. The config file takes \
+                  precedence over both, and options on the command line over all of them."
+)]
+struct Cli {
+    /// Listen on ADDRESS (IP:PORT); repeat it for several addresses;
+    /// LISTEN_ADDRESS, comma-separated, also sets them [default: 127.0.0.1:3000]
+    #[arg(long, value_name = "ADDRESS")]
+    listen: Vec<SocketAddr>,
+    /// Listen on HOST, such as 0.0.0.0 for every interface; repeat it for
+    /// several hosts on the same port; SERVER_HOST, comma-separated, also sets
+    /// them
+    #[arg(long)]
+    host: Vec<IpAddr>,
+    /// Listen on PORT of each --host [default: 3000]; SERVER_PORT also sets it
+    #[arg(long)]
+    port: Option<u16>,
+    /// No per-connection reports
+    #[arg(short, long)]
+    quiet: bool,
+    /// Report the request line, content type, and time taken too; -vv adds
+    /// every request and response header
+    #[arg(short, long, action = ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+    /// Table (the default) or ndjson (also named json), or with the `tracing`
+    /// feature tracing (one line per event) or pretty (events as tables),
+    /// filtered by RUST_LOG; LOG_FORMAT also sets it
+    #[arg(long, value_name = "FORMAT")]
+    log_format: Option<console::Format>,
+    /// Table borders: rounded (the default), heavy, light, basic (ASCII), or
+    /// none, or plain for text columns instead of tables; TABLE_THEME also
+    /// sets it
+    #[arg(long, visible_alias = "table-theme")]
+    theme: Option<console::Theme>,
+    /// Print text columns instead of tables, as --theme plain
+    #[arg(long, conflicts_with = "theme")]
+    no_table: bool,
+    /// Draw tables N columns wide rather than as wide as the terminal,
+    /// COLUMNS, or 80; TABLE_WIDTH also sets it
+    #[arg(long, value_name = "N")]
+    table_width: Option<NonZeroUsize>,
+    /// Report connections in one summary per interval instead of one by one;
+    /// LOG_SUMMARY also sets it
+    #[arg(long, value_name = "SECONDS", value_parser = interval)]
+    summary: Option<Duration>,
+    /// Report connections one by one under --summary too; LOG_LEVEL=debug
+    /// also sets it
+    #[arg(long)]
+    debug: bool,
+    /// Read settings from FILE, NAME=value lines named like the environment
+    /// variables; SERVER_CONFIG also sets it
+    #[arg(long, value_name = "FILE")]
+    config: Option<String>,
+    /// Also write the console output to a file for the day in DIR; LOG_DIR
+    /// also sets it
+    #[arg(long, value_name = "DIR")]
+    log_dir: Option<String>,
+    /// Also write the console output to FILE, without colors; LOG_FILE also
+    /// sets it
+    #[arg(long, value_name = "FILE")]
+    log_file: Option<String>,
+    /// Start a new --log-file daily, hourly, or before it grows past a size
+    /// such as 10M, moving the old one to FILE.1; never (the default) keeps
+    /// one file; LOG_ROTATE also sets it
+    #[arg(long, value_name = "ROTATION")]
+    log_rotate: Option<Rotation>,
+    /// Keep the N newest rotated log files (7 by default); LOG_KEEP also sets
+    /// it
+    #[arg(long, value_name = "N")]
+    log_keep: Option<usize>,
+    /// Serve the files in DIR under /static; STATIC_DIR also sets it
+    #[arg(long, value_name = "DIR")]
+    static_dir: Option<String>,
+    /// Serve --static-dir under PREFIX rather than /static; STATIC_PREFIX also
+    /// sets it
+    #[arg(long, value_name = "PREFIX")]
+    static_prefix: Option<String>,
+    /// Answer GET requests under --static-prefix naming no file with the
+    /// --static-dir index.html, for single-page apps routing on the client;
+    /// SPA=1 also sets it
+    #[arg(long)]
+    spa: bool,
+    /// Serve --static-dir files ending in .EXT as TYPE rather than as their
+    /// extension says; repeat it for several extensions; MIME_TYPES,
+    /// comma-separated, also sets them
+    #[arg(long, value_name = "EXT=TYPE", value_parser = parse_mime_type)]
+    mime_type: Vec<(String, String)>,
+    /// Let pages from ORIGINS, comma-separated, or from any origin for *, call
+    /// the server from the browser, and answer their preflight requests;
+    /// CORS_ORIGINS also sets it, and CORS_METHODS, CORS_HEADERS,
+    /// CORS_EXPOSE_HEADERS, CORS_CREDENTIALS=1, and CORS_MAX_AGE (seconds)
+    /// tune it
+    #[arg(long, value_name = "ORIGINS")]
+    cors: Option<String>,
+    /// Ask for a user name and password on the paths under PREFIXES,
+    /// comma-separated, such as /admin,/metrics (`auth` feature); BASIC_AUTH
+    /// also sets it, with the users in BASIC_AUTH_USERS (user:password,
+    /// comma-separated) or --htpasswd, and BASIC_AUTH_REALM naming the realm
+    #[arg(long, value_name = "PREFIXES")]
+    basic_auth: Option<String>,
+    /// Let in the users of the htpasswd FILE, with plain or {SHA} passwords,
+    /// for --basic-auth; HTPASSWD also sets it
+    #[arg(long, value_name = "FILE")]
+    htpasswd: Option<String>,
+    /// Ask for one of the bearer tokens in BEARER_TOKENS, comma-separated, on
+    /// the paths under PREFIXES, such as /metrics (`auth` feature);
+    /// BEARER_AUTH also sets it
+    #[arg(long, value_name = "PREFIXES")]
+    bearer_auth: Option<String>,
+    /// Answer 429 to clients sending more than RATE requests a second, by IP
+    /// address; RATE_LIMIT also sets it
+    #[arg(long, value_name = "RATE", value_parser = rate)]
+    rate_limit: Option<f64>,
+    /// Let each client send N requests at once before --rate-limit applies
+    /// (default: RATE, rounded up); RATE_LIMIT_BURST also sets it
+    #[arg(long, value_name = "N")]
+    rate_limit_burst: Option<NonZeroU32>,
+    /// Serve the templates in DIR, such as 404.html and 500.html, as the HTML
+    /// error pages for their status codes (`html` feature); ERROR_PAGES also
+    /// sets it
+    #[arg(long, value_name = "DIR")]
+    error_pages: Option<String>,
+    /// Compress text responses of 1 KiB or more (COMPRESS_MIN_SIZE bytes) with
+    /// gzip, or br or zstd with the `brotli` or `zstd` feature, as the client
+    /// prefers (`compression` feature); COMPRESS=1 also sets it
+    #[arg(long)]
+    compress: bool,
+    /// Append a Combined Log Format line per request to FILE, or to stdout if
+    /// FILE is -; ACCESS_LOG also sets it
+    #[arg(long, value_name = "FILE")]
+    access_log: Option<String>,
+    /// Time requests into histogram buckets with these comma-separated upper
+    /// bounds, in seconds, in /metrics; METRICS_BUCKETS also sets them
+    #[arg(long, value_name = "BOUNDS")]
+    metrics_buckets: Option<String>,
+    /// Serve connections on a pool of N threads rather than a thread each;
+    /// WORKERS also sets it
+    #[arg(long, value_name = "N")]
+    workers: Option<NonZeroUsize>,
+    /// Keep at most N connections open at once, answering 503 with
+    /// Retry-After to those beyond it, or closing them with SHED=close;
+    /// MAX_CONNECTIONS also sets it
+    #[arg(long, value_name = "N")]
+    max_connections: Option<NonZeroUsize>,
+    /// Sync (the default) or tokio, which accepts connections on a tokio
+    /// runtime (`tokio` feature); RUNTIME also sets it
+    #[arg(long)]
+    runtime: Option<rusty_server::Runtime>,
+    /// Serve HTTPS with the PEM certificate chain in FILE (`tls` feature);
+    /// TLS_CERT also sets it
+    #[arg(long, value_name = "FILE")]
+    tls_cert: Option<String>,
+    /// The PEM private key for --tls-cert; TLS_KEY also sets it
+    #[arg(long, value_name = "FILE")]
+    tls_key: Option<String>,
+    /// Require client certificates signed by a CA in the PEM FILE;
+    /// TLS_CLIENT_CA also sets it
+    #[arg(long, value_name = "FILE")]
+    tls_client_ca: Option<String>,
+    /// Present the PEM certificate chain in CERT, with the key in KEY, to
+    /// clients asking for HOST, and --tls-cert (or the first HOST) to the
+    /// others; repeat it for several hosts; TLS_SNI, comma-separated, also
+    /// sets them
+    #[arg(long, value_name = "HOST=CERT:KEY", value_parser = parse_named)]
+    tls_sni: Vec<(String, String, String)>,
+    /// Serve HTTPS with a certificate for DOMAIN from Let's Encrypt, renewed
+    /// automatically (`acme` feature); repeat it for several domains;
+    /// ACME_DOMAINS, comma-separated, also sets them
+    #[arg(long, value_name = "DOMAIN")]
+    acme_domain: Vec<String>,
+    /// Show a live dashboard in place of the console tables
+    #[cfg(feature = "tui")]
+    #[arg(long, visible_alias = "dashboard", conflicts_with = "repl")]
+    tui: bool,
+    /// Read admin commands from the terminal while serving
+    #[arg(long)]
+    repl: bool,
+    #[command(subcommand)]
+    mode: Option<Mode>,
+}
+
+impl Cli {
+    /// What the terminal shows while the server runs.
+    fn frontend(&self) -> Frontend {
+        #[cfg(feature = "tui")]
+        if self.tui {
+            return Frontend::Dashboard;
+        }
+        if self.repl {
+            Frontend::Repl
+        } else {
+            Frontend::Tables
+        }
+    }
+}
+
+/// What runs in place of the HTTP server.
+#[derive(Debug, Subcommand)]
+enum Mode {
+    /// Echo raw TCP, with a hex dump of every chunk received
+    TcpEcho {
+        /// The address to listen on, or a port of the loopback interface
+        /// [default: 127.0.0.1:7007]
+        #[arg(value_name = "ADDRESS | PORT")]
+        address: Option<String>,
+    },
+    /// Probe the endpoints of a running server, failing if any probe does
+    Check(CheckArgs),
+}
+
+/// The options of [`Mode::Check`].
+#[derive(Debug, Args)]
+struct CheckArgs {
+    /// The server to probe [default: http://127.0.0.1:3000]
+    #[arg(long)]
+    url: Option<String>,
+    /// The paths to probe, comma-separated
+    #[arg(long, value_name = "PATH,...")]
+    endpoints: Option<String>,
+    /// The status every endpoint must answer with
+    #[arg(long, value_name = "CODE")]
+    status: Option<u16>,
+    /// The content type every endpoint must answer with
+    #[arg(long, value_name = "TYPE")]
+    content_type: Option<String>,
+    /// How long to wait for each endpoint
+    #[arg(long, value_name = "SECONDS", value_parser = seconds)]
+    timeout: Option<Duration>,
+}
+
+fn main() -> ExitCode {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => CLI.get_or_init(|| cli),
+        Err(e) => {
+            let _ = e.print();
+            return if e.use_stderr() { ExitCode::from(64) } else { ExitCode::SUCCESS };
+        }
+    };
+    if let Some(path) = cli.config.clone().or_else(|| std::env::var("SERVER_CONFIG").ok()) {
+        match read_config_file(&path) {
+            Ok(settings) => *FILE_SETTINGS.write().unwrap_or_else(PoisonError::into_inner) = settings,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                // EX_CONFIG
+                return ExitCode::from(78);
+            }
+        }
+        let _ = CONFIG_FILE.set(path);
+    }
+    let log_dir = cli.log_dir.clone().or_else(|| env_var("LOG_DIR").ok());
+    let Ok(log_file) = log_file(cli) else {
+        return usage();
+    };
+    let access_log = cli.access_log.clone().or_else(|| env_var("ACCESS_LOG").ok());
+    let Ok(files) = static_settings(cli) else {
+        return usage();
+    };
+    let Ok(error_pages) = error_pages(cli) else {
+        return usage();
+    };
+    if let Some(origins) = &cli.cors {
+        let _ = CORS_ORIGINS.set(origins.clone());
+    }
+    let any_origin = cli
+        .cors
+        .clone()
+        .or_else(|| env_var("CORS_ORIGINS").ok())
+        .is_some_and(|origins| split_list(&origins).iter().any(|origin| origin == "*"));
+    if any_origin && matches!(env_var("CORS_CREDENTIALS").as_deref(), Ok("1" | "true")) {
+        eprintln!("Error: CORS_CREDENTIALS needs --cors to name the origins rather than allow *");
+        // EX_CONFIG
+        return ExitCode::from(78);
+    }
+    let Ok(basic_auth) = basic_auth(cli) else {
+        return usage();
+    };
+    let Ok(bearer_auth) = bearer_auth(cli) else {
+        return usage();
+    };
+    match rate_limit(cli) {
+        Ok(Some(limit)) => {
+            let _ = RATE_LIMIT.set(limit);
+        }
+        Ok(None) => {}
+        Err(()) => return usage(),
+    }
+    if configure_console(cli).is_err() || configure_metrics(cli).is_err() {
+        return usage();
+    }
+    let Ok(compress) = compress(cli) else {
+        return usage();
+    };
+    COMPRESS.store(compress, Ordering::Relaxed);
+    let Ok(workers) = workers(cli) else {
+        return usage();
+    };
+    if let Some(max) = cli.max_connections {
+        let _ = MAX_CONNECTIONS.set(max);
+    }
+    let Ok(runtime) = runtime(cli) else {
+        return usage();
+    };
+    let Ok(https) = https(cli) else {
+        return usage();
+    };
+    let Ok(addresses) = addresses(cli) else {
+        return usage();
+    };
+    if let Some(dir) = log_dir {
+        if let Err(e) = mirror_console(&dir) {
+            eprintln!("Error: {}", e);
+            return e.exit_code();
+        }
+    }
+    if let Some((path, rotation, keep)) = log_file {
+        match rusty_server::logfile::LogFile::open(&path, rotation, keep) {
+            Ok(log) => {
+                rusty_server::console::set_log_file(log);
+                rusty_server::console::print_line(&format!("Log file: {} (rotation: {})", path, rotation));
             }
             Err(e) => {
-                let mut error_table = NuTable::new(1, 2);
-                error_table.insert((0, 0).into(), "Error".to_string());
-                error_table.insert((0, 1).into(), e.to_string());
-                
-                if let Some(output) = error_table.draw(config.clone(), 80) {
-                    println!("{}", output);
-                }
+                eprintln!("Error: cannot open the log file {}: {}", path, e);
+                return ServerError::from(e).exit_code();
+            }
+        }
+    }
+    if let Some(path) = access_log {
+        if let Err(e) = rusty_server::access::open(&path) {
+            eprintln!("Error: cannot open the access log {}: {}", path, e);
+            return ServerError::from(e).exit_code();
+        }
+    }
+    if let Some(dir) = error_pages {
+        match rusty_server::pages::set_error_pages(std::path::Path::new(&dir)) {
+            Ok(codes) => {
+                let codes: Vec<String> = codes.iter().map(u16::to_string).collect();
+                let codes = if codes.is_empty() { "none".to_string() } else { codes.join(", ") };
+                rusty_server::console::print_line(&format!("Error pages: {} ({})", dir, codes));
+            }
+            Err(e) => {
+                eprintln!("Error: cannot read the error pages in {}: {}", dir, e);
+                return ServerError::from(e).exit_code();
+            }
+        }
+    }
+    // A request carries one `Authorization` header, so it could never pass
+    // both schemes.
+    #[cfg(feature = "auth")]
+    if let (Some((basic, _)), Some(bearer)) = (&basic_auth, &bearer_auth) {
+        let bearer = split_list(bearer);
+        let shared = split_list(basic)
+            .into_iter()
+            .find(|prefix| bearer.iter().any(|other| overlap(prefix, other)));
+        if let Some(prefix) = shared {
+            eprintln!("Error: --basic-auth and --bearer-auth both protect {}, which no request could pass", prefix);
+            // EX_CONFIG
+            return ExitCode::from(78);
+        }
+    }
+    #[cfg(feature = "auth")]
+    if let Some((prefixes, htpasswd)) = basic_auth {
+        if let Some(prefix) = streamed(&prefixes) {
+            eprintln!("Error: --basic-auth cannot protect {}, which CGI or FastCGI serves past authentication", prefix);
+            // EX_CONFIG
+            return ExitCode::from(78);
+        }
+        match basic_auth_for(&prefixes, htpasswd.as_deref()) {
+            Ok(auth) => {
+                rusty_server::console::print_line(&format!("Basic authentication: {}", prefixes));
+                let _ = BASIC_AUTH.set(auth);
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                // EX_CONFIG
+                return ExitCode::from(78);
+            }
+        }
+    }
+    #[cfg(feature = "auth")]
+    if let Some(prefixes) = bearer_auth {
+        if let Some(prefix) = streamed(&prefixes) {
+            eprintln!("Error: --bearer-auth cannot protect {}, which CGI or FastCGI serves past authentication", prefix);
+            // EX_CONFIG
+            return ExitCode::from(78);
+        }
+        let tokens = env_var("BEARER_TOKENS").map(|tokens| split_list(&tokens)).unwrap_or_default();
+        if tokens.is_empty() {
+            eprintln!("Error: --bearer-auth needs the tokens in BEARER_TOKENS");
+            // EX_CONFIG
+            return ExitCode::from(78);
+        }
+        let mut auth = rusty_server::auth::BearerAuth::new();
+        for prefix in split_list(&prefixes) {
+            auth = auth.protect(&prefix);
+        }
+        for token in &tokens {
+            auth = auth.token(token);
+        }
+        rusty_server::console::print_line(&format!(
+            "Bearer authentication: {} ({} tokens)",
+            prefixes,
+            tokens.len()
+        ));
+        let _ = BEARER_AUTH.set(auth);
+    }
+    #[cfg(not(feature = "auth"))]
+    let _ = (basic_auth, bearer_auth);
+    if let Some(files) = &files.files {
+        rusty_server::console::print_line(&format!(
+            "Static files: {} at {}/",
+            files.dir().display(),
+            files.prefix()
+        ));
+    }
+    *STATIC_FILES.write().unwrap_or_else(PoisonError::into_inner) = Some(files);
+    let frontend = cli.frontend();
+    let result = match &cli.mode {
+        None => run(frontend, &addresses, workers, runtime, https),
+        Some(_) if frontend != Frontend::Tables => {
+            eprintln!("Error: --tui and --repl cannot be combined with tcp-echo or check");
+            return usage();
+        }
+        Some(Mode::TcpEcho { address }) => tcp_echo(address.as_deref().unwrap_or(ECHO_ADDRESS)),
+        Some(Mode::Check(options)) => return check(options),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            e.exit_code()
+        }
+    }
+}
+
+/// What the terminal shows while the server runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Frontend {
+    /// A console table for every connection.
+    Tables,
+    /// The live dashboard.
+    #[cfg(feature = "tui")]
+    Dashboard,
+    /// The console tables, plus the admin console on stdin if it is a
+    /// terminal.
+    Repl,
+}
+
+/// Where the certificate for HTTPS comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Https {
+    /// Certificate, key, and client CA files, and the host, certificate,
+    /// and key files of each certificate for clients asking for a host.
+    #[cfg(feature = "tls")]
+    Files {
+        cert: String,
+        key: String,
+        client_ca: Option<String>,
+        named: Vec<(String, String, String)>,
+    },
+    /// An ACME certificate authority, with a certificate for these domains.
+    #[cfg(feature = "acme")]
+    Acme(Vec<String>),
+}
+
+/// Runs the server on `addresses` and `runtime`, showing its traffic on
+/// `frontend`, with `workers` threads serving connections if given, over
+/// and `https` if given. The first address is the one advertised over mDNS
+/// and registered.
+fn run(
+    frontend: Frontend,
+    addresses: &[SocketAddr],
+    workers: Option<NonZeroUsize>,
+    runtime: rusty_server::Runtime,
+    https: Option<Https>,
+) -> Result<(), ServerError> {
+    #[cfg(feature = "acme")]
+    let mut acme = None;
+    #[cfg(feature = "tls")]
+    let tls = match https {
+        None => None,
+        Some(Https::Files { cert, key, client_ca, named }) => {
+            let acceptor = match client_ca {
+                Some(client_ca) => rusty_server::tls::Acceptor::from_pem_files_with_client_ca(
+                    cert, key, client_ca,
+                )?,
+                None => rusty_server::tls::Acceptor::from_pem_files(cert, key)?,
+            };
+            let acceptor = named.into_iter().try_fold(acceptor, |acceptor, (host, cert, key)| {
+                acceptor.with_named_pem_files(&host, cert, key)
+            })?;
+            acceptor.keep_reloaded(CERTIFICATE_RELOAD_INTERVAL);
+            Some(acceptor)
+        }
+        #[cfg(feature = "acme")]
+        Some(Https::Acme(domains)) => {
+            let certificates = std::sync::Arc::new(acme_certificates(domains));
+            let acceptor = certificates.acceptor();
+            acme = Some(certificates);
+            Some(acceptor)
+        }
+    };
+    // Without the feature, `https` has already turned HTTPS down.
+    #[cfg(not(feature = "tls"))]
+    let _ = https;
+    let config = move || {
+        let mut config = config();
+        config.workers = workers;
+        config.runtime = runtime;
+        #[cfg(feature = "tls")]
+        {
+            config.tls = tls.clone();
+        }
+        *RUNNING.lock().unwrap_or_else(PoisonError::into_inner) = Some(config.clone());
+        config
+    };
+    let listeners = addresses
+        .iter()
+        .map(|address| bind(&address.to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // The listeners answer the certificate authority's validations, so they
+    // are bound first.
+    #[cfg(feature = "acme")]
+    if let Some(acme) = acme {
+        acme.keep_renewed();
+    }
+
+    #[cfg(feature = "mdns")]
+    if let Some(advertisement) = advertise(&listeners[0]) {
+        on_shutdown(move || drop(advertisement));
+    }
+
+    #[cfg(feature = "registry")]
+    register(&listeners[0]);
+
+    #[cfg(feature = "grpc")]
+    {
+        let grpc_listener = bind(GRPC_ADDRESS)?;
+        std::thread::spawn(move || {
+            if let Err(e) = rusty_server::grpc::serve(grpc_listener) {
+                eprintln!("Error: gRPC server stopped: {}", e);
             }
+        });
+    }
+
+    #[cfg(feature = "webhooks")]
+    start_webhooks();
+
+    #[cfg(feature = "history")]
+    start_history();
+
+    #[cfg(feature = "otel")]
+    start_otel();
+
+    #[cfg(all(unix, feature = "reload"))]
+    reload_on_hangup();
+
+    #[cfg(any(
+        feature = "webhooks",
+        feature = "mdns",
+        feature = "registry",
+        feature = "history",
+        feature = "otel"
+    ))]
+    handle_shutdown();
+
+    #[cfg(feature = "tui")]
+    if frontend == Frontend::Dashboard {
+        // The terminal is in raw mode, so Ctrl-C reaches the dashboard as a
+        // key rather than as a signal: the hooks run once it returns.
+        let result = rusty_server::tui::serve(listeners, router(), config());
+        #[cfg(any(
+            feature = "webhooks",
+            feature = "mdns",
+            feature = "registry",
+            feature = "history",
+            feature = "otel"
+        ))]
+        run_shutdown_hooks();
+        return result;
+    }
+
+    if frontend == Frontend::Repl {
+        use std::io::IsTerminal;
+
+        if std::io::stdin().is_terminal() {
+            let router = router();
+            std::thread::spawn(move || {
+                if let Err(e) = rusty_server::serve_all(listeners, router, config()) {
+                    eprintln!("Error: {}", e);
+                }
+            });
+            rusty_server::repl::run(std::io::stdin().lock());
+            #[cfg(any(
+                feature = "webhooks",
+                feature = "mdns",
+                feature = "registry",
+                feature = "history",
+                feature = "otel"
+            ))]
+            run_shutdown_hooks();
+            return Ok(());
         }
+        rusty_server::console::print_line("Admin console: ✗ stdin is not a terminal");
+    }
+
+    rusty_server::serve_all(listeners, router(), config())
+}
+
+/// The log file of `--log-file`, or `LOG_FILE`, with its rotation and how
+/// many rotated files to keep, from `--log-rotate` and `--log-keep` or
+/// `LOG_ROTATE` and `LOG_KEEP`; fails if the rotation or count is invalid.
+fn log_file(cli: &Cli) -> Result<Option<(String, Rotation, usize)>, ()> {
+    let path = cli.log_file.clone().or_else(|| env_var("LOG_FILE").ok());
+    let rotation = match cli.log_rotate {
+        Some(rotation) => Some(rotation),
+        None => env_parsed("LOG_ROTATE", str::parse)?,
+    };
+    let keep = match cli.log_keep {
+        Some(keep) => Some(keep),
+        None => env_parsed("LOG_KEEP", str::parse::<usize>)?,
+    };
+    Ok(path.map(|path| (path, rotation.unwrap_or_default(), keep.unwrap_or(LOG_KEEP))))
+}
+
+/// The static file settings, and the files they serve.
+#[derive(Debug, Clone)]
+struct StaticSettings {
+    dir: Option<String>,
+    prefix: String,
+    spa: bool,
+    mime_types: Vec<(String, String)>,
+    /// The files in `dir`, if given.
+    files: Option<rusty_server::files::StaticFiles>,
+}
+
+/// The files to serve, from `--static-dir`, `--static-prefix`, `--spa`, and
+/// every `--mime-type`, or from `STATIC_DIR`, `STATIC_PREFIX`, `SPA`, and
+/// `MIME_TYPES` (comma-separated) without them; fails if the directory is
+/// not one, the prefix does not start with a slash, or a `MIME_TYPES` item
+/// is malformed.
+fn static_settings(cli: &Cli) -> Result<StaticSettings, ()> {
+    let dir = cli.static_dir.clone().or_else(|| env_var("STATIC_DIR").ok());
+    let prefix = cli.static_prefix.clone().or_else(|| env_var("STATIC_PREFIX").ok());
+    let prefix = prefix.unwrap_or_else(|| STATIC_PREFIX.to_string());
+    let types = match &cli.mime_type[..] {
+        [] => env_list("MIME_TYPES", parse_mime_type)?,
+        types => types.to_vec(),
+    };
+    let spa = cli.spa || matches!(env_var("SPA").as_deref(), Ok("1" | "true"));
+    if !prefix.starts_with('/') {
+        eprintln!("Error: invalid static file prefix `{}`; it must start with /", prefix);
+        return Err(());
     }
+    let files = match &dir {
+        Some(dir) if !std::path::Path::new(dir).is_dir() => {
+            eprintln!("Error: static file directory `{}` is not a directory", dir);
+            return Err(());
+        }
+        Some(dir) => {
+            let files = rusty_server::files::StaticFiles::new(&prefix, dir).spa(spa);
+            Some(types.iter().fold(files, |files, (extension, content_type)| {
+                files.mime_type(extension, content_type)
+            }))
+        }
+        None => None,
+    };
+    Ok(StaticSettings { dir, prefix, spa, mime_types: types, files })
+}
+
+/// The extension and content type in `named`, a `--mime-type` value of the
+/// form `EXT=TYPE`.
+fn parse_mime_type(named: &str) -> Result<(String, String), String> {
+    named
+        .split_once('=')
+        .map(|(extension, content_type)| (extension.trim(), content_type.trim()))
+        .filter(|(extension, content_type)| !extension.is_empty() && content_type.contains('/'))
+        .map(|(extension, content_type)| (extension.to_string(), content_type.to_string()))
+        .ok_or_else(|| "expected EXT=TYPE".to_string())
+}
+
+/// The directory of error page templates of `--error-pages`, or
+/// `ERROR_PAGES`, if any; fails without the `html` feature.
+fn error_pages(cli: &Cli) -> Result<Option<String>, ()> {
+    let dir = cli.error_pages.clone().or_else(|| env_var("ERROR_PAGES").ok());
+    if dir.is_some() && cfg!(not(feature = "html")) {
+        eprintln!("Error: --error-pages needs the `html` feature");
+        return Err(());
+    }
+    Ok(dir)
+}
+
+/// The prefixes `--basic-auth`, or `BASIC_AUTH`, protects, and the htpasswd
+/// file of `--htpasswd`, or `HTPASSWD`, if any; fails without the `auth`
+/// feature, and for `--htpasswd` without `--basic-auth`.
+fn basic_auth(cli: &Cli) -> Result<Option<(String, Option<String>)>, ()> {
+    let prefixes = cli.basic_auth.clone().or_else(|| env_var("BASIC_AUTH").ok());
+    let htpasswd = cli.htpasswd.clone().or_else(|| env_var("HTPASSWD").ok());
+    let Some(prefixes) = prefixes else {
+        if htpasswd.is_some() {
+            eprintln!("Error: --htpasswd needs --basic-auth");
+            return Err(());
+        }
+        return Ok(None);
+    };
+    if cfg!(not(feature = "auth")) {
+        eprintln!("Error: --basic-auth needs the `auth` feature");
+        return Err(());
+    }
+    Ok(Some((prefixes, htpasswd)))
+}
+
+/// The prefixes `--bearer-auth`, or `BEARER_AUTH`, protects, if any; fails
+/// without the `auth` feature.
+fn bearer_auth(cli: &Cli) -> Result<Option<String>, ()> {
+    let prefixes = cli.bearer_auth.clone().or_else(|| env_var("BEARER_AUTH").ok());
+    if prefixes.is_some() && cfg!(not(feature = "auth")) {
+        eprintln!("Error: --bearer-auth needs the `auth` feature");
+        return Err(());
+    }
+    Ok(prefixes)
+}
+
+/// The requests a second and the burst to allow each client, from
+/// `--rate-limit` and `--rate-limit-burst`, or `RATE_LIMIT` and
+/// `RATE_LIMIT_BURST`, if limited; fails if either setting is not a
+/// positive number, or for a burst without a rate.
+fn rate_limit(cli: &Cli) -> Result<Option<(f64, Option<u32>)>, ()> {
+    let rate = match cli.rate_limit {
+        Some(rate) => Some(rate),
+        None => env_parsed("RATE_LIMIT", rate)?,
+    };
+    let burst = match cli.rate_limit_burst {
+        Some(burst) => Some(burst),
+        None => env_parsed("RATE_LIMIT_BURST", |burst| burst.trim().parse::<NonZeroU32>())?,
+    };
+    match (rate, burst) {
+        (None, Some(_)) => {
+            eprintln!("Error: --rate-limit-burst needs --rate-limit");
+            Err(())
+        }
+        (rate, burst) => Ok(rate.map(|rate| (rate, burst.map(NonZeroU32::get)))),
+    }
+}
+
+/// A `--rate-limit`: a positive number of requests a second.
+fn rate(value: &str) -> Result<f64, String> {
+    match value.trim().parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+        _ => Err("expected requests a second".to_string()),
+    }
+}
+
+/// Whether `--compress`, or `COMPRESS`, asks to compress responses; fails
+/// without the `compression` feature.
+fn compress(cli: &Cli) -> Result<bool, ()> {
+    let compress = cli.compress || matches!(env_var("COMPRESS").as_deref(), Ok("1" | "true"));
+    if compress && cfg!(not(feature = "compression")) {
+        eprintln!("Error: --compress needs the `compression` feature");
+        return Err(());
+    }
+    Ok(compress)
+}
+
+/// Times requests into the comma-separated buckets of `--metrics-buckets`,
+/// or `METRICS_BUCKETS`; fails if a bound is not a number or they are not
+/// increasing.
+fn configure_metrics(cli: &Cli) -> Result<(), ()> {
+    let Some(buckets) = cli.metrics_buckets.clone().or_else(|| env_var("METRICS_BUCKETS").ok()) else {
+        return Ok(());
+    };
+    let Ok(bounds) = buckets.split(',').map(|bound| bound.trim().parse()).collect::<Result<Vec<f64>, _>>() else {
+        eprintln!("Error: invalid latency buckets `{}`", buckets);
+        return Err(());
+    };
+    rusty_server::metrics::set_buckets(&bounds).map_err(|e| eprintln!("Error: {}", e))
+}
+
+/// Applies the console options of `cli`, or the environment variables
+/// standing in for them.
+fn configure_console(cli: &Cli) -> Result<(), ()> {
+    let format = match cli.log_format {
+        Some(format) => Some(format),
+        None => env_parsed("LOG_FORMAT", str::parse)?,
+    };
+    if let Some(format) = format {
+        console::set_format(format);
+        #[cfg(feature = "tracing")]
+        if let Err(e) = rusty_server::trace::init(format) {
+            eprintln!("Error: cannot install the tracing subscriber: {}", e);
+            return Err(());
+        }
+    }
+    let theme = match (cli.no_table, cli.theme) {
+        (true, _) => Some(console::Theme::Plain),
+        (false, Some(theme)) => Some(theme),
+        (false, None) => env_parsed("TABLE_THEME", str::parse)?,
+    };
+    if let Some(theme) = theme {
+        console::set_theme(theme);
+    }
+    let width = match cli.table_width {
+        Some(width) => Some(width),
+        None => env_parsed("TABLE_WIDTH", str::parse::<NonZeroUsize>)?,
+    };
+    if let Some(width) = width {
+        console::set_width(Some(width));
+    }
+    let summary = match cli.summary {
+        Some(interval) => Some(interval),
+        None => env_parsed("LOG_SUMMARY", interval)?,
+    };
+    if let Some(interval) = summary {
+        console::set_summary(interval);
+    }
+    DEBUG_FLAG.store(cli.debug, Ordering::Relaxed);
+    console::set_debug(cli.debug || log_level_is_debug());
+    console::set_verbosity(match (cli.quiet, cli.verbose) {
+        (true, _) => console::Verbosity::Quiet,
+        (false, 0) => console::Verbosity::Normal,
+        (false, 1) => console::Verbosity::Verbose,
+        (false, _) => console::Verbosity::VeryVerbose,
+    });
     Ok(())
 }
 
-fn handle_connection(mut stream: TcpStream) -> io::Result<(usize, String, String)> {
-    let mut buffer = [0; 1024];
-    let bytes_read = stream.read(&mut buffer)?;
-    
-    let request = String::from_utf8_lossy(&buffer[..bytes_read]);
-    let first_line = request.lines().next().unwrap_or("");
-    
-    let (status_line, content, status_text, path) = match first_line {
-        s if s.starts_with("GET /version ") => {
-            let json = format!(r#"{{
-                "version": "{}",
-                "commit": "unknown",
-                "branch": "main",
-                "built_at": "{}",
-                "rust_version": "{}",
-                "platform": "{}",
-                "arch": "{}"
-            }}"#,
-                env!("CARGO_PKG_VERSION"),
-                SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
-                rustc_version_runtime::version().to_string(), 
-                std::env::consts::OS,
-                std::env::consts::ARCH
-            );
-            
-            if request.contains("Accept: application/json") {
-                (
-                    "HTTP/1.1 200 OK",
-                    json,
-                    "200 OK",
-                    "/version"
-                )
-            } else {
-                (
-                    "HTTP/1.1 200 OK",
-                    format!(r#"<!DOCTYPE html>
-<html>
-<head>
-    <title>Version Information</title>
-    <style>
-        body {{ 
-            font-family: 'Courier New', monospace;
-            background: #1a1a1a;
-            color: #e0e0e0;
-            padding: 40px;
-            line-height: 1.6;
-        }}
-        .terminal {{
-            background: #252525;
-            border-radius: 6px;
-            padding: 20px;
-            box-shadow: 0 4px 6px rgba(0, 0, 0, 0.3);
-            border: 1px solid #333;
-        }}
-        .info-title {{
-            color: #6ba2ff;
-            font-size: 24px;
-            margin: 0 0 20px 0;
-        }}
-        .data-grid {{
-            display: grid;
-            grid-template-columns: auto 1fr;
-            gap: 10px;
-            margin: 20px 0;
-        }}
-        .label {{
-            color: #a0a0a0;
-            padding-right: 20px;
-        }}
-        .value {{
-            color: #6ba2ff;
-        }}
-        pre {{
-            background: #1a1a1a;
-            padding: 15px;
-            border-radius: 4px;
-            border: 1px solid #404040;
-            overflow-x: auto;
-        }}
-    </style>
-</head>
-<body>
-    <div class="terminal">
-        <h1 class="info-title">Server Version Information</h1>
-        <div class="data-grid">
-            <div class="label">Version:</div>
-            <div class="value">0.1.0</div>
-            <div class="label">Platform:</div>
-            <div class="value">{}</div>
-            <div class="label">Architecture:</div>
-            <div class="value">{}</div>
-            <div class="label">Build Time:</div>
-            <div class="value">{}</div>
-        </div>
-        <h2 class="info-title">Raw JSON Response</h2>
-        <pre>{}</pre>
-    </div>
-</body>
-</html>"#,
-                    std::env::consts::OS,
-                    std::env::consts::ARCH,
-                    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
-                    json
-                ),
-                "200 OK",
-                "/version"
-                )
+/// A number of seconds, such as `2.5`.
+fn seconds(value: &str) -> Result<Duration, String> {
+    value
+        .parse()
+        .map_err(|_| "expected a number of seconds".to_string())
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).map_err(|e| e.to_string()))
+}
+
+/// A `--summary` interval: a positive number of seconds.
+fn interval(value: &str) -> Result<Duration, String> {
+    match seconds(value)? {
+        interval if interval.is_zero() => Err("expected a positive number of seconds".to_string()),
+        interval => Ok(interval),
+    }
+}
+
+/// The worker count of `--workers`, or `WORKERS`, if given; fails if
+/// `WORKERS` is not a positive number.
+fn workers(cli: &Cli) -> Result<Option<NonZeroUsize>, ()> {
+    match cli.workers {
+        Some(workers) => Ok(Some(workers)),
+        None => env_parsed("WORKERS", str::parse),
+    }
+}
+
+/// The runtime of `--runtime`, or `RUNTIME`, to serve on; fails if it is
+/// unknown.
+fn runtime(cli: &Cli) -> Result<rusty_server::Runtime, ()> {
+    match cli.runtime {
+        Some(runtime) => Ok(runtime),
+        None => Ok(env_parsed("RUNTIME", str::parse)?.unwrap_or_default()),
+    }
+}
+
+/// Where the certificate for HTTPS comes from: `--tls-cert`, `--tls-key`,
+/// `--tls-client-ca`, each `--tls-sni`, and each `--acme-domain`, or
+/// `TLS_CERT`, `TLS_KEY`, `TLS_CLIENT_CA`, `TLS_SNI`, and `ACME_DOMAINS`
+/// (both comma-separated) without them; the first `--tls-sni` certificate
+/// stands in for `--tls-cert` without it. Fails if only one of the
+/// certificate and key is given, the client CA is given without them, ACME
+/// domains are given with them, a `TLS_SNI` item is malformed, or any is
+/// given without the feature it needs.
+fn https(cli: &Cli) -> Result<Option<Https>, ()> {
+    let cert = cli.tls_cert.clone().or_else(|| env_var("TLS_CERT").ok());
+    let key = cli.tls_key.clone().or_else(|| env_var("TLS_KEY").ok());
+    let client_ca = cli.tls_client_ca.clone().or_else(|| env_var("TLS_CLIENT_CA").ok());
+    let named = match &cli.tls_sni[..] {
+        [] => env_list("TLS_SNI", parse_named)?,
+        named => named.to_vec(),
+    };
+    let domains = match &cli.acme_domain[..] {
+        [] => env_var("ACME_DOMAINS").map(|list| split_list(&list)).unwrap_or_default(),
+        domains => domains.to_vec(),
+    };
+    let (cert, key) = match (cert, key, named.first()) {
+        (None, None, Some((_, cert, key))) => (Some(cert.clone()), Some(key.clone())),
+        (cert, key, _) => (cert, key),
+    };
+    match (cert, key) {
+        (None, None) if client_ca.is_some() => {
+            eprintln!("Error: --tls-client-ca needs --tls-cert and --tls-key, or --tls-sni");
+            Err(())
+        }
+        (None, None) if domains.is_empty() => Ok(None),
+        #[cfg(feature = "acme")]
+        (None, None) => Ok(Some(Https::Acme(domains))),
+        #[cfg(not(feature = "acme"))]
+        (None, None) => {
+            eprintln!("Error: --acme-domain needs the `acme` feature");
+            Err(())
+        }
+        (Some(_), Some(_)) if !domains.is_empty() => {
+            eprintln!("Error: --acme-domain cannot be combined with --tls-cert or --tls-sni");
+            Err(())
+        }
+        #[cfg(feature = "tls")]
+        (Some(cert), Some(key)) => Ok(Some(Https::Files { cert, key, client_ca, named })),
+        #[cfg(not(feature = "tls"))]
+        (Some(_), Some(_)) => {
+            eprintln!("Error: HTTPS needs the `tls` feature");
+            Err(())
+        }
+        (Some(_), None) => {
+            eprintln!("Error: --tls-cert needs --tls-key");
+            Err(())
+        }
+        (None, Some(_)) => {
+            eprintln!("Error: --tls-key needs --tls-cert");
+            Err(())
+        }
+    }
+}
+
+/// The host, certificate file, and key file in `named`, a `--tls-sni`
+/// value of the form `HOST=CERT:KEY`.
+fn parse_named(named: &str) -> Result<(String, String, String), String> {
+    named
+        .split_once('=')
+        .and_then(|(host, files)| Some((host, files.split_once(':')?)))
+        .filter(|(host, (cert, key))| !host.is_empty() && !cert.is_empty() && !key.is_empty())
+        .map(|(host, (cert, key))| (host.to_string(), cert.to_string(), key.to_string()))
+        .ok_or_else(|| "expected HOST=CERT:KEY".to_string())
+}
+
+/// Certificates for `domains` from the ACME certificate authority at
+/// `ACME_DIRECTORY` (Let's Encrypt by default), stored in `ACME_DIR`
+/// (`acme` by default), with `ACME_EMAIL` as the account's contact. A
+/// stored certificate is loaded straight away.
+#[cfg(feature = "acme")]
+fn acme_certificates(domains: Vec<String>) -> rusty_server::acme::Acme {
+    let dir = env_var("ACME_DIR").unwrap_or_else(|_| ACME_DIR.to_string());
+    let mut acme = rusty_server::acme::Acme::new(domains, dir);
+    if let Ok(url) = env_var("ACME_DIRECTORY") {
+        acme = acme.directory(url);
+    }
+    if let Ok(email) = env_var("ACME_EMAIL") {
+        acme = acme.contact(email);
+    }
+    if let Err(e) = acme.load() {
+        console::print_table(&[("ACME", format!("✗ cannot load the stored certificate: {}", e))]);
+    }
+    acme
+}
+
+/// The addresses to listen on: each `--listen`, and each `--host` on
+/// `--port`. Without any of them, `LISTEN_ADDRESS`, `SERVER_HOST`, and
+/// `SERVER_PORT` stand in for them, and the address defaults to
+/// [`ADDRESS`]. Fails if one of the variables is invalid.
+fn addresses(cli: &Cli) -> Result<Vec<SocketAddr>, ()> {
+    let mut addresses = cli.listen.clone();
+    let mut hosts = cli.host.clone();
+    let mut port = cli.port;
+    if addresses.is_empty() && hosts.is_empty() && port.is_none() {
+        addresses = env_list("LISTEN_ADDRESS", str::parse::<SocketAddr>)?;
+        hosts = match server_var("HOST") {
+            Ok(list) => split_list(&list)
+                .iter()
+                .map(|host| host.parse().map_err(|_| eprintln!("Error: invalid SERVER_HOST item `{}`", host)))
+                .collect::<Result<_, _>>()?,
+            Err(_) => Vec::new(),
+        };
+        port = match server_var("PORT") {
+            Ok(port) => Some(port.parse().map_err(|_| eprintln!("Error: invalid SERVER_PORT `{}`", port))?),
+            Err(_) => None,
+        };
+    }
+    if hosts.is_empty() && port.is_none() {
+        if addresses.is_empty() {
+            addresses.push(ADDRESS.parse().expect("the default address is valid"));
+        }
+        return Ok(addresses);
+    }
+    if hosts.is_empty() {
+        hosts.push(HOST);
+    }
+    let port = port.unwrap_or(PORT);
+    addresses.extend(hosts.into_iter().map(|host| SocketAddr::new(host, port)));
+    Ok(addresses)
+}
+
+/// Reads the settings in the config file at `path`: `NAME=value` lines, with
+/// the names of the environment variables (with or without the `SERVER_`
+/// prefix) and optionally quoted values. Blank lines and lines starting with
+/// `#` are skipped.
+fn read_config_file(path: &str) -> Result<BTreeMap<String, String>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    let mut settings = BTreeMap::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((name, value)) = line.split_once('=') else {
+            return Err(format!("{}:{}: expected NAME=value", path, index + 1));
+        };
+        let name = name.trim();
+        let name = name.strip_prefix("SERVER_").unwrap_or(name);
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value);
+        settings.insert(name.to_string(), value.to_string());
+    }
+    Ok(settings)
+}
+
+/// Reads the config file again and applies the settings that can change
+/// while the server runs: the keep-alive, request, and write timeouts, the
+/// requests per connection, the request size limits, the connection limit,
+/// the log level, and the static files. Prints a table of what changed, and returns the
+/// changes as `(setting, old, new)`.
+fn reload() -> Result<Vec<(&'static str, String, String)>, String> {
+    let result = (|| {
+        let path = CONFIG_FILE.get().ok_or("no config file was given")?;
+        let settings = read_config_file(path)?;
+        let mut running = RUNNING.lock().unwrap_or_else(PoisonError::into_inner);
+        let running = running.as_mut().ok_or("the server is not running yet")?;
+        let before = reloadable(running);
+
+        let mut file = FILE_SETTINGS.write().unwrap_or_else(PoisonError::into_inner);
+        let previous = std::mem::replace(&mut *file, settings);
+        drop(file);
+        // The config file is only kept if the static files it names can be
+        // served.
+        let Some(Ok(files)) = CLI.get().map(static_settings) else {
+            *FILE_SETTINGS.write().unwrap_or_else(PoisonError::into_inner) = previous;
+            return Err("invalid static file settings".to_string());
+        };
+        *STATIC_FILES.write().unwrap_or_else(PoisonError::into_inner) = Some(files);
+        let reloaded = config();
+        running.keep_alive_timeout = reloaded.keep_alive_timeout;
+        running.max_requests = reloaded.max_requests;
+        running.request_timeout = reloaded.request_timeout;
+        running.write_timeout = reloaded.write_timeout;
+        running.max_head_size = reloaded.max_head_size;
+        running.max_body_size = reloaded.max_body_size;
+        running.max_connections = reloaded.max_connections;
+        running.shedding = reloaded.shedding;
+        rusty_server::server::reconfigure(running.clone());
+        if !DEBUG_FLAG.load(Ordering::Relaxed) {
+            console::set_debug(log_level_is_debug());
+        }
+
+        let changes: Vec<_> = before
+            .into_iter()
+            .zip(reloadable(running))
+            .filter(|((_, old), (_, new))| old != new)
+            .map(|((setting, old), (_, new))| (setting, old, new))
+            .collect();
+        Ok(changes)
+    })();
+
+    match &result {
+        Ok(changes) => {
+            let mut rows = vec![("Status", "Config Reloaded".to_string())];
+            rows.extend(changes.iter().map(|(setting, old, new)| (*setting, format!("{} → {}", old, new))));
+            if changes.is_empty() {
+                rows.push(("Changes", "none".to_string()));
             }
-        },
-        s if s.starts_with("GET ") => {
-            let path = s.split_whitespace().nth(1).unwrap_or("/unknown");
-            (
-                "HTTP/1.1 404 NOT FOUND",
-                format!(r#"<!DOCTYPE html>
-<html>
-<head>
-    <title>404 - Not Found</title>
-    <style>
-        @import url('https://fonts.googleapis.com/css2?family=Fira+Code:wght@400;600&display=swap');
-        body {{ 
-            font-family: 'Fira Code', monospace;
-            background: #1c1c1c;
-            color: #d4d4d4;
-            padding: 2rem;
-            margin: 0;
-            line-height: 1.5;
-        }}
-        .terminal {{
-            background: #252525;
-            border: 1px solid #333;
-            border-radius: 8px;
-            padding: 2rem;
-            max-width: 800px;
-            margin: 2rem auto;
-            box-shadow: 0 10px 30px rgba(0,0,0,0.3);
-        }}
-        .error-code {{
-            color: #ff6b6b;
-            font-size: 1.5rem;
-            margin-bottom: 1.5rem;
-            font-weight: 600;
-        }}
-        .path-box {{
-            background: #1c1c1c;
-            border: 1px solid #333;
-            border-radius: 4px;
-            padding: 1rem;
-            margin: 1rem 0;
-            font-family: 'Fira Code', monospace;
-            color: #4d9375;
-        }}
-        .divider {{
-            border-top: 1px solid #333;
-            margin: 2rem 0;
-        }}
-        .endpoints-table {{
-            width: 100%;
-            border-collapse: collapse;
-            margin: 1rem 0;
-        }}
-        .endpoints-table th {{
-            text-align: left;
-            padding: 0.5rem;
-            color: #808080;
-            border-bottom: 1px solid #333;
-        }}
-        .endpoints-table td {{
-            padding: 0.5rem;
-            border-bottom: 1px solid #2a2a2a;
-        }}
-        .endpoint-path {{
-            color: #4d9375;
-            font-weight: 600;
-        }}
-        .endpoint-method {{
-            color: #569cd6;
-        }}
-        .endpoint-desc {{
-            color: #808080;
-        }}
-        .status {{
-            color: #ff6b6b;
-            margin-bottom: 1rem;
-        }}
-    </style>
-</head>
-<body>
-    <div class="terminal">
-        <div class="error-code">Error: Path Not Found</div>
-        <div class="status">Status: 404 Not Found</div>
-        <p>The requested path does not exist:</p>
-        <div class="path-box">{path}</div>
-        
-        <div class="divider"></div>
-        
-        <p>Available Endpoints:</p>
-        <table class="endpoints-table">
-            <thead>
-                <tr>
-                    <th>Method</th>
-                    <th>Path</th>
-                    <th>Description</th>
-                    <th>Response Type</th>
-                </tr>
-            </thead>
-            <tbody>
-                <tr>
-                    <td class="endpoint-method">GET</td>
-                    <td class="endpoint-path">/version</td>
-                    <td class="endpoint-desc">Server version information</td>
-                    <td class="endpoint-desc">application/json</td>
-                </tr>
-                <tr>
-                    <td class="endpoint-method">GET</td>
-                    <td class="endpoint-path">/healthz</td>
-                    <td class="endpoint-desc">Health check endpoint</td>
-                    <td class="endpoint-desc">application/json</td>
-                </tr>
-                <tr>
-                    <td class="endpoint-method">GET</td>
-                    <td class="endpoint-path">/metrics</td>
-                    <td class="endpoint-desc">Prometheus metrics</td>
-                    <td class="endpoint-desc">text/plain</td>
-                </tr>
-            </tbody>
-        </table>
-
-        <div class="divider"></div>
-        <p class="endpoint-desc">Tip: Use curl -v for detailed request/response information</p>
-    </div>
-</body>
-</html>"#),
-                "404 Not Found",
-                path
-            )
-        },
-        &_ => (
-            "HTTP/1.1 400 BAD REQUEST",
-            format!(r#"<!DOCTYPE html>
-<html>
-<head>
-    <title>400 - Bad Request</title>
-    <style>
-        body {{ 
-            font-family: 'Courier New', monospace;
-            background: #1a1a1a;
-            color: #e0e0e0;
-            padding: 40px;
-            line-height: 1.6;
-        }}
-        .terminal {{
-            background: #252525;
-            border-radius: 6px;
-            padding: 20px;
-            box-shadow: 0 4px 6px rgba(0, 0, 0, 0.3);
-            border: 1px solid #333;
-        }}
-        .error-title {{
-            color: #ff6b6b;
-            font-size: 24px;
-            margin: 0 0 20px 0;
-            display: flex;
-            align-items: center;
-            gap: 10px;
-        }}
-        .error-title::before {{
-            content: "✗";
-            color: #ff6b6b;
-        }}
-    </style>
-</head>
-<body>
-    <div class="terminal">
-        <h1 class="error-title">400 - Bad Request</h1>
-        <p>The request was malformed or invalid.</p>
-    </div>
-</body>
-</html>"#),
-            "400 Bad Request",
-            "/unknown"
+            console::print_table(&rows);
+        }
+        Err(e) => console::print_table(&[("Error", format!("cannot reload the config: {}", e))]),
+    }
+    result
+}
+
+/// The settings [`reload`] can change, as shown in its table.
+fn reloadable(config: &rusty_server::Config) -> Vec<(&'static str, String)> {
+    let keep_alive = match config.keep_alive_timeout {
+        Some(timeout) => format!("{}s", timeout.as_secs_f64()),
+        None => "off".to_string(),
+    };
+    let max_requests = match config.max_requests {
+        Some(max) => max.to_string(),
+        None => "unlimited".to_string(),
+    };
+    let request_timeout = match config.request_timeout {
+        Some(timeout) => format!("{}s", timeout.as_secs_f64()),
+        None => "off".to_string(),
+    };
+    let write_timeout = match config.write_timeout {
+        Some(timeout) => format!("{}s", timeout.as_secs_f64()),
+        None => "off".to_string(),
+    };
+    let max_connections = match (config.max_connections, config.shedding) {
+        (Some(max), rusty_server::Shedding::Close) => format!("{} (close)", max),
+        (Some(max), _) => max.to_string(),
+        (None, _) => "unlimited".to_string(),
+    };
+    let log_level = if rusty_server::console::debug() { "debug" } else { "normal" };
+    let files = STATIC_FILES.read().unwrap_or_else(PoisonError::into_inner);
+    let (static_dir, static_prefix, spa, mime_types) = match &*files {
+        Some(files) => (
+            files.dir.clone().unwrap_or_else(|| "off".to_string()),
+            files.prefix.clone(),
+            if files.spa { "on" } else { "off" },
+            files
+                .mime_types
+                .iter()
+                .map(|(extension, content_type)| format!("{}={}", extension, content_type))
+                .collect(),
         ),
+        None => ("off".to_string(), STATIC_PREFIX.to_string(), "off", Vec::<String>::new()),
     };
+    let mime_types = if mime_types.is_empty() {
+        "none".to_string()
+    } else {
+        mime_types.join(", ")
+    };
+    vec![
+        ("Keep-alive timeout", keep_alive),
+        ("Max requests", max_requests),
+        ("Request timeout", request_timeout),
+        ("Write timeout", write_timeout),
+        ("Max head size", format!("{} bytes", config.max_head_size)),
+        ("Max body size", format!("{} bytes", config.max_body_size)),
+        ("Max connections", max_connections),
+        ("Log level", log_level.to_string()),
+        ("Static dir", static_dir),
+        ("Static prefix", static_prefix),
+        ("SPA", spa.to_string()),
+        ("MIME types", mime_types),
+    ]
+}
+
+/// `POST /admin/reload`: [`reload`], answered with the changes as a JSON
+/// array.
+fn reload_endpoint() -> rusty_server::Response {
+    match reload() {
+        Ok(changes) => {
+            let changes: Vec<_> = changes
+                .iter()
+                .map(|(setting, old, new)| serde_json::json!({ "setting": setting, "old": old, "new": new }))
+                .collect();
+            rusty_server::Response::json(200, &changes)
+        }
+        Err(e) => rusty_server::Response::text(500, format!("cannot reload the config: {}\n", e)),
+    }
+}
 
-    let response = format!(
-        "{}\r\nContent-Type: text/html\r\nContent-Length: {}\r\n\r\n{}",
-        status_line,
-        content.len(),
-        content
-    );
+/// Makes SIGHUP reload the config file, if one was given; otherwise SIGHUP
+/// keeps its default effect.
+#[cfg(all(unix, feature = "reload"))]
+fn reload_on_hangup() {
+    use signal_hook::consts::SIGHUP;
+    use signal_hook::iterator::Signals;
 
-    stream.write_all(response.as_bytes())?;
-    Ok((bytes_read, status_text.to_string(), path.to_string()))
-}
\ No newline at end of file
+    if CONFIG_FILE.get().is_none() {
+        return;
+    }
+    match Signals::new([SIGHUP]) {
+        Ok(mut signals) => {
+            std::thread::spawn(move || {
+                for _ in signals.forever() {
+                    let _ = reload();
+                }
+            });
+        }
+        Err(e) => eprintln!("Error: cannot handle SIGHUP: {}", e),
+    }
+}
+
+/// Whether `LOG_LEVEL` is `debug`, which keeps the per-connection reports
+/// under `--summary`.
+fn log_level_is_debug() -> bool {
+    env_var("LOG_LEVEL").is_ok_and(|level| level == "debug")
+}
+
+/// The non-empty items of the comma-separated `list`.
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// The setting `name` from the config file, or else the environment variable
+/// `name`, or `SERVER_<name>` in its place: the prefixed name takes
+/// precedence, so a container can set the server's settings without
+/// clashing with other programs'.
+fn env_var(name: &str) -> Result<String, std::env::VarError> {
+    server_var(name).or_else(|_| std::env::var(name))
+}
+
+/// The setting `name` from the config file, or else the environment variable
+/// `SERVER_<name>`.
+fn server_var(name: &str) -> Result<String, std::env::VarError> {
+    let file = FILE_SETTINGS.read().unwrap_or_else(PoisonError::into_inner);
+    match file.get(name) {
+        Some(value) => Ok(value.clone()),
+        None => std::env::var(format!("SERVER_{}", name)),
+    }
+}
+
+/// The setting `name` (see [`env_var`]) parsed with `parse`, if set; fails
+/// if it does not parse.
+fn env_parsed<T, E: std::fmt::Display>(name: &str, parse: impl Fn(&str) -> Result<T, E>) -> Result<Option<T>, ()> {
+    let Ok(value) = env_var(name) else {
+        return Ok(None);
+    };
+    parse(&value)
+        .map(Some)
+        .map_err(|e| eprintln!("Error: invalid {} `{}`: {}", name, value, e))
+}
+
+/// The items of the comma-separated setting `name` (see [`env_var`]), each
+/// parsed with `parse`; fails if one does not parse.
+fn env_list<T, E: std::fmt::Display>(name: &str, parse: impl Fn(&str) -> Result<T, E>) -> Result<Vec<T>, ()> {
+    let list = env_var(name).map(|list| split_list(&list)).unwrap_or_default();
+    list.iter()
+        .map(|item| parse(item).map_err(|e| eprintln!("Error: invalid {} item `{}`: {}", name, item, e)))
+        .collect()
+}
+
+/// Mirrors the console output to a file for the day in `dir`, and says
+/// which.
+fn mirror_console(dir: &str) -> Result<(), ServerError> {
+    let path = rusty_server::console::set_log_dir(dir)?;
+    rusty_server::console::print_line(&format!("Log file: {}", path.display()));
+    Ok(())
+}
+
+/// Points at `--help` after an invalid setting.
+fn usage() -> ExitCode {
+    eprintln!("\n{}\n\nFor more information, try '--help'.", Cli::command().render_usage());
+    // EX_USAGE
+    ExitCode::from(64)
+}
+
+/// Probes the endpoints named by `options` and reports them; fails if any
+/// probe does.
+fn check(options: &CheckArgs) -> ExitCode {
+    use rusty_server::check::{self, Check};
+
+    let url = options.url.clone().unwrap_or_else(|| format!("http://{}", ADDRESS));
+    let mut probe = Check::new(url);
+    if let Some(endpoints) = &options.endpoints {
+        probe = probe.endpoints(endpoints.split(',').filter(|e| !e.is_empty()));
+    }
+    if let Some(status) = options.status {
+        probe = probe.status(status);
+    }
+    if let Some(content_type) = &options.content_type {
+        probe = probe.content_type(content_type);
+    }
+    if let Some(timeout) = options.timeout {
+        probe = probe.timeout(timeout);
+    }
+
+    let probes = probe.run();
+    check::print(&probes);
+    if probes.iter().all(|probe| probe.passed()) {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+/// Echoes raw TCP on `address`, or on a port of the loopback interface.
+fn tcp_echo(address: &str) -> Result<(), ServerError> {
+    let address = match address.parse::<u16>() {
+        Ok(port) => format!("127.0.0.1:{}", port),
+        Err(_) => address.to_string(),
+    };
+    rusty_server::echo::serve(bind(&address)?)
+}
+
+/// Announces the server over mDNS as `MDNS_NAME`, or as `rusty-server on
+/// <host>`, and reports the outcome.
+#[cfg(feature = "mdns")]
+fn advertise(listener: &TcpListener) -> Option<rusty_server::mdns::Advertisement> {
+    let name = env_var("MDNS_NAME").unwrap_or_else(|_| {
+        let host = std::env::var("HOSTNAME")
+            .or_else(|_| std::fs::read_to_string("/etc/hostname"))
+            .map(|host| host.trim().to_string())
+            .unwrap_or_default();
+        if host.is_empty() {
+            "rusty-server".to_string()
+        } else {
+            format!("rusty-server on {}", host)
+        }
+    });
+    let advertised = listener
+        .local_addr()
+        .map_err(|e| e.to_string())
+        .and_then(|addr| rusty_server::mdns::advertise(&name, addr).map_err(|e| e.to_string()));
+    match advertised {
+        Ok(advertisement) => {
+            console::print_line(&format!("mDNS: ✓ {}", advertisement.fullname()));
+            Some(advertisement)
+        }
+        Err(e) => {
+            console::print_line(&format!("mDNS: ✗ {}", e));
+            None
+        }
+    }
+}
+
+/// Registers the server as `SERVICE_NAME` (`rusty-server` by default) with
+/// the Consul agent at `CONSUL_URL` (using `CONSUL_TOKEN` if set) or the etcd
+/// cluster at `ETCD_URL`, and deregisters it on Ctrl-C.
+#[cfg(feature = "registry")]
+fn register(listener: &TcpListener) {
+    use rusty_server::registry::{Consul, Etcd, Service};
+
+    let name = env_var("SERVICE_NAME").unwrap_or_else(|_| "rusty-server".to_string());
+    let registered = if let Ok(url) = env_var("CONSUL_URL") {
+        let mut consul = Consul::new(url);
+        if let Ok(token) = env_var("CONSUL_TOKEN") {
+            consul = consul.token(token);
+        }
+        ("Consul", consul.register(&service(&name, listener)))
+    } else if let Ok(url) = env_var("ETCD_URL") {
+        ("etcd", Etcd::new(url).register(&service(&name, listener)))
+    } else {
+        return;
+    };
+    match registered {
+        (registry, Ok(registration)) => {
+            console::print_line(&format!("{}: ✓ registered {}", registry, name));
+            on_shutdown(move || {
+                if let Err(e) = registration.deregister() {
+                    console::print_line(&format!("{}: ✗ cannot deregister: {}", registry, e));
+                }
+            });
+        }
+        (registry, Err(e)) => console::print_line(&format!("{}: ✗ {}", registry, e)),
+    }
+
+    fn service(name: &str, listener: &TcpListener) -> Service {
+        let addr = listener
+            .local_addr()
+            .unwrap_or_else(|_| ADDRESS.parse().expect("valid default address"));
+        Service::new(name, addr)
+    }
+}
+
+/// Work to do on Ctrl-C before the process exits, in registration order.
+#[cfg(any(
+    feature = "webhooks",
+    feature = "mdns",
+    feature = "registry",
+    feature = "history",
+    feature = "otel"
+))]
+static SHUTDOWN_HOOKS: std::sync::Mutex<Vec<Box<dyn FnOnce() + Send>>> =
+    std::sync::Mutex::new(Vec::new());
+
+#[cfg(any(
+    feature = "webhooks",
+    feature = "mdns",
+    feature = "registry",
+    feature = "history",
+    feature = "otel"
+))]
+fn on_shutdown(hook: impl FnOnce() + Send + 'static) {
+    shutdown_hooks().push(Box::new(hook));
+}
+
+/// Makes Ctrl-C run the shutdown hooks before exiting, if there are any;
+/// otherwise Ctrl-C keeps its default effect.
+#[cfg(any(
+    feature = "webhooks",
+    feature = "mdns",
+    feature = "registry",
+    feature = "history",
+    feature = "otel"
+))]
+fn handle_shutdown() {
+    if shutdown_hooks().is_empty() {
+        return;
+    }
+    let installed = ctrlc::set_handler(|| {
+        run_shutdown_hooks();
+        std::process::exit(0);
+    });
+    if let Err(e) = installed {
+        eprintln!("Error: cannot handle Ctrl-C: {}", e);
+    }
+}
+
+/// Runs the shutdown hooks registered so far, each at most once.
+#[cfg(any(
+    feature = "webhooks",
+    feature = "mdns",
+    feature = "registry",
+    feature = "history",
+    feature = "otel"
+))]
+fn run_shutdown_hooks() {
+    let hooks = std::mem::take(&mut *shutdown_hooks());
+    for hook in hooks {
+        hook();
+    }
+}
+
+#[cfg(any(
+    feature = "webhooks",
+    feature = "mdns",
+    feature = "registry",
+    feature = "history",
+    feature = "otel"
+))]
+fn shutdown_hooks() -> std::sync::MutexGuard<'static, Vec<Box<dyn FnOnce() + Send>>> {
+    SHUTDOWN_HOOKS
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+/// Starts webhook delivery to the comma-separated `WEBHOOK_URLS`, signed with
+/// `WEBHOOK_SECRET` if that is set. Ctrl-C then reports a shutdown event and
+/// waits for it to go out before exiting.
+#[cfg(feature = "webhooks")]
+fn start_webhooks() {
+    use rusty_server::webhook::{self, Event, Webhooks};
+
+    let Ok(urls) = env_var("WEBHOOK_URLS") else {
+        return;
+    };
+    let mut webhooks = Webhooks::new(urls.split(',').map(str::trim).filter(|url| !url.is_empty()));
+    if let Ok(secret) = env_var("WEBHOOK_SECRET") {
+        webhooks = webhooks.secret(secret);
+    }
+    webhook::install(webhooks.start());
+    on_shutdown(|| {
+        webhook::notify(Event::Shutdown);
+        if let Some(dispatcher) = webhook::installed() {
+            dispatcher.flush(WEBHOOK_FLUSH_TIMEOUT);
+        }
+    });
+}
+
+/// Exports request spans to the OTLP/HTTP collector at
+/// `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`, or at `/v1/traces` under
+/// `OTEL_EXPORTER_OTLP_ENDPOINT`, listed under `OTEL_SERVICE_NAME` if that
+/// is set. Ctrl-C then waits for the last spans to go out before exiting.
+#[cfg(feature = "otel")]
+fn start_otel() {
+    use rusty_server::otel::{self, Otlp};
+
+    let url = match env_var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT") {
+        Ok(url) => url,
+        Err(_) => match env_var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+            Ok(endpoint) => format!("{}/v1/traces", endpoint.trim_end_matches('/')),
+            Err(_) => return,
+        },
+    };
+    let mut otlp = Otlp::new(url);
+    if let Ok(name) = env_var("OTEL_SERVICE_NAME") {
+        otlp = otlp.service_name(name);
+    }
+    otel::install(otlp.start());
+    on_shutdown(|| {
+        if let Some(exporter) = otel::installed() {
+            exporter.flush(OTEL_FLUSH_TIMEOUT);
+        }
+    });
+}
+
+/// Records the requests served if `HISTORY_DIR` is set, and exports them
+/// there on Ctrl-C, or when the dashboard or the admin console quits.
+#[cfg(feature = "history")]
+fn start_history() {
+    use rusty_server::history;
+
+    let Ok(dir) = env_var("HISTORY_DIR") else {
+        return;
+    };
+    history::start(dir);
+    on_shutdown(|| {
+        if let Err(e) = history::export() {
+            eprintln!("Error: cannot export the request history: {}", e);
+        }
+    });
+}
+
+/// The default connection settings, with the request size limits from
+/// `MAX_HEAD_SIZE` and `MAX_BODY_SIZE` (in bytes), the idle timeout from
+/// `KEEP_ALIVE_TIMEOUT` (in seconds; 0 closes connections after one
+/// response), the requests per connection from `MAX_REQUESTS` (0 for no
+/// limit), the request head and response write deadlines from
+/// `REQUEST_TIMEOUT` and `WRITE_TIMEOUT` (in seconds; 0 waits as long as it
+/// takes), and the connection limit from `--max-connections` or
+/// `MAX_CONNECTIONS` and `SHED` if they are set.
+fn config() -> rusty_server::Config {
+    let mut config = rusty_server::Config::default();
+    if let Some(size) = size_from_env("MAX_HEAD_SIZE") {
+        config.max_head_size = size;
+    }
+    if let Some(size) = size_from_env("MAX_BODY_SIZE") {
+        config.max_body_size = size;
+    }
+    if let Ok(seconds) = env_var("KEEP_ALIVE_TIMEOUT") {
+        match seconds.parse().map(std::time::Duration::try_from_secs_f64) {
+            Ok(Ok(timeout)) => config.keep_alive_timeout = (!timeout.is_zero()).then_some(timeout),
+            _ => eprintln!("Error: invalid KEEP_ALIVE_TIMEOUT `{}`; using the default", seconds),
+        }
+    }
+    if let Ok(max) = env_var("MAX_REQUESTS") {
+        match max.parse() {
+            Ok(max) => config.max_requests = NonZeroUsize::new(max),
+            Err(_) => eprintln!("Error: invalid MAX_REQUESTS `{}`; using the default", max),
+        }
+    }
+    if let Ok(seconds) = env_var("REQUEST_TIMEOUT") {
+        match seconds.parse().map(std::time::Duration::try_from_secs_f64) {
+            Ok(Ok(timeout)) => config.request_timeout = (!timeout.is_zero()).then_some(timeout),
+            _ => eprintln!("Error: invalid REQUEST_TIMEOUT `{}`; using the default", seconds),
+        }
+    }
+    if let Ok(seconds) = env_var("WRITE_TIMEOUT") {
+        match seconds.parse().map(std::time::Duration::try_from_secs_f64) {
+            Ok(Ok(timeout)) => config.write_timeout = (!timeout.is_zero()).then_some(timeout),
+            _ => eprintln!("Error: invalid WRITE_TIMEOUT `{}`; using the default", seconds),
+        }
+    }
+    config.max_connections = MAX_CONNECTIONS
+        .get()
+        .copied()
+        .or_else(|| size_from_env("MAX_CONNECTIONS").and_then(NonZeroUsize::new));
+    if let Ok(shedding) = env_var("SHED") {
+        match shedding.parse() {
+            Ok(shedding) => config.shedding = shedding,
+            Err(e) => eprintln!("Error: {}; using the default", e),
+        }
+    }
+    config
+}
+
+/// The positive byte count in the environment variable `name`; an invalid
+/// one is reported and ignored.
+fn size_from_env(name: &str) -> Option<usize> {
+    let value = env_var(name).ok()?;
+    match value.parse() {
+        Ok(size) if size > 0 => Some(size),
+        _ => {
+            eprintln!("Error: invalid {} `{}`; using the default", name, value);
+            None
+        }
+    }
+}
+
+/// CORS middleware allowing `origins`, a comma-separated list or `*`, as
+/// tuned by the `CORS_*` settings.
+fn cors(origins: &str) -> rusty_server::cors::Cors {
+    let mut cors = rusty_server::cors::Cors::new();
+    for origin in split_list(origins) {
+        cors = cors.allow_origin(&origin);
+    }
+    let list = |name| env_var(name).ok().map(|list| split_list(&list));
+    if let Some(methods) = list("CORS_METHODS") {
+        cors = cors.allow_methods(&methods.iter().map(String::as_str).collect::<Vec<_>>());
+    }
+    if let Some(headers) = list("CORS_HEADERS") {
+        cors = cors.allow_headers(&headers.iter().map(String::as_str).collect::<Vec<_>>());
+    }
+    if let Some(headers) = list("CORS_EXPOSE_HEADERS") {
+        cors = cors.expose_headers(&headers.iter().map(String::as_str).collect::<Vec<_>>());
+    }
+    if matches!(env_var("CORS_CREDENTIALS").as_deref(), Ok("1" | "true")) {
+        cors = cors.allow_credentials(true);
+    }
+    if let Some(secs) = size_from_env("CORS_MAX_AGE") {
+        cors = cors.max_age(std::time::Duration::from_secs(secs as u64));
+    }
+    cors
+}
+
+/// Basic authentication on `prefixes`, a comma-separated list, for the users
+/// in `BASIC_AUTH_USERS` and the `htpasswd` file, in the realm
+/// `BASIC_AUTH_REALM`.
+#[cfg(feature = "auth")]
+fn basic_auth_for(
+    prefixes: &str,
+    htpasswd: Option<&str>,
+) -> Result<rusty_server::auth::BasicAuth, rusty_server::auth::HtpasswdError> {
+    let realm = env_var("BASIC_AUTH_REALM").unwrap_or_else(|_| "rusty-server".to_string());
+    let mut auth = rusty_server::auth::BasicAuth::new(&realm);
+    for prefix in split_list(prefixes) {
+        auth = auth.protect(&prefix);
+    }
+    if let Ok(users) = env_var("BASIC_AUTH_USERS") {
+        auth = auth.htpasswd(&split_list(&users).join("\n"))?;
+    }
+    match htpasswd {
+        Some(path) => auth.htpasswd_file(path),
+        None => Ok(auth),
+    }
+}
+
+/// The first of the comma-separated `prefixes` that overlaps a prefix
+/// [`router`] mounts CGI or FastCGI on: their streaming handlers are
+/// dispatched before middleware runs, so authentication cannot cover them.
+#[cfg(feature = "auth")]
+fn streamed(prefixes: &str) -> Option<String> {
+    let mut streams = Vec::new();
+    if env_var("FASTCGI_ADDRESS").is_ok() {
+        streams.push(FASTCGI_PREFIX);
+    }
+    if env_var("CGI_DIR").is_ok() {
+        streams.push(CGI_PREFIX);
+    }
+    split_list(prefixes)
+        .into_iter()
+        .find(|prefix| streams.iter().any(|stream| overlap(prefix, stream)))
+}
+
+/// Whether the path prefixes `a` and `b` cover any path in common: `/admin`
+/// and `/admin/stats` do, `/php` and `/phpinfo` do not.
+#[cfg(feature = "auth")]
+fn overlap(a: &str, b: &str) -> bool {
+    let covers = |prefix: &str, path: &str| {
+        let prefix = prefix.trim_end_matches('/');
+        path.strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    };
+    covers(a, b) || covers(b, a)
+}
+
+/// The built-in routes plus the CGI and FastCGI mounts, the static files
+/// of [`STATIC_FILES`], and the routes of every plugin and script, with the admin
+/// endpoints if `ADMIN_API` is `1` or `true` (for the client certificate
+/// names in `ADMIN_CLIENTS` only, if it is set), documented at `/docs` if
+/// `API_DOCS` is, open to the origins in `--cors` or `CORS_ORIGINS`, limited
+/// to [`RATE_LIMIT`] per client, behind the authentication of
+/// `--basic-auth` and `--bearer-auth`, and compressed if [`COMPRESS`] is
+/// set.
+fn router() -> rusty_server::Router {
+    #[cfg(any(feature = "plugins", feature = "wasm"))]
+    let mut router = router_with_plugins();
+    #[cfg(not(any(feature = "plugins", feature = "wasm")))]
+    let mut router = rusty_server::Router::default();
+    if let Ok(address) = env_var("FASTCGI_ADDRESS") {
+        let root = env_var("FASTCGI_ROOT").unwrap_or_else(|_| ".".to_string());
+        let backend = rusty_server::fastcgi::FastCgi::new(&address, root);
+        router.stream(FASTCGI_PREFIX, move |request, connection| {
+            backend.serve(request, connection)
+        });
+    }
+    if let Ok(dir) = env_var("CGI_DIR") {
+        let cgi = rusty_server::cgi::Cgi::new(CGI_PREFIX, dir);
+        router.stream(CGI_PREFIX, move |request, connection| {
+            cgi.serve(request, connection)
+        });
+    }
+    router.fallback(|request| {
+        let files = STATIC_FILES.read().unwrap_or_else(PoisonError::into_inner);
+        files.as_ref()?.files.as_ref()?.serve(request)
+    });
+    #[cfg(feature = "scripting")]
+    {
+        let scripts = std::sync::Arc::new(rusty_server::script::Scripts::new(SCRIPT_DIR));
+        report_scripts(scripts.reload());
+        let handler = std::sync::Arc::clone(&scripts);
+        router.fallback(move |request| handler.handle(request));
+        std::thread::spawn(move || loop {
+            std::thread::sleep(SCRIPT_RELOAD_INTERVAL);
+            report_scripts(scripts.reload());
+        });
+    }
+    if matches!(env_var("ADMIN_API").as_deref(), Ok("1" | "true")) {
+        if CONFIG_FILE.get().is_some() {
+            router.post(RELOAD_PATH, |_| reload_endpoint());
+        }
+        if let Ok(clients) = env_var("ADMIN_CLIENTS") {
+            let clients = split_list(&clients);
+            router.guard(ADMIN_PREFIX, move |request| {
+                let allowed = request.client_cn.as_ref().is_some_and(|cn| clients.contains(cn));
+                (!allowed).then(|| rusty_server::Response::text(403, "client certificate required\n"))
+            });
+        }
+        router.admin();
+    }
+    if matches!(env_var("API_DOCS").as_deref(), Ok("1" | "true")) {
+        router.docs();
+    }
+    if let Some(origins) = CORS_ORIGINS.get().cloned().or_else(|| env_var("CORS_ORIGINS").ok()) {
+        router.middleware(cors(&origins));
+    }
+    // After CORS, so that pages on other origins can read the 429 and the
+    // 401, and before authentication, so that passwords cannot be guessed
+    // at full speed.
+    if let Some(&(rate, burst)) = RATE_LIMIT.get() {
+        let mut limit = rusty_server::ratelimit::RateLimit::new(rate);
+        if let Some(burst) = burst {
+            limit = limit.burst(burst);
+        }
+        router.middleware(limit);
+    }
+    #[cfg(feature = "auth")]
+    if let Some(auth) = BASIC_AUTH.get() {
+        router.middleware(auth.clone());
+    }
+    #[cfg(feature = "auth")]
+    if let Some(auth) = BEARER_AUTH.get() {
+        router.middleware(auth.clone());
+    }
+    #[cfg(feature = "compression")]
+    if COMPRESS.load(Ordering::Relaxed) {
+        let mut compress = rusty_server::compress::Compress::new();
+        if let Some(size) = size_from_env("COMPRESS_MIN_SIZE") {
+            compress = compress.min_size(size);
+        }
+        router.middleware(compress);
+    }
+    router
+}
+
+#[cfg(feature = "scripting")]
+fn report_scripts(
+    outcomes: Vec<(std::path::PathBuf, Result<Vec<String>, rusty_server::script::ScriptError>)>,
+) {
+    for (path, result) in outcomes {
+        let status = match result {
+            Ok(routes) => format!("✓ {}", routes.join(", ")),
+            Err(e) => format!("✗ {}", e),
+        };
+        rusty_server::console::print_line(&format!("Script {}: {}", path.display(), status));
+    }
+}
+
+/// The built-in routes plus those of every plugin in [`PLUGIN_DIR`].
+#[cfg(any(feature = "plugins", feature = "wasm"))]
+fn router_with_plugins() -> rusty_server::Router {
+    let mut router = rusty_server::Router::default();
+    let mut results = Vec::new();
+    // SAFETY: the plugins directory is operator-controlled, like the binary.
+    #[cfg(feature = "plugins")]
+    results.extend(unsafe { rusty_server::plugin::load_dir(&mut router, PLUGIN_DIR.as_ref()) });
+    #[cfg(feature = "wasm")]
+    results.extend(rusty_server::plugin::wasm::load_dir(
+        &mut router,
+        PLUGIN_DIR.as_ref(),
+        Default::default(),
+    ));
+    for (path, result) in results {
+        let status = match result {
+            Ok(routes) => format!("✓ {}", routes.join(", ")),
+            Err(e) => format!("✗ {}", e),
+        };
+        rusty_server::console::print_line(&format!("Plugin {}: {}", path.display(), status));
+    }
+    router
+}
+
+fn bind(addr: &str) -> Result<TcpListener, ServerError> {
+    TcpListener::bind(addr).map_err(|source| ServerError::Bind {
+        addr: addr.to_string(),
+        source,
+    })
+}
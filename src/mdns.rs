@@ -0,0 +1,109 @@
+//! mDNS (zeroconf) advertisement of the server as an `_http._tcp` service.
+//!
+//! [`advertise`] announces a listening address on the local network, so
+//! that machines on the same LAN can find the server without being told its
+//! address: with `dns-sd -B _http._tcp` on macOS, `avahi-browse _http._tcp`
+//! on Linux, or the service browser of most file managers. The service
+//! stays announced until the returned [`Advertisement`] is dropped, which
+//! sends a goodbye so it disappears from browsers straight away.
+
+use std::net::SocketAddr;
+
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+
+/// The DNS-SD service type the server is announced under.
+pub const SERVICE_TYPE: &str = "_http._tcp.local.";
+
+/// Why the server could not be advertised.
+#[derive(Debug, thiserror::Error)]
+pub enum MdnsError {
+    /// The server only listens on a loopback address, which no other
+    /// machine can reach.
+    #[error("{0} is only reachable from this machine")]
+    Loopback(SocketAddr),
+    /// The mDNS responder could not be started or rejected the service.
+    #[error("mDNS responder failed: {0}")]
+    Daemon(#[from] mdns_sd::Error),
+}
+
+/// A running advertisement; dropping it withdraws the service.
+pub struct Advertisement {
+    daemon: ServiceDaemon,
+    fullname: String,
+}
+
+impl Advertisement {
+    /// The full service instance name, such as
+    /// `rusty-server on lab-3._http._tcp.local.`.
+    pub fn fullname(&self) -> &str {
+        &self.fullname
+    }
+}
+
+impl Drop for Advertisement {
+    fn drop(&mut self) {
+        // Best effort: the goodbye only speeds up what the record TTL would
+        // do anyway.
+        if let Ok(done) = self.daemon.unregister(&self.fullname) {
+            let _ = done.recv();
+        }
+        let _ = self.daemon.shutdown();
+    }
+}
+
+/// Announces the server listening on `addr` under the instance name `name`.
+///
+/// An unspecified address (`0.0.0.0` or `::`) is announced with the
+/// addresses of every network interface, kept up to date as they change.
+pub fn advertise(name: &str, addr: SocketAddr) -> Result<Advertisement, MdnsError> {
+    if addr.ip().is_loopback() {
+        return Err(MdnsError::Loopback(addr));
+    }
+    let host_name = format!("{}.local.", host_label(name));
+    let properties = [("path", "/"), ("version", env!("CARGO_PKG_VERSION"))];
+    let info = if addr.ip().is_unspecified() {
+        ServiceInfo::new(
+            SERVICE_TYPE,
+            name,
+            &host_name,
+            "",
+            addr.port(),
+            &properties[..],
+        )?
+        .enable_addr_auto()
+    } else {
+        ServiceInfo::new(
+            SERVICE_TYPE,
+            name,
+            &host_name,
+            addr.ip(),
+            addr.port(),
+            &properties[..],
+        )?
+    };
+    let fullname = info.get_fullname().to_string();
+
+    let daemon = ServiceDaemon::new()?;
+    daemon.register(info)?;
+    Ok(Advertisement { daemon, fullname })
+}
+
+/// `name` as a DNS host label: lower-case letters, digits, and hyphens.
+fn host_label(name: &str) -> String {
+    let label: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let label = label.trim_matches('-');
+    if label.is_empty() {
+        "rusty-server".to_string()
+    } else {
+        label.chars().take(63).collect()
+    }
+}
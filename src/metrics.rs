@@ -0,0 +1,375 @@
+//! Request counters and latency histograms for `/metrics`, in the
+//! Prometheus text exposition format.
+//!
+//! Every answered request is counted into `rusty_server_http_requests_total`
+//! by the route it matched (as registered, so `/status/:code` rather than
+//! `/status/404`), its method, and its status class (`2xx`, `4xx`, ...), and
+//! timed into `rusty_server_http_request_duration_seconds` by route, method,
+//! and status. Requests no route matches share `path="unmatched"`, which
+//! keeps the number of series bounded. The bucket
+//! bounds default to [`DEFAULT_BUCKETS`]; [`set_buckets`] changes them.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use crate::server::Exchange;
+use crate::{stats, Router, ServerError};
+
+/// The upper bounds of the latency buckets, in seconds, unless
+/// [`set_buckets`] changes them: the Prometheus client libraries' defaults.
+pub const DEFAULT_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// The `path` label of requests no route matches.
+const UNMATCHED: &str = "unmatched";
+
+/// Responses sent, by status code.
+static RESPONSES: Mutex<BTreeMap<u16, u64>> = Mutex::new(BTreeMap::new());
+/// Connections that ended in an I/O error or a handler panic.
+static FAILURES: AtomicU64 = AtomicU64::new(0);
+/// Requests turned away by rate limiting.
+static THROTTLED: AtomicU64 = AtomicU64::new(0);
+/// Connections shed beyond the connection limit.
+static REJECTED: AtomicU64 = AtomicU64::new(0);
+/// Connections aborted because the client stopped reading.
+static WRITE_TIMEOUTS: AtomicU64 = AtomicU64::new(0);
+static BYTES_READ: AtomicU64 = AtomicU64::new(0);
+static BYTES_WRITTEN: AtomicU64 = AtomicU64::new(0);
+/// Time spent on connections, in microseconds.
+static DURATION_MICROS: AtomicU64 = AtomicU64::new(0);
+/// Requests answered, by route, method, and status class.
+static REQUESTS: Mutex<BTreeMap<(String, String, String), u64>> = Mutex::new(BTreeMap::new());
+static LATENCY: Mutex<Latency> = Mutex::new(Latency {
+    buckets: Cow::Borrowed(&DEFAULT_BUCKETS),
+    series: BTreeMap::new(),
+});
+
+/// Request durations, by route, method, and status.
+struct Latency {
+    /// Upper bounds, in seconds, in increasing order; `+Inf` is implied.
+    buckets: Cow<'static, [f64]>,
+    series: BTreeMap<(String, String, u16), Histogram>,
+}
+
+#[derive(Default)]
+struct Histogram {
+    /// Observations per bucket, not cumulative; the last is for `+Inf`.
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+/// Bucket bounds that are not finite, positive, and strictly increasing.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("latency bucket bounds must be finite, positive, and strictly increasing: {0:?}")]
+pub struct InvalidBuckets(pub Vec<f64>);
+
+/// Times requests into buckets with the upper bounds `buckets`, in seconds,
+/// instead of [`DEFAULT_BUCKETS`]. The durations recorded so far are
+/// discarded, since they were counted into the old buckets.
+pub fn set_buckets(buckets: &[f64]) -> Result<(), InvalidBuckets> {
+    let valid = buckets.iter().all(|bound| bound.is_finite() && *bound > 0.0)
+        && buckets.windows(2).all(|pair| pair[0] < pair[1]);
+    if !valid {
+        return Err(InvalidBuckets(buckets.to_vec()));
+    }
+    let mut latency = LATENCY.lock().unwrap_or_else(PoisonError::into_inner);
+    latency.buckets = Cow::Owned(buckets.to_vec());
+    latency.series.clear();
+    Ok(())
+}
+
+/// Counts a finished connection that wrote `bytes_written` bytes, and
+/// counts and times its request under the route `router` matched.
+pub(crate) fn record(
+    result: &Result<Exchange, ServerError>,
+    router: &Router,
+    bytes_written: u64,
+    elapsed: Duration,
+) {
+    match result {
+        Ok(exchange) => {
+            *RESPONSES
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .entry(exchange.status)
+                .or_default() += 1;
+            BYTES_READ.fetch_add(exchange.bytes_read as u64, Ordering::Relaxed);
+            // Requests that could not be parsed have no method or route.
+            if let Some(request) = &exchange.request {
+                let path = router.pattern(&request.method, &request.path).unwrap_or(UNMATCHED);
+                *REQUESTS
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .entry((path.to_string(), request.method.clone(), class(exchange.status)))
+                    .or_default() += 1;
+                observe(path, &request.method, exchange.status, elapsed);
+            }
+        }
+        Err(_) => {
+            FAILURES.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    BYTES_WRITTEN.fetch_add(bytes_written, Ordering::Relaxed);
+    DURATION_MICROS.fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+}
+
+/// Counts a request turned away by rate limiting.
+pub(crate) fn throttled() {
+    THROTTLED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counts a connection shed beyond the connection limit.
+pub(crate) fn rejected() {
+    REJECTED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counts a connection aborted because the client stopped reading.
+pub(crate) fn write_timeout() {
+    WRITE_TIMEOUTS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// The class of `status`, such as `2xx` for 204.
+pub(crate) fn class(status: u16) -> String {
+    format!("{}xx", status / 100)
+}
+
+/// Counts a request to `path` that took `elapsed` into its histogram.
+fn observe(path: &str, method: &str, status: u16, elapsed: Duration) {
+    let seconds = elapsed.as_secs_f64();
+    let mut latency = LATENCY.lock().unwrap_or_else(PoisonError::into_inner);
+    let bucket = latency
+        .buckets
+        .iter()
+        .position(|bound| seconds <= *bound)
+        .unwrap_or(latency.buckets.len());
+    let buckets = latency.buckets.len();
+    let histogram = latency
+        .series
+        .entry((path.to_string(), method.to_string(), status))
+        .or_default();
+    histogram.counts.resize(buckets + 1, 0);
+    histogram.counts[bucket] += 1;
+    histogram.sum += seconds;
+    histogram.count += 1;
+}
+
+/// All counters, in the Prometheus text format.
+pub(crate) fn render() -> String {
+    let snapshot = stats::snapshot();
+    let responses = RESPONSES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    let failures = FAILURES.load(Ordering::Relaxed);
+    let finished = responses.values().sum::<u64>() + failures;
+
+    let mut text = String::new();
+    metric(
+        &mut text,
+        "rusty_server_uptime_seconds",
+        "gauge",
+        "Seconds since the server started.",
+    );
+    let _ = writeln!(text, "rusty_server_uptime_seconds {}", snapshot.uptime_secs);
+    metric(
+        &mut text,
+        "rusty_server_start_time_seconds",
+        "gauge",
+        "When the server started, in seconds since the Unix epoch.",
+    );
+    let _ = writeln!(text, "rusty_server_start_time_seconds {}", stats::started_at());
+    metric(
+        &mut text,
+        "rusty_server_connections_total",
+        "counter",
+        "Connections accepted.",
+    );
+    let _ = writeln!(
+        text,
+        "rusty_server_connections_total {}",
+        snapshot.connections_total
+    );
+    metric(
+        &mut text,
+        "rusty_server_connections_active",
+        "gauge",
+        "Connections open right now.",
+    );
+    let _ = writeln!(
+        text,
+        "rusty_server_connections_active {}",
+        snapshot.connections_active
+    );
+    metric(
+        &mut text,
+        "rusty_server_connections_idle",
+        "gauge",
+        "Open connections kept alive waiting for another request.",
+    );
+    let _ = writeln!(
+        text,
+        "rusty_server_connections_idle {}",
+        snapshot.connections_idle
+    );
+    metric(
+        &mut text,
+        "rusty_server_responses_total",
+        "counter",
+        "Responses sent, by status code.",
+    );
+    for (status, count) in &responses {
+        let _ = writeln!(
+            text,
+            "rusty_server_responses_total{{status=\"{}\"}} {}",
+            status, count
+        );
+    }
+    metric(
+        &mut text,
+        "rusty_server_connection_failures_total",
+        "counter",
+        "Connections that ended in an I/O error or a handler panic.",
+    );
+    let _ = writeln!(text, "rusty_server_connection_failures_total {}", failures);
+    metric(
+        &mut text,
+        "rusty_server_rejected_connections_total",
+        "counter",
+        "Connections shed beyond the connection limit.",
+    );
+    let _ = writeln!(
+        text,
+        "rusty_server_rejected_connections_total {}",
+        REJECTED.load(Ordering::Relaxed)
+    );
+    metric(
+        &mut text,
+        "rusty_server_write_timeouts_total",
+        "counter",
+        "Connections aborted because the client stopped reading.",
+    );
+    let _ = writeln!(
+        text,
+        "rusty_server_write_timeouts_total {}",
+        WRITE_TIMEOUTS.load(Ordering::Relaxed)
+    );
+    metric(
+        &mut text,
+        "rusty_server_throttled_requests_total",
+        "counter",
+        "Requests turned away with 429 by rate limiting.",
+    );
+    let _ = writeln!(
+        text,
+        "rusty_server_throttled_requests_total {}",
+        THROTTLED.load(Ordering::Relaxed)
+    );
+    metric(
+        &mut text,
+        "rusty_server_received_bytes_total",
+        "counter",
+        "Request bytes read from clients.",
+    );
+    let _ = writeln!(
+        text,
+        "rusty_server_received_bytes_total {}",
+        BYTES_READ.load(Ordering::Relaxed)
+    );
+    metric(
+        &mut text,
+        "rusty_server_sent_bytes_total",
+        "counter",
+        "Response bytes written to clients.",
+    );
+    let _ = writeln!(
+        text,
+        "rusty_server_sent_bytes_total {}",
+        BYTES_WRITTEN.load(Ordering::Relaxed)
+    );
+    metric(
+        &mut text,
+        "rusty_server_connection_duration_seconds",
+        "summary",
+        "Time spent on finished connections.",
+    );
+    let _ = writeln!(
+        text,
+        "rusty_server_connection_duration_seconds_sum {:.6}",
+        DURATION_MICROS.load(Ordering::Relaxed) as f64 / 1e6
+    );
+    let _ = writeln!(
+        text,
+        "rusty_server_connection_duration_seconds_count {}",
+        finished
+    );
+    metric(
+        &mut text,
+        "rusty_server_http_requests_total",
+        "counter",
+        "Requests answered, by route, method, and status class.",
+    );
+    for ((path, method, class), count) in REQUESTS.lock().unwrap_or_else(PoisonError::into_inner).iter() {
+        let _ = writeln!(
+            text,
+            "rusty_server_http_requests_total{{path=\"{}\",method=\"{}\",class=\"{}\"}} {}",
+            escape(path),
+            escape(method),
+            class,
+            count
+        );
+    }
+    metric(
+        &mut text,
+        "rusty_server_http_request_duration_seconds",
+        "histogram",
+        "Time taken to answer requests, by route, method, and status.",
+    );
+    let latency = LATENCY.lock().unwrap_or_else(PoisonError::into_inner);
+    for ((path, method, status), histogram) in &latency.series {
+        let labels = format!(
+            "path=\"{}\",method=\"{}\",status=\"{}\"",
+            escape(path),
+            escape(method),
+            status
+        );
+        let mut cumulative = 0;
+        let bounds = latency.buckets.iter().map(|bound| bound.to_string());
+        for (bound, count) in bounds.chain(["+Inf".to_string()]).zip(&histogram.counts) {
+            cumulative += count;
+            let _ = writeln!(
+                text,
+                "rusty_server_http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                labels, bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            text,
+            "rusty_server_http_request_duration_seconds_sum{{{}}} {:.6}",
+            labels, histogram.sum
+        );
+        let _ = writeln!(
+            text,
+            "rusty_server_http_request_duration_seconds_count{{{}}} {}",
+            labels, histogram.count
+        );
+    }
+    text
+}
+
+/// The `# HELP` and `# TYPE` lines introducing a metric.
+fn metric(text: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(text, "# HELP {} {}", name, help);
+    let _ = writeln!(text, "# TYPE {} {}", name, kind);
+}
+
+/// `value` escaped for a label value: backslashes, quotes, and newlines.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
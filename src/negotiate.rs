@@ -0,0 +1,152 @@
+//! Content negotiation: picking the response format a request's `Accept`
+//! header prefers.
+//!
+//! `Accept` lists media ranges, each with an optional weight:
+//!
+//! ```text
+//! Accept: text/html, application/json;q=0.9, */*;q=0.1
+//! ```
+//!
+//! [`preferred`] weighs each offered media type by the most specific range
+//! that matches it (`text/html` before `text/*` before `*/*`), so the above
+//! takes HTML over JSON and either over anything else. The built-in
+//! endpoints and error pages offer their bodies as the [`Format`]s HTML,
+//! JSON, and plain text, and answer in the one the client prefers.
+
+use std::fmt;
+
+use crate::http::Request;
+
+/// One media range of an `Accept` header, such as `text/*;q=0.5`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaRange {
+    /// The type, such as `text`, or `*`.
+    pub kind: String,
+    /// The subtype, such as `html`, or `*`.
+    pub subtype: String,
+    /// The weight, from 0 (not acceptable) to 1; 1 when not given.
+    pub q: f32,
+}
+
+impl MediaRange {
+    /// Whether the range covers `media_type`, such as `text/html` (any
+    /// parameters after `;` are ignored).
+    pub fn matches(&self, media_type: &str) -> bool {
+        let (kind, subtype) = split(media_type);
+        (self.kind == "*" || self.kind.eq_ignore_ascii_case(kind))
+            && (self.subtype == "*" || self.subtype.eq_ignore_ascii_case(subtype))
+    }
+
+    /// How specific the range is: 2 for `text/html`, 1 for `text/*`, 0 for
+    /// `*/*`.
+    fn specificity(&self) -> u8 {
+        u8::from(self.kind != "*") + u8::from(self.subtype != "*")
+    }
+}
+
+/// The media ranges of `request`'s `Accept` headers, in order; empty
+/// without one. Ranges that are not `type/subtype` are skipped.
+pub fn accept(request: &Request) -> Vec<MediaRange> {
+    request
+        .header_all("Accept")
+        .flat_map(|value| value.split(','))
+        .filter_map(|range| {
+            let mut params = range.split(';');
+            let (kind, subtype) = params.next()?.trim().split_once('/')?;
+            if kind.is_empty() || subtype.is_empty() {
+                return None;
+            }
+            let q = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0)
+                .clamp(0.0, 1.0);
+            Some(MediaRange {
+                kind: kind.trim().to_string(),
+                subtype: subtype.trim().to_string(),
+                q,
+            })
+        })
+        .collect()
+}
+
+/// The one of `offered` that `request` prefers, the earliest among equals;
+/// the first of `offered` when it sends no `Accept`, and `None` when it
+/// accepts none of them.
+pub fn preferred<'a>(request: &Request, offered: &[&'a str]) -> Option<&'a str> {
+    let ranges = accept(request);
+    if ranges.is_empty() {
+        return offered.first().copied();
+    }
+    let mut best: Option<(&str, f32)> = None;
+    for &media_type in offered {
+        let q = ranges
+            .iter()
+            .filter(|range| range.matches(media_type))
+            .max_by_key(|range| range.specificity())
+            .map_or(0.0, |range| range.q);
+        if q > 0.0 && best.is_none_or(|(_, best)| q > best) {
+            best = Some((media_type, q));
+        }
+    }
+    best.map(|(media_type, _)| media_type)
+}
+
+/// A format the built-in endpoints and error pages can answer in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// A terminal-themed HTML page, with the `html` feature.
+    Html,
+    /// A JSON document.
+    Json,
+    /// Plain text.
+    Text,
+}
+
+impl Format {
+    /// The formats of pages meant for people, the default first: HTML with
+    /// the `html` feature, plain text without it, then JSON.
+    pub const PAGES: &'static [Format] = &[
+        #[cfg(feature = "html")]
+        Format::Html,
+        Format::Text,
+        Format::Json,
+    ];
+
+    /// The media type the format is matched against `Accept` by.
+    pub fn media_type(self) -> &'static str {
+        match self {
+            Format::Html => "text/html",
+            Format::Json => "application/json",
+            Format::Text => "text/plain",
+        }
+    }
+
+    /// The one of `offered` that `request` prefers, as [`preferred`] has
+    /// it, falling back to the first when it accepts none: an endpoint
+    /// answers in a format the client did not ask for rather than with
+    /// `406 Not Acceptable`.
+    ///
+    /// # Panics
+    ///
+    /// If `offered` is empty.
+    pub fn negotiate(request: &Request, offered: &[Format]) -> Format {
+        let media_types: Vec<&str> = offered.iter().map(|format| format.media_type()).collect();
+        preferred(request, &media_types)
+            .and_then(|media_type| offered.iter().find(|format| format.media_type() == media_type))
+            .copied()
+            .unwrap_or(offered[0])
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.media_type())
+    }
+}
+
+/// The type and subtype of `media_type`, without parameters.
+fn split(media_type: &str) -> (&str, &str) {
+    let essence = media_type.split(';').next().unwrap_or_default().trim();
+    essence.split_once('/').unwrap_or((essence, ""))
+}
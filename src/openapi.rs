@@ -0,0 +1,136 @@
+//! An OpenAPI document generated from a router's routes, and a Swagger UI
+//! page to explore it.
+//!
+//! [`Router::docs`] serves both: the document at [`DOCUMENT_PATH`] and the
+//! page at [`DOCS_PATH`]. The document lists every route, event stream, and
+//! WebSocket registered before it, with route patterns written as path
+//! templates; streaming prefixes and fallbacks answer paths that cannot be
+//! listed and are left out.
+
+use crate::http::Response;
+use crate::json_escape;
+use crate::router::Router;
+
+/// Where [`Router::docs`] serves the OpenAPI document.
+pub const DOCUMENT_PATH: &str = "/openapi.json";
+
+/// Where [`Router::docs`] serves Swagger UI.
+pub const DOCS_PATH: &str = "/docs";
+
+/// The Swagger UI release the docs page loads.
+const SWAGGER_UI: &str = "https://unpkg.com/swagger-ui-dist@5";
+
+/// How an operation answers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    /// A plain request handler.
+    Route,
+    /// A Server-Sent Events stream.
+    Events,
+    /// A WebSocket upgrade.
+    #[cfg(feature = "websocket")]
+    WebSocket,
+}
+
+/// The OpenAPI 3.0 document describing `router`, as JSON.
+pub fn document(router: &Router) -> String {
+    let mut paths: Vec<(String, Vec<String>)> = Vec::new();
+    for (method, path, kind) in router.operations() {
+        let (path, parameters) = template(path);
+        let method = method.to_ascii_lowercase();
+        if !matches!(
+            method.as_str(),
+            "get" | "put" | "post" | "delete" | "options" | "head" | "patch" | "trace"
+        ) {
+            continue;
+        }
+        let operation = match parameters.as_str() {
+            "" => format!(r#""{}":{}"#, method, operation(kind)),
+            parameters => format!(
+                r#""{}":{{"parameters":[{}],{}"#,
+                method,
+                parameters,
+                &operation(kind)[1..]
+            ),
+        };
+        match paths.iter_mut().find(|(known, _)| *known == path) {
+            Some((_, operations)) => operations.push(operation),
+            None => paths.push((path, vec![operation])),
+        }
+    }
+    let paths: Vec<String> = paths
+        .iter()
+        .map(|(path, operations)| {
+            format!(r#""{}":{{{}}}"#, json_escape(path), operations.join(","))
+        })
+        .collect();
+    format!(
+        r#"{{"openapi":"3.0.3","info":{{"title":"{}","version":"{}"}},"paths":{{{}}}}}"#,
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        paths.join(",")
+    )
+}
+
+/// `path` as an OpenAPI path template, with the `:name` and `*name`
+/// segments of a route pattern written `{name}`, and the JSON parameter
+/// objects describing them, comma-separated.
+fn template(path: &str) -> (String, String) {
+    let mut parameters = Vec::new();
+    let segments: Vec<String> = path
+        .split('/')
+        .map(|segment| match segment.strip_prefix([':', '*']) {
+            Some(name) => {
+                parameters.push(format!(
+                    r#"{{"name":"{}","in":"path","required":true,"schema":{{"type":"string"}}}}"#,
+                    json_escape(name)
+                ));
+                format!("{{{}}}", name)
+            }
+            None => segment.to_string(),
+        })
+        .collect();
+    (segments.join("/"), parameters.join(","))
+}
+
+fn operation(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Route => r#"{"responses":{"default":{"description":"The handler's response"}}}"#,
+        Kind::Events => {
+            r#"{"summary":"Server-Sent Events","responses":{"200":{"description":"An event stream","content":{"text/event-stream":{}}}}}"#
+        }
+        #[cfg(feature = "websocket")]
+        Kind::WebSocket => {
+            r#"{"summary":"WebSocket","responses":{"101":{"description":"Switching Protocols"},"426":{"description":"Upgrade Required"}}}"#
+        }
+    }
+}
+
+/// The Swagger UI page, showing the document at [`DOCUMENT_PATH`].
+///
+/// Swagger UI's script and styles are loaded from unpkg, so the browser
+/// needs to reach it; the server itself does not.
+pub fn docs() -> Response {
+    Response::html(
+        200,
+        format!(
+            r##"<!DOCTYPE html>
+<html>
+<head>
+    <title>API Documentation</title>
+    <meta charset="utf-8">
+    <link rel="stylesheet" href="{ui}/swagger-ui.css">
+</head>
+<body>
+    <div id="swagger-ui"></div>
+    <script src="{ui}/swagger-ui-bundle.js"></script>
+    <script>
+        SwaggerUIBundle({{ url: "{document}", dom_id: "#swagger-ui" }});
+    </script>
+</body>
+</html>"##,
+            ui = SWAGGER_UI,
+            document = DOCUMENT_PATH
+        ),
+    )
+}
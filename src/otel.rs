@@ -0,0 +1,443 @@
+//! OpenTelemetry traces, exported to a collector over OTLP/HTTP.
+//!
+//! Configure an [`Otlp`] exporter with the collector's traces URL,
+//! [`start`](Otlp::start) it, and [`install`] the resulting [`Exporter`];
+//! the server then records a server [`Span`] for every request, named and
+//! attributed after the OpenTelemetry HTTP semantic conventions: the
+//! method, the route's pattern as `http.route`, the status code, and the
+//! client's address among them. A span fails when the response is a 5xx or
+//! none could be sent.
+//!
+//! A request carrying a W3C `traceparent` header joins the caller's trace,
+//! with the caller's span as its parent; one whose caller chose not to
+//! sample it is not recorded. Any other request starts a trace of its own.
+//!
+//! Spans are batched and POSTed as OTLP JSON every [`Otlp::interval`], or as
+//! soon as [`MAX_BATCH`] are waiting, which Jaeger, Tempo, and the
+//! OpenTelemetry Collector all accept on their OTLP/HTTP port (4318). A
+//! batch the collector does not accept is dropped with a console error, and
+//! spans beyond [`MAX_QUEUE`] waiting ones are dropped as they come.
+
+use std::sync::{Arc, Condvar, Mutex, OnceLock, PoisonError};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::client::{self, Client};
+use crate::router::Router;
+use crate::server::Exchange;
+use crate::{console, json_escape, unique_u64, ServerError};
+
+/// Most spans sent in one request to the collector.
+pub const MAX_BATCH: usize = 512;
+
+/// Most spans waiting to be sent; further ones are dropped.
+pub const MAX_QUEUE: usize = 2048;
+
+/// `SPAN_KIND_SERVER` in OTLP.
+const KIND_SERVER: u8 = 2;
+
+/// `STATUS_CODE_ERROR` in OTLP.
+const STATUS_ERROR: u8 = 2;
+
+/// One finished operation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub trace_id: u128,
+    pub span_id: u64,
+    /// The caller's span, for requests that carried a `traceparent`.
+    pub parent_span_id: Option<u64>,
+    pub name: String,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    pub attributes: Vec<(&'static str, Value)>,
+    /// Whether the operation failed.
+    pub error: bool,
+}
+
+/// The value of a span attribute.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Int(i64),
+}
+
+impl Span {
+    /// The server span for a request that took `elapsed` and ended now,
+    /// from `peer`, answered by a route of `router`; `None` if the caller
+    /// did not sample its trace.
+    fn server(
+        peer: &str,
+        result: &Result<Exchange, ServerError>,
+        router: &Router,
+        elapsed: Duration,
+    ) -> Option<Span> {
+        let end = SystemTime::now();
+        let mut span = Span {
+            trace_id: (u128::from(unique_u64()) << 64) | u128::from(unique_u64()),
+            span_id: unique_u64(),
+            parent_span_id: None,
+            name: "HTTP".to_string(),
+            start: end.checked_sub(elapsed).unwrap_or(end),
+            end,
+            attributes: Vec::new(),
+            error: false,
+        };
+        if let Some((address, port)) = peer.rsplit_once(':') {
+            span.attributes
+                .push(("client.address", Value::String(address.to_string())));
+            if let Ok(port) = port.parse() {
+                span.attributes.push(("client.port", Value::Int(port)));
+            }
+        }
+        let exchange = match result {
+            Ok(exchange) => exchange,
+            Err(e) => {
+                let kind = match e {
+                    ServerError::Parse(_) => return Some(span.failed(&e.status().0.to_string())),
+                    ServerError::Timeout(_) => "timeout",
+                    ServerError::Tls(_) => "tls",
+                    ServerError::Handler(_) => "handler",
+                    ServerError::Io(_) | ServerError::Bind { .. } => "io",
+                };
+                return Some(span.failed(kind));
+            }
+        };
+        if let Some(request) = &exchange.request {
+            if let Some(parent) = request.header("traceparent") {
+                match traceparent(parent) {
+                    Some((_, _, false)) => return None,
+                    Some((trace_id, parent_span_id, true)) => {
+                        span.trace_id = trace_id;
+                        span.parent_span_id = Some(parent_span_id);
+                    }
+                    None => {}
+                }
+            }
+            let route = router.pattern(&request.method, &request.path);
+            span.name = match route {
+                Some(route) => format!("{} {}", request.method, route),
+                None => request.method.clone(),
+            };
+            span.attributes
+                .push(("http.request.method", Value::String(request.method.clone())));
+            if let Some(route) = route {
+                span.attributes
+                    .push(("http.route", Value::String(route.to_string())));
+            }
+            span.attributes
+                .push(("url.path", Value::String(exchange.path.clone())));
+            if let Some((_, query)) = request.target.split_once('?') {
+                span.attributes
+                    .push(("url.query", Value::String(query.to_string())));
+            }
+            let scheme = if request.secure { "https" } else { "http" };
+            span.attributes
+                .push(("url.scheme", Value::String(scheme.to_string())));
+            let version = request.version.to_string();
+            let version = version.strip_prefix("HTTP/").unwrap_or(&version);
+            span.attributes.push((
+                "network.protocol.version",
+                Value::String(version.to_string()),
+            ));
+            if let Some(user_agent) = request.header("User-Agent") {
+                span.attributes
+                    .push(("user_agent.original", Value::String(user_agent.to_string())));
+            }
+        }
+        span.attributes.push((
+            "http.response.status_code",
+            Value::Int(exchange.status.into()),
+        ));
+        if exchange.status >= 500 {
+            span = span.failed(&exchange.status.to_string());
+        }
+        Some(span)
+    }
+
+    /// The span, failed with the `error.type` `kind`.
+    fn failed(mut self, kind: &str) -> Span {
+        self.error = true;
+        self.attributes
+            .push(("error.type", Value::String(kind.to_string())));
+        self
+    }
+}
+
+/// The trace ID, parent span ID, and sampled flag of a W3C `traceparent`
+/// header value, such as
+/// `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`; `None` if it
+/// is malformed or its IDs are all zeros.
+pub fn traceparent(value: &str) -> Option<(u128, u64, bool)> {
+    let mut fields = value.trim().split('-');
+    let (version, trace_id, parent_id, flags) = (
+        fields.next()?,
+        fields.next()?,
+        fields.next()?,
+        fields.next()?,
+    );
+    let hex = |field: &str, len: usize| {
+        field.len() == len
+            && field
+                .bytes()
+                .all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    };
+    // Later versions may add fields, but keep these four.
+    if !hex(version, 2) || version == "ff" || (version == "00" && fields.next().is_some()) {
+        return None;
+    }
+    if !hex(trace_id, 32) || !hex(parent_id, 16) || !hex(flags, 2) {
+        return None;
+    }
+    let trace_id = u128::from_str_radix(trace_id, 16).ok()?;
+    let parent_id = u64::from_str_radix(parent_id, 16).ok()?;
+    let flags = u8::from_str_radix(flags, 16).ok()?;
+    (trace_id != 0 && parent_id != 0).then_some((trace_id, parent_id, flags & 1 == 1))
+}
+
+/// The OTLP JSON request exporting `spans` for the service `service_name`.
+pub fn to_json(service_name: &str, spans: &[Span]) -> String {
+    let spans: Vec<String> = spans.iter().map(span_json).collect();
+    format!(
+        concat!(
+            r#"{{"resourceSpans":[{{"resource":{{"attributes":[{}]}},"#,
+            r#""scopeSpans":[{{"scope":{{"name":"rusty-server","version":"{}"}},"spans":[{}]}}]}}]}}"#
+        ),
+        attribute_json("service.name", &Value::String(service_name.to_string())),
+        env!("CARGO_PKG_VERSION"),
+        spans.join(",")
+    )
+}
+
+fn span_json(span: &Span) -> String {
+    let parent = span
+        .parent_span_id
+        .map(|id| format!(r#""parentSpanId":"{:016x}","#, id))
+        .unwrap_or_default();
+    let attributes: Vec<String> = span
+        .attributes
+        .iter()
+        .map(|(key, value)| attribute_json(key, value))
+        .collect();
+    let status = if span.error {
+        format!(r#","status":{{"code":{}}}"#, STATUS_ERROR)
+    } else {
+        String::new()
+    };
+    format!(
+        concat!(
+            r#"{{"traceId":"{:032x}","spanId":"{:016x}",{}"name":"{}","kind":{},"#,
+            r#""startTimeUnixNano":"{}","endTimeUnixNano":"{}","attributes":[{}]{}}}"#
+        ),
+        span.trace_id,
+        span.span_id,
+        parent,
+        json_escape(&span.name),
+        KIND_SERVER,
+        unix_nanos(span.start),
+        unix_nanos(span.end),
+        attributes.join(","),
+        status
+    )
+}
+
+fn attribute_json(key: &str, value: &Value) -> String {
+    let value = match value {
+        Value::String(value) => format!(r#"{{"stringValue":"{}"}}"#, json_escape(value)),
+        Value::Int(value) => format!(r#"{{"intValue":"{}"}}"#, value),
+    };
+    format!(r#"{{"key":"{}","value":{}}}"#, json_escape(key), value)
+}
+
+/// Nanoseconds since the Unix epoch, or 0 before it.
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_nanos())
+}
+
+/// OTLP export settings. Start from [`Otlp::new`] and adjust with the
+/// setters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Otlp {
+    url: String,
+    service_name: String,
+    interval: Duration,
+    timeout: Duration,
+}
+
+impl Otlp {
+    /// Exports to the OTLP/HTTP traces URL `url`, such as
+    /// `http://localhost:4318/v1/traces`.
+    pub fn new(url: impl Into<String>) -> Self {
+        Otlp {
+            url: url.into(),
+            service_name: "rusty-server".to_string(),
+            interval: Duration::from_secs(5),
+            timeout: client::DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Sets the `service.name` the spans are listed under; `rusty-server`
+    /// by default.
+    pub fn service_name(mut self, service_name: impl Into<String>) -> Self {
+        self.service_name = service_name.into();
+        self
+    }
+
+    /// Sends the waiting spans every `interval`; 5 seconds by default.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets the connect, write, and read timeout for one export; 10 seconds
+    /// by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Starts the export thread.
+    pub fn start(self) -> Exporter {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue::default()),
+            changed: Condvar::new(),
+        });
+        let worker = Arc::clone(&shared);
+        thread::spawn(move || export_forever(&self, &worker));
+        Exporter { shared }
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    spans: Vec<Span>,
+    /// Spans taken by the worker and not sent yet.
+    in_flight: usize,
+    /// Set while [`Exporter::flush`] waits, to send without waiting for the
+    /// interval.
+    flushing: bool,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    changed: Condvar,
+}
+
+/// Queues spans for export by the background thread.
+pub struct Exporter {
+    shared: Arc<Shared>,
+}
+
+impl Exporter {
+    /// Queues `span`; returns at once. Dropped if [`MAX_QUEUE`] spans are
+    /// already waiting.
+    pub fn record(&self, span: Span) {
+        let mut queue = self
+            .shared
+            .queue
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if queue.spans.len() < MAX_QUEUE {
+            queue.spans.push(span);
+            if queue.spans.len() >= MAX_BATCH {
+                self.shared.changed.notify_all();
+            }
+        }
+    }
+
+    /// Sends the waiting spans now, and waits up to `timeout` for them to be
+    /// sent or given up on. Returns whether the queue drained in time.
+    pub fn flush(&self, timeout: Duration) -> bool {
+        let mut queue = self
+            .shared
+            .queue
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        queue.flushing = true;
+        self.shared.changed.notify_all();
+        let (mut queue, _) = self
+            .shared
+            .changed
+            .wait_timeout_while(queue, timeout, |queue| {
+                !queue.spans.is_empty() || queue.in_flight > 0
+            })
+            .unwrap_or_else(PoisonError::into_inner);
+        queue.flushing = false;
+        queue.spans.is_empty() && queue.in_flight == 0
+    }
+}
+
+fn export_forever(settings: &Otlp, shared: &Shared) {
+    let client = Client::new().timeout(settings.timeout);
+    loop {
+        let spans = {
+            let queue = shared.queue.lock().unwrap_or_else(PoisonError::into_inner);
+            let (mut queue, _) = shared
+                .changed
+                .wait_timeout_while(queue, settings.interval, |queue| {
+                    queue.spans.len() < MAX_BATCH && (!queue.flushing || queue.spans.is_empty())
+                })
+                .unwrap_or_else(PoisonError::into_inner);
+            let count = queue.spans.len().min(MAX_BATCH);
+            queue.in_flight += count;
+            queue.spans.drain(..count).collect::<Vec<_>>()
+        };
+        if !spans.is_empty() {
+            export(&client, settings, &spans);
+        }
+        let mut queue = shared.queue.lock().unwrap_or_else(PoisonError::into_inner);
+        queue.in_flight -= spans.len();
+        shared.changed.notify_all();
+    }
+}
+
+/// Sends one batch, reporting on the console if it is not accepted.
+fn export(client: &Client, settings: &Otlp, spans: &[Span]) {
+    let body = to_json(&settings.service_name, spans);
+    let failure = match client.post(
+        &settings.url,
+        &[("Content-Type", "application/json")],
+        body.as_bytes(),
+    ) {
+        Ok(response) if (200..300).contains(&response.status) => return,
+        Ok(response) => format!("answered {}", response.status),
+        Err(e) => e.to_string(),
+    };
+    console::print_table(&[(
+        "OTLP",
+        format!(
+            "✗ dropped {} spans: {}: {}",
+            spans.len(),
+            settings.url,
+            failure
+        ),
+    )]);
+}
+
+static EXPORTER: OnceLock<Exporter> = OnceLock::new();
+
+/// Makes `exporter` the one the server records its spans with. Only the
+/// first call takes effect; it returns `false` for later ones.
+pub fn install(exporter: Exporter) -> bool {
+    EXPORTER.set(exporter).is_ok()
+}
+
+/// The installed exporter, if any.
+pub fn installed() -> Option<&'static Exporter> {
+    EXPORTER.get()
+}
+
+/// Records the span of a finished request with the installed exporter, if
+/// any.
+pub(crate) fn record(
+    peer: &str,
+    result: &Result<Exchange, ServerError>,
+    router: &Router,
+    elapsed: Duration,
+) {
+    if let Some(exporter) = installed() {
+        if let Some(span) = Span::server(peer, result, router, elapsed) {
+            exporter.record(span);
+        }
+    }
+}
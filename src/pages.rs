@@ -0,0 +1,349 @@
+//! Response bodies for the built-in endpoints and for requests that could
+//! not be served.
+//!
+//! With the `html` feature (on by default) browsers get the terminal-themed
+//! HTML pages; without it every page is plain text, which keeps the binary
+//! small and the output readable from `curl`. [`not_found_for`] and
+//! [`error_for`] answer in JSON instead for clients that prefer it.
+//!
+//! The HTML pages are Askama templates in `templates/`, compiled into the
+//! binary and checked against their typed contexts at build time: a
+//! placeholder naming no field fails the build. Restyling a page means
+//! editing its template, not this code. Operators can brand the error pages
+//! without rebuilding, too: [`set_error_pages`] loads their own templates,
+//! such as `404.html`, from a directory, in place of the built-in ones, and
+//! checks their placeholders as it does.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{PoisonError, RwLock};
+
+#[cfg(feature = "html")]
+use askama::Template;
+use serde::Serialize;
+
+use crate::endpoints::VersionInfo;
+use crate::http::{vary, Request, Response};
+use crate::negotiate::Format;
+
+/// The built-in endpoints, as the 404 page lists them: method, path,
+/// description, and response type.
+const ENDPOINTS: &[(&str, &str, &str, &str)] = &[
+    ("GET", "/version", "Server version information", "application/json"),
+    ("GET", "/healthz", "Health check endpoint", "application/json"),
+    ("GET", "/metrics", "Prometheus metrics", "text/plain"),
+    ("GET", "/events", "Live server stats every second", "text/event-stream"),
+];
+
+/// Operators' error page templates, by status code.
+static ERROR_PAGES: RwLock<BTreeMap<u16, String>> = RwLock::new(BTreeMap::new());
+
+/// The placeholders operators' error page templates can use.
+const PLACEHOLDERS: &[&str] = &["status", "reason", "message", "path", "endpoints"];
+
+/// Loads the error page templates in `dir`, one per status code and named
+/// after it (`404.html`, `400.html`, `500.html`, and so on), to be served
+/// in place of the built-in pages; other files are ignored. Returns the
+/// status codes it found templates for, replacing any loaded before.
+///
+/// The templates are read now, so later edits need another call. They fill
+/// in the placeholders
+///
+/// - `{{ status }}`: the status code, such as `404`
+/// - `{{ reason }}`: its reason phrase, such as `Not Found`
+/// - `{{ message }}`: what went wrong, in a sentence
+/// - `{{ path }}`: the path requested, empty where it is not known, such as
+///   for a request that could not be parsed
+/// - `{{ endpoints }}`: an HTML table of the built-in endpoints
+///
+/// A template with any other placeholder, or a `{{` left open, fails with
+/// [`io::ErrorKind::InvalidData`] naming it, and nothing is replaced.
+///
+/// Only HTML pages are replaced: clients preferring JSON or plain text get
+/// those as before, and without the `html` feature the templates are never
+/// used.
+pub fn set_error_pages(dir: &Path) -> io::Result<Vec<u16>> {
+    let mut pages = BTreeMap::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let code = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".html"))
+            .filter(|code| code.len() == 3)
+            .and_then(|code| code.parse::<u16>().ok())
+            .filter(|code| (400..600).contains(code));
+        if let Some(code) = code {
+            let template = fs::read_to_string(&path)?;
+            fill(&template, |name| PLACEHOLDERS.contains(&name).then(String::new)).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e))
+            })?;
+            pages.insert(code, template);
+        }
+    }
+    let codes = pages.keys().copied().collect();
+    *ERROR_PAGES.write().unwrap_or_else(PoisonError::into_inner) = pages;
+    Ok(codes)
+}
+
+/// The `/version` page showing `info`, and its JSON document.
+#[cfg(feature = "html")]
+pub(crate) fn version(info: &VersionInfo) -> Response {
+    let page = VersionPage {
+        info,
+        json: serde_json::to_string_pretty(info).unwrap_or_default(),
+    };
+    html(200, &page)
+}
+
+/// The `/version` document `info`, as JSON.
+#[cfg(not(feature = "html"))]
+pub(crate) fn version(info: &VersionInfo) -> Response {
+    Response::json(200, info)
+}
+
+/// The 404 page for `path`, listing the endpoints that do exist.
+#[cfg(feature = "html")]
+pub fn not_found(path: &str) -> Response {
+    let page = ErrorPage {
+        status: 404,
+        reason: "Not Found",
+        message: "The requested path does not exist.",
+        path,
+    };
+    page.operators().unwrap_or_else(|| {
+        let page = NotFoundPage {
+            path,
+            endpoints: endpoints_table(),
+        };
+        html(404, &page)
+    })
+}
+
+/// The 404 page for `path`, listing the endpoints that do exist.
+#[cfg(not(feature = "html"))]
+pub fn not_found(path: &str) -> Response {
+    not_found_text(path)
+}
+
+/// The plain-text 404 page for `path`.
+fn not_found_text(path: &str) -> Response {
+    let mut body = format!("404 Not Found: {}\n\nAvailable endpoints:\n", path);
+    for (method, path, _, _) in ENDPOINTS {
+        body.push_str(&format!("  {} {}\n", method, path));
+    }
+    Response::text(404, body)
+}
+
+/// Terminal-styled page for requests that could not be served.
+#[cfg(feature = "html")]
+pub fn error(code: u16, reason: &str, message: &str) -> Response {
+    error_html(code, reason, message, "")
+}
+
+/// Plain-text body for requests that could not be served.
+#[cfg(not(feature = "html"))]
+pub fn error(code: u16, reason: &str, message: &str) -> Response {
+    error_text(code, reason, message)
+}
+
+/// Terminal-styled page for a request for `path` that could not be served.
+#[cfg(feature = "html")]
+fn error_html(code: u16, reason: &str, message: &str, path: &str) -> Response {
+    let page = ErrorPage {
+        status: code,
+        reason,
+        message,
+        path,
+    };
+    page.operators().unwrap_or_else(|| html(code, &page))
+}
+
+/// Plain-text body for requests that could not be served.
+fn error_text(code: u16, reason: &str, message: &str) -> Response {
+    Response::text(code, format!("{} {}: {}\n", code, reason, message))
+}
+
+/// The 404 page for `request`, in the [`Format`] its `Accept` header
+/// prefers.
+pub fn not_found_for(request: &Request) -> Response {
+    let mut response = match Format::negotiate(request, Format::PAGES) {
+        Format::Json => error_json(404, "Not Found", &format!("No endpoint at {}.", request.path)),
+        Format::Text => not_found_text(&request.path),
+        Format::Html => not_found(&request.path),
+    };
+    vary(&mut response, "Accept");
+    response
+}
+
+/// The error page for `request`, in the [`Format`] its `Accept` header
+/// prefers.
+pub fn error_for(request: &Request, code: u16, reason: &str, message: &str) -> Response {
+    let mut response = match Format::negotiate(request, Format::PAGES) {
+        Format::Json => error_json(code, reason, message),
+        Format::Text => error_text(code, reason, message),
+        #[cfg(feature = "html")]
+        Format::Html => error_html(code, reason, message, &request.path),
+        #[cfg(not(feature = "html"))]
+        Format::Html => error(code, reason, message),
+    };
+    vary(&mut response, "Accept");
+    response
+}
+
+/// The JSON body of error responses.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ErrorBody<'a> {
+    /// The status code.
+    pub status: u16,
+    /// Its reason phrase, such as `Not Found`.
+    pub error: &'a str,
+    pub message: &'a str,
+}
+
+/// The JSON body for requests that could not be served.
+fn error_json(code: u16, reason: &str, message: &str) -> Response {
+    let body = ErrorBody {
+        status: code,
+        error: reason,
+        message,
+    };
+    Response::json(code, &body)
+}
+
+/// `page` rendered as an HTML response with status `code`.
+#[cfg(feature = "html")]
+fn html(code: u16, page: &impl Template) -> Response {
+    // The templates were checked at build time; only writing to the
+    // string could fail, and it does not.
+    Response::html(code, page.render().unwrap_or_default())
+}
+
+/// `template` with every `{{ name }}` replaced by `field(name)`, for
+/// operators' templates, which are read at run time. Fails naming a
+/// placeholder `field` has no value for, or a `{{` that is not closed.
+fn fill(template: &str, field: impl Fn(&str) -> Option<String>) -> Result<String, String> {
+    let mut page = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let end = rest[start..].find("}}").ok_or("a `{{` is not closed with `}}`")?;
+        page.push_str(&rest[..start]);
+        let name = rest[start + 2..start + end].trim();
+        let value = field(name).ok_or_else(|| {
+            format!("unknown placeholder `{{{{ {} }}}}` (expected one of {})", name, PLACEHOLDERS.join(", "))
+        })?;
+        page.push_str(&value);
+        rest = &rest[start + end + 2..];
+    }
+    page.push_str(rest);
+    Ok(page)
+}
+
+/// The context of `templates/version.html`.
+#[cfg(feature = "html")]
+#[derive(Template)]
+#[template(path = "version.html")]
+struct VersionPage<'a> {
+    info: &'a VersionInfo,
+    /// `info` as the JSON document `/version` sends, pretty-printed.
+    json: String,
+}
+
+/// The context of `templates/not_found.html`.
+#[cfg(feature = "html")]
+#[derive(Template)]
+#[template(path = "not_found.html")]
+struct NotFoundPage<'a> {
+    path: &'a str,
+    /// [`endpoints_table`], which is HTML already.
+    endpoints: String,
+}
+
+/// The context of `templates/error.html`, and of the templates loaded by
+/// [`set_error_pages`].
+#[cfg(feature = "html")]
+#[derive(Template)]
+#[template(path = "error.html")]
+struct ErrorPage<'a> {
+    status: u16,
+    reason: &'a str,
+    message: &'a str,
+    /// The path requested, which only operators' templates show.
+    path: &'a str,
+}
+
+#[cfg(feature = "html")]
+impl ErrorPage<'_> {
+    /// The page rendered from the operator's template for its status code,
+    /// if [`set_error_pages`] loaded one.
+    fn operators(&self) -> Option<Response> {
+        let pages = ERROR_PAGES.read().unwrap_or_else(PoisonError::into_inner);
+        let template = pages.get(&self.status)?;
+        // Checked by `set_error_pages`, which knows every placeholder this does.
+        let page = fill(template, |name| self.field(name)).unwrap_or_default();
+        Some(Response::html(self.status, page))
+    }
+
+    /// The value of the placeholder `name` in operators' templates, as HTML.
+    fn field(&self, name: &str) -> Option<String> {
+        match name {
+            "status" => Some(self.status.to_string()),
+            "reason" => Some(html_escape(self.reason)),
+            "message" => Some(html_escape(self.message)),
+            "path" => Some(html_escape(self.path)),
+            "endpoints" => Some(endpoints_table()),
+            _ => None,
+        }
+    }
+}
+
+/// The built-in endpoints as an HTML table.
+#[cfg(feature = "html")]
+fn endpoints_table() -> String {
+    let mut table = String::from(
+        r#"<table class="endpoints-table">
+            <thead>
+                <tr>
+                    <th>Method</th>
+                    <th>Path</th>
+                    <th>Description</th>
+                    <th>Response Type</th>
+                </tr>
+            </thead>
+            <tbody>
+"#,
+    );
+    for (method, path, description, content_type) in ENDPOINTS {
+        table.push_str(&format!(
+            r#"                <tr>
+                    <td class="endpoint-method">{}</td>
+                    <td class="endpoint-path">{}</td>
+                    <td class="endpoint-desc">{}</td>
+                    <td class="endpoint-desc">{}</td>
+                </tr>
+"#,
+            method, path, description, content_type
+        ));
+    }
+    table.push_str("            </tbody>\n        </table>");
+    table
+}
+
+#[cfg(feature = "html")]
+/// Escapes the characters that are significant in HTML text and attributes.
+fn html_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
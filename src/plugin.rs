@@ -0,0 +1,347 @@
+//! Handler plugins loaded from shared libraries at startup.
+//!
+//! A plugin is a `cdylib` exporting [`REGISTER_SYMBOL`] with the signature
+//! [`RegisterFn`]. The server calls it once with a [`Host`] function table,
+//! and the plugin adds its routes through [`Host::add_route`]. Everything
+//! crossing the boundary is `#[repr(C)]`, so plugins do not have to be built
+//! with the same compiler as the server, or in Rust at all.
+//!
+//! Loading a library runs its code with the server's privileges: only point
+//! the server at a plugins directory you trust. Sandboxed WebAssembly plugins
+//! live in [`wasm`] (with the `wasm` feature).
+
+use std::any::Any;
+use std::ffi::c_void;
+use std::sync::Arc;
+use std::{ptr, slice, str};
+
+use crate::http::{Request, Response};
+use crate::router::Router;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+/// Version of the [`Host`] table. Plugins should refuse to register when
+/// [`Host::abi_version`] differs from the version they were built for.
+pub const ABI_VERSION: u32 = 1;
+
+/// Name of the registration function every plugin exports.
+pub const REGISTER_SYMBOL: &str = "rusty_server_plugin_register";
+
+/// A borrowed UTF-8 string: valid only for the duration of the call it is
+/// passed to.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Str {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+
+impl Str {
+    /// The string, or `""` if it is not valid UTF-8.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to `len` readable bytes that outlive `'a`.
+    pub unsafe fn as_str<'a>(self) -> &'a str {
+        if self.ptr.is_null() {
+            return "";
+        }
+        str::from_utf8(slice::from_raw_parts(self.ptr, self.len)).unwrap_or("")
+    }
+}
+
+impl From<&str> for Str {
+    fn from(s: &str) -> Self {
+        Str {
+            ptr: s.as_ptr(),
+            len: s.len(),
+        }
+    }
+}
+
+/// One request header.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Header {
+    pub name: Str,
+    pub value: Str,
+}
+
+/// The request passed to a plugin handler.
+#[repr(C)]
+#[derive(Debug)]
+pub struct PluginRequest {
+    pub method: Str,
+    /// Decoded path, without the query.
+    pub path: Str,
+    /// The request target as sent, including any query.
+    pub target: Str,
+    pub headers: *const Header,
+    pub header_count: usize,
+}
+
+/// The response a handler fills in through the [`Host`] functions. Starts out
+/// as an empty `200 OK`.
+#[repr(C)]
+pub struct ResponseHandle {
+    _private: [u8; 0],
+}
+
+/// A route handler exported by a plugin. It must be safe to call from several
+/// threads at once.
+pub type HandlerFn = unsafe extern "C" fn(
+    user_data: *mut c_void,
+    request: *const PluginRequest,
+    response: *mut ResponseHandle,
+    host: *const Host,
+);
+
+/// The plugin's registration function, exported as [`REGISTER_SYMBOL`].
+/// Returns 0 on success; any other value rejects the plugin.
+pub type RegisterFn = unsafe extern "C" fn(host: *const Host) -> i32;
+
+/// Functions the server offers to plugins.
+#[repr(C)]
+pub struct Host {
+    pub abi_version: u32,
+    /// Opaque server state for [`add_route`](Self::add_route).
+    pub context: *mut c_void,
+    /// Registers `handler` for `method` and `path`; `user_data` is passed back
+    /// on every call. Only valid during registration; returns `false`
+    /// otherwise.
+    pub add_route: unsafe extern "C" fn(
+        context: *mut c_void,
+        method: Str,
+        path: Str,
+        handler: HandlerFn,
+        user_data: *mut c_void,
+    ) -> bool,
+    pub set_status: unsafe extern "C" fn(response: *mut ResponseHandle, status: u16),
+    pub add_header: unsafe extern "C" fn(response: *mut ResponseHandle, name: Str, value: Str),
+    pub set_body: unsafe extern "C" fn(response: *mut ResponseHandle, body: *const u8, len: usize),
+}
+
+/// State behind [`Host::context`] while a plugin registers.
+struct Registration<'a> {
+    router: &'a mut Router,
+    /// Keeps the library mapped for as long as its handlers are routed.
+    keep_alive: Option<KeepAlive>,
+    routes: Vec<String>,
+}
+
+type KeepAlive = Arc<dyn Any + Send + Sync>;
+
+/// A plugin handler as stored in the router.
+struct PluginHandler {
+    handler: HandlerFn,
+    user_data: *mut c_void,
+    _library: Option<KeepAlive>,
+}
+
+// SAFETY: the plugin contract requires handlers and their user data to be
+// usable from any thread.
+unsafe impl Send for PluginHandler {}
+unsafe impl Sync for PluginHandler {}
+
+impl PluginHandler {
+    fn call(&self, request: &Request) -> Response {
+        let headers: Vec<Header> = request
+            .headers
+            .iter()
+            .map(|(name, value)| Header {
+                name: name.as_str().into(),
+                value: value.as_str().into(),
+            })
+            .collect();
+        let plugin_request = PluginRequest {
+            method: request.method.as_str().into(),
+            path: request.path.as_str().into(),
+            target: request.target.as_str().into(),
+            headers: headers.as_ptr(),
+            header_count: headers.len(),
+        };
+        let mut response = Response::new(200);
+        let host = host(ptr::null_mut());
+        // SAFETY: every pointer handed over lives until the call returns.
+        unsafe {
+            (self.handler)(
+                self.user_data,
+                &plugin_request,
+                &mut response as *mut Response as *mut ResponseHandle,
+                &host,
+            );
+        }
+        response
+    }
+}
+
+fn host(context: *mut c_void) -> Host {
+    Host {
+        abi_version: ABI_VERSION,
+        context,
+        add_route,
+        set_status,
+        add_header,
+        set_body,
+    }
+}
+
+unsafe extern "C" fn add_route(
+    context: *mut c_void,
+    method: Str,
+    path: Str,
+    handler: HandlerFn,
+    user_data: *mut c_void,
+) -> bool {
+    if context.is_null() {
+        return false;
+    }
+    let registration = &mut *(context as *mut Registration<'_>);
+    let (method, path) = (method.as_str(), path.as_str());
+    if method.is_empty() || !path.starts_with('/') {
+        return false;
+    }
+    let plugin_handler = PluginHandler {
+        handler,
+        user_data,
+        _library: registration.keep_alive.clone(),
+    };
+    registration
+        .router
+        .route(method, path, move |request| plugin_handler.call(request));
+    registration.routes.push(format!("{} {}", method, path));
+    true
+}
+
+unsafe extern "C" fn set_status(response: *mut ResponseHandle, status: u16) {
+    if let Some(response) = (response as *mut Response).as_mut() {
+        response.status = status;
+    }
+}
+
+unsafe extern "C" fn add_header(response: *mut ResponseHandle, name: Str, value: Str) {
+    if let Some(response) = (response as *mut Response).as_mut() {
+        let (name, value) = (name.as_str(), value.as_str());
+        if is_valid_header(name, value) {
+            response.headers.push((name.to_string(), value.to_string()));
+        }
+    }
+}
+
+unsafe extern "C" fn set_body(response: *mut ResponseHandle, body: *const u8, len: usize) {
+    if let Some(response) = (response as *mut Response).as_mut() {
+        response.body = if body.is_null() {
+            Vec::new()
+        } else {
+            slice::from_raw_parts(body, len).to_vec()
+        };
+    }
+}
+
+/// Whether a plugin-supplied header can be sent as is. Line breaks would let
+/// a plugin smuggle extra header lines in.
+pub(crate) fn is_valid_header(name: &str, value: &str) -> bool {
+    !name.is_empty() && !name.contains(['\r', '\n', ':']) && !value.contains(['\r', '\n'])
+}
+
+/// Why a plugin could not be loaded.
+#[derive(Debug, thiserror::Error)]
+pub enum PluginError {
+    /// The library could not be opened or lacks [`REGISTER_SYMBOL`].
+    #[cfg(feature = "plugins")]
+    #[error("cannot load plugin: {0}")]
+    Load(#[from] libloading::Error),
+    /// The registration function returned this non-zero status.
+    #[error("plugin rejected registration with status {0}")]
+    Rejected(i32),
+    /// The WebAssembly component could not be compiled, linked, or asked for
+    /// its routes.
+    #[cfg(feature = "wasm")]
+    #[error("cannot load WebAssembly plugin: {0:#}")]
+    Wasm(wasmtime::Error),
+}
+
+/// Runs `register` against `router` and returns the routes it added, as
+/// `METHOD /path` strings.
+///
+/// # Safety
+///
+/// `register` must follow the plugin contract described in the module docs.
+pub unsafe fn register(
+    router: &mut Router,
+    register: RegisterFn,
+) -> Result<Vec<String>, PluginError> {
+    register_with(router, register, None)
+}
+
+unsafe fn register_with(
+    router: &mut Router,
+    register: RegisterFn,
+    keep_alive: Option<KeepAlive>,
+) -> Result<Vec<String>, PluginError> {
+    let mut registration = Registration {
+        router,
+        keep_alive,
+        routes: Vec::new(),
+    };
+    let host = host(&mut registration as *mut Registration<'_> as *mut c_void);
+    match register(&host) {
+        0 => Ok(registration.routes),
+        status => Err(PluginError::Rejected(status)),
+    }
+}
+
+/// Loads the plugin at `path` and adds its routes to `router`.
+///
+/// Routes added before a plugin rejects registration stay in place.
+///
+/// # Safety
+///
+/// Loading a library runs its initialisers, and the plugin's code then runs
+/// inside the server: `path` must be a trusted plugin.
+#[cfg(feature = "plugins")]
+pub unsafe fn load(
+    router: &mut Router,
+    path: &std::path::Path,
+) -> Result<Vec<String>, PluginError> {
+    let library = Arc::new(libloading::Library::new(path)?);
+    let register: RegisterFn = *library.get::<RegisterFn>(REGISTER_SYMBOL.as_bytes())?;
+    register_with(router, register, Some(library))
+}
+
+/// Loads every shared library (`.so`, `.dylib`, or `.dll`) in `dir`, in file
+/// name order, and reports each one's outcome. A missing directory loads
+/// nothing.
+///
+/// # Safety
+///
+/// See [`load`]: every library in `dir` must be a trusted plugin.
+#[cfg(feature = "plugins")]
+pub unsafe fn load_dir(
+    router: &mut Router,
+    dir: &std::path::Path,
+) -> Vec<(std::path::PathBuf, Result<Vec<String>, PluginError>)> {
+    files_with_extension(dir, std::env::consts::DLL_EXTENSION)
+        .into_iter()
+        .map(|path| {
+            let result = load(router, &path);
+            (path, result)
+        })
+        .collect()
+}
+
+/// Files in `dir` with extension `ext`, sorted by name. A missing or
+/// unreadable directory has none.
+#[cfg(any(feature = "plugins", feature = "wasm"))]
+fn files_with_extension(dir: &std::path::Path, ext: &str) -> Vec<std::path::PathBuf> {
+    let mut paths: Vec<_> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|e| e == ext))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    paths.sort();
+    paths
+}
@@ -0,0 +1,180 @@
+//! WebAssembly handler plugins: WASI components implementing the `handler`
+//! world in `wit/plugin.wit`.
+//!
+//! Unlike shared-library plugins, these are sandboxed. Every request runs in
+//! a fresh instance with no filesystem, environment, or network access, and
+//! with caps on the instructions it may execute ([`Limits::fuel`]) and the
+//! memory it may grow ([`Limits::memory_bytes`]). A handler that exceeds
+//! either, or traps, gets its client a 500 page; the server carries on.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use wasmtime::component::{Component, Linker, ResourceTable};
+use wasmtime::{Config, Engine, Store, StoreLimits, StoreLimitsBuilder};
+use wasmtime_wasi::{WasiCtx, WasiCtxBuilder, WasiCtxView, WasiView};
+
+use super::{is_valid_header, PluginError};
+use crate::http::{Request, Response};
+use crate::pages;
+use crate::router::Router;
+
+mod bindings {
+    wasmtime::component::bindgen!({ path: "wit", world: "handler" });
+}
+
+use bindings::rusty_server::plugin::types;
+use bindings::HandlerPre;
+
+/// Resource caps applied to each request a plugin handles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Limits {
+    /// Fuel per request; one unit is roughly one WebAssembly instruction.
+    pub fuel: u64,
+    /// Largest total linear memory an instance may grow to, in bytes.
+    pub memory_bytes: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            fuel: 100_000_000,
+            memory_bytes: 16 << 20,
+        }
+    }
+}
+
+/// Per-instance state: a WASI context without any capabilities, and the
+/// memory limiter.
+struct State {
+    wasi: WasiCtx,
+    table: ResourceTable,
+    limits: StoreLimits,
+}
+
+impl WasiView for State {
+    fn ctx(&mut self) -> WasiCtxView<'_> {
+        WasiCtxView {
+            ctx: &mut self.wasi,
+            table: &mut self.table,
+        }
+    }
+}
+
+/// A compiled plugin, ready to be instantiated per request.
+struct WasmHandler {
+    engine: Engine,
+    pre: HandlerPre<State>,
+    limits: Limits,
+}
+
+impl WasmHandler {
+    fn new(path: &Path, limits: Limits) -> wasmtime::Result<Self> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config)?;
+        let component = Component::from_file(&engine, path)?;
+        let mut linker = Linker::new(&engine);
+        wasmtime_wasi::p2::add_to_linker_sync(&mut linker)?;
+        let pre = HandlerPre::new(linker.instantiate_pre(&component)?)?;
+        Ok(WasmHandler {
+            engine,
+            pre,
+            limits,
+        })
+    }
+
+    fn store(&self) -> wasmtime::Result<Store<State>> {
+        let state = State {
+            wasi: WasiCtxBuilder::new().build(),
+            table: ResourceTable::new(),
+            limits: StoreLimitsBuilder::new()
+                .memory_size(self.limits.memory_bytes)
+                .build(),
+        };
+        let mut store = Store::new(&self.engine, state);
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(self.limits.fuel)?;
+        Ok(store)
+    }
+
+    fn routes(&self) -> wasmtime::Result<Vec<types::Route>> {
+        let mut store = self.store()?;
+        self.pre.instantiate(&mut store)?.call_routes(&mut store)
+    }
+
+    fn handle(&self, request: &Request) -> wasmtime::Result<types::Response> {
+        let request = types::Request {
+            method: request.method.clone(),
+            path: request.path.clone(),
+            target: request.target.clone(),
+            headers: request
+                .headers
+                .iter()
+                .map(|(name, value)| types::Header {
+                    name: name.clone(),
+                    value: value.clone(),
+                })
+                .collect(),
+        };
+        let mut store = self.store()?;
+        self.pre
+            .instantiate(&mut store)?
+            .call_handle(&mut store, &request)
+    }
+
+    fn call(&self, request: &Request) -> Response {
+        match self.handle(request) {
+            Ok(answer) => {
+                let mut response = Response::new(answer.status).body(answer.body);
+                for header in answer.headers {
+                    if is_valid_header(&header.name, &header.value) {
+                        response.headers.push((header.name, header.value));
+                    }
+                }
+                response
+            }
+            Err(_) => pages::error(
+                500,
+                "Internal Server Error",
+                "The plugin failed to handle the request.",
+            ),
+        }
+    }
+}
+
+/// Compiles the component at `path`, asks it for its routes, and adds them to
+/// `router`. Returns the routes added, as `METHOD /path` strings.
+pub fn load(router: &mut Router, path: &Path, limits: Limits) -> Result<Vec<String>, PluginError> {
+    let handler = Arc::new(WasmHandler::new(path, limits).map_err(PluginError::Wasm)?);
+    let routes = handler.routes().map_err(PluginError::Wasm)?;
+    let mut added = Vec::new();
+    for route in routes {
+        if route.method.is_empty() || !route.path.starts_with('/') {
+            continue;
+        }
+        let handler = Arc::clone(&handler);
+        router.route(&route.method, &route.path, move |request| {
+            handler.call(request)
+        });
+        added.push(format!("{} {}", route.method, route.path));
+    }
+    Ok(added)
+}
+
+/// Loads every `.wasm` component in `dir`, in file name order, and reports
+/// each one's outcome. A missing directory loads nothing.
+pub fn load_dir(
+    router: &mut Router,
+    dir: &Path,
+    limits: Limits,
+) -> Vec<(PathBuf, Result<Vec<String>, PluginError>)> {
+    super::files_with_extension(dir, "wasm")
+        .into_iter()
+        .map(|path| {
+            let result = load(router, &path, limits);
+            (path, result)
+        })
+        .collect()
+}
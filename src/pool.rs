@@ -0,0 +1,47 @@
+//! A fixed-size pool of worker threads, for serving connections without a
+//! thread each.
+
+use std::io;
+use std::num::NonZeroUsize;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Worker threads taking jobs off a shared queue, oldest first. The workers
+/// stop once the pool is dropped and the queue is empty.
+pub(crate) struct ThreadPool {
+    sender: Sender<Job>,
+}
+
+impl ThreadPool {
+    /// Starts `size` workers; fails if one cannot be spawned.
+    pub(crate) fn new(size: NonZeroUsize) -> io::Result<Self> {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for id in 0..size.get() {
+            let receiver = Arc::clone(&receiver);
+            thread::Builder::new()
+                .name(format!("worker-{}", id))
+                .spawn(move || loop {
+                    // The lock is only held while waiting, not while working.
+                    let job = receiver
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .recv();
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => return,
+                    }
+                })?;
+        }
+        Ok(ThreadPool { sender })
+    }
+
+    /// Queues `job` for the next free worker.
+    pub(crate) fn execute(&self, job: impl FnOnce() + Send + 'static) {
+        // The workers only stop once `self.sender` is gone, so this succeeds.
+        let _ = self.sender.send(Box::new(job));
+    }
+}
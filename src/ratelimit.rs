@@ -0,0 +1,154 @@
+//! Per-client rate limiting, so that one client, such as a `curl` stuck in
+//! a loop, cannot take the server's time from the others.
+//!
+//! [`RateLimit`] keeps a token bucket for each client: an IPv4 address, or
+//! an IPv6 /64 network, as one host is usually given a whole /64. A bucket
+//! holds up to `burst` tokens and refills at `rate` tokens a second, and
+//! every request takes one. A client can thus send `burst` requests at once,
+//! and `rate` a second after that:
+//!
+//! ```no_run
+//! use rusty_server::ratelimit::RateLimit;
+//!
+//! let mut router = rusty_server::Router::default();
+//! router.middleware(RateLimit::new(10.0).burst(20));
+//! ```
+//!
+//! A request finding its bucket empty is answered with `429 Too Many
+//! Requests` and a `Retry-After` header saying how many seconds until the
+//! next token, and counted in `rusty_server_throttled_requests_total` at
+//! `/metrics`. Requests without a client address, such as those served over
+//! [`serve_connection`](crate::server::serve_connection), are not limited.
+//!
+//! At most [`MAX_CLIENTS`] buckets are kept: the bucket updated longest ago
+//! makes way for a new client, and refilled buckets, the same as none, are
+//! dropped as requests come in.
+
+use std::collections::{BTreeSet, HashMap};
+use std::net::{IpAddr, Ipv6Addr};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::http::{Request, Response};
+use crate::router::Middleware;
+
+/// How many clients' buckets are kept at most.
+pub const MAX_CLIENTS: usize = 4096;
+
+/// A client's bucket.
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    /// When `tokens` was last brought up to date.
+    updated: Instant,
+}
+
+/// The clients tracked, with their buckets.
+#[derive(Debug, Default)]
+struct Buckets {
+    by_client: HashMap<IpAddr, Bucket>,
+    /// The clients by when their bucket was last updated, oldest first.
+    by_age: BTreeSet<(Instant, IpAddr)>,
+}
+
+/// Middleware answering `429 Too Many Requests` to clients sending requests
+/// faster than it allows.
+#[derive(Debug)]
+pub struct RateLimit {
+    rate: f64,
+    burst: u32,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimit {
+    /// Allows each client `rate` requests a second, with bursts of as many,
+    /// rounded up.
+    ///
+    /// # Panics
+    ///
+    /// If `rate` is not positive and finite.
+    pub fn new(rate: f64) -> Self {
+        assert!(rate.is_finite() && rate > 0.0, "rate limit must be positive: {}", rate);
+        RateLimit {
+            rate,
+            burst: rate.ceil() as u32,
+            buckets: Mutex::new(Buckets::default()),
+        }
+    }
+
+    /// Allows bursts of `burst` requests, at least one, before the rate
+    /// applies.
+    pub fn burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+        self
+    }
+
+    /// Takes a token from the bucket of `client`, shared with the rest of
+    /// its /64 for IPv6: `Ok` if there was one, and otherwise how long until
+    /// there is.
+    pub fn acquire(&self, client: IpAddr) -> Result<(), Duration> {
+        self.acquire_at(client, Instant::now())
+    }
+
+    fn acquire_at(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        let client = client_key(client);
+        let burst = f64::from(self.burst);
+        let refill = Duration::from_secs_f64(burst / self.rate);
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        let Buckets { by_client, by_age } = &mut *buckets;
+        // The oldest buckets are the first to refill, so dropping refilled
+        // ones from the front costs a step per bucket ever made.
+        while let Some(&(updated, oldest)) = by_age.first() {
+            let refilled = now.saturating_duration_since(updated) >= refill;
+            let full = by_client.len() >= MAX_CLIENTS && !by_client.contains_key(&client);
+            if !refilled && !full {
+                break;
+            }
+            by_age.pop_first();
+            by_client.remove(&oldest);
+        }
+        let bucket = by_client.entry(client).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        by_age.remove(&(bucket.updated, client));
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(burst);
+        bucket.updated = now;
+        by_age.insert((now, client));
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+        }
+    }
+}
+
+/// The key of `client`'s bucket: its IPv4 address, or its IPv6 /64.
+fn client_key(client: IpAddr) -> IpAddr {
+    match client.to_canonical() {
+        IpAddr::V6(address) => {
+            let network = u128::from(address) & !(u128::MAX >> 64);
+            IpAddr::V6(Ipv6Addr::from(network))
+        }
+        address => address,
+    }
+}
+
+impl Middleware for RateLimit {
+    fn before(&self, request: &Request) -> Option<Response> {
+        let wait = self.acquire(request.peer?).err()?;
+        crate::metrics::throttled();
+        // Whole seconds, rounded up, so that a client waiting as long finds
+        // a token.
+        let retry_after = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+        let response = crate::pages::error_for(
+            request,
+            429,
+            "Too Many Requests",
+            "This client is sending requests too fast; retry later.",
+        );
+        Some(response.header("Retry-After", retry_after.max(1).to_string()))
+    }
+}
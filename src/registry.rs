@@ -0,0 +1,365 @@
+//! Self-registration with a service registry: Consul or etcd.
+//!
+//! Describe the server as a [`Service`] and register it with [`Consul`] or
+//! [`Etcd`]; call [`Registration::deregister`] on shutdown to take it out
+//! again.
+//!
+//! - Consul: the service goes to the local agent's catalog together with an
+//!   HTTP health check on [`Service::health_url`], and Consul removes it by
+//!   itself if the check keeps failing.
+//! - etcd: the service is a JSON value under `<prefix>/<name>/<id>`,
+//!   attached to a lease that a background thread keeps alive. If the
+//!   server dies without deregistering, the key expires with the lease.
+//!
+//! Both are spoken to over plain HTTP: Consul's agent API, and the JSON
+//! gateway of etcd v3.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+use crate::client::{Client, ClientError};
+use crate::http::percent_encode_path;
+use crate::{console, json_escape};
+
+/// Path of the health check, unless set with [`Service::health_url`].
+pub const DEFAULT_HEALTH_PATH: &str = "/version";
+
+/// Why registering or deregistering failed.
+#[derive(Debug, thiserror::Error)]
+pub enum RegistryError {
+    /// The registry could not be reached.
+    #[error(transparent)]
+    Client(#[from] ClientError),
+    /// The registry answered with an error status.
+    #[error("registry answered {status}: {body}")]
+    Rejected { status: u16, body: String },
+    /// The registry's answer lacked what was asked for.
+    #[error("unexpected answer from registry: {0}")]
+    InvalidResponse(&'static str),
+}
+
+/// The server as a registry sees it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Service {
+    name: String,
+    id: String,
+    address: IpAddr,
+    port: u16,
+    health_url: String,
+}
+
+impl Service {
+    /// The service `name`, listening on `addr`. Its ID is
+    /// `<name>-<address>-<port>`, and its health check is
+    /// [`DEFAULT_HEALTH_PATH`] on `addr`.
+    ///
+    /// An unspecified address (`0.0.0.0` or `::`) is replaced by the address
+    /// of the interface that routes to the outside world, which is the one
+    /// other machines can reach the server on.
+    pub fn new(name: &str, addr: SocketAddr) -> Self {
+        let address = if addr.ip().is_unspecified() {
+            outbound_address().unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST))
+        } else {
+            addr.ip()
+        };
+        let authority = SocketAddr::new(address, addr.port());
+        Service {
+            name: name.to_string(),
+            id: format!("{}-{}-{}", name, address, addr.port()).replace([':', '.'], "-"),
+            address,
+            port: addr.port(),
+            health_url: format!("http://{}{}", authority, DEFAULT_HEALTH_PATH),
+        }
+    }
+
+    /// Replaces the generated service ID.
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = id.into();
+        self
+    }
+
+    /// Replaces the health-check URL.
+    pub fn health_url(mut self, url: impl Into<String>) -> Self {
+        self.health_url = url.into();
+        self
+    }
+
+    /// The service ID the registry knows the server by.
+    pub fn service_id(&self) -> &str {
+        &self.id
+    }
+
+    /// The address registered for the service.
+    pub fn address(&self) -> SocketAddr {
+        SocketAddr::new(self.address, self.port)
+    }
+
+    /// The service as a JSON object: name, ID, address, port, and health URL.
+    pub fn to_json(&self) -> String {
+        format!(
+            r#"{{"name":"{}","id":"{}","address":"{}","port":{},"health":"{}"}}"#,
+            json_escape(&self.name),
+            json_escape(&self.id),
+            self.address,
+            self.port,
+            json_escape(&self.health_url)
+        )
+    }
+}
+
+/// The local address used to reach the outside world. Connecting a UDP
+/// socket only picks a route; no packet is sent.
+fn outbound_address() -> Option<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("192.0.2.1:80").ok()?;
+    Some(socket.local_addr().ok()?.ip())
+}
+
+/// Fails unless `response` has a 2xx status.
+fn check(response: crate::Response) -> Result<crate::Response, RegistryError> {
+    if (200..300).contains(&response.status) {
+        Ok(response)
+    } else {
+        Err(RegistryError::Rejected {
+            status: response.status,
+            body: String::from_utf8_lossy(&response.body).trim().to_string(),
+        })
+    }
+}
+
+/// A Consul agent.
+pub struct Consul {
+    url: String,
+    token: Option<String>,
+    interval: Duration,
+}
+
+impl Consul {
+    /// The agent at `url`, such as `http://127.0.0.1:8500`.
+    pub fn new(url: impl Into<String>) -> Self {
+        Consul {
+            url: url.into().trim_end_matches('/').to_string(),
+            token: None,
+            interval: Duration::from_secs(10),
+        }
+    }
+
+    /// Sends the ACL `token` with every request.
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Sets how often Consul runs the health check; 10 seconds by default.
+    /// A service whose check has failed for a minute is removed.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Registers `service` with the agent.
+    pub fn register(self, service: &Service) -> Result<Registration, RegistryError> {
+        let body = format!(
+            r#"{{"ID":"{}","Name":"{}","Address":"{}","Port":{},"Check":{{"HTTP":"{}","Interval":"{}s","DeregisterCriticalServiceAfter":"1m"}}}}"#,
+            json_escape(&service.id),
+            json_escape(&service.name),
+            service.address,
+            service.port,
+            json_escape(&service.health_url),
+            self.interval.as_secs().max(1)
+        );
+        let client = Client::new();
+        self.put(&client, "/v1/agent/service/register", body.as_bytes())?;
+        Ok(Registration {
+            kind: Kind::Consul {
+                consul: self,
+                client,
+                id: service.id.clone(),
+            },
+        })
+    }
+
+    fn put(&self, client: &Client, path: &str, body: &[u8]) -> Result<(), RegistryError> {
+        let mut headers = vec![("Content-Type", "application/json")];
+        if let Some(token) = &self.token {
+            headers.push(("X-Consul-Token", token));
+        }
+        let url = format!("{}{}", self.url, path);
+        check(client.request("PUT", &url, &headers, body)?)?;
+        Ok(())
+    }
+}
+
+/// An etcd v3 cluster, reached through its JSON gateway.
+pub struct Etcd {
+    url: String,
+    prefix: String,
+    ttl: Duration,
+}
+
+impl Etcd {
+    /// The cluster member at `url`, such as `http://127.0.0.1:2379`.
+    pub fn new(url: impl Into<String>) -> Self {
+        Etcd {
+            url: url.into().trim_end_matches('/').to_string(),
+            prefix: "/services".to_string(),
+            ttl: Duration::from_secs(30),
+        }
+    }
+
+    /// Sets the key prefix; services are stored under
+    /// `<prefix>/<name>/<id>`. `/services` by default.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Sets the lease TTL: how long the key outlives a server that stopped
+    /// without deregistering. 30 seconds by default; the lease is renewed
+    /// at a third of it.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Stores `service` under a new lease and starts keeping the lease alive.
+    pub fn register(self, service: &Service) -> Result<Registration, RegistryError> {
+        let client = Client::new();
+        let granted = self.post(
+            &client,
+            "/v3/lease/grant",
+            &format!(r#"{{"TTL":{}}}"#, self.ttl.as_secs().max(1)),
+        )?;
+        let lease = json_field(&granted, "ID")
+            .ok_or(RegistryError::InvalidResponse("lease grant without an ID"))?;
+        let key = format!("{}/{}/{}", self.prefix, service.name, service.id);
+        self.post(
+            &client,
+            "/v3/kv/put",
+            &format!(
+                r#"{{"key":"{}","value":"{}","lease":"{}"}}"#,
+                BASE64.encode(&key),
+                BASE64.encode(service.to_json()),
+                lease
+            ),
+        )?;
+
+        let etcd = Arc::new(self);
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let keepalive = {
+            let (etcd, stop, lease) = (Arc::clone(&etcd), Arc::clone(&stop), lease.clone());
+            thread::spawn(move || etcd.keep_alive(&lease, &stop))
+        };
+        Ok(Registration {
+            kind: Kind::Etcd {
+                etcd,
+                client,
+                lease,
+                stop,
+                keepalive,
+            },
+        })
+    }
+
+    /// Renews `lease` every third of the TTL until `stop` is set.
+    fn keep_alive(&self, lease: &str, stop: &(Mutex<bool>, Condvar)) {
+        let client = Client::new();
+        let body = format!(r#"{{"ID":"{}"}}"#, lease);
+        let (stopped, wake) = stop;
+        let mut stopped = stopped.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            stopped = wake
+                .wait_timeout_while(stopped, self.ttl / 3, |stopped| !*stopped)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+            if *stopped {
+                return;
+            }
+            if let Err(e) = self.post(&client, "/v3/lease/keepalive", &body) {
+                console::print_table(&[("etcd", format!("✗ cannot renew lease: {}", e))]);
+            }
+        }
+    }
+
+    fn post(&self, client: &Client, path: &str, body: &str) -> Result<String, RegistryError> {
+        let url = format!("{}{}", self.url, path);
+        let response = check(client.post(
+            &url,
+            &[("Content-Type", "application/json")],
+            body.as_bytes(),
+        )?)?;
+        Ok(String::from_utf8_lossy(&response.body).into_owned())
+    }
+}
+
+/// The value of the top-level string or number field `name` in the JSON
+/// object `json`. etcd's gateway sends 64-bit integers, such as lease IDs,
+/// as strings.
+fn json_field(json: &str, name: &str) -> Option<String> {
+    let pattern = format!("\"{}\"", name);
+    let after = &json[json.find(&pattern)? + pattern.len()..];
+    let value = after.trim_start().strip_prefix(':')?.trim_start();
+    let value = match value.strip_prefix('"') {
+        Some(quoted) => &quoted[..quoted.find('"')?],
+        None => &value[..value.find([',', '}']).unwrap_or(value.len())],
+    };
+    let value = value.trim();
+    (!value.is_empty() && value.bytes().all(|b| b.is_ascii_digit() || b == b'-'))
+        .then(|| value.to_string())
+}
+
+enum Kind {
+    Consul {
+        consul: Consul,
+        client: Client,
+        id: String,
+    },
+    Etcd {
+        etcd: Arc<Etcd>,
+        client: Client,
+        lease: String,
+        stop: Arc<(Mutex<bool>, Condvar)>,
+        keepalive: JoinHandle<()>,
+    },
+}
+
+/// A registered service.
+pub struct Registration {
+    kind: Kind,
+}
+
+impl Registration {
+    /// Takes the service out of the registry.
+    pub fn deregister(self) -> Result<(), RegistryError> {
+        match self.kind {
+            Kind::Consul { consul, client, id } => consul.put(
+                &client,
+                &format!("/v1/agent/service/deregister/{}", percent_encode_path(&id)),
+                b"",
+            ),
+            Kind::Etcd {
+                etcd,
+                client,
+                lease,
+                stop,
+                keepalive,
+            } => {
+                *stop.0.lock().unwrap_or_else(PoisonError::into_inner) = true;
+                stop.1.notify_all();
+                let _ = keepalive.join();
+                // Revoking the lease deletes the key attached to it.
+                etcd.post(
+                    &client,
+                    "/v3/lease/revoke",
+                    &format!(r#"{{"ID":"{}"}}"#, lease),
+                )
+                .map(drop)
+            }
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! An interactive admin console on stdin, for driving a running server
+//! without curl. It reads one command per line:
+//!
+//! - `stats`: uptime and connection counts
+//! - `connections`: the open connections, oldest first
+//! - `loglevel [LEVEL]`: how much is reported about each connection:
+//!   `quiet`, `normal`, `verbose`, or `debug`; without a level, the current
+//!   one
+//! - `drain`: stop taking new connections, wait up to [`DRAIN_TIMEOUT`] for
+//!   the open ones to finish, and quit
+//! - `export`: write the request history to CSV and JSON files (with the
+//!   `history` feature, once recording has started)
+//! - `help`: the commands
+//! - `quit`: quit at once
+//!
+//! Results are printed like the rest of the console output, as tables or
+//! NDJSON. The prompt goes to stderr, so it stays out of that output.
+
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::console::{self, UnknownVerbosity, Verbosity};
+use crate::{server, stats, unix_timestamp};
+
+/// Longest `drain` waits for open connections before quitting anyway.
+pub const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often `drain` checks whether the connections have finished.
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// One console command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Stats,
+    Connections,
+    /// Sets the verbosity, or shows it if `None`.
+    LogLevel(Option<Verbosity>),
+    Drain,
+    #[cfg(feature = "history")]
+    Export,
+    Help,
+    Quit,
+}
+
+/// A line that is not a [`Command`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum CommandError {
+    #[error("unknown command `{0}`; `help` lists the commands")]
+    Unknown(String),
+    #[error(transparent)]
+    LogLevel(#[from] UnknownVerbosity),
+}
+
+impl FromStr for Command {
+    type Err = CommandError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            ["stats"] => Ok(Command::Stats),
+            ["connections"] => Ok(Command::Connections),
+            ["loglevel"] => Ok(Command::LogLevel(None)),
+            ["loglevel", level] => Ok(Command::LogLevel(Some(level.parse()?))),
+            ["drain"] => Ok(Command::Drain),
+            #[cfg(feature = "history")]
+            ["export"] => Ok(Command::Export),
+            ["help"] => Ok(Command::Help),
+            ["quit" | "exit"] => Ok(Command::Quit),
+            _ => Err(CommandError::Unknown(line.trim().to_string())),
+        }
+    }
+}
+
+/// Reads and carries out commands from `input` until `quit`, a finished
+/// `drain`, or the end of the input.
+pub fn run(mut input: impl BufRead) {
+    loop {
+        eprint!("> ");
+        let _ = io::stderr().flush();
+        let mut line = String::new();
+        match input.read_line(&mut line) {
+            Ok(0) | Err(_) => return,
+            Ok(_) if line.trim().is_empty() => continue,
+            Ok(_) => {}
+        }
+        match line.parse() {
+            Ok(command) => {
+                if !execute(command) {
+                    return;
+                }
+            }
+            Err(e) => eprintln!("Error: {}", e),
+        }
+    }
+}
+
+/// Carries out `command`; returns whether to read further commands.
+pub fn execute(command: Command) -> bool {
+    match command {
+        Command::Stats => {
+            let snapshot = stats::snapshot();
+            console::print_table(&[
+                ("Uptime", format!("{} s", snapshot.uptime_secs)),
+                ("Connections", snapshot.connections_total.to_string()),
+                ("Active", snapshot.connections_active.to_string()),
+                ("Idle", snapshot.connections_idle.to_string()),
+            ]);
+        }
+        Command::Connections => {
+            let now = unix_timestamp();
+            let rows: Vec<Vec<String>> = stats::connections()
+                .into_iter()
+                .map(|connection| {
+                    vec![
+                        connection.peer,
+                        connection.opened.to_string(),
+                        format!("{} s", now.saturating_sub(connection.opened)),
+                        connection.requests.to_string(),
+                        connection
+                            .idle_since
+                            .map_or("-".to_string(), |since| format!("{} s", now.saturating_sub(since))),
+                    ]
+                })
+                .collect();
+            console::print_grid(&["Peer", "Opened", "Age", "Requests", "Idle"], &rows);
+        }
+        Command::LogLevel(Some(verbosity)) => {
+            console::set_verbosity(verbosity);
+            console::print_line(&format!("Log level: {}", level_name(verbosity)));
+        }
+        Command::LogLevel(None) => {
+            console::print_line(&format!("Log level: {}", level_name(console::verbosity())));
+        }
+        Command::Drain => {
+            drain();
+            return false;
+        }
+        #[cfg(feature = "history")]
+        Command::Export => {
+            if let Err(e) = crate::history::export() {
+                eprintln!("Error: {}", e);
+            }
+        }
+        Command::Help => {
+            let rows: Vec<Vec<String>> = [
+                ("stats", "uptime and connection counts"),
+                ("connections", "the open connections"),
+                (
+                    "loglevel [LEVEL]",
+                    "quiet, normal, verbose, or debug reports",
+                ),
+                ("drain", "finish the open connections and quit"),
+                #[cfg(feature = "history")]
+                ("export", "write the request history to files"),
+                ("help", "this list"),
+                ("quit", "quit at once"),
+            ]
+            .iter()
+            .map(|(command, effect)| vec![command.to_string(), effect.to_string()])
+            .collect();
+            console::print_grid(&["Command", "Effect"], &rows);
+        }
+        Command::Quit => return false,
+    }
+    true
+}
+
+fn level_name(verbosity: Verbosity) -> &'static str {
+    match verbosity {
+        Verbosity::Quiet => "quiet",
+        Verbosity::Normal => "normal",
+        Verbosity::Verbose => "verbose",
+        Verbosity::VeryVerbose => "debug",
+    }
+}
+
+/// Stops taking connections and waits for the open ones to finish.
+fn drain() {
+    server::drain();
+    let deadline = Instant::now() + DRAIN_TIMEOUT;
+    loop {
+        let active = stats::snapshot().connections_active;
+        if active == 0 {
+            console::print_line("Drain: ✓ all connections finished");
+            return;
+        }
+        if Instant::now() >= deadline {
+            console::print_line(&format!("Drain: ✗ {} connections still open", active));
+            return;
+        }
+        thread::sleep(DRAIN_POLL_INTERVAL);
+    }
+}
@@ -0,0 +1,404 @@
+//! Request routing: maps a method and path to the handler that answers it.
+
+use crate::http::{Request, Response};
+use crate::openapi::{self, Kind};
+use crate::sse::{EventHandler, EventStream};
+use crate::stream::{Connection, StreamHandler};
+use crate::{admin, endpoints, pages};
+#[cfg(feature = "websocket")]
+use crate::websocket::{self, WebSocket, WebSocketHandler};
+
+/// A request handler: anything that turns a request into a response.
+pub type Handler = Box<dyn Fn(&Request) -> Response + Send + Sync>;
+
+/// A handler for requests no route matches: answers with `Some` response, or
+/// passes with `None`.
+pub type Fallback = Box<dyn Fn(&Request) -> Option<Response> + Send + Sync>;
+
+/// Code run around every request the router answers, registered with
+/// [`Router::middleware`], for concerns such as logging, authentication,
+/// CORS, or rate limiting that span routes.
+///
+/// Both hooks do nothing by default, so an implementation only provides the
+/// ones it needs.
+pub trait Middleware: Send + Sync {
+    /// Runs before the request is routed, in registration order. Answering
+    /// with `Some` response sends it instead: neither the later middleware
+    /// nor the routes see the request.
+    fn before(&self, request: &Request) -> Option<Response> {
+        let _ = request;
+        None
+    }
+
+    /// Runs on the response, in reverse registration order, for every
+    /// middleware whose `before` ran.
+    fn after(&self, request: &Request, response: &mut Response) {
+        let _ = (request, response);
+    }
+}
+
+struct Route {
+    method: String,
+    path: String,
+    handler: Handler,
+}
+
+/// Maps request methods and paths to handlers.
+///
+/// [`Router::default`] serves the built-in endpoints, [`Router::new`] starts
+/// empty. A route's path is matched exactly unless it is a
+/// [pattern](Router::route). Routes are matched in registration order. A request matching no
+/// route is offered to the [fallbacks](Router::fallback) in turn; if none
+/// answers, it gets `405 Method Not Allowed`, with an `Allow` header, when
+/// its path has routes for other methods, and the 404 page otherwise.
+pub struct Router {
+    routes: Vec<Route>,
+    fallbacks: Vec<Fallback>,
+    guards: Vec<(String, Fallback)>,
+    middleware: Vec<Box<dyn Middleware>>,
+    event_streams: Vec<(String, EventHandler)>,
+    streams: Vec<(String, StreamHandler)>,
+    #[cfg(feature = "websocket")]
+    websockets: Vec<(String, WebSocketHandler)>,
+}
+
+impl Router {
+    /// A router without any routes.
+    pub fn new() -> Self {
+        Router {
+            routes: Vec::new(),
+            fallbacks: Vec::new(),
+            guards: Vec::new(),
+            middleware: Vec::new(),
+            event_streams: Vec::new(),
+            streams: Vec::new(),
+            #[cfg(feature = "websocket")]
+            websockets: Vec::new(),
+        }
+    }
+
+    /// Registers `handler` for requests with `method` and `path`.
+    ///
+    /// `path` can be a pattern: a segment `:name` matches any one non-empty
+    /// segment, and a last segment `*name` matches the rest of the path,
+    /// however many segments, none included. `/kv/:key` matches `/kv/a` but
+    /// not `/kv/a/b`, and `/static/*file` matches `/static/css/site.css`.
+    /// The handler finds what they matched in [`Request::params`].
+    pub fn route<F>(&mut self, method: &str, path: &str, handler: F) -> &mut Self
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        self.routes.push(Route {
+            method: method.to_string(),
+            path: path.to_string(),
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    /// Registers `handler` for `GET` requests to `path`.
+    pub fn get<F>(&mut self, path: &str, handler: F) -> &mut Self
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        self.route("GET", path, handler)
+    }
+
+    /// Registers `handler` for `POST` requests to `path`. The request body
+    /// is read in full before `handler` runs; requests without a
+    /// `Content-Length` are answered with 411.
+    pub fn post<F>(&mut self, path: &str, handler: F) -> &mut Self
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        self.route("POST", path, handler)
+    }
+
+    /// The handler registered for `method` and `path`, if any.
+    pub fn handler(&self, method: &str, path: &str) -> Option<&Handler> {
+        self.matching(method, path).map(|(route, _)| &route.handler)
+    }
+
+    /// The path of the route for `method` matching `path`, as registered:
+    /// `/status/:code` for `/status/404`.
+    pub fn pattern(&self, method: &str, path: &str) -> Option<&str> {
+        self.matching(method, path).map(|(route, _)| route.path.as_str())
+    }
+
+    /// The first route for `method` whose path matches `path`, and what its
+    /// pattern captured.
+    fn matching(&self, method: &str, path: &str) -> Option<(&Route, Vec<(String, String)>)> {
+        self.routes
+            .iter()
+            .filter(|route| route.method == method)
+            .find_map(|route| Some((route, captures(&route.path, path)?)))
+    }
+
+    /// Registers `fallback` for requests no route matches. Fallbacks are
+    /// tried in registration order, and the first `Some` response is sent.
+    ///
+    /// Unlike routes, a fallback decides per request what it answers, so it
+    /// can serve a set of paths that changes while the server runs.
+    pub fn fallback<F>(&mut self, fallback: F) -> &mut Self
+    where
+        F: Fn(&Request) -> Option<Response> + Send + Sync + 'static,
+    {
+        self.fallbacks.push(Box::new(fallback));
+        self
+    }
+
+    /// Registers `guard` for requests to `prefix` and the paths below it,
+    /// as for [`stream`](Router::stream). A guard runs before the routes and
+    /// fallbacks, and answers with `Some` response to turn a request away,
+    /// or passes with `None`; guards are tried in registration order.
+    ///
+    /// Guards do not cover event streams, streaming handlers, or WebSockets.
+    pub fn guard<F>(&mut self, prefix: &str, guard: F) -> &mut Self
+    where
+        F: Fn(&Request) -> Option<Response> + Send + Sync + 'static,
+    {
+        let prefix = prefix.trim_end_matches('/');
+        self.guards.push((prefix.to_string(), Box::new(guard)));
+        self
+    }
+
+    /// Registers `middleware` to run around every request the router
+    /// answers, outside the guards; see [`Middleware`] for the order.
+    ///
+    /// Like guards, middleware does not cover event streams, streaming
+    /// handlers, or WebSockets, nor requests the server answers itself,
+    /// such as malformed ones.
+    pub fn middleware<M>(&mut self, middleware: M) -> &mut Self
+    where
+        M: Middleware + 'static,
+    {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// Registers an event-stream `handler` for `GET` requests to `path`.
+    ///
+    /// The `text/event-stream` head is sent first, then `handler` writes
+    /// events until it returns.
+    pub fn events<F>(&mut self, path: &str, handler: F) -> &mut Self
+    where
+        F: Fn(&Request, &mut EventStream<'_>) + Send + Sync + 'static,
+    {
+        self.event_streams.push((path.to_string(), Box::new(handler)));
+        self
+    }
+
+    /// The event-stream handler registered for `path`, if any.
+    pub fn events_handler(&self, path: &str) -> Option<&EventHandler> {
+        self.event_streams
+            .iter()
+            .find(|(route, _)| route == path)
+            .map(|(_, handler)| handler)
+    }
+
+    /// Registers a streaming `handler` for requests with any method to
+    /// `prefix` and the paths below it: `/php` covers `/php` and
+    /// `/php/index.php`, but not `/phpinfo`.
+    ///
+    /// These take precedence over routes. The handler reads the request body
+    /// from the [`Connection`] and writes the whole response to it.
+    pub fn stream<F>(&mut self, prefix: &str, handler: F) -> &mut Self
+    where
+        F: Fn(&Request, &mut Connection<'_>) -> std::io::Result<u16> + Send + Sync + 'static,
+    {
+        let prefix = prefix.trim_end_matches('/');
+        self.streams.push((prefix.to_string(), Box::new(handler)));
+        self
+    }
+
+    /// The streaming handler whose prefix covers `path`, if any.
+    pub fn stream_handler(&self, path: &str) -> Option<&StreamHandler> {
+        self.streams
+            .iter()
+            .find(|(prefix, _)| covers(prefix, path))
+            .map(|(_, handler)| handler)
+    }
+
+    /// Registers a WebSocket `handler` for `path`.
+    ///
+    /// Upgrade requests to `path` are answered with `101 Switching Protocols`
+    /// and the connection is handed to `handler` until it returns; any other
+    /// request to `path` gets `426 Upgrade Required`.
+    #[cfg(feature = "websocket")]
+    pub fn websocket<F>(&mut self, path: &str, handler: F) -> &mut Self
+    where
+        F: Fn(&mut WebSocket<'_>) + Send + Sync + 'static,
+    {
+        self.websockets.push((path.to_string(), Box::new(handler)));
+        self
+    }
+
+    /// The WebSocket handler registered for `path`, if any.
+    #[cfg(feature = "websocket")]
+    pub fn websocket_handler(&self, path: &str) -> Option<&WebSocketHandler> {
+        self.websockets
+            .iter()
+            .find(|(route, _)| route == path)
+            .map(|(_, handler)| handler)
+    }
+
+    /// Serves the [OpenAPI document](openapi::document) of this router at
+    /// `/openapi.json` and Swagger UI at `/docs`.
+    ///
+    /// The document is generated now, so register every other route first.
+    pub fn docs(&mut self) -> &mut Self {
+        self.get(openapi::DOCS_PATH, |_| openapi::docs());
+        let document = openapi::document(self);
+        self.get(openapi::DOCUMENT_PATH, move |_| {
+            Response::with_body(200, "application/json", document.clone())
+        });
+        self
+    }
+
+    /// Serves the [admin endpoints](admin): server stats, open
+    /// connections, and routes, as JSON.
+    ///
+    /// The route list is taken now, so register every other route first.
+    pub fn admin(&mut self) -> &mut Self {
+        self.get(admin::STATS_PATH, |_| admin::stats());
+        self.get(admin::CONNECTIONS_PATH, |_| admin::connections());
+        let routes = admin::routes(self);
+        self.get(admin::ROUTES_PATH, move |_| Response::json(200, &routes));
+        self
+    }
+
+    /// The method, path, and kind of every route, event stream, and
+    /// WebSocket, in registration order.
+    pub(crate) fn operations(&self) -> Vec<(&str, &str, Kind)> {
+        let mut operations: Vec<(&str, &str, Kind)> = self
+            .routes
+            .iter()
+            .map(|route| (route.method.as_str(), route.path.as_str(), Kind::Route))
+            .collect();
+        operations.extend(
+            self.event_streams
+                .iter()
+                .map(|(path, _)| ("GET", path.as_str(), Kind::Events)),
+        );
+        #[cfg(feature = "websocket")]
+        operations.extend(
+            self.websockets
+                .iter()
+                .map(|(path, _)| ("GET", path.as_str(), Kind::WebSocket)),
+        );
+        operations
+    }
+
+    /// Produces the response for `request`, tagged with an `ETag` and
+    /// turned into `304 Not Modified` when the client has it already, as
+    /// [`Response::conditional`] does.
+    pub fn handle(&self, request: &Request) -> Response {
+        let mut ran = 0;
+        let mut answered = None;
+        for middleware in &self.middleware {
+            ran += 1;
+            answered = middleware.before(request);
+            if answered.is_some() {
+                break;
+            }
+        }
+        let mut response = answered.unwrap_or_else(|| self.answer(request));
+        for middleware in self.middleware[..ran].iter().rev() {
+            middleware.after(request, &mut response);
+        }
+        response.conditional(request)
+    }
+
+    /// The response to `request` from the guards, routes, and fallbacks.
+    fn answer(&self, request: &Request) -> Response {
+        let refused = self
+            .guards
+            .iter()
+            .filter(|(prefix, _)| covers(prefix, &request.path))
+            .find_map(|(_, guard)| guard(request));
+        if let Some(response) = refused {
+            return response;
+        }
+        if let Some((route, params)) = self.matching(&request.method, &request.path) {
+            if params.is_empty() {
+                return (route.handler)(request);
+            }
+            let mut request = request.clone();
+            request.params = params;
+            return (route.handler)(&request);
+        }
+        if let Some(response) = self.fallbacks.iter().find_map(|fallback| fallback(request)) {
+            return response;
+        }
+        let allowed = self.allowed(&request.path);
+        if allowed.is_empty() {
+            return pages::not_found_for(request);
+        }
+        let message = format!("{} is not supported here; try {}.", request.method, allowed.join(", "));
+        pages::error_for(request, 405, "Method Not Allowed", &message).header("Allow", allowed.join(", "))
+    }
+
+    /// The methods `path` has routes, event streams, or WebSockets for, in
+    /// registration order and each once.
+    fn allowed(&self, path: &str) -> Vec<&str> {
+        let mut allowed = Vec::new();
+        for (method, pattern, _) in self.operations() {
+            if !allowed.contains(&method) && captures(pattern, path).is_some() {
+                allowed.push(method);
+            }
+        }
+        allowed
+    }
+}
+
+impl Default for Router {
+    /// A router serving the built-in endpoints.
+    fn default() -> Self {
+        let mut router = Router::new();
+        router.get("/version", endpoints::version);
+        router.get("/healthz", endpoints::healthz);
+        router.get("/metrics", endpoints::metrics);
+        router.events("/events", endpoints::events);
+        #[cfg(feature = "websocket")]
+        {
+            let chat = websocket::Broadcast::new();
+            router.websocket("/ws/echo", websocket::echo);
+            router.websocket("/ws/chat", move |ws| chat.serve(ws));
+        }
+        router
+    }
+}
+
+/// Whether `prefix` covers `path`: `/php` covers `/php` and `/php/index.php`,
+/// but not `/phpinfo`.
+pub(crate) fn covers(prefix: &str, path: &str) -> bool {
+    path.strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// What the route path `pattern` captures from `path`, or `None` if `path`
+/// does not match it; see [`Router::route`].
+fn captures(pattern: &str, path: &str) -> Option<Vec<(String, String)>> {
+    if !pattern.contains([':', '*']) {
+        return (pattern == path).then(Vec::new);
+    }
+    let mut params = Vec::new();
+    let mut rest = path.strip_prefix('/');
+    for segment in pattern.strip_prefix('/')?.split('/') {
+        if let Some(name) = segment.strip_prefix('*') {
+            params.push((name.to_string(), rest.unwrap_or_default().to_string()));
+            return Some(params);
+        }
+        let (part, after) = match rest?.split_once('/') {
+            Some((part, after)) => (part, Some(after)),
+            None => (rest?, None),
+        };
+        rest = after;
+        match segment.strip_prefix(':') {
+            Some(name) if !part.is_empty() => params.push((name.to_string(), part.to_string())),
+            None if segment == part => {}
+            _ => return None,
+        }
+    }
+    rest.is_none().then_some(params)
+}
@@ -0,0 +1,350 @@
+//! Routes written in [Rhai](https://rhai.rs) scripts.
+//!
+//! Every `.rhai` file in the scripts directory is run once when it is loaded,
+//! and registers its routes by calling `route`:
+//!
+//! ```text
+//! route("GET", "/hello", |req| {
+//!     response(200).text(`Hello, ${req.headers["user-agent"] ?? "stranger"}!`)
+//! });
+//! ```
+//!
+//! A handler receives the request as a map with `method`, `path`, `target`,
+//! `query`, and `headers` (keyed by lower-case name), and returns one of:
+//!
+//! - a response built with `response(status)` and the `header`, `body`,
+//!   `text`, `html`, and `json` methods;
+//! - a string, sent as a `200` plain-text body;
+//! - a map, sent as a `200` JSON body;
+//! - `()`, sent as `204 No Content`.
+//!
+//! [`Scripts::reload`] picks up added, changed, and removed files, so routes
+//! can be edited while the server runs. A script that fails to compile keeps
+//! its previous routes. Scripts run with an operation limit, so a runaway
+//! loop fails its request rather than holding a thread forever.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::time::SystemTime;
+use std::{fs, io};
+
+use rhai::{Dynamic, Engine, EvalAltResult, FnPtr, ImmutableString, Map, AST};
+
+use crate::http::{Request, Response};
+use crate::pages;
+use crate::plugin::is_valid_header;
+
+/// Operations one script run may perform before it is aborted.
+pub const MAX_OPERATIONS: u64 = 10_000_000;
+
+/// Why a script could not be loaded.
+#[derive(Debug, thiserror::Error)]
+pub enum ScriptError {
+    /// The file could not be read.
+    #[error("cannot read script: {0}")]
+    Io(#[from] io::Error),
+    /// The file is not valid Rhai.
+    #[error("cannot compile script: {0}")]
+    Compile(#[from] rhai::ParseError),
+    /// Running the file, and with it its `route` calls, failed.
+    #[error("script failed: {0}")]
+    Run(#[from] Box<EvalAltResult>),
+}
+
+/// A route registered by a script.
+struct ScriptRoute {
+    method: String,
+    path: String,
+    handler: FnPtr,
+}
+
+/// A loaded script: the compiled file and the routes it registered.
+#[derive(Clone)]
+struct Script {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    ast: Arc<AST>,
+    routes: Arc<[ScriptRoute]>,
+}
+
+/// The scripts loaded from one directory, answering the requests their routes
+/// match.
+///
+/// Mount it on a router with [`Router::fallback`](crate::Router::fallback),
+/// so the routes can change on reload:
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use rusty_server::script::Scripts;
+///
+/// let scripts = Arc::new(Scripts::new("scripts"));
+/// scripts.reload();
+/// let mut router = rusty_server::Router::default();
+/// let handler = Arc::clone(&scripts);
+/// router.fallback(move |request| handler.handle(request));
+/// ```
+pub struct Scripts {
+    dir: PathBuf,
+    engine: Engine,
+    /// Routes registered by the script currently being run.
+    registered: Arc<Mutex<Vec<ScriptRoute>>>,
+    scripts: RwLock<Vec<Script>>,
+    /// Serialises reloads, which share `registered`.
+    reloading: Mutex<()>,
+}
+
+impl Scripts {
+    /// Scripts from `dir`. Nothing is loaded until [`reload`](Self::reload).
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let registered = Arc::new(Mutex::new(Vec::new()));
+        Scripts {
+            dir: dir.into(),
+            engine: engine(Arc::clone(&registered)),
+            registered,
+            scripts: RwLock::new(Vec::new()),
+            reloading: Mutex::new(()),
+        }
+    }
+
+    /// Loads every `.rhai` file in the directory that is new or changed since
+    /// the last reload, in file name order, and drops scripts whose files
+    /// are gone. Reports the outcome for each file loaded: the routes it
+    /// registered, as `METHOD /path` strings, or why it failed.
+    pub fn reload(&self) -> Vec<(PathBuf, Result<Vec<String>, ScriptError>)> {
+        let _reloading = self
+            .reloading
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        // Requests are served from the current scripts until the new set is
+        // swapped in.
+        let mut previous = self
+            .scripts
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        let mut current = Vec::new();
+        let mut outcomes = Vec::new();
+        for path in script_files(&self.dir) {
+            let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok();
+            let old = previous
+                .iter()
+                .position(|script| script.path == path)
+                .map(|index| previous.swap_remove(index));
+            if let Some(old) = old.as_ref().filter(|old| old.modified == modified) {
+                current.push(old.clone());
+                continue;
+            }
+            match self.load(&path) {
+                Ok((ast, routes)) => {
+                    let names = routes
+                        .iter()
+                        .map(|route| format!("{} {}", route.method, route.path))
+                        .collect();
+                    current.push(Script {
+                        path: path.clone(),
+                        modified,
+                        ast: Arc::new(ast),
+                        routes: routes.into(),
+                    });
+                    outcomes.push((path, Ok(names)));
+                }
+                Err(e) => {
+                    // Keep serving the last version that loaded, but retry
+                    // once the file changes again.
+                    if let Some(old) = old {
+                        current.push(Script { modified, ..old });
+                    }
+                    outcomes.push((path, Err(e)));
+                }
+            }
+        }
+        *self.scripts.write().unwrap_or_else(PoisonError::into_inner) = current;
+        outcomes
+    }
+
+    fn load(&self, path: &Path) -> Result<(AST, Vec<ScriptRoute>), ScriptError> {
+        let source = fs::read_to_string(path)?;
+        let ast = self.engine.compile(&source)?;
+        self.registered
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+        let result = self.engine.run_ast(&ast);
+        let routes = std::mem::take(
+            &mut *self
+                .registered
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        );
+        result?;
+        Ok((ast, routes))
+    }
+
+    /// The routes currently loaded, as `METHOD /path` strings.
+    pub fn routes(&self) -> Vec<String> {
+        self.scripts
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .flat_map(|script| script.routes.iter())
+            .map(|route| format!("{} {}", route.method, route.path))
+            .collect()
+    }
+
+    /// Runs the script route matching `request`, if any. A handler that fails
+    /// or returns something unusable gets its client a 500 page.
+    pub fn handle(&self, request: &Request) -> Option<Response> {
+        let (ast, handler) = self
+            .scripts
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .find_map(|script| {
+                script
+                    .routes
+                    .iter()
+                    .find(|route| route.method == request.method && route.path == request.path)
+                    .map(|route| (Arc::clone(&script.ast), route.handler.clone()))
+            })?;
+        // The lock is released before the handler runs, so a slow handler
+        // does not hold up reloads.
+        let answer = handler.call::<Dynamic>(&self.engine, &ast, (request_map(request),));
+        Some(
+            match answer.map_err(|e| e.to_string()).and_then(into_response) {
+                Ok(response) => response,
+                Err(_) => pages::error(
+                    500,
+                    "Internal Server Error",
+                    "The script failed to handle the request.",
+                ),
+            },
+        )
+    }
+}
+
+/// `.rhai` files in `dir`, sorted by name. A missing directory has none.
+fn script_files(dir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<_> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|e| e == "rhai"))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    paths.sort();
+    paths
+}
+
+/// The engine scripts run in, with `route`, `response`, and the response
+/// builder methods registered.
+fn engine(registered: Arc<Mutex<Vec<ScriptRoute>>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+
+    engine.register_fn(
+        "route",
+        move |method: &str, path: &str, handler: FnPtr| -> Result<(), Box<EvalAltResult>> {
+            if method.is_empty() || !path.starts_with('/') {
+                return Err(format!("invalid route: {} {}", method, path).into());
+            }
+            registered
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(ScriptRoute {
+                    method: method.to_string(),
+                    path: path.to_string(),
+                    handler,
+                });
+            Ok(())
+        },
+    );
+
+    engine.register_type_with_name::<Response>("Response");
+    engine.register_fn(
+        "response",
+        |status: i64| -> Result<Response, Box<EvalAltResult>> {
+            match u16::try_from(status) {
+                Ok(status @ 100..=599) => Ok(Response::new(status)),
+                _ => Err(format!("invalid status: {}", status).into()),
+            }
+        },
+    );
+    engine.register_fn(
+        "header",
+        |response: Response, name: &str, value: &str| -> Result<Response, Box<EvalAltResult>> {
+            if !is_valid_header(name, value) {
+                return Err(format!("invalid header: {}", name).into());
+            }
+            Ok(response.header(name, value))
+        },
+    );
+    engine.register_fn("body", |response: Response, body: ImmutableString| {
+        response.body(body.as_bytes())
+    });
+    engine.register_fn("text", |response: Response, body: ImmutableString| {
+        with_body(response, "text/plain; charset=utf-8", &body)
+    });
+    engine.register_fn("html", |response: Response, body: ImmutableString| {
+        with_body(response, "text/html", &body)
+    });
+    engine.register_fn("json", |response: Response, body: Map| {
+        with_body(
+            response,
+            "application/json",
+            &rhai::format_map_as_json(&body),
+        )
+    });
+    engine.register_fn("json", |response: Response, body: ImmutableString| {
+        with_body(response, "application/json", &body)
+    });
+    engine
+}
+
+fn with_body(response: Response, content_type: &str, body: &str) -> Response {
+    let mut response = response.body(body);
+    response
+        .headers
+        .retain(|(name, _)| !name.eq_ignore_ascii_case("Content-Type"));
+    response.header("Content-Type", content_type)
+}
+
+/// The request as scripts see it.
+fn request_map(request: &Request) -> Map {
+    let mut headers = Map::new();
+    for (name, value) in &request.headers {
+        headers
+            .entry(name.to_ascii_lowercase().into())
+            .or_insert_with(|| value.clone().into());
+    }
+    let query = request
+        .target
+        .split_once('?')
+        .map_or("", |(_, query)| query);
+
+    let mut map = Map::new();
+    map.insert("method".into(), request.method.clone().into());
+    map.insert("path".into(), request.path.clone().into());
+    map.insert("target".into(), request.target.clone().into());
+    map.insert("query".into(), query.into());
+    map.insert("headers".into(), headers.into());
+    map
+}
+
+/// The response a handler's return value stands for.
+fn into_response(value: Dynamic) -> Result<Response, String> {
+    if value.is_unit() {
+        return Ok(Response::new(204));
+    }
+    if value.is::<Response>() {
+        return Ok(value.cast::<Response>());
+    }
+    if value.is_string() {
+        let body = value.into_immutable_string()?;
+        return Ok(Response::text(200, body.as_bytes()));
+    }
+    if value.is_map() {
+        let body = rhai::format_map_as_json(&value.cast::<Map>());
+        return Ok(Response::with_body(200, "application/json", body));
+    }
+    Err(format!("cannot send a {} as a response", value.type_name()))
+}
@@ -0,0 +1,1280 @@
+//! The built-in listener and the per-connection request cycle.
+//!
+//! [`serve`] runs the accept loop with console reporting; [`serve_connection`]
+//! is the transport-independent core it drives for every connection.
+//! [`Server::builder`] puts addresses, routes, and config together for
+//! programs embedding the server.
+
+use std::any::Any;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock, PoisonError, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub use crate::config::{Config, Runtime, Shedding, UnknownRuntime, UnknownShedding};
+
+use crate::http::{ParseError, Request, Response};
+use crate::pool::ThreadPool;
+use crate::router::{Middleware, Router};
+use crate::{access, console, http, metrics, pages, sse, stats, stream, unique_u64, ServerError};
+#[cfg(feature = "websocket")]
+use crate::websocket;
+
+/// Longest `X-Request-Id` taken from a client; longer ones are replaced.
+const MAX_REQUEST_ID: usize = 128;
+
+/// Pause after a failed `accept` before trying again.
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(50);
+
+/// How long the accept loop waits for a shed client to take its `503`
+/// before giving up on it.
+const SHED_WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Longest chunk-size line, and largest trailer section, accepted in a
+/// chunked request body.
+const MAX_CHUNK_LINE: usize = 4096;
+
+/// Set once the server is draining; see [`drain`].
+static DRAINING: AtomicBool = AtomicBool::new(false);
+
+/// The config set by [`reconfigure`], if any.
+static RECONFIGURED: RwLock<Option<Arc<Config>>> = RwLock::new(None);
+
+/// Prints the startup table for `listener` and serves connections on it with
+/// the built-in routes until the listener fails.
+pub fn serve(listener: TcpListener) -> Result<(), ServerError> {
+    serve_with(listener, Router::default(), Config::default())
+}
+
+/// [`serve`] with a custom `router` and `config`.
+pub fn serve_with(listener: TcpListener, router: Router, config: Config) -> Result<(), ServerError> {
+    serve_all(vec![listener], router, config)
+}
+
+/// [`serve_with`] on several listeners at once, such as one per address,
+/// all sharing the router, the config, and the worker pool. The startup
+/// table lists every address.
+pub fn serve_all(
+    listeners: Vec<TcpListener>,
+    router: Router,
+    config: Config,
+) -> Result<(), ServerError> {
+    let (router, config) = (Arc::new(router), Arc::new(config));
+    let addresses = listeners
+        .iter()
+        .map(TcpListener::local_addr)
+        .collect::<Result<Vec<_>, _>>()?;
+    stats::start();
+    let open = Arc::new(AtomicUsize::new(0));
+
+    #[cfg(feature = "tokio")]
+    if config.runtime == Runtime::Tokio {
+        return serve_tokio(listeners, &addresses, router, config, open);
+    }
+
+    let pool = config.workers.map(ThreadPool::new).transpose()?;
+    announce(&addresses, &config);
+
+    let (router, config, pool, open) = (&router, &config, pool.as_ref(), &open);
+    thread::scope(|scope| {
+        for listener in listeners {
+            scope.spawn(move || accept(listener, router, config, pool, open));
+        }
+    });
+    Ok(())
+}
+
+/// Where a [`ServerBuilder`] listens unless given an address.
+const DEFAULT_ADDRESS: &str = "127.0.0.1:3000";
+
+/// Collects the addresses, routes, and config of a [`Server`]; see
+/// [`Server::builder`].
+pub struct ServerBuilder {
+    addresses: Vec<String>,
+    router: Router,
+    config: Config,
+}
+
+impl ServerBuilder {
+    /// Listens on `address` (`HOST:PORT`) as well as on any given before;
+    /// `127.0.0.1:3000` if none is.
+    pub fn bind(mut self, address: impl Into<String>) -> Self {
+        self.addresses.push(address.into());
+        self
+    }
+
+    /// Registers `handler` for requests with `method` and `path`, as
+    /// [`Router::route`] does.
+    pub fn route<F>(mut self, method: &str, path: &str, handler: F) -> Self
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        self.router.route(method, path, handler);
+        self
+    }
+
+    /// Registers `handler` for `GET` requests to `path`.
+    pub fn get<F>(self, path: &str, handler: F) -> Self
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        self.route("GET", path, handler)
+    }
+
+    /// Registers `handler` for `POST` requests to `path`.
+    pub fn post<F>(self, path: &str, handler: F) -> Self
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        self.route("POST", path, handler)
+    }
+
+    /// Registers `middleware`, as [`Router::middleware`] does.
+    pub fn middleware<M>(mut self, middleware: M) -> Self
+    where
+        M: Middleware + 'static,
+    {
+        self.router.middleware(middleware);
+        self
+    }
+
+    /// Replaces the routes registered so far with `router`, such as
+    /// [`Router::default`] for the built-in endpoints; later routes are
+    /// added to it.
+    pub fn router(mut self, router: Router) -> Self {
+        self.router = router;
+        self
+    }
+
+    /// Serves with `config` instead of the default one.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Binds every address, failing with [`ServerError::Bind`] on the first
+    /// that cannot be bound.
+    pub fn build(self) -> Result<Server, ServerError> {
+        let mut addresses = self.addresses;
+        if addresses.is_empty() {
+            addresses.push(DEFAULT_ADDRESS.to_string());
+        }
+        let listeners = addresses
+            .into_iter()
+            .map(|addr| {
+                TcpListener::bind(&addr).map_err(|source| ServerError::Bind { addr, source })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Server {
+            listeners,
+            router: self.router,
+            config: self.config,
+        })
+    }
+
+    /// [`build`](Self::build)s the server and [`run`](Server::run)s it.
+    pub fn run(self) -> Result<(), ServerError> {
+        self.build()?.run()
+    }
+}
+
+/// A server for embedding in other programs: bound listeners, the router
+/// serving them, and their config.
+///
+/// ```no_run
+/// use rusty_server::{Response, Server};
+///
+/// Server::builder()
+///     .bind("127.0.0.1:8080")
+///     .get("/hello/:name", |request| {
+///         Response::text(200, format!("Hello, {}!\n", request.param("name").unwrap()))
+///     })
+///     .run()?;
+/// # Ok::<(), rusty_server::ServerError>(())
+/// ```
+pub struct Server {
+    listeners: Vec<TcpListener>,
+    router: Router,
+    config: Config,
+}
+
+impl Server {
+    /// A builder without routes, listening on `127.0.0.1:3000` unless
+    /// given addresses.
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder {
+            addresses: Vec::new(),
+            router: Router::new(),
+            config: Config::default(),
+        }
+    }
+
+    /// The addresses the server is bound to, with the ports the system
+    /// chose for port 0.
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.listeners
+            .iter()
+            .filter_map(|listener| listener.local_addr().ok())
+            .collect()
+    }
+
+    /// Serves connections on every address, as [`serve_all`] does, until a
+    /// listener fails.
+    pub fn run(self) -> Result<(), ServerError> {
+        serve_all(self.listeners, self.router, self.config)
+    }
+}
+
+/// Accepts connections on `listener` until it fails, and serves them on
+/// `pool` or on a thread each, counting them in `open`.
+fn accept(
+    listener: TcpListener,
+    router: &Arc<Router>,
+    config: &Arc<Config>,
+    pool: Option<&ThreadPool>,
+    open: &Arc<AtomicUsize>,
+) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) if DRAINING.load(Ordering::Relaxed) => drop(stream),
+            // Unless there is a pool of workers, each connection gets its own
+            // thread: an upgraded WebSocket stays open for as long as the
+            // client wants, and must not stall the accept loop meanwhile.
+            Ok(stream) => {
+                let Some(slot) = admit(open, &current(config)) else {
+                    shed(stream, current(config).shedding);
+                    continue;
+                };
+                let (router, config) = (Arc::clone(router), Arc::clone(config));
+                let serve = move || {
+                    let _slot = slot;
+                    process_connection(stream, &router, &config)
+                };
+                if let Some(pool) = pool {
+                    pool.execute(serve);
+                } else if let Err(e) = thread::Builder::new().spawn(serve) {
+                    console::print_table(&[("Error", ServerError::from(e).to_string())]);
+                }
+            }
+            Err(e) => {
+                console::print_table(&[("Error", ServerError::from(e).to_string())]);
+
+                // Accept errors such as EMFILE tend to repeat until some
+                // connection closes, so back off instead of spinning.
+                thread::sleep(ACCEPT_ERROR_BACKOFF);
+            }
+        }
+    }
+}
+
+/// [`serve_all`] on a tokio runtime, with an accept task per listener.
+/// Connections are handed to [`process_connection`] on the runtime's
+/// blocking threads, so handlers run exactly as they do on the sync runtime.
+#[cfg(feature = "tokio")]
+fn serve_tokio(
+    listeners: Vec<TcpListener>,
+    addresses: &[SocketAddr],
+    router: Arc<Router>,
+    config: Arc<Config>,
+    open: Arc<AtomicUsize>,
+) -> Result<(), ServerError> {
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    runtime.enable_all();
+    if let Some(workers) = config.workers {
+        runtime.max_blocking_threads(workers.get());
+    }
+    let runtime = runtime.build()?;
+    for listener in &listeners {
+        listener.set_nonblocking(true)?;
+    }
+    announce(addresses, &config);
+
+    runtime.block_on(async move {
+        let mut accepting = tokio::task::JoinSet::new();
+        for listener in listeners {
+            let listener = tokio::net::TcpListener::from_std(listener)?;
+            let (router, config, open) = (Arc::clone(&router), Arc::clone(&config), Arc::clone(&open));
+            accepting.spawn(accept_tokio(listener, router, config, open));
+        }
+        while accepting.join_next().await.is_some() {}
+        Ok(())
+    })
+}
+
+/// The accept task of [`serve_tokio`] for one listener.
+#[cfg(feature = "tokio")]
+async fn accept_tokio(
+    listener: tokio::net::TcpListener,
+    router: Arc<Router>,
+    config: Arc<Config>,
+    open: Arc<AtomicUsize>,
+) {
+    loop {
+        let accepted = match listener.accept().await {
+            Ok(_) if draining() => continue,
+            Ok((stream, _)) => {
+                let Some(slot) = admit(&open, &current(&config)) else {
+                    tokio::spawn(shed_tokio(stream, current(&config).shedding));
+                    continue;
+                };
+                stream
+                    .into_std()
+                    .and_then(|stream| stream.set_nonblocking(false).map(|()| (stream, slot)))
+            }
+            Err(e) => Err(e),
+        };
+        match accepted {
+            Ok((stream, slot)) => {
+                let (router, config) = (Arc::clone(&router), Arc::clone(&config));
+                tokio::task::spawn_blocking(move || {
+                    let _slot = slot;
+                    process_connection(stream, &router, &config)
+                });
+            }
+            Err(e) => {
+                console::print_table(&[("Error", ServerError::from(e).to_string())]);
+                tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+            }
+        }
+    }
+}
+
+/// One of the connections counted against [`Config::max_connections`],
+/// given back when dropped.
+struct Slot(Arc<AtomicUsize>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Takes a slot in `open` for a connection just accepted, or `None` if
+/// `config` allows no more.
+fn admit(open: &Arc<AtomicUsize>, config: &Config) -> Option<Slot> {
+    let before = open.fetch_add(1, Ordering::AcqRel);
+    let slot = Slot(Arc::clone(open));
+    match config.max_connections {
+        Some(max) if before >= max.get() => None,
+        _ => Some(slot),
+    }
+}
+
+/// The answer to connections shed with [`Shedding::Unavailable`].
+fn shed_response() -> Response {
+    pages::error(503, "Service Unavailable", "The server is at its connection limit.")
+        .header("Retry-After", "1")
+        .header("Connection", "close")
+}
+
+/// Turns away `stream`, accepted beyond [`Config::max_connections`], as
+/// `shedding` says, and counts it.
+fn shed(mut stream: TcpStream, shedding: Shedding) {
+    metrics::rejected();
+    if shedding == Shedding::Close {
+        return;
+    }
+    // The accept loop must not wait on a client that does not read.
+    let _ = stream.set_write_timeout(Some(SHED_WRITE_TIMEOUT));
+    if shed_response().write_to(&mut stream).is_ok() {
+        // Closing with the request unread would reset the connection, and
+        // could lose the response; read what has arrived of it first.
+        let _ = stream.shutdown(std::net::Shutdown::Write);
+        if stream.set_nonblocking(true).is_ok() {
+            let _ = stream.read(&mut [0; 4096]);
+        }
+    }
+}
+
+/// [`shed`] for the tokio accept loop, as a task of its own, so that a
+/// client that does not read holds neither the loop nor a runtime worker.
+#[cfg(feature = "tokio")]
+async fn shed_tokio(mut stream: tokio::net::TcpStream, shedding: Shedding) {
+    use tokio::io::AsyncWriteExt;
+
+    metrics::rejected();
+    if shedding == Shedding::Close {
+        return;
+    }
+    let mut response = Vec::new();
+    let _ = shed_response().write_to(&mut response);
+    let written = tokio::time::timeout(SHED_WRITE_TIMEOUT, stream.write_all(&response)).await;
+    if matches!(written, Ok(Ok(()))) {
+        // As in `shed`: read what has arrived of the request before closing.
+        let _ = stream.shutdown().await;
+        let _ = stream.try_read(&mut [0; 4096]);
+    }
+}
+
+/// Prints the startup table for listeners on `addresses`, and sends the
+/// startup webhook.
+fn announce(addresses: &[SocketAddr], config: &Config) {
+    #[cfg(feature = "tls")]
+    let scheme = if config.tls.is_some() { "https" } else { "http" };
+    #[cfg(not(feature = "tls"))]
+    let scheme = "http";
+    let urls = addresses
+        .iter()
+        .map(|address| format!("{}://{}", scheme, address))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut status = vec![
+        ("Status", "Server Started".to_string()),
+        ("Address", urls.clone()),
+    ];
+    #[cfg(feature = "tokio")]
+    if config.runtime == Runtime::Tokio {
+        status.push(("Runtime", "tokio".to_string()));
+    }
+    if let Some(workers) = config.workers {
+        status.push(("Workers", workers.to_string()));
+    }
+    console::print_table(&status);
+    #[cfg(feature = "webhooks")]
+    crate::webhook::notify(crate::webhook::Event::Startup { address: urls });
+}
+
+/// Stops serving new connections: from now on, every listener closes the
+/// connections it accepts without reading from them. Those already open are
+/// served to the end.
+pub(crate) fn drain() {
+    DRAINING.store(true, Ordering::Relaxed);
+}
+
+/// Replaces the config of every listener in the process, such as after a
+/// config file was reloaded: connections accepted from now on, and the next
+/// request on those already open, use `config`. The worker count and the
+/// runtime only take effect when a listener starts, so running listeners
+/// keep theirs.
+pub fn reconfigure(config: Config) {
+    *RECONFIGURED.write().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(config));
+}
+
+/// `config`, unless [`reconfigure`] has replaced it.
+fn current(config: &Arc<Config>) -> Arc<Config> {
+    match &*RECONFIGURED.read().unwrap_or_else(PoisonError::into_inner) {
+        Some(reconfigured) => Arc::clone(reconfigured),
+        None => Arc::clone(config),
+    }
+}
+
+/// Whether [`drain`] has been called.
+pub(crate) fn draining() -> bool {
+    DRAINING.load(Ordering::Relaxed)
+}
+
+/// Handles one accepted connection, request after request while the client
+/// keeps it alive, and prints a status table for each.
+///
+/// Nothing that goes wrong here, including a panic while building the
+/// response, escapes to the accept loop: the failure is reported in the
+/// table and the next connection is served as usual.
+fn process_connection(stream: TcpStream, router: &Router, config: &Arc<Config>) {
+    let peer_addr = stream.peer_addr();
+    let peer = match &peer_addr {
+        Ok(peer_addr) => format!("{}:{}", peer_addr.ip(), peer_addr.port()),
+        Err(_) => "unknown".to_string(),
+    };
+    let peer_ip = peer_addr.as_ref().ok().map(SocketAddr::ip);
+    let mut peer_error = peer_addr.err();
+    let open = stats::connection_opened(&peer);
+    #[cfg(feature = "tracing")]
+    let _connection = tracing::info_span!("connection", peer = peer.as_str()).entered();
+
+    // Clients that stop reading must not hold the thread, TLS handshake
+    // included; nor may ones that do not send, which the handshake gets the
+    // request timeout for.
+    let _ = stream.set_write_timeout(current(config).write_timeout);
+    #[cfg(feature = "tls")]
+    let socket = match &current(config).tls {
+        Some(acceptor) => match acceptor.accept(stream, current(config).request_timeout) {
+            // Certificate authorities validating a domain only need the
+            // handshake.
+            Ok(stream) if crate::tls::is_acme_challenge(&stream.conn) => return,
+            Ok(stream) => Socket::Tls(Box::new(stream)),
+            Err(e) => return console::report(&peer, &Err(e), 0, std::time::Duration::ZERO),
+        },
+        None => Socket::Plain(stream),
+    };
+    #[cfg(not(feature = "tls"))]
+    let socket = Socket::Plain(stream);
+    let secure = socket.is_secure();
+    let client_cn = socket.client_cn();
+
+    let mut stream = Pipelined::new(Counted::new(socket));
+    stream.secure = secure;
+    stream.client_cn = client_cn;
+    stream.peer = peer_ip;
+    stream.set_timeout = Some(|io, timeout| io.inner.tcp().set_read_timeout(timeout));
+    loop {
+        let config = &*current(config);
+        let started = std::time::Instant::now();
+        let written = stream.io.written;
+        let reusable = config.keep_alive_timeout.is_some() && !draining();
+        let result = match peer_error.take() {
+            None => panic::catch_unwind(AssertUnwindSafe(|| {
+                let writer = stream.io.inner.writer();
+                exchange(&mut stream, router, config, writer, reusable)
+            }))
+            .unwrap_or_else(|payload| Err(ServerError::Handler(panic_message(&*payload)))),
+            // The peer is usually already gone; there is nobody to answer.
+            Some(e) => Err(ServerError::from(e)),
+        };
+        stream.served += 1;
+        let keep_alive = matches!(result, Ok((_, true)));
+        let mut result = result.map(|(exchange, _)| exchange);
+        if stream.io.timed_out {
+            metrics::write_timeout();
+            if let Err(e @ ServerError::Timeout(_)) = &mut result {
+                *e = ServerError::Timeout("sending the response");
+            }
+        }
+
+        #[cfg(feature = "webhooks")]
+        crate::webhook::record_status(match &result {
+            Ok(exchange) => exchange.status,
+            Err(e) => e.status().0,
+        });
+
+        metrics::record(&result, router, stream.io.written - written, started.elapsed());
+        access::record(&peer, &result, stream.io.written - written);
+
+        #[cfg(feature = "otel")]
+        crate::otel::record(&peer, &result, router, started.elapsed());
+
+        #[cfg(feature = "tui")]
+        crate::tui::record(&peer, &result, started.elapsed());
+
+        #[cfg(feature = "history")]
+        crate::history::record(&peer, &result, started.elapsed());
+
+        console::report(&peer, &result, stream.io.written - written, started.elapsed());
+
+        open.served();
+        if !keep_alive || !next_request_arrives(&mut stream, config) {
+            break;
+        }
+        open.busy();
+    }
+    // A client that stopped reading would not take the TLS close either.
+    if !stream.io.timed_out {
+        stream.io.inner.close();
+    }
+}
+
+/// Waits up to the keep-alive timeout for the client to start another
+/// request; false if it closes the connection or stays idle.
+fn next_request_arrives(stream: &mut Pipelined<Counted<Socket>>, config: &Config) -> bool {
+    if !stream.pending.is_empty() {
+        return true;
+    }
+    if stream.io.inner.tcp().set_read_timeout(config.keep_alive_timeout).is_err() {
+        return false;
+    }
+    // Read rather than peek, so that TLS records are decrypted: one could
+    // be the client closing the session.
+    let mut chunk = [0; 1024];
+    let arrived = match stream.read(&mut chunk) {
+        Ok(n) if n > 0 => {
+            stream.unread(&chunk[..n]);
+            true
+        }
+        _ => false,
+    };
+    // The request head gets its own deadline as it is read.
+    arrived && stream.io.inner.tcp().set_read_timeout(None).is_ok()
+}
+
+/// An accepted connection, encrypted or not.
+enum Socket {
+    Plain(TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(Box<rustls::StreamOwned<rustls::ServerConnection, TcpStream>>),
+}
+
+impl Socket {
+    /// The TCP stream underneath.
+    fn tcp(&self) -> &TcpStream {
+        match self {
+            Socket::Plain(stream) => stream,
+            #[cfg(feature = "tls")]
+            Socket::Tls(stream) => &stream.sock,
+        }
+    }
+
+    fn is_secure(&self) -> bool {
+        !matches!(self, Socket::Plain(_))
+    }
+
+    /// The common name in the client's certificate, under mutual TLS.
+    fn client_cn(&self) -> Option<String> {
+        match self {
+            Socket::Plain(_) => None,
+            #[cfg(feature = "tls")]
+            Socket::Tls(stream) => crate::tls::client_cn(&stream.conn),
+        }
+    }
+
+    /// A second handle for writing, for WebSocket senders. TLS sessions
+    /// cannot be shared between threads, so they have none.
+    fn writer(&self) -> Option<Box<dyn Write + Send>> {
+        match self {
+            Socket::Plain(stream) => shared_writer(stream),
+            #[cfg(feature = "tls")]
+            Socket::Tls(_) => None,
+        }
+    }
+
+    /// Ends a TLS session by telling the client that nothing more follows,
+    /// so that it can tell the end of the session from a truncation.
+    fn close(&mut self) {
+        #[cfg(feature = "tls")]
+        if let Socket::Tls(stream) = self {
+            stream.conn.send_close_notify();
+            let _ = stream.conn.complete_io(&mut stream.sock);
+        }
+    }
+}
+
+impl Read for Socket {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Socket::Plain(stream) => stream.read(buf),
+            #[cfg(feature = "tls")]
+            Socket::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Socket {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Socket::Plain(stream) => stream.write(buf),
+            #[cfg(feature = "tls")]
+            Socket::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Socket::Plain(stream) => stream.flush(),
+            #[cfg(feature = "tls")]
+            Socket::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+/// A stream that counts the bytes written to it, and notes whether a write
+/// ran past the write timeout.
+struct Counted<S> {
+    inner: S,
+    written: u64,
+    timed_out: bool,
+}
+
+impl<S> Counted<S> {
+    fn new(inner: S) -> Self {
+        Counted {
+            inner,
+            written: 0,
+            timed_out: false,
+        }
+    }
+}
+
+impl<S: Read> Read for Counted<S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<S: Write> Write for Counted<S> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf).inspect_err(|e| self.note(e))?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush().inspect_err(|e| self.note(e))
+    }
+}
+
+impl<S> Counted<S> {
+    /// Notes `e` if it is the write timeout running out.
+    fn note(&mut self, e: &std::io::Error) {
+        self.timed_out |= matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock);
+    }
+}
+
+/// A connection's byte stream, which keeps the bytes read past the end of one
+/// request for the next: clients may send requests back to back without
+/// waiting for the responses.
+struct Pipelined<S> {
+    io: S,
+    /// Bytes put back with [`Pipelined::unread`], returned by the next reads.
+    pending: Vec<u8>,
+    /// Bytes read and not put back, over the connection's life.
+    consumed: u64,
+    /// Whether the connection is encrypted, for [`http::Request::secure`].
+    secure: bool,
+    /// The client's certificate name, for [`http::Request::client_cn`].
+    client_cn: Option<String>,
+    /// The client's address, for [`http::Request::peer`].
+    peer: Option<IpAddr>,
+    /// Requests answered on the connection, for [`Config::max_requests`].
+    served: usize,
+    /// Sets the read timeout of the socket underneath, for
+    /// [`Config::request_timeout`]; `None` for streams without one.
+    set_timeout: Option<SetTimeout<S>>,
+}
+
+/// Sets the read timeout of the socket underneath a stream.
+type SetTimeout<S> = fn(&S, Option<Duration>) -> std::io::Result<()>;
+
+impl<S> Pipelined<S> {
+    fn new(io: S) -> Self {
+        Pipelined {
+            io,
+            pending: Vec::new(),
+            consumed: 0,
+            secure: false,
+            client_cn: None,
+            peer: None,
+            served: 0,
+            set_timeout: None,
+        }
+    }
+
+    /// Puts `bytes`, the last ones read, back in front of the stream.
+    fn unread(&mut self, bytes: &[u8]) {
+        self.pending.splice(0..0, bytes.iter().copied());
+        self.consumed -= bytes.len() as u64;
+    }
+}
+
+impl<S: Read> Read for Pipelined<S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = if self.pending.is_empty() {
+            self.io.read(buf)?
+        } else {
+            let n = buf.len().min(self.pending.len());
+            buf[..n].copy_from_slice(&self.pending[..n]);
+            self.pending.drain(..n);
+            n
+        };
+        self.consumed += n as u64;
+        Ok(n)
+    }
+}
+
+impl<S: Write> Write for Pipelined<S> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.io.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.io.flush()
+    }
+}
+
+/// A second handle for writing to `stream`, so WebSocket sessions can be
+/// written to from other threads.
+#[cfg(feature = "websocket")]
+fn shared_writer(stream: &TcpStream) -> Option<Box<dyn Write + Send>> {
+    stream
+        .try_clone()
+        .ok()
+        .map(|clone| Box::new(clone) as Box<dyn Write + Send>)
+}
+
+#[cfg(not(feature = "websocket"))]
+fn shared_writer(_stream: &TcpStream) -> Option<Box<dyn Write + Send>> {
+    None
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        format!("panicked: {}", message)
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        format!("panicked: {}", message)
+    } else {
+        "panicked".to_string()
+    }
+}
+
+fn default_router() -> &'static Router {
+    static ROUTER: OnceLock<Router> = OnceLock::new();
+    ROUTER.get_or_init(Router::default)
+}
+
+/// What happened on a connection, as reported by [`serve_connection`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exchange {
+    /// Bytes read from the client.
+    pub bytes_read: usize,
+    /// Status code of the response that was sent.
+    pub status: u16,
+    /// Decoded request path, or `/unknown` if the request could not be parsed.
+    pub path: String,
+    /// The request head, if it could be parsed.
+    pub request: Option<http::Request>,
+    /// Header fields of the response, as the router built it; empty for
+    /// streamed, event-stream, and WebSocket responses, which write their own.
+    pub response_headers: Vec<(String, String)>,
+    /// The request's ID, as in [`Request::id`]; generated for requests that
+    /// could not be parsed.
+    pub request_id: String,
+}
+
+impl Exchange {
+    /// Status code and reason phrase, e.g. `404 Not Found`.
+    pub fn status_text(&self) -> String {
+        format!("{} {}", self.status, http::reason_phrase(self.status))
+    }
+}
+
+/// Reads one request from `io`, answers it using `router`, and reports what
+/// was exchanged.
+///
+/// `io` can be any bidirectional byte stream: a `TcpStream`, a TLS stream, an
+/// in-memory pipe, or a tunnel. Requests that cannot be parsed are answered
+/// with the matching 4xx/5xx page; only I/O failures are returned as errors.
+pub fn serve_connection<S: Read + Write>(
+    io: S,
+    router: &Router,
+    config: &Config,
+) -> Result<Exchange, ServerError> {
+    exchange(&mut Pipelined::new(io), router, config, None, false).map(|(exchange, _)| exchange)
+}
+
+/// [`serve_connection`], with `writer` as an optional second handle to `io`
+/// for WebSocket sessions to share. If `reusable`, the connection may be kept
+/// open for another request, and the response says whether it will be; the
+/// returned flag is then true if it should.
+#[cfg_attr(not(feature = "websocket"), allow(unused_variables))]
+fn exchange<S: Read + Write>(
+    io: &mut Pipelined<S>,
+    router: &Router,
+    config: &Config,
+    writer: Option<Box<dyn Write + Send>>,
+    reusable: bool,
+) -> Result<(Exchange, bool), ServerError> {
+    #[cfg(feature = "tracing")]
+    let _request = tracing::info_span!(
+        "request",
+        method = tracing::field::Empty,
+        path = tracing::field::Empty,
+        request_id = tracing::field::Empty
+    )
+    .entered();
+    let consumed = io.consumed;
+    let buffer = match read_head(io, config.max_head_size, config.request_timeout) {
+        Ok(buffer) => buffer,
+        Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => {
+            return timed_out(io, consumed);
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    // Whether the whole request, body included, was read, so that the next
+    // one can follow it on the connection.
+    let mut complete = false;
+    let (mut response, path, request) = match http::parse_request_with_limit(&buffer, config.max_head_size) {
+        Ok(mut request) => {
+            request.secure = io.secure;
+            request.client_cn = io.client_cn.clone();
+            request.peer = io.peer;
+            request.id = request_id(&request);
+            #[cfg(feature = "tracing")]
+            _request
+                .record("method", request.method.as_str())
+                .record("path", request.path.as_str())
+                .record("request_id", request.id.as_str());
+            let head_len = http::head_len(&buffer).unwrap_or(buffer.len());
+            #[cfg(feature = "websocket")]
+            if let Some(handler) = router.websocket_handler(&request.path) {
+                // Frames the client sent right behind its handshake.
+                let status = websocket::upgrade(io, &request, handler, &buffer[head_len..], writer)?;
+                return Ok((streamed(buffer.len(), status, request), false));
+            }
+            if request.method == "GET" {
+                if let Some(handler) = router.events_handler(&request.path) {
+                    let status = sse::stream(io, &request, handler)?;
+                    return Ok((streamed(buffer.len(), status, request), false));
+                }
+            }
+            // Whatever follows the body bytes read along with the head.
+            io.unread(&buffer[head_len + request.body.len()..]);
+            let response = 'respond: {
+                if request.is_chunked() {
+                    match read_chunked(io, &mut request, config.max_body_size) {
+                        Ok(()) => {}
+                        Err(e @ ServerError::Parse(_)) => break 'respond error_page(&e),
+                        Err(e) => return Err(e),
+                    }
+                }
+                if let Some(handler) = router.stream_handler(&request.path) {
+                    // The body bytes read so far: those that came along with
+                    // the head, or the whole of a chunked body.
+                    let status = stream::serve(io, &request, handler, &request.body)?;
+                    let bytes_read = (io.consumed - consumed) as usize;
+                    return Ok((streamed(bytes_read, status, request), false));
+                }
+                // A route taking a body must be told how long it is.
+                let routed = router.handler(&request.method, &request.path).is_some();
+                let length_required = routed && matches!(request.method.as_str(), "POST" | "PUT" | "PATCH");
+                if length_required && request.header("Content-Length").is_none() {
+                    break 'respond error_page(&ParseError::LengthRequired.into());
+                }
+                match read_body(io, &mut request, config.max_body_size) {
+                    Ok(()) => {
+                        complete = true;
+                        router.handle(&request)
+                    }
+                    Err(e @ ServerError::Parse(_)) => error_page(&e),
+                    Err(e) => return Err(e),
+                }
+            };
+            (response, request.path.clone(), Some(request))
+        }
+        Err(e) => (error_page(&e.into()), "/unknown".to_string(), None),
+    };
+    let request_id = request.as_ref().map_or_else(new_request_id, |request| request.id.clone());
+    if response.header_value("X-Request-Id").is_none() {
+        response = response.header("X-Request-Id", request_id.as_str());
+    }
+
+    // Requests the connection may still carry after this one.
+    let left = config.max_requests.map(|max| max.get().saturating_sub(io.served + 1));
+    let keep_alive = reusable
+        && complete
+        && left != Some(0)
+        && request.as_ref().is_some_and(http::Request::keeps_alive)
+        && !response
+            .header_value("Connection")
+            .is_some_and(|value| value.eq_ignore_ascii_case("close"));
+    if reusable && response.header_value("Connection").is_none() {
+        response = response.header("Connection", if keep_alive { "keep-alive" } else { "close" });
+    }
+    if let (true, Some(timeout)) = (keep_alive, config.keep_alive_timeout) {
+        if response.header_value("Keep-Alive").is_none() {
+            // Rounded up: `timeout=0` would tell clients not to reuse it.
+            let mut value = format!("timeout={}", timeout.as_secs_f64().ceil());
+            if let Some(left) = left {
+                value.push_str(&format!(", max={}", left));
+            }
+            response = response.header("Keep-Alive", value);
+        }
+    }
+    response.write_to(io)?;
+    io.flush()?;
+    let exchange = Exchange {
+        bytes_read: (io.consumed - consumed) as usize,
+        status: response.status,
+        path,
+        request,
+        response_headers: response.headers,
+        request_id,
+    };
+    Ok((exchange, keep_alive))
+}
+
+/// The ID for `request`: its `X-Request-Id`, if that is 1 to
+/// [`MAX_REQUEST_ID`] visible ASCII characters, and a new one otherwise.
+fn request_id(request: &Request) -> String {
+    match request.header("X-Request-Id") {
+        Some(id) if (1..=MAX_REQUEST_ID).contains(&id.len()) && id.bytes().all(|b| b.is_ascii_graphic()) => {
+            id.to_string()
+        }
+        _ => new_request_id(),
+    }
+}
+
+/// A fresh request ID: 32 hex digits.
+fn new_request_id() -> String {
+    format!("{:016x}{:016x}", unique_u64(), unique_u64())
+}
+
+/// The page answering a request that failed with `e`.
+fn error_page(e: &ServerError) -> Response {
+    let (code, reason) = e.status();
+    pages::error(code, reason, &e.to_string())
+}
+
+/// Reads from `io` until it has a complete request head, the client stops
+/// sending, or `max_head_size` bytes have arrived, whichever comes first.
+/// Body bytes sent along with the head are kept after it.
+///
+/// If `io` has a socket underneath, the whole head must arrive within
+/// `timeout`; otherwise this fails with [`ErrorKind::TimedOut`].
+fn read_head<S: Read>(
+    io: &mut Pipelined<S>,
+    max_head_size: usize,
+    timeout: Option<Duration>,
+) -> std::io::Result<Vec<u8>> {
+    let deadline = timeout.zip(io.set_timeout).map(|(timeout, set)| (Instant::now() + timeout, set));
+    let mut buffer = vec![0; max_head_size];
+    let mut filled = 0;
+    while filled < buffer.len() {
+        if let Some((deadline, set_timeout)) = deadline {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(ErrorKind::TimedOut.into());
+            }
+            set_timeout(&io.io, Some(left))?;
+        }
+        let n = io.read(&mut buffer[filled..])?;
+        if n == 0 {
+            break;
+        }
+        // The blank line ending the head may have started in the last read.
+        let from = filled.saturating_sub(3);
+        filled += n;
+        if http::head_len(&buffer[from..filled]).is_some() {
+            break;
+        }
+    }
+    if let Some((_, set_timeout)) = deadline {
+        set_timeout(&io.io, None)?;
+    }
+    buffer.truncate(filled);
+    Ok(buffer)
+}
+
+/// Answers a client that did not send its request head within
+/// [`Config::request_timeout`] with 408, and has the connection closed.
+fn timed_out<S: Write>(io: &mut Pipelined<S>, consumed: u64) -> Result<(Exchange, bool), ServerError> {
+    let request_id = new_request_id();
+    let response = error_page(&ServerError::Timeout("receiving the request head"))
+        .header("X-Request-Id", request_id.as_str())
+        .header("Connection", "close");
+    response.write_to(io)?;
+    io.flush()?;
+    let exchange = Exchange {
+        bytes_read: (io.consumed - consumed) as usize,
+        status: response.status,
+        path: "/unknown".to_string(),
+        request: None,
+        response_headers: response.headers,
+        request_id,
+    };
+    Ok((exchange, false))
+}
+
+/// Reads the rest of `request`'s body from `io`, after the part that arrived
+/// with the head.
+fn read_body<S: Read + Write>(
+    io: &mut S,
+    request: &mut http::Request,
+    max_body_size: usize,
+) -> Result<(), ServerError> {
+    let length = request.content_length();
+    if length > max_body_size as u64 {
+        return Err(ParseError::ContentTooLarge.into());
+    }
+    let received = request.body.len();
+    if received == 0 && length > 0 {
+        send_continue(io, request)?;
+    }
+    request.body.resize(length as usize, 0);
+    io.read_exact(&mut request.body[received..]).map_err(|e| match e.kind() {
+        ErrorKind::UnexpectedEof => ParseError::BadRequest("body shorter than Content-Length").into(),
+        _ => ServerError::from(e),
+    })
+}
+
+/// Decodes the chunked body of `request` (RFC 9112 section 7.1) from `io`,
+/// skipping any trailer fields. The request is then rewritten as if it had
+/// been sent with a `Content-Length`.
+fn read_chunked<S: Read + Write>(
+    io: &mut Pipelined<S>,
+    request: &mut http::Request,
+    max_body_size: usize,
+) -> Result<(), ServerError> {
+    if io.pending.is_empty() {
+        send_continue(io, request)?;
+    }
+    let mut reader = BufReader::new(&mut *io);
+    let mut body = Vec::new();
+    loop {
+        let line = read_chunk_line(&mut reader)?;
+        // Chunk extensions, after a `;`, are ignored.
+        let size = line.split(|&b| b == b';').next().unwrap_or_default();
+        let size = std::str::from_utf8(size)
+            .ok()
+            .map(|size| size.trim_matches([' ', '\t']))
+            .filter(|size| !size.is_empty() && size.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|size| u64::from_str_radix(size, 16).ok())
+            .ok_or(ParseError::BadRequest("malformed chunk size"))?;
+        if size == 0 {
+            break;
+        }
+        if (body.len() as u64).checked_add(size).is_none_or(|total| total > max_body_size as u64) {
+            return Err(ParseError::ContentTooLarge.into());
+        }
+        let start = body.len();
+        body.resize(start + size as usize, 0);
+        reader.read_exact(&mut body[start..]).map_err(|e| match e.kind() {
+            ErrorKind::UnexpectedEof => ParseError::BadRequest("incomplete chunked body").into(),
+            _ => ServerError::from(e),
+        })?;
+        if !read_chunk_line(&mut reader)?.is_empty() {
+            return Err(ParseError::BadRequest("chunk longer than its size").into());
+        }
+    }
+    // The trailer section, which is not passed on.
+    let mut trailer_size = 0;
+    loop {
+        let line = read_chunk_line(&mut reader)?;
+        if line.is_empty() {
+            break;
+        }
+        trailer_size += line.len();
+        if trailer_size > MAX_CHUNK_LINE {
+            return Err(ParseError::HeaderFieldsTooLarge.into());
+        }
+    }
+    // The start of the next request, if the reader took some of it.
+    let next = reader.buffer().to_vec();
+    drop(reader);
+    io.unread(&next);
+
+    request
+        .headers
+        .retain(|(name, _)| !name.eq_ignore_ascii_case("Transfer-Encoding"));
+    request
+        .headers
+        .push(("Content-Length".to_string(), body.len().to_string()));
+    request.body = body;
+    Ok(())
+}
+
+/// Tells a client waiting with `Expect: 100-continue` to send its body.
+fn send_continue<S: Write>(io: &mut S, request: &http::Request) -> std::io::Result<()> {
+    if request
+        .header("Expect")
+        .is_some_and(|value| value.eq_ignore_ascii_case("100-continue"))
+    {
+        io.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+        io.flush()?;
+    }
+    Ok(())
+}
+
+/// Reads one CRLF-terminated line of a chunked body, without the CRLF.
+fn read_chunk_line<R: BufRead>(reader: &mut R) -> Result<Vec<u8>, ServerError> {
+    let mut line = Vec::new();
+    reader
+        .take(MAX_CHUNK_LINE as u64 + 2)
+        .read_until(b'\n', &mut line)?;
+    match line.strip_suffix(b"\r\n") {
+        Some(content) if !content.contains(&b'\r') => Ok(content.to_vec()),
+        _ if !line.ends_with(b"\n") && line.len() > MAX_CHUNK_LINE => {
+            Err(ParseError::BadRequest("chunk line too long").into())
+        }
+        _ if !line.ends_with(b"\n") => Err(ParseError::BadRequest("incomplete chunked body").into()),
+        _ => Err(ParseError::BadRequest("chunk line not terminated by CRLF").into()),
+    }
+}
+
+/// The exchange for a response that a handler wrote itself.
+fn streamed(bytes_read: usize, status: u16, request: http::Request) -> Exchange {
+    Exchange {
+        bytes_read,
+        status,
+        path: request.path.clone(),
+        request_id: request.id.clone(),
+        request: Some(request),
+        response_headers: Vec::new(),
+    }
+}
+
+/// Reads a single request from `stream`, writes the response back, and returns
+/// the number of request bytes read together with the status text and path
+/// that were served.
+///
+/// This is [`serve_connection`] with the built-in routes and the default
+/// [`Config`].
+pub fn handle_connection<S: Read + Write>(stream: S) -> Result<(usize, String, String), ServerError> {
+    let exchange = serve_connection(stream, default_router(), &Config::default())?;
+    Ok((exchange.bytes_read, exchange.status_text(), exchange.path))
+}
+
+/// [`serve_connection`] over an async stream, such as a tokio `TcpStream` or
+/// an in-memory `DuplexStream` (with the `tokio` feature).
+///
+/// The request is served on one of the runtime's blocking threads, as on the
+/// tokio listener, with `io` driven by the runtime from there.
+#[cfg(feature = "tokio")]
+pub async fn serve_connection_async<S>(
+    io: S,
+    router: Arc<Router>,
+    config: Arc<Config>,
+) -> Result<Exchange, ServerError>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
+    on_blocking_thread(io, move |io| serve_connection(io, &router, &config)).await
+}
+
+/// [`handle_connection`] over an async stream (with the `tokio` feature).
+#[cfg(feature = "tokio")]
+pub async fn handle_connection_async<S>(stream: S) -> Result<(usize, String, String), ServerError>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
+    let exchange = on_blocking_thread(stream, |io| {
+        serve_connection(io, default_router(), &Config::default())
+    })
+    .await?;
+    Ok((exchange.bytes_read, exchange.status_text(), exchange.path))
+}
+
+/// Runs `serve` on `io` on one of the runtime's blocking threads.
+#[cfg(feature = "tokio")]
+async fn on_blocking_thread<S, F>(io: S, serve: F) -> Result<Exchange, ServerError>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+    F: FnOnce(Blocking<S>) -> Result<Exchange, ServerError> + Send + 'static,
+{
+    let io = Blocking {
+        io,
+        runtime: tokio::runtime::Handle::current(),
+    };
+    tokio::task::spawn_blocking(move || serve(io))
+        .await
+        .map_err(|e| ServerError::Handler(e.to_string()))?
+}
+
+/// An async stream read and written by blocking on `runtime`, which only
+/// works off the runtime's own worker threads.
+#[cfg(feature = "tokio")]
+struct Blocking<S> {
+    io: S,
+    runtime: tokio::runtime::Handle,
+}
+
+#[cfg(feature = "tokio")]
+impl<S: tokio::io::AsyncRead + Unpin> Read for Blocking<S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        use tokio::io::AsyncReadExt;
+        self.runtime.block_on(self.io.read(buf))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<S: tokio::io::AsyncWrite + Unpin> Write for Blocking<S> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        use tokio::io::AsyncWriteExt;
+        self.runtime.block_on(self.io.write(buf))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        use tokio::io::AsyncWriteExt;
+        self.runtime.block_on(self.io.flush())
+    }
+}
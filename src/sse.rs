@@ -0,0 +1,132 @@
+//! Server-Sent Events: the `text/event-stream` framing and the stream handed
+//! to event handlers.
+//!
+//! Handlers are registered with [`Router::events`](crate::Router::events).
+//! The server sends the response head, then the handler writes events for as
+//! long as it likes; the stream ends when the handler returns or the client
+//! goes away.
+
+use std::io::{self, Write};
+
+use crate::http::{Request, Response};
+
+/// An event handler: writes events to one client until it returns.
+pub type EventHandler = Box<dyn Fn(&Request, &mut EventStream<'_>) + Send + Sync>;
+
+/// One event, built up with the setters and written by [`EventStream::send`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Event {
+    id: Option<String>,
+    event: Option<String>,
+    data: String,
+    retry: Option<u64>,
+}
+
+impl Event {
+    /// An unnamed event (a `message` event for `EventSource`) carrying `data`.
+    pub fn new(data: impl Into<String>) -> Self {
+        Event {
+            data: data.into(),
+            ..Event::default()
+        }
+    }
+
+    /// Sets the event ID, which the client sends back as `Last-Event-ID` when
+    /// it reconnects.
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Sets the event type `EventSource` listeners subscribe to.
+    pub fn event(mut self, name: impl Into<String>) -> Self {
+        self.event = Some(name.into());
+        self
+    }
+
+    /// Sets how long, in milliseconds, the client waits before reconnecting.
+    pub fn retry(mut self, millis: u64) -> Self {
+        self.retry = Some(millis);
+        self
+    }
+
+    /// The event in wire format, terminated by the blank line that
+    /// dispatches it.
+    ///
+    /// Multi-line data is split into one `data:` field per line. Line breaks
+    /// in the ID and event type, which would end the field early, are
+    /// dropped.
+    pub fn to_wire(&self) -> String {
+        let mut wire = String::new();
+        if let Some(id) = &self.id {
+            wire.push_str(&format!("id: {}\n", single_line(id)));
+        }
+        if let Some(event) = &self.event {
+            wire.push_str(&format!("event: {}\n", single_line(event)));
+        }
+        if let Some(retry) = self.retry {
+            wire.push_str(&format!("retry: {}\n", retry));
+        }
+        for line in self.data.split('\n') {
+            wire.push_str(&format!("data: {}\n", line.trim_end_matches('\r')));
+        }
+        wire.push('\n');
+        wire
+    }
+}
+
+fn single_line(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| !matches!(c, '\r' | '\n' | '\0'))
+        .collect()
+}
+
+/// The response head sent before the first event.
+pub fn response() -> Response {
+    Response::new(200)
+        .header("Content-Type", "text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .header("Connection", "close")
+}
+
+/// The body of an event-stream response.
+pub struct EventStream<'a> {
+    writer: &'a mut dyn Write,
+}
+
+impl<'a> EventStream<'a> {
+    /// A stream writing to `writer`, after the response head was sent.
+    pub fn new<W: Write>(writer: &'a mut W) -> Self {
+        EventStream { writer }
+    }
+
+    /// Sends `event`. Fails once the client has gone away.
+    pub fn send(&mut self, event: &Event) -> io::Result<()> {
+        self.write(event.to_wire().as_bytes())
+    }
+
+    /// Sends a comment line, which clients ignore. Proxies that close idle
+    /// connections see traffic, so quiet streams use this as a keep-alive.
+    pub fn comment(&mut self, text: &str) -> io::Result<()> {
+        self.write(format!(": {}\n\n", single_line(text)).as_bytes())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.writer.write_all(bytes)?;
+        self.writer.flush()
+    }
+}
+
+/// Sends the event-stream head on `io` and runs `handler` on the stream.
+pub(crate) fn stream<W: Write>(
+    io: &mut W,
+    request: &Request,
+    handler: &EventHandler,
+) -> io::Result<u16> {
+    let response = response();
+    response.write_head_to(io)?;
+    io.flush()?;
+    handler(request, &mut EventStream::new(io));
+    Ok(response.status)
+}
@@ -0,0 +1,134 @@
+//! Process-wide counters reported by the stats endpoints.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+/// When the server started, and the same moment in seconds since the Unix
+/// epoch.
+static STARTED: OnceLock<(Instant, u64)> = OnceLock::new();
+static CONNECTIONS_TOTAL: AtomicU64 = AtomicU64::new(0);
+static CONNECTIONS_ACTIVE: AtomicU64 = AtomicU64::new(0);
+
+/// The connections open right now, by the order they were accepted in.
+static OPEN: Mutex<BTreeMap<u64, Connection>> = Mutex::new(BTreeMap::new());
+
+/// Marks the server start, which uptime is measured from. Later calls keep
+/// the first start.
+pub(crate) fn start() {
+    started();
+}
+
+pub(crate) fn uptime() -> Duration {
+    started().0.elapsed()
+}
+
+/// When the server started, in seconds since the Unix epoch.
+pub(crate) fn started_at() -> u64 {
+    started().1
+}
+
+fn started() -> &'static (Instant, u64) {
+    STARTED.get_or_init(|| (Instant::now(), crate::unix_timestamp()))
+}
+
+/// An open connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Connection {
+    pub peer: String,
+    /// Seconds since the Unix epoch.
+    pub opened: u64,
+    /// Requests answered on it so far.
+    pub requests: u64,
+    /// Since when it has been kept alive waiting for another request, in
+    /// seconds since the Unix epoch, or `None` while a request is served.
+    pub idle_since: Option<u64>,
+}
+
+/// Counts a connection from `peer` accepted by the listener as open until
+/// the returned guard is dropped.
+pub(crate) fn connection_opened(peer: &str) -> ConnectionGuard {
+    let id = CONNECTIONS_TOTAL.fetch_add(1, Ordering::Relaxed);
+    CONNECTIONS_ACTIVE.fetch_add(1, Ordering::Relaxed);
+    open().insert(
+        id,
+        Connection {
+            peer: peer.to_string(),
+            opened: crate::unix_timestamp(),
+            requests: 0,
+            idle_since: None,
+        },
+    );
+    ConnectionGuard { id }
+}
+
+pub(crate) struct ConnectionGuard {
+    id: u64,
+}
+
+impl ConnectionGuard {
+    /// Counts a request answered on the connection, which now waits idle
+    /// for another.
+    pub(crate) fn served(&self) {
+        if let Some(connection) = open().get_mut(&self.id) {
+            connection.requests += 1;
+            connection.idle_since = Some(crate::unix_timestamp());
+        }
+    }
+
+    /// Marks the connection busy with another request.
+    pub(crate) fn busy(&self) {
+        if let Some(connection) = open().get_mut(&self.id) {
+            connection.idle_since = None;
+        }
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        CONNECTIONS_ACTIVE.fetch_sub(1, Ordering::Relaxed);
+        open().remove(&self.id);
+    }
+}
+
+/// The connections open right now, oldest first.
+pub(crate) fn connections() -> Vec<Connection> {
+    open().values().cloned().collect()
+}
+
+fn open() -> std::sync::MutexGuard<'static, BTreeMap<u64, Connection>> {
+    OPEN.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The counters at one point in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub(crate) struct Snapshot {
+    pub time: u64,
+    pub uptime_secs: u64,
+    pub connections_total: u64,
+    pub connections_active: u64,
+    /// Open connections kept alive waiting for another request.
+    pub connections_idle: u64,
+}
+
+pub(crate) fn snapshot() -> Snapshot {
+    Snapshot {
+        time: crate::unix_timestamp(),
+        uptime_secs: uptime().as_secs(),
+        connections_total: CONNECTIONS_TOTAL.load(Ordering::Relaxed),
+        connections_active: CONNECTIONS_ACTIVE.load(Ordering::Relaxed),
+        connections_idle: open()
+            .values()
+            .filter(|connection| connection.idle_since.is_some())
+            .count() as u64,
+    }
+}
+
+impl Snapshot {
+    pub fn to_json(self) -> String {
+        serde_json::to_string(&self).unwrap_or_default()
+    }
+}
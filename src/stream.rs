@@ -0,0 +1,119 @@
+//! Streaming handlers: they read the request body and write the response
+//! themselves, as it is produced.
+//!
+//! Handlers are registered with [`Router::stream`](crate::Router::stream) for
+//! every path under a prefix. They suit relays such as FastCGI, where neither
+//! the request body nor the response has to be held in memory at once.
+
+use std::io::{self, Read, Write};
+
+use crate::http::{Request, Response};
+
+/// A streaming handler: reads the request body from the [`Connection`] and
+/// writes the whole response, head included, to it. Returns the status sent.
+pub type StreamHandler =
+    Box<dyn Fn(&Request, &mut Connection<'_>) -> io::Result<u16> + Send + Sync>;
+
+trait Stream: Read + Write {}
+
+impl<T: Read + Write + ?Sized> Stream for T {}
+
+/// The client connection as a streaming handler sees it: reads return the
+/// request body, as framed by `Content-Length`, and writes go to the client.
+pub struct Connection<'a> {
+    io: &'a mut dyn Stream,
+    /// Body bytes that arrived along with the head.
+    buffered: Vec<u8>,
+    position: usize,
+    /// Body bytes still to be read from `io`.
+    remaining: u64,
+    /// Whether the client waits for `100 Continue` before sending the body.
+    expects_continue: bool,
+}
+
+impl<'a> Connection<'a> {
+    /// The connection for `request` on `io`, where `buffered` holds whatever
+    /// was read past the request head.
+    pub(crate) fn new<S: Read + Write>(io: &'a mut S, request: &Request, buffered: &[u8]) -> Self {
+        let length = request.content_length();
+        let buffered = &buffered[..buffered
+            .len()
+            .min(usize::try_from(length).unwrap_or(usize::MAX))];
+        Connection {
+            io,
+            buffered: buffered.to_vec(),
+            position: 0,
+            remaining: length - buffered.len() as u64,
+            expects_continue: request
+                .header("Expect")
+                .is_some_and(|value| value.eq_ignore_ascii_case("100-continue")),
+        }
+    }
+
+    /// Body bytes not yet read.
+    pub fn body_remaining(&self) -> u64 {
+        (self.buffered.len() - self.position) as u64 + self.remaining
+    }
+
+    /// Sends the head of `response`, without `Content-Length`; the body is
+    /// then written as it is produced, and ends when the connection closes.
+    pub fn send_head(&mut self, response: &Response) -> io::Result<()> {
+        response.write_head_to(self)
+    }
+}
+
+impl Read for Connection<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position < self.buffered.len() {
+            let n = buf.len().min(self.buffered.len() - self.position);
+            buf[..n].copy_from_slice(&self.buffered[self.position..self.position + n]);
+            self.position += n;
+            return Ok(n);
+        }
+        if self.remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+        if std::mem::take(&mut self.expects_continue) {
+            self.io.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+            self.io.flush()?;
+        }
+        let limit = buf
+            .len()
+            .min(usize::try_from(self.remaining).unwrap_or(usize::MAX));
+        let n = self.io.read(&mut buf[..limit])?;
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "client closed the connection before sending the whole body",
+            ));
+        }
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+impl Write for Connection<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // The client has sent its whole body, so it is not waiting on us.
+        self.expects_continue = false;
+        self.io.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.io.flush()
+    }
+}
+
+/// Runs `handler` on `io`, with `buffered` holding whatever was read past the
+/// request head.
+pub(crate) fn serve<S: Read + Write>(
+    io: &mut S,
+    request: &Request,
+    handler: &StreamHandler,
+    buffered: &[u8],
+) -> io::Result<u16> {
+    let mut connection = Connection::new(io, request, buffered);
+    let status = handler(request, &mut connection)?;
+    connection.flush()?;
+    Ok(status)
+}
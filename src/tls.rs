@@ -0,0 +1,561 @@
+//! HTTPS for the listener: the certificate and key it presents, set as
+//! [`Config::tls`](crate::Config::tls) (with the `tls` feature).
+//!
+//! Every connection a listener accepts then starts with a TLS handshake, and
+//! requests are served over it as usual, on either runtime. With a client
+//! CA, clients must present a certificate it signed, and the common name in
+//! it is passed on to handlers as [`Request::client_cn`]. Further
+//! certificates can be named by host, for clients that ask for that host
+//! through SNI. Certificates read from files are read again once the files
+//! change, with [`Acceptor::reload`] or [`Acceptor::keep_reloaded`], so a
+//! renewed certificate is presented without a restart.
+//!
+//! [`Request::client_cn`]: crate::http::Request::client_cn
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+use rustls::client::verify_server_name;
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::server::{ClientHello, ParsedCertificate, ResolvesServerCert, WebPkiClientVerifier};
+use rustls::sign::CertifiedKey;
+use rustls::{RootCertStore, ServerConfig, ServerConnection, StreamOwned};
+
+use crate::{console, ServerError};
+
+/// The TLS side of a listener: a rustls server config, shared by every
+/// connection.
+#[derive(Clone)]
+pub struct Acceptor {
+    config: Arc<ServerConfig>,
+    /// The certificates added by host name, lowercased.
+    names: HashMap<String, Arc<Certificate>>,
+    /// The resolver `config` had before any certificate was named.
+    fallback: Arc<dyn ResolvesServerCert>,
+    /// Every certificate the acceptor was made with or given, for reloads.
+    certificates: Vec<Arc<Certificate>>,
+}
+
+impl Acceptor {
+    /// Presents the certificate chain in the PEM file `cert` (the server's
+    /// certificate first), signed with the private key in the PEM file
+    /// `key`, and reads them again on [`reload`](Self::reload) once they
+    /// change. Fails with [`ServerError::Tls`] if either cannot be read or
+    /// they do not match.
+    pub fn from_pem_files(
+        cert: impl AsRef<Path>,
+        key: impl AsRef<Path>,
+    ) -> Result<Self, ServerError> {
+        let certificate = Certificate::from_pem_files(cert.as_ref(), key.as_ref(), None)?;
+        Self::build(certificate, Vec::new())
+    }
+
+    /// Like [`from_pem_files`](Self::from_pem_files), and requires clients to
+    /// present a certificate signed by one of the CA certificates in the PEM
+    /// file `client_ca`.
+    pub fn from_pem_files_with_client_ca(
+        cert: impl AsRef<Path>,
+        key: impl AsRef<Path>,
+        client_ca: impl AsRef<Path>,
+    ) -> Result<Self, ServerError> {
+        let certificate = Certificate::from_pem_files(cert.as_ref(), key.as_ref(), None)?;
+        let roots = read_certificates(client_ca.as_ref())?;
+        Self::build(certificate, roots)
+    }
+
+    /// Presents the DER-encoded certificate `chain`, signed with `key`.
+    pub fn from_der(
+        chain: Vec<CertificateDer<'static>>,
+        key: PrivateKeyDer<'static>,
+    ) -> Result<Self, ServerError> {
+        Self::build(Certificate::from_der(chain, key, None)?, Vec::new())
+    }
+
+    /// Like [`from_der`](Self::from_der), and requires clients to present a
+    /// certificate signed by one of the DER-encoded CA certificates `roots`.
+    pub fn from_der_with_client_ca(
+        chain: Vec<CertificateDer<'static>>,
+        key: PrivateKeyDer<'static>,
+        roots: Vec<CertificateDer<'static>>,
+    ) -> Result<Self, ServerError> {
+        if roots.is_empty() {
+            return Err(ServerError::Tls("no client CA certificate".to_string()));
+        }
+        Self::build(Certificate::from_der(chain, key, None)?, roots)
+    }
+
+    /// The acceptor presenting `certificate`, verifying client certificates
+    /// against `roots` unless there are none.
+    fn build(
+        certificate: Certificate,
+        roots: Vec<CertificateDer<'static>>,
+    ) -> Result<Self, ServerError> {
+        let provider = Arc::clone(&certificate.provider);
+        let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+            .with_safe_default_protocol_versions()
+            .expect("the ring provider supports the default protocol versions");
+        let builder = if roots.is_empty() {
+            builder.with_no_client_auth()
+        } else {
+            let mut store = RootCertStore::empty();
+            for root in roots {
+                store
+                    .add(root)
+                    .map_err(|e| ServerError::Tls(format!("client CA: {}", e)))?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(store), provider)
+                .build()
+                .map_err(|e| ServerError::Tls(format!("client CA: {}", e)))?;
+            builder.with_client_cert_verifier(verifier)
+        };
+        let certificate = Arc::new(certificate);
+        let mut config = builder.with_cert_resolver(Arc::clone(&certificate) as _);
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        let mut acceptor = Acceptor::new(Arc::new(config));
+        acceptor.certificates.push(certificate);
+        Ok(acceptor)
+    }
+
+    /// Uses `config` as it is, for settings the other constructors do not
+    /// offer, such as client certificates.
+    pub fn new(config: Arc<ServerConfig>) -> Self {
+        let fallback = Arc::clone(&config.cert_resolver);
+        Acceptor {
+            config,
+            names: HashMap::new(),
+            fallback,
+            certificates: Vec::new(),
+        }
+    }
+
+    /// Also presents the certificate chain in the PEM file `cert`, signed
+    /// with the private key in the PEM file `key`, to clients asking for
+    /// `name` through SNI; every other client is still presented the
+    /// acceptor's own certificate. Fails with [`ServerError::Tls`] if the
+    /// files cannot be read, do not match, or the certificate is not valid
+    /// for `name`.
+    pub fn with_named_pem_files(
+        self,
+        name: &str,
+        cert: impl AsRef<Path>,
+        key: impl AsRef<Path>,
+    ) -> Result<Self, ServerError> {
+        let certificate = Certificate::from_pem_files(cert.as_ref(), key.as_ref(), Some(name))?;
+        Ok(self.with_named(name, certificate))
+    }
+
+    /// Like [`with_named_pem_files`](Self::with_named_pem_files), with the
+    /// DER-encoded certificate `chain` and `key`.
+    pub fn with_named_der(
+        self,
+        name: &str,
+        chain: Vec<CertificateDer<'static>>,
+        key: PrivateKeyDer<'static>,
+    ) -> Result<Self, ServerError> {
+        let certificate = Certificate::from_der(chain, key, Some(name))?;
+        Ok(self.with_named(name, certificate))
+    }
+
+    /// Presents `certificate` to clients asking for `name`.
+    fn with_named(mut self, name: &str, certificate: Certificate) -> Self {
+        let certificate = Arc::new(certificate);
+        self.names
+            .insert(name.to_ascii_lowercase(), Arc::clone(&certificate));
+        self.certificates.push(certificate);
+
+        let mut config = ServerConfig::clone(&self.config);
+        config.cert_resolver = Arc::new(ByName {
+            names: self.names.clone(),
+            fallback: Arc::clone(&self.fallback),
+        });
+        self.config = Arc::new(config);
+        self
+    }
+
+    /// Reads the certificate and key files again where either has changed
+    /// since they were last read, and returns the certificate file and the
+    /// outcome of each. New handshakes get a reloaded certificate at once,
+    /// while connections already open keep theirs; one that fails to load
+    /// is left as it was.
+    pub fn reload(&self) -> Vec<(PathBuf, Result<(), ServerError>)> {
+        self.certificates
+            .iter()
+            .filter_map(|certificate| certificate.reload())
+            .collect()
+    }
+
+    /// Starts a thread that calls [`reload`](Self::reload) every `interval`
+    /// and reports each certificate reloaded, or not, on the console.
+    pub fn keep_reloaded(&self, interval: Duration) -> JoinHandle<()> {
+        let acceptor = self.clone();
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            for (path, result) in acceptor.reload() {
+                let status = match result {
+                    Ok(()) => format!("✓ reloaded {}", path.display()),
+                    Err(e) => format!("✗ cannot reload {}: {}", path.display(), e),
+                };
+                console::print_table(&[("TLS", status)]);
+            }
+        })
+    }
+
+    /// Starts a TLS session on `stream` and completes its handshake, so that
+    /// the client's certificate, if any, is known. A client that does not
+    /// finish the handshake within `timeout` fails it with
+    /// [`ServerError::Timeout`], so that it cannot hold the thread.
+    pub(crate) fn accept(
+        &self,
+        mut stream: TcpStream,
+        timeout: Option<Duration>,
+    ) -> Result<StreamOwned<ServerConnection, TcpStream>, ServerError> {
+        const HANDSHAKE: &str = "completing the TLS handshake";
+        let mut connection = ServerConnection::new(Arc::clone(&self.config))
+            .map_err(|e| ServerError::Tls(e.to_string()))?;
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        while connection.is_handshaking() {
+            if let Some(deadline) = deadline {
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left.is_zero() {
+                    return Err(ServerError::Timeout(HANDSHAKE));
+                }
+                stream.set_read_timeout(Some(left))?;
+            }
+            connection.complete_io(&mut stream).map_err(|e| match e.kind() {
+                ErrorKind::TimedOut | ErrorKind::WouldBlock => ServerError::Timeout(HANDSHAKE),
+                _ => ServerError::Tls(format!("handshake failed: {}", e)),
+            })?;
+        }
+        if deadline.is_some() {
+            stream.set_read_timeout(None)?;
+        }
+        Ok(StreamOwned::new(connection, stream))
+    }
+}
+
+/// One certificate of an [`Acceptor`], swapped for a new one when the files
+/// it came from, if any, change.
+#[derive(Debug)]
+struct Certificate {
+    current: RwLock<Arc<CertifiedKey>>,
+    /// The host name it is presented for, which it must be valid for.
+    name: Option<String>,
+    /// The certificate and key files.
+    files: Option<(PathBuf, PathBuf)>,
+    /// When the files were modified as of the last time they were read.
+    modified: Mutex<[Option<SystemTime>; 2]>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl Certificate {
+    /// The certificate `chain`, signed with `key`, for `name` if given.
+    fn from_der(
+        chain: Vec<CertificateDer<'static>>,
+        key: PrivateKeyDer<'static>,
+        name: Option<&str>,
+    ) -> Result<Self, ServerError> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let certified = certified_key(chain, key, name, &provider)?;
+        Ok(Certificate {
+            current: RwLock::new(Arc::new(certified)),
+            name: name.map(String::from),
+            files: None,
+            modified: Mutex::new([None, None]),
+            provider,
+        })
+    }
+
+    /// The certificate in the PEM file `cert`, with the key in `key`, for
+    /// `name` if given.
+    fn from_pem_files(cert: &Path, key: &Path, name: Option<&str>) -> Result<Self, ServerError> {
+        let modified = modified(cert, key);
+        let (chain, private_key) = read_pem_files(cert, key)?;
+        let mut certificate = Self::from_der(chain, private_key, name)?;
+        certificate.files = Some((cert.to_path_buf(), key.to_path_buf()));
+        certificate.modified = Mutex::new(modified);
+        Ok(certificate)
+    }
+
+    /// Reads the files again if they have changed, and returns the
+    /// certificate file and the outcome; `None` if nothing changed.
+    fn reload(&self) -> Option<(PathBuf, Result<(), ServerError>)> {
+        let (cert, key) = self.files.as_ref()?;
+        let modified = modified(cert, key);
+        {
+            let mut last = self.modified.lock().unwrap_or_else(PoisonError::into_inner);
+            if *last == modified {
+                return None;
+            }
+            // A failed load is not retried until the files change again,
+            // such as when the key is written after the certificate.
+            *last = modified;
+        }
+        let result = read_pem_files(cert, key)
+            .and_then(|(chain, key)| {
+                certified_key(chain, key, self.name.as_deref(), &self.provider)
+            })
+            .map(|certified| {
+                *self.current.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(certified);
+            });
+        Some((cert.clone(), result))
+    }
+
+    fn current(&self) -> Arc<CertifiedKey> {
+        Arc::clone(&self.current.read().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+impl ResolvesServerCert for Certificate {
+    fn resolve(&self, _: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current())
+    }
+}
+
+/// `chain` and `key` as rustls presents them, checked to match each other
+/// and, if given, `name`.
+fn certified_key(
+    chain: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+    name: Option<&str>,
+    provider: &CryptoProvider,
+) -> Result<CertifiedKey, ServerError> {
+    let Some(name) = name else {
+        return CertifiedKey::from_der(chain, key, provider)
+            .map_err(|e| ServerError::Tls(e.to_string()));
+    };
+    let invalid = |e: rustls::Error| ServerError::Tls(format!("{}: {}", name, e));
+    let server_name = ServerName::try_from(name)
+        .map_err(|_| ServerError::Tls(format!("invalid host name `{}`", name)))?;
+    let certified = CertifiedKey::from_der(chain, key, provider).map_err(invalid)?;
+    certified
+        .end_entity_cert()
+        .and_then(ParsedCertificate::try_from)
+        .and_then(|parsed| verify_server_name(&parsed, &server_name))
+        .map_err(invalid)?;
+    Ok(certified)
+}
+
+/// When the files `cert` and `key` were last modified, where known.
+fn modified(cert: &Path, key: &Path) -> [Option<SystemTime>; 2] {
+    [cert, key].map(|path| {
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    })
+}
+
+/// The certificates of an [`Acceptor`] named by host, and the resolver for
+/// clients asking for no host or another one.
+#[derive(Debug)]
+struct ByName {
+    names: HashMap<String, Arc<Certificate>>,
+    fallback: Arc<dyn ResolvesServerCert>,
+}
+
+impl ResolvesServerCert for ByName {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        // ACME validations expect the fallback's challenge certificate.
+        let validating = client_hello
+            .alpn()
+            .is_some_and(|mut protocols| protocols.any(|protocol| protocol == ACME_TLS_ALPN));
+        match client_hello
+            .server_name()
+            .and_then(|name| self.names.get(name))
+        {
+            Some(certificate) if !validating => Some(certificate.current()),
+            _ => self.fallback.resolve(client_hello),
+        }
+    }
+}
+
+/// The ALPN protocol of ACME TLS-ALPN-01 validations (RFC 8737), whose
+/// connections end with the handshake.
+pub(crate) const ACME_TLS_ALPN: &[u8] = b"acme-tls/1";
+
+/// Whether `connection` is an ACME TLS-ALPN-01 validation rather than a
+/// client.
+pub(crate) fn is_acme_challenge(connection: &ServerConnection) -> bool {
+    connection.alpn_protocol() == Some(ACME_TLS_ALPN)
+}
+
+/// The common name in the certificate the client of `connection` presented,
+/// if it presented one.
+pub(crate) fn client_cn(connection: &ServerConnection) -> Option<String> {
+    common_name(connection.peer_certificates()?.first()?)
+}
+
+/// The certificate chain in the PEM file `cert` and the private key in the
+/// PEM file `key`.
+pub(crate) fn read_pem_files(
+    cert: &Path,
+    key: &Path,
+) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>), ServerError> {
+    let chain = read_certificates(cert)?;
+    let key = PrivateKeyDer::from_pem_file(key)
+        .map_err(|e| ServerError::Tls(format!("cannot read {}: {}", key.display(), e)))?;
+    Ok((chain, key))
+}
+
+/// Every certificate in the PEM file `path`, of which there must be one at
+/// least.
+fn read_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>, ServerError> {
+    let certificates = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| ServerError::Tls(format!("cannot read {}: {}", path.display(), e)))?;
+    if certificates.is_empty() {
+        return Err(ServerError::Tls(format!(
+            "no certificate in {}",
+            path.display()
+        )));
+    }
+    Ok(certificates)
+}
+
+/// The DER encoding of the common name attribute type, 2.5.4.3.
+const COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+
+/// The first common name in the subject of the DER-encoded X.509
+/// certificate `der`, if it has one and is well formed.
+fn common_name(der: &[u8]) -> Option<String> {
+    const SET: u8 = 0x31;
+    const OID: u8 = 0x06;
+
+    let (tag, mut subject) = tbs_field(der, SUBJECT)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+    while !subject.is_empty() {
+        let (tag, mut names, rest) = der_element(subject)?;
+        subject = rest;
+        if tag != SET {
+            return None;
+        }
+        while !names.is_empty() {
+            let (_, attribute, rest) = der_element(names)?;
+            names = rest;
+            let (tag, kind, value) = der_element(attribute)?;
+            if tag == OID && kind == COMMON_NAME {
+                let (_, value, _) = der_element(value)?;
+                return String::from_utf8(value.to_vec()).ok();
+            }
+        }
+    }
+    None
+}
+
+/// When the DER-encoded X.509 certificate `der` expires, in seconds since
+/// the Unix epoch, if it is well formed.
+#[cfg(feature = "acme")]
+pub(crate) fn not_after(der: &[u8]) -> Option<u64> {
+    const UTC_TIME: u8 = 0x17;
+    const GENERALIZED_TIME: u8 = 0x18;
+
+    let (_, validity) = tbs_field(der, VALIDITY)?;
+    let (_, _, rest) = der_element(validity)?;
+    let (tag, time, _) = der_element(rest)?;
+    let time = std::str::from_utf8(time).ok()?.strip_suffix('Z')?;
+    // UTCTime years 50 to 99 are 1950 to 1999 (RFC 5280, section 4.1.2.5.1).
+    let (year, rest) = match tag {
+        UTC_TIME => {
+            let year: i64 = time.get(..2)?.parse().ok()?;
+            (
+                if year < 50 { 2000 + year } else { 1900 + year },
+                &time[2..],
+            )
+        }
+        GENERALIZED_TIME => (time.get(..4)?.parse().ok()?, &time[4..]),
+        _ => return None,
+    };
+    if rest.len() != 10 || !rest.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let field = |at: usize| rest[at..at + 2].parse::<i64>().unwrap_or_default();
+    let (month, day) = (field(0), field(2));
+    // Days from 1970-01-01 to the date, after Howard Hinnant's
+    // `days_from_civil`.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+    let seconds = days * 86_400 + field(4) * 3600 + field(6) * 60 + field(8);
+    u64::try_from(seconds).ok()
+}
+
+const SEQUENCE: u8 = 0x30;
+
+/// Positions of fields in a certificate's `tbsCertificate`, after the
+/// optional version.
+#[cfg(feature = "acme")]
+const VALIDITY: usize = 3;
+const SUBJECT: usize = 4;
+
+/// The tag and contents of field `index` of the `tbsCertificate` in the
+/// DER-encoded X.509 certificate `der`.
+fn tbs_field(der: &[u8], index: usize) -> Option<(u8, &[u8])> {
+    const VERSION: u8 = 0xa0;
+
+    let (_, certificate, _) = der_element(der)?;
+    let (_, mut tbs, _) = der_element(certificate)?;
+    // version (optional), serialNumber, signature, issuer, validity, subject
+    if tbs.first() == Some(&VERSION) {
+        tbs = der_element(tbs)?.2;
+    }
+    for _ in 0..index {
+        tbs = der_element(tbs)?.2;
+    }
+    let (tag, contents, _) = der_element(tbs)?;
+    Some((tag, contents))
+}
+
+/// Splits the DER element at the start of `der` into its tag, its contents,
+/// and the bytes after it.
+fn der_element(der: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = der.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (length, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > size_of::<usize>() || rest.len() < count {
+            return None;
+        }
+        let (bytes, rest) = rest.split_at(count);
+        (
+            bytes
+                .iter()
+                .fold(0, |length, &byte| length << 8 | byte as usize),
+            rest,
+        )
+    };
+    if rest.len() < length {
+        return None;
+    }
+    let (contents, rest) = rest.split_at(length);
+    Some((tag, contents, rest))
+}
+
+impl fmt::Debug for Acceptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Acceptor").finish_non_exhaustive()
+    }
+}
+
+/// Acceptors are equal if they share the same rustls config.
+impl PartialEq for Acceptor {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.config, &other.config)
+    }
+}
+
+impl Eq for Acceptor {}
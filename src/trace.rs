@@ -0,0 +1,154 @@
+//! Structured logging with [tracing](https://docs.rs/tracing).
+//!
+//! Every connection the listener accepts is served inside a `connection`
+//! span with the peer's address, and every request inside a `request` span,
+//! which records the method and path once the head is parsed. The spans cost
+//! next to nothing until a subscriber is installed.
+//!
+//! [`init`] installs one, filtered by `RUST_LOG` (`info` when it is unset),
+//! for [`Format::Tracing`] or [`Format::Pretty`]. Under either, the report of
+//! each finished connection is an event with the path, status, bytes read,
+//! and time taken, and the request and response headers follow at `debug`.
+//! [`Format::Tracing`] writes the events one per line; [`Format::Pretty`]
+//! draws them as console tables with the [`Pretty`] layer.
+
+use std::fmt::{self, Write as _};
+use std::time::Duration;
+
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::{SubscriberInitExt, TryInitError};
+
+use crate::console::{self, Format};
+use crate::server::Exchange;
+use crate::ServerError;
+
+/// Installs the global subscriber for `format`: the tracing formatter on
+/// stdout for [`Format::Tracing`], and [`Pretty`] for [`Format::Pretty`].
+/// Fails if a subscriber is already installed; other formats install none.
+pub fn init(format: Format) -> Result<(), TryInitError> {
+    let filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::INFO.into())
+        .from_env_lossy();
+    match format {
+        Format::Tracing => tracing_subscriber::registry()
+            .with(filter)
+            .with(tracing_subscriber::fmt::layer().with_ansi(console::color()))
+            .try_init(),
+        Format::Pretty => tracing_subscriber::registry().with(filter).with(Pretty).try_init(),
+        Format::Table | Format::Ndjson => Ok(()),
+    }
+}
+
+/// Logs a finished connection that took `elapsed`.
+pub(crate) fn report(result: &Result<Exchange, ServerError>, elapsed: Duration) {
+    let elapsed_ms = elapsed.as_secs_f64() * 1000.0;
+    match result {
+        Ok(exchange) => {
+            let request = exchange.request.as_ref();
+            let content_type = exchange
+                .response_headers
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case("Content-Type"))
+                .map(|(_, value)| value.as_str());
+            tracing::info!(
+                target: "rusty_server::connection",
+                method = request.map(|request| request.method.as_str()),
+                path = exchange.path.as_str(),
+                request_id = exchange.request_id.as_str(),
+                client = request.and_then(|request| request.client_cn.as_deref()),
+                status = exchange.status,
+                content_type,
+                bytes_read = exchange.bytes_read,
+                elapsed_ms,
+                "served"
+            );
+            tracing::debug!(
+                target: "rusty_server::connection",
+                request_headers = console::text_headers(request.map_or(&[][..], |request| &request.headers)),
+                response_headers = console::text_headers(&exchange.response_headers),
+                "headers"
+            );
+        }
+        Err(e) => tracing::warn!(target: "rusty_server::connection", error = %e, elapsed_ms, "failed"),
+    }
+}
+
+/// A layer drawing each event as a console table: its level, the fields of
+/// the spans it happened in, outermost first, and its own fields.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Pretty;
+
+/// The fields recorded on a span, kept in its extensions.
+struct Fields(Vec<(String, String)>);
+
+impl<S> Layer<S> for Pretty
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let mut fields = Fields(Vec::new());
+        attrs.record(&mut fields);
+        span.extensions_mut().insert(fields);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let mut extensions = span.extensions_mut();
+        if let Some(fields) = extensions.get_mut::<Fields>() {
+            values.record(fields);
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut rows = vec![("Level".to_string(), event.metadata().level().to_string())];
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                if let Some(fields) = span.extensions().get::<Fields>() {
+                    rows.extend(fields.0.iter().map(|(key, value)| (title(key), value.clone())));
+                }
+            }
+        }
+        let mut fields = Fields(Vec::new());
+        event.record(&mut fields);
+        rows.extend(fields.0.into_iter().map(|(key, value)| (title(&key), value)));
+        let rows: Vec<(&str, String)> = rows.iter().map(|(key, value)| (key.as_str(), value.clone())).collect();
+        console::print_drawn(&rows);
+    }
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.set(field, value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        let mut text = String::new();
+        let _ = write!(text, "{:?}", value);
+        self.set(field, text);
+    }
+}
+
+impl Fields {
+    /// Sets `field` to `value`, replacing the value it was recorded with.
+    fn set(&mut self, field: &Field, value: String) {
+        match self.0.iter_mut().find(|(key, _)| key == field.name()) {
+            Some((_, old)) => *old = value,
+            None => self.0.push((field.name().to_string(), value)),
+        }
+    }
+}
+
+/// `bytes_read` as `Bytes read`, the way console tables title their rows.
+fn title(key: &str) -> String {
+    let mut title = key.replace('_', " ");
+    if let Some(first) = title.get_mut(..1) {
+        first.make_ascii_uppercase();
+    }
+    title
+}
@@ -0,0 +1,448 @@
+//! A live dashboard in the terminal, in place of the per-connection tables.
+//!
+//! [`serve`] runs the listener in the background and draws a [`Dashboard`]
+//! until the user quits: the uptime, requests per second, a latency
+//! sparkline, active connections, the responses by status, the busiest
+//! paths, and the most recent requests. Keys:
+//!
+//! - `q` or Ctrl-C: quit, restoring the terminal
+//! - `p` or space: pause or resume the display; requests are still counted
+//! - `/`: filter recent requests by path, peer, or status; Enter keeps the
+//!   filter, Esc clears it
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::TcpListener;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Row, Sparkline, Table};
+use ratatui::Frame;
+
+use crate::router::Router;
+use crate::server::{serve_all, Config, Exchange};
+use crate::{console, stats, unix_timestamp, ServerError};
+
+/// How often a point is added to the sparklines.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Longest wait for a key before the screen is redrawn.
+const FRAME_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Seconds of history kept for the sparklines.
+const HISTORY: usize = 300;
+
+/// Requests kept for the recent-requests panel.
+const RECENT: usize = 200;
+
+/// Most distinct paths counted for the top-paths panel; requests to others
+/// are not counted there, so scans for random paths cannot grow it without
+/// bound.
+const PATHS: usize = 1000;
+
+static DASHBOARD: OnceLock<Mutex<Dashboard>> = OnceLock::new();
+
+/// One finished connection, as the dashboard lists it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Activity {
+    /// Seconds since the Unix epoch.
+    pub time: u64,
+    pub peer: String,
+    pub path: String,
+    /// The response status, or `None` if the connection failed.
+    pub status: Option<u16>,
+    pub bytes_read: usize,
+    pub elapsed: Duration,
+}
+
+/// What the dashboard shows at one point in time.
+#[derive(Debug, Clone, Default)]
+struct View {
+    /// Requests per second, oldest first.
+    rps: VecDeque<u64>,
+    /// Mean latency in each second with requests, in microseconds.
+    latency: VecDeque<u64>,
+    /// Active connections at each sample.
+    active: VecDeque<u64>,
+    recent: VecDeque<Activity>,
+    requests_total: u64,
+    /// Responses by status.
+    statuses: BTreeMap<u16, u64>,
+    /// Connections that failed.
+    failed: u64,
+    /// Requests by path, for the first [`PATHS`] paths.
+    paths: HashMap<String, u64>,
+}
+
+/// The dashboard's state: the live view, the paused one, and the filter.
+#[derive(Debug, Clone)]
+pub struct Dashboard {
+    address: String,
+    live: View,
+    paused: Option<View>,
+    filter: String,
+    editing: bool,
+    /// Requests and their summed latency since the last sample.
+    pending: (u64, Duration),
+    error: Option<String>,
+}
+
+impl Dashboard {
+    /// An empty dashboard for the server at `address`.
+    pub fn new(address: impl Into<String>) -> Self {
+        Dashboard {
+            address: address.into(),
+            live: View::default(),
+            paused: None,
+            filter: String::new(),
+            editing: false,
+            pending: (0, Duration::ZERO),
+            error: None,
+        }
+    }
+
+    /// Counts `activity` and lists it among the recent requests.
+    pub fn record(&mut self, activity: Activity) {
+        self.pending.0 += 1;
+        self.pending.1 += activity.elapsed;
+        self.live.requests_total += 1;
+        match activity.status {
+            Some(status) => *self.live.statuses.entry(status).or_default() += 1,
+            None => self.live.failed += 1,
+        }
+        let paths = &mut self.live.paths;
+        if activity.status.is_some() && (paths.len() < PATHS || paths.contains_key(&activity.path))
+        {
+            *paths.entry(activity.path.clone()).or_default() += 1;
+        }
+        push_bounded(&mut self.live.recent, activity, RECENT);
+    }
+
+    /// Closes the current second of history, with `active` connections open.
+    pub fn sample(&mut self, active: u64) {
+        let (requests, latency) = std::mem::take(&mut self.pending);
+        let mean = match requests {
+            0 => self.live.latency.back().copied().unwrap_or(0),
+            n => (latency / n as u32).as_micros() as u64,
+        };
+        push_bounded(&mut self.live.rps, requests, HISTORY);
+        push_bounded(&mut self.live.latency, mean, HISTORY);
+        push_bounded(&mut self.live.active, active, HISTORY);
+    }
+
+    /// Reacts to `key`; returns `false` once the user asked to quit.
+    pub fn key(&mut self, key: KeyEvent) -> bool {
+        if self.editing {
+            match key.code {
+                KeyCode::Enter => self.editing = false,
+                KeyCode::Esc => {
+                    self.editing = false;
+                    self.filter.clear();
+                }
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                }
+                KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    self.filter.push(c)
+                }
+                _ => {}
+            }
+            return true;
+        }
+        match key.code {
+            KeyCode::Char('q') => return false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Char('p') | KeyCode::Char(' ') => {
+                self.paused = match self.paused {
+                    Some(_) => None,
+                    None => Some(self.live.clone()),
+                }
+            }
+            KeyCode::Char('/') => self.editing = true,
+            KeyCode::Esc => self.filter.clear(),
+            _ => {}
+        }
+        true
+    }
+
+    /// Draws the dashboard over the whole of `frame`.
+    pub fn draw(&self, frame: &mut Frame) {
+        let view = self.paused.as_ref().unwrap_or(&self.live);
+        let [header, charts, totals, recent, help] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(7),
+            Constraint::Length(7),
+            Constraint::Min(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let mut title = format!(
+            " rusty-server  http://{}  up {}  {} requests ",
+            self.address,
+            clock(stats::uptime().as_secs()),
+            view.requests_total
+        );
+        if self.paused.is_some() {
+            title.push_str(" PAUSED ");
+        }
+        let mut header_line = Line::from(title.bold());
+        if let Some(error) = &self.error {
+            header_line.push_span(format!(" ✗ {} ", error).red());
+        }
+        frame.render_widget(header_line, header);
+
+        let [rps, latency, active] = Layout::horizontal([Constraint::Ratio(1, 3); 3]).areas(charts);
+        let last = |history: &VecDeque<u64>| history.back().copied().unwrap_or(0);
+        sparkline(
+            frame,
+            rps,
+            format!("Requests/s: {}", last(&view.rps)),
+            &view.rps,
+            Color::Green,
+        );
+        sparkline(
+            frame,
+            latency,
+            format!("Latency: {:.1} ms", last(&view.latency) as f64 / 1000.0),
+            &view.latency,
+            Color::Yellow,
+        );
+        sparkline(
+            frame,
+            active,
+            format!("Active connections: {}", last(&view.active)),
+            &view.active,
+            Color::Cyan,
+        );
+
+        let [statuses, paths] =
+            Layout::horizontal([Constraint::Ratio(1, 3), Constraint::Ratio(2, 3)]).areas(totals);
+        let mut rows: Vec<Row> = view
+            .statuses
+            .iter()
+            .map(|(status, count)| {
+                Row::new([status.to_string(), format!("×{}", count)])
+                    .style(status_style(Some(*status)))
+            })
+            .collect();
+        if view.failed > 0 {
+            rows.push(
+                Row::new(["✗".to_string(), format!("×{}", view.failed)]).style(status_style(None)),
+            );
+        }
+        let table = Table::new(rows, [Constraint::Length(6), Constraint::Fill(1)])
+            .block(Block::bordered().title("Statuses"));
+        frame.render_widget(table, statuses);
+
+        let mut top: Vec<(&String, &u64)> = view.paths.iter().collect();
+        top.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        let rows: Vec<Row> = top
+            .into_iter()
+            .take(paths.height.saturating_sub(2) as usize)
+            .map(|(path, count)| Row::new([format!("×{}", count), path.clone()]))
+            .collect();
+        let table = Table::new(rows, [Constraint::Length(8), Constraint::Fill(1)])
+            .block(Block::bordered().title("Top paths"));
+        frame.render_widget(table, paths);
+
+        let filter = self.filter.to_lowercase();
+        let rows: Vec<Row> = view
+            .recent
+            .iter()
+            .rev()
+            .filter(|activity| filter.is_empty() || matches(activity, &filter))
+            .take(recent.height.saturating_sub(3) as usize)
+            .map(|activity| {
+                let status = match activity.status {
+                    Some(status) => status.to_string(),
+                    None => "✗".to_string(),
+                };
+                let style = status_style(activity.status);
+                Row::new([
+                    clock(activity.time % 86_400),
+                    activity.peer.clone(),
+                    status,
+                    activity.path.clone(),
+                    activity.bytes_read.to_string(),
+                    format!("{:.1} ms", activity.elapsed.as_secs_f64() * 1000.0),
+                ])
+                .style(style)
+            })
+            .collect();
+        let title = match (self.editing, self.filter.is_empty()) {
+            (true, _) => format!("Recent requests — filter: {}▏", self.filter),
+            (false, false) => format!("Recent requests — filter: {}", self.filter),
+            (false, true) => "Recent requests".to_string(),
+        };
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(8),
+                Constraint::Length(21),
+                Constraint::Length(6),
+                Constraint::Fill(1),
+                Constraint::Length(6),
+                Constraint::Length(10),
+            ],
+        )
+        .header(Row::new(["Time", "Peer", "Status", "Path", "Bytes", "Latency"]).bold())
+        .block(Block::bordered().title(title));
+        frame.render_widget(table, recent);
+
+        let keys = if self.editing {
+            " Enter keep filter  Esc clear filter"
+        } else {
+            " q quit  p pause  / filter  Esc clear filter"
+        };
+        frame.render_widget(Line::from(keys).dim(), help);
+    }
+}
+
+fn push_bounded<T>(items: &mut VecDeque<T>, item: T, capacity: usize) {
+    if items.len() == capacity {
+        items.pop_front();
+    }
+    items.push_back(item);
+}
+
+/// How a response with `status`, or a failed connection, is colored.
+fn status_style(status: Option<u16>) -> Style {
+    match status {
+        Some(200..=399) => Style::default(),
+        Some(400..=499) => Style::default().fg(Color::Yellow),
+        _ => Style::default().fg(Color::Red),
+    }
+}
+
+/// Whether `activity` matches the lower-case `filter`.
+fn matches(activity: &Activity, filter: &str) -> bool {
+    activity.path.to_lowercase().contains(filter)
+        || activity.peer.contains(filter)
+        || activity
+            .status
+            .is_some_and(|status| status.to_string().contains(filter))
+}
+
+/// `seconds` as `HH:MM:SS`.
+fn clock(seconds: u64) -> String {
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Renders the newest points of `history` that fit in `area`.
+fn sparkline(frame: &mut Frame, area: Rect, title: String, history: &VecDeque<u64>, color: Color) {
+    let width = area.width.saturating_sub(2) as usize;
+    let points = history.iter().skip(history.len().saturating_sub(width));
+    let sparkline = Sparkline::default()
+        .block(Block::bordered().title(title))
+        .data(points)
+        .style(Style::default().fg(color));
+    frame.render_widget(sparkline, area);
+}
+
+fn dashboard() -> Option<std::sync::MutexGuard<'static, Dashboard>> {
+    DASHBOARD
+        .get()
+        .map(|dashboard| dashboard.lock().unwrap_or_else(PoisonError::into_inner))
+}
+
+/// Lists a finished connection on the dashboard, if one is running.
+pub(crate) fn record(peer: &str, result: &Result<Exchange, ServerError>, elapsed: Duration) {
+    let Some(mut dashboard) = dashboard() else {
+        return;
+    };
+    let (status, path, bytes_read) = match result {
+        Ok(exchange) => (
+            Some(exchange.status),
+            exchange.path.clone(),
+            exchange.bytes_read,
+        ),
+        Err(e) => (None, e.to_string(), 0),
+    };
+    dashboard.record(Activity {
+        time: unix_timestamp(),
+        peer: peer.to_string(),
+        path,
+        status,
+        bytes_read,
+        elapsed,
+    });
+}
+
+/// Serves connections on `listeners` in the background, like [`serve_all`],
+/// and shows them on the dashboard until the user quits. The title shows
+/// the first listener's address.
+///
+/// The console tables are turned off for the rest of the process, since
+/// they would draw over the dashboard.
+pub fn serve(
+    listeners: Vec<TcpListener>,
+    router: Router,
+    config: Config,
+) -> Result<(), ServerError> {
+    let mut address = match listeners.first() {
+        Some(listener) => listener.local_addr()?.to_string(),
+        None => return Ok(()),
+    };
+    if listeners.len() > 1 {
+        address.push_str(&format!(" (+{} more)", listeners.len() - 1));
+    }
+    if DASHBOARD.set(Mutex::new(Dashboard::new(address))).is_err() {
+        return Err(ServerError::Io(std::io::Error::other(
+            "a dashboard is already running",
+        )));
+    }
+    console::silence();
+    thread::spawn(move || {
+        if let Err(e) = serve_all(listeners, router, config) {
+            if let Some(mut dashboard) = dashboard() {
+                dashboard.error = Some(e.to_string());
+            }
+        }
+    });
+
+    let mut terminal = ratatui::try_init()?;
+    let result = show(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+/// Draws the dashboard and handles keys until the user quits.
+fn show(terminal: &mut ratatui::DefaultTerminal) -> Result<(), ServerError> {
+    let mut next_sample = Instant::now() + SAMPLE_INTERVAL;
+    loop {
+        terminal.draw(|frame| {
+            if let Some(dashboard) = dashboard() {
+                dashboard.draw(frame);
+            }
+        })?;
+
+        let wait = next_sample
+            .saturating_duration_since(Instant::now())
+            .min(FRAME_INTERVAL);
+        if event::poll(wait)? {
+            if let Event::Key(key) = event::read()? {
+                let keep_going = key.kind != KeyEventKind::Press
+                    || dashboard().is_none_or(|mut dashboard| dashboard.key(key));
+                if !keep_going {
+                    return Ok(());
+                }
+            }
+        }
+        if Instant::now() >= next_sample {
+            if let Some(mut dashboard) = dashboard() {
+                dashboard.sample(stats::snapshot().connections_active);
+            }
+            next_sample += SAMPLE_INTERVAL;
+        }
+    }
+}
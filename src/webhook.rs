@@ -0,0 +1,369 @@
+//! Outgoing webhooks: JSON event notifications POSTed to configured URLs.
+//!
+//! Configure a [`Webhooks`], [`start`](Webhooks::start) it, and
+//! [`install`] the resulting [`Dispatcher`]; the server then reports its own
+//! [`Event::Startup`] and [`Event::ErrorRate`] events, and anything else can
+//! report through [`notify`].
+//!
+//! Each event is delivered to every URL in the background, with up to
+//! [`Webhooks::retries`] further attempts after a failure, waiting twice as
+//! long before each. A delivery succeeds on any 2xx answer. With a
+//! [`secret`](Webhooks::secret), every request carries an
+//! `X-Webhook-Signature: sha256=<hex>` header: the HMAC-SHA256 of the body,
+//! which receivers should check before trusting the event.
+
+use std::collections::VecDeque;
+use std::net::IpAddr;
+use std::sync::{Arc, Condvar, Mutex, OnceLock, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::client::{self, Client, ClientError};
+use crate::{json_escape, unix_timestamp};
+
+/// Longest wait between two delivery attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Something worth telling the outside world about.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Event {
+    /// The server started listening on `address`; several addresses are
+    /// separated by `, `.
+    Startup { address: String },
+    /// The server is shutting down.
+    Shutdown,
+    /// The share of failed responses (5xx, or no response at all) over the
+    /// last `window_secs` seconds reached `threshold`. Reported again only
+    /// after the rate has dropped back below it.
+    ErrorRate {
+        rate: f64,
+        threshold: f64,
+        window_secs: u64,
+        requests: u64,
+    },
+    /// A client address was banned.
+    IpBanned { ip: IpAddr, reason: String },
+}
+
+impl Event {
+    /// The event's name, as sent in `X-Webhook-Event` and the `event` field.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::Startup { .. } => "startup",
+            Event::Shutdown => "shutdown",
+            Event::ErrorRate { .. } => "error_rate",
+            Event::IpBanned { .. } => "ip_banned",
+        }
+    }
+
+    /// The request body delivered for the event, stamped with `time`.
+    pub fn to_json(&self, time: u64) -> String {
+        let fields = match self {
+            Event::Startup { address } => format!(r#","address":"{}""#, json_escape(address)),
+            Event::Shutdown => String::new(),
+            Event::ErrorRate {
+                rate,
+                threshold,
+                window_secs,
+                requests,
+            } => format!(
+                r#","rate":{},"threshold":{},"window_secs":{},"requests":{}"#,
+                rate, threshold, window_secs, requests
+            ),
+            Event::IpBanned { ip, reason } => {
+                format!(r#","ip":"{}","reason":"{}""#, ip, json_escape(reason))
+            }
+        };
+        format!(r#"{{"event":"{}","time":{}{}}}"#, self.name(), time, fields)
+    }
+}
+
+/// `X-Webhook-Signature` value for `body`: `sha256=` and the hex HMAC.
+pub fn signature(secret: &[u8], body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(body);
+    let digest = mac.finalize().into_bytes();
+    let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256={}", hex)
+}
+
+/// Webhook settings. Start from [`Webhooks::new`] and adjust with the
+/// setters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Webhooks {
+    urls: Vec<String>,
+    secret: Option<Vec<u8>>,
+    retries: u32,
+    backoff: Duration,
+    timeout: Duration,
+    error_threshold: f64,
+    error_window: Duration,
+    error_min_requests: u64,
+}
+
+impl Webhooks {
+    /// Delivers events to every `http://` URL in `urls`.
+    pub fn new<I, S>(urls: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Webhooks {
+            urls: urls.into_iter().map(Into::into).collect(),
+            secret: None,
+            retries: 5,
+            backoff: Duration::from_secs(1),
+            timeout: client::DEFAULT_TIMEOUT,
+            error_threshold: 0.5,
+            error_window: Duration::from_secs(60),
+            error_min_requests: 20,
+        }
+    }
+
+    /// Signs every delivery with `secret`.
+    pub fn secret(mut self, secret: impl Into<Vec<u8>>) -> Self {
+        self.secret = Some(secret.into());
+        self
+    }
+
+    /// Retries a failed delivery up to `retries` times, waiting `backoff`
+    /// before the first retry and twice as long before each further one
+    /// (at most a minute). Defaults to 5 retries starting at one second.
+    pub fn retries(mut self, retries: u32, backoff: Duration) -> Self {
+        self.retries = retries;
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets the connect, write, and read timeout for one attempt; 10 seconds
+    /// by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Reports [`Event::ErrorRate`] once the share of failed responses in a
+    /// `window` of at least `min_requests` requests reaches `threshold`
+    /// (0.0 to 1.0). Defaults to half of at least 20 requests in a minute.
+    pub fn error_rate(mut self, threshold: f64, window: Duration, min_requests: u64) -> Self {
+        self.error_threshold = threshold;
+        self.error_window = window;
+        self.error_min_requests = min_requests;
+        self
+    }
+
+    /// Starts the delivery thread.
+    pub fn start(self) -> Dispatcher {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue::default()),
+            changed: Condvar::new(),
+        });
+        let worker = Arc::clone(&shared);
+        let settings = self.clone();
+        thread::spawn(move || deliver_forever(&settings, &worker));
+        Dispatcher {
+            settings: self,
+            shared,
+            window: Mutex::new(ErrorWindow::new()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    events: VecDeque<(Event, u64)>,
+    /// Events taken by the worker and not fully delivered yet.
+    in_flight: usize,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    changed: Condvar,
+}
+
+/// Counts of the current error-rate window.
+struct ErrorWindow {
+    started: Instant,
+    requests: u64,
+    errors: u64,
+    alerted: bool,
+}
+
+impl ErrorWindow {
+    fn new() -> Self {
+        ErrorWindow {
+            started: Instant::now(),
+            requests: 0,
+            errors: 0,
+            alerted: false,
+        }
+    }
+}
+
+/// Queues events for delivery by the background thread.
+pub struct Dispatcher {
+    settings: Webhooks,
+    shared: Arc<Shared>,
+    window: Mutex<ErrorWindow>,
+}
+
+impl Dispatcher {
+    /// Queues `event`; returns at once.
+    pub fn notify(&self, event: Event) {
+        let mut queue = self
+            .shared
+            .queue
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        queue.events.push_back((event, unix_timestamp()));
+        self.shared.changed.notify_all();
+    }
+
+    /// Waits up to `timeout` for every queued event to be delivered or given
+    /// up on. Returns whether the queue drained in time.
+    pub fn flush(&self, timeout: Duration) -> bool {
+        let queue = self
+            .shared
+            .queue
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let (queue, _) = self
+            .shared
+            .changed
+            .wait_timeout_while(queue, timeout, |queue| {
+                !queue.events.is_empty() || queue.in_flight > 0
+            })
+            .unwrap_or_else(PoisonError::into_inner);
+        queue.events.is_empty() && queue.in_flight == 0
+    }
+
+    /// Records the status of one response (a 5xx for a connection that got
+    /// none), and reports [`Event::ErrorRate`] when a window closes with the
+    /// threshold newly crossed.
+    pub fn record_status(&self, status: u16) {
+        let settings = &self.settings;
+        let mut window = self.window.lock().unwrap_or_else(PoisonError::into_inner);
+        if window.started.elapsed() >= settings.error_window {
+            let rate = match window.requests {
+                0 => 0.0,
+                requests => window.errors as f64 / requests as f64,
+            };
+            let crossed =
+                window.requests >= settings.error_min_requests && rate >= settings.error_threshold;
+            if crossed && !window.alerted {
+                self.notify(Event::ErrorRate {
+                    rate,
+                    threshold: settings.error_threshold,
+                    window_secs: settings.error_window.as_secs(),
+                    requests: window.requests,
+                });
+            }
+            *window = ErrorWindow {
+                alerted: crossed,
+                ..ErrorWindow::new()
+            };
+        }
+        window.requests += 1;
+        if status >= 500 {
+            window.errors += 1;
+        }
+    }
+}
+
+fn deliver_forever(settings: &Webhooks, shared: &Shared) {
+    let client = Client::new().timeout(settings.timeout);
+    loop {
+        let (event, time) = {
+            let queue = shared.queue.lock().unwrap_or_else(PoisonError::into_inner);
+            let mut queue = shared
+                .changed
+                .wait_while(queue, |queue| queue.events.is_empty())
+                .unwrap_or_else(PoisonError::into_inner);
+            queue.in_flight += 1;
+            queue.events.pop_front().expect("queue is not empty")
+        };
+        let body = event.to_json(time);
+        for url in &settings.urls {
+            deliver(&client, settings, url, event.name(), &body);
+        }
+        let mut queue = shared.queue.lock().unwrap_or_else(PoisonError::into_inner);
+        queue.in_flight -= 1;
+        shared.changed.notify_all();
+    }
+}
+
+/// Delivers one event to one URL, retrying with backoff.
+fn deliver(client: &Client, settings: &Webhooks, url: &str, event: &str, body: &str) {
+    let mut backoff = settings.backoff;
+    for attempt in 0..=settings.retries {
+        if attempt > 0 {
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+        match post(client, settings, url, event, body) {
+            Ok(status) if (200..300).contains(&status) => return,
+            Ok(status) => report(url, &format!("answered {}", status)),
+            Err(e) => report(url, &e.to_string()),
+        }
+    }
+    report(url, &format!("gave up on {} event", event));
+}
+
+fn report(url: &str, message: &str) {
+    crate::console::print_table(&[("Webhook", format!("✗ {}: {}", url, message))]);
+}
+
+/// POSTs `body` to `url` and returns the response status.
+fn post(
+    client: &Client,
+    settings: &Webhooks,
+    url: &str,
+    event: &str,
+    body: &str,
+) -> Result<u16, ClientError> {
+    let signature = settings
+        .secret
+        .as_ref()
+        .map(|secret| signature(secret, body.as_bytes()));
+    let mut headers = vec![
+        ("Content-Type", "application/json"),
+        ("X-Webhook-Event", event),
+    ];
+    if let Some(signature) = &signature {
+        headers.push(("X-Webhook-Signature", signature));
+    }
+    client
+        .post(url, &headers, body.as_bytes())
+        .map(|response| response.status)
+}
+
+static DISPATCHER: OnceLock<Dispatcher> = OnceLock::new();
+
+/// Makes `dispatcher` the one the server reports to. Only the first call
+/// takes effect; it returns `false` for later ones.
+pub fn install(dispatcher: Dispatcher) -> bool {
+    DISPATCHER.set(dispatcher).is_ok()
+}
+
+/// The installed dispatcher, if any.
+pub fn installed() -> Option<&'static Dispatcher> {
+    DISPATCHER.get()
+}
+
+/// Queues `event` on the installed dispatcher; without one, does nothing.
+pub fn notify(event: Event) {
+    if let Some(dispatcher) = installed() {
+        dispatcher.notify(event);
+    }
+}
+
+/// Records a response status with the installed dispatcher, if any.
+pub(crate) fn record_status(status: u16) {
+    if let Some(dispatcher) = installed() {
+        dispatcher.record_status(status);
+    }
+}
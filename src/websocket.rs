@@ -0,0 +1,530 @@
+//! WebSocket support (RFC 6455): the opening handshake, the frame codec, and
+//! the per-connection session handed to WebSocket handlers.
+//!
+//! Handlers are registered with [`Router::websocket`](crate::Router::websocket)
+//! and run on the connection after the `101 Switching Protocols` response.
+//! [`echo`] and [`Broadcast`] are ready-made handlers for the two classic
+//! demos.
+
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use sha1::{Digest, Sha1};
+
+use crate::http::{Request, Response};
+use crate::pages;
+
+/// Appended to the client's key to compute `Sec-WebSocket-Accept`.
+pub const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Largest message, after reassembling fragments, a session accepts.
+pub const MAX_MESSAGE_SIZE: usize = 1 << 20;
+
+/// A WebSocket handler: runs for the lifetime of one upgraded connection.
+pub type WebSocketHandler = Box<dyn Fn(&mut WebSocket<'_>) + Send + Sync>;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+/// Close status codes (RFC 6455 section 7.4.1).
+pub mod close_code {
+    pub const NORMAL: u16 = 1000;
+    pub const PROTOCOL_ERROR: u16 = 1002;
+    pub const INVALID_DATA: u16 = 1007;
+    pub const MESSAGE_TOO_BIG: u16 = 1009;
+    pub const INTERNAL_ERROR: u16 = 1011;
+}
+
+/// A message received from or sent to the peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    /// Sent to check the peer is alive; incoming pings are answered
+    /// automatically and never returned by [`WebSocket::recv`].
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+}
+
+impl Message {
+    fn opcode(&self) -> u8 {
+        match self {
+            Message::Text(_) => OP_TEXT,
+            Message::Binary(_) => OP_BINARY,
+            Message::Ping(_) => OP_PING,
+            Message::Pong(_) => OP_PONG,
+        }
+    }
+
+    fn payload(&self) -> &[u8] {
+        match self {
+            Message::Text(text) => text.as_bytes(),
+            Message::Binary(data) | Message::Ping(data) | Message::Pong(data) => data,
+        }
+    }
+}
+
+/// `Sec-WebSocket-Accept` value for the client's `Sec-WebSocket-Key`.
+pub fn accept_key(key: &str) -> String {
+    let mut sha1 = Sha1::new();
+    sha1.update(key.as_bytes());
+    sha1.update(GUID.as_bytes());
+    BASE64.encode(sha1.finalize())
+}
+
+/// Validates an opening handshake and returns the `101 Switching Protocols`
+/// response to send, or the error response if `request` is not a valid
+/// WebSocket upgrade.
+pub fn handshake(request: &Request) -> Result<Response, Response> {
+    if request.method != "GET" {
+        return Err(pages::error(
+            400,
+            "Bad Request",
+            "WebSocket upgrades must use GET.",
+        ));
+    }
+    if !has_token(request, "Upgrade", "websocket") || !has_token(request, "Connection", "upgrade") {
+        return Err(pages::error(
+            426,
+            "Upgrade Required",
+            "This endpoint only speaks WebSocket.",
+        )
+        .header("Upgrade", "websocket")
+        .header("Connection", "Upgrade"));
+    }
+    if request.header("Sec-WebSocket-Version") != Some("13") {
+        return Err(pages::error(
+            426,
+            "Upgrade Required",
+            "Only WebSocket version 13 is supported.",
+        )
+        .header("Sec-WebSocket-Version", "13"));
+    }
+    let key = request.header("Sec-WebSocket-Key").unwrap_or("");
+    if BASE64.decode(key).map_or(true, |nonce| nonce.len() != 16) {
+        return Err(pages::error(
+            400,
+            "Bad Request",
+            "Invalid Sec-WebSocket-Key.",
+        ));
+    }
+    Ok(Response::new(101)
+        .header("Upgrade", "websocket")
+        .header("Connection", "Upgrade")
+        .header("Sec-WebSocket-Accept", accept_key(key)))
+}
+
+/// Whether any `name` header lists `token` in its comma-separated value.
+fn has_token(request: &Request, name: &str, token: &str) -> bool {
+    request
+        .header_all(name)
+        .flat_map(|value| value.split(','))
+        .any(|item| item.trim().eq_ignore_ascii_case(token))
+}
+
+/// A stream the session can both read and write.
+trait Stream: Read + Write {}
+
+impl<T: Read + Write + ?Sized> Stream for T {}
+
+/// A write handle to a session that can be used from other threads, e.g. to
+/// push broadcast messages while the session's own thread waits in
+/// [`WebSocket::recv`].
+#[derive(Clone)]
+pub struct Sender {
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl Sender {
+    fn new(writer: Box<dyn Write + Send>) -> Self {
+        Sender {
+            writer: Arc::new(Mutex::new(writer)),
+        }
+    }
+
+    /// Sends `message` as a single frame.
+    pub fn send(&self, message: &Message) -> io::Result<()> {
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        write_frame(&mut *writer, message.opcode(), message.payload())
+    }
+}
+
+/// Why reading a frame failed.
+enum Failure {
+    Io(io::Error),
+    /// The peer violated the protocol; close with this status code.
+    Close(u16),
+}
+
+impl From<io::Error> for Failure {
+    fn from(e: io::Error) -> Self {
+        Failure::Io(e)
+    }
+}
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+/// The server side of an upgraded connection.
+pub struct WebSocket<'a> {
+    io: &'a mut dyn Stream,
+    /// Shared write handle when the transport supports one; frames then go
+    /// through it so they never interleave with [`Sender`] writes.
+    writer: Option<Sender>,
+    /// Bytes read from the peer but not yet consumed as frames.
+    buffer: Vec<u8>,
+    /// Opcode and data of a fragmented message still being received.
+    fragments: Option<(u8, Vec<u8>)>,
+    max_message_size: usize,
+    closed: bool,
+}
+
+impl<'a> WebSocket<'a> {
+    /// A session on `io`, which has already completed the handshake.
+    pub fn new<S: Read + Write>(io: &'a mut S) -> Self {
+        WebSocket {
+            io,
+            writer: None,
+            buffer: Vec::new(),
+            fragments: None,
+            max_message_size: MAX_MESSAGE_SIZE,
+            closed: false,
+        }
+    }
+
+    /// Lets the session hand out [`Sender`]s writing through `writer`, a
+    /// second handle to the same connection (e.g. `TcpStream::try_clone`).
+    pub(crate) fn with_writer(mut self, writer: Option<Box<dyn Write + Send>>) -> Self {
+        self.writer = writer.map(Sender::new);
+        self
+    }
+
+    /// Bytes the client sent after its handshake, read along with it.
+    pub(crate) fn with_buffered(mut self, bytes: &[u8]) -> Self {
+        self.buffer.extend_from_slice(bytes);
+        self
+    }
+
+    /// Changes the largest message [`recv`](Self::recv) accepts; larger ones
+    /// close the connection with 1009 (message too big).
+    pub fn set_max_message_size(&mut self, size: usize) {
+        self.max_message_size = size;
+    }
+
+    /// A handle for sending from other threads, or `None` if the transport
+    /// cannot be shared (custom transports passed to
+    /// [`serve_connection`](crate::serve_connection)).
+    pub fn sender(&self) -> Option<Sender> {
+        self.writer.clone()
+    }
+
+    /// Whether the closing handshake has happened.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Sends `message` as a single frame.
+    pub fn send(&mut self, message: &Message) -> io::Result<()> {
+        self.write_frame(message.opcode(), message.payload())
+    }
+
+    /// Waits for the next text, binary, or pong message.
+    ///
+    /// Pings are answered as they arrive. Returns `Ok(None)` once the peer
+    /// closed the connection or broke the protocol, in which case the
+    /// matching close frame has already been sent.
+    pub fn recv(&mut self) -> io::Result<Option<Message>> {
+        while !self.closed {
+            let frame = match self.read_frame() {
+                Ok(frame) => frame,
+                Err(Failure::Close(code)) => {
+                    self.close(code, "")?;
+                    return Ok(None);
+                }
+                Err(Failure::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    self.closed = true;
+                    return Ok(None);
+                }
+                Err(Failure::Io(e)) => return Err(e),
+            };
+            match self.accept_frame(frame) {
+                Ok(Some(message)) => return Ok(Some(message)),
+                Ok(None) => {}
+                Err(Failure::Close(code)) => {
+                    self.close(code, "")?;
+                    return Ok(None);
+                }
+                Err(Failure::Io(e)) => return Err(e),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Sends a close frame with `code` and `reason`. Does nothing if the
+    /// session is already closed.
+    pub fn close(&mut self, code: u16, reason: &str) -> io::Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        self.closed = true;
+        let mut payload = code.to_be_bytes().to_vec();
+        payload.extend_from_slice(reason.as_bytes());
+        self.write_frame(OP_CLOSE, &payload)
+    }
+
+    /// Handles one frame; returns a message once one is complete.
+    fn accept_frame(&mut self, frame: Frame) -> Result<Option<Message>, Failure> {
+        match frame.opcode {
+            OP_PING => {
+                self.write_frame(OP_PONG, &frame.payload)?;
+                Ok(None)
+            }
+            OP_PONG => Ok(Some(Message::Pong(frame.payload))),
+            OP_CLOSE => {
+                if frame.payload.len() == 1 {
+                    return Err(Failure::Close(close_code::PROTOCOL_ERROR));
+                }
+                // Echo the peer's status code, as the closing handshake asks.
+                self.closed = true;
+                let code = frame.payload.get(..2).unwrap_or_default().to_vec();
+                self.write_frame(OP_CLOSE, &code)?;
+                Ok(None)
+            }
+            OP_CONTINUATION => {
+                let (opcode, mut data) = self
+                    .fragments
+                    .take()
+                    .ok_or(Failure::Close(close_code::PROTOCOL_ERROR))?;
+                data.extend_from_slice(&frame.payload);
+                if data.len() > self.max_message_size {
+                    return Err(Failure::Close(close_code::MESSAGE_TOO_BIG));
+                }
+                if frame.fin {
+                    message(opcode, data).map(Some)
+                } else {
+                    self.fragments = Some((opcode, data));
+                    Ok(None)
+                }
+            }
+            opcode => {
+                if self.fragments.is_some() {
+                    return Err(Failure::Close(close_code::PROTOCOL_ERROR));
+                }
+                if frame.fin {
+                    message(opcode, frame.payload).map(Some)
+                } else {
+                    self.fragments = Some((opcode, frame.payload));
+                    Ok(None)
+                }
+            }
+        }
+    }
+
+    /// Reads until `buffer` holds at least `len` bytes.
+    fn fill(&mut self, len: usize) -> io::Result<()> {
+        let mut chunk = [0; 4096];
+        while self.buffer.len() < len {
+            let n = self.io.read(&mut chunk)?;
+            if n == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            self.buffer.extend_from_slice(&chunk[..n]);
+        }
+        Ok(())
+    }
+
+    fn read_frame(&mut self) -> Result<Frame, Failure> {
+        self.fill(2)?;
+        let (b0, b1) = (self.buffer[0], self.buffer[1]);
+        let fin = b0 & 0x80 != 0;
+        let opcode = b0 & 0x0f;
+        if b0 & 0x70 != 0 || !matches!(opcode, 0x0..=0x2 | 0x8..=0xA) {
+            return Err(Failure::Close(close_code::PROTOCOL_ERROR));
+        }
+        // Clients must mask every frame (section 5.1).
+        if b1 & 0x80 == 0 {
+            return Err(Failure::Close(close_code::PROTOCOL_ERROR));
+        }
+        let (len, mut offset) = match b1 & 0x7f {
+            126 => {
+                self.fill(4)?;
+                (
+                    u64::from(u16::from_be_bytes([self.buffer[2], self.buffer[3]])),
+                    4,
+                )
+            }
+            127 => {
+                self.fill(10)?;
+                let bytes: [u8; 8] = self.buffer[2..10].try_into().expect("8 bytes");
+                (u64::from_be_bytes(bytes), 10)
+            }
+            len => (u64::from(len), 2),
+        };
+        let is_control = opcode & 0x8 != 0;
+        if is_control && (!fin || len > 125) {
+            return Err(Failure::Close(close_code::PROTOCOL_ERROR));
+        }
+        let len = match usize::try_from(len) {
+            Ok(len) if len <= self.max_message_size => len,
+            _ => return Err(Failure::Close(close_code::MESSAGE_TOO_BIG)),
+        };
+
+        self.fill(offset + 4 + len)?;
+        let mask: [u8; 4] = self.buffer[offset..offset + 4].try_into().expect("4 bytes");
+        offset += 4;
+        let mut payload: Vec<u8> = self.buffer.drain(..offset + len).skip(offset).collect();
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+        Ok(Frame {
+            fin,
+            opcode,
+            payload,
+        })
+    }
+
+    fn write_frame(&mut self, opcode: u8, payload: &[u8]) -> io::Result<()> {
+        match &self.writer {
+            Some(sender) => {
+                let mut writer = sender.writer.lock().unwrap_or_else(|e| e.into_inner());
+                write_frame(&mut *writer, opcode, payload)
+            }
+            None => write_frame(&mut self.io, opcode, payload),
+        }
+    }
+}
+
+/// Turns a complete data message into a [`Message`].
+fn message(opcode: u8, data: Vec<u8>) -> Result<Message, Failure> {
+    if opcode == OP_TEXT {
+        String::from_utf8(data)
+            .map(Message::Text)
+            .map_err(|_| Failure::Close(close_code::INVALID_DATA))
+    } else {
+        Ok(Message::Binary(data))
+    }
+}
+
+/// Writes a single unmasked frame, as servers send them.
+fn write_frame<W: Write + ?Sized>(writer: &mut W, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xffff => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame)?;
+    writer.flush()
+}
+
+/// Completes the handshake for `request` on `io` and runs `handler` on the
+/// upgraded connection. Returns the status of the handshake response.
+pub(crate) fn upgrade<S: Read + Write>(
+    io: &mut S,
+    request: &Request,
+    handler: &WebSocketHandler,
+    buffered: &[u8],
+    writer: Option<Box<dyn Write + Send>>,
+) -> io::Result<u16> {
+    let response = match handshake(request) {
+        Ok(response) => response,
+        Err(response) => {
+            response.write_to(io)?;
+            io.flush()?;
+            return Ok(response.status);
+        }
+    };
+    response.write_to(io)?;
+    io.flush()?;
+
+    let mut ws = WebSocket::new(io)
+        .with_writer(writer)
+        .with_buffered(buffered);
+    handler(&mut ws);
+    ws.close(close_code::NORMAL, "")?;
+    Ok(response.status)
+}
+
+/// Sends every text and binary message straight back.
+pub fn echo(ws: &mut WebSocket<'_>) {
+    while let Ok(Some(message)) = ws.recv() {
+        if let Message::Text(_) | Message::Binary(_) = message {
+            if ws.send(&message).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// A group of sessions where every message one member sends is relayed to
+/// all members, the sender included.
+///
+/// Clones share the same group, so one `Broadcast` can be captured by a
+/// handler and used from elsewhere to push server-side announcements.
+#[derive(Clone, Default)]
+pub struct Broadcast {
+    members: Arc<Mutex<Vec<(u64, Sender)>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Broadcast {
+    pub fn new() -> Self {
+        Broadcast::default()
+    }
+
+    /// Number of connected members.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Sends `message` to every member, dropping members that cannot be
+    /// written to any more.
+    pub fn send(&self, message: &Message) {
+        self.lock()
+            .retain(|(_, sender)| sender.send(message).is_ok());
+    }
+
+    /// Joins `ws` to the group and relays its messages until it closes.
+    ///
+    /// Sessions that cannot hand out a [`Sender`] are closed with 1011.
+    pub fn serve(&self, ws: &mut WebSocket<'_>) {
+        let Some(sender) = ws.sender() else {
+            let _ = ws.close(close_code::INTERNAL_ERROR, "transport cannot be shared");
+            return;
+        };
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.lock().push((id, sender));
+
+        while let Ok(Some(message)) = ws.recv() {
+            if let Message::Text(_) | Message::Binary(_) = message {
+                self.send(&message);
+            }
+        }
+        self.lock().retain(|(member, _)| *member != id);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<(u64, Sender)>> {
+        self.members.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
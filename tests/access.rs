@@ -0,0 +1,76 @@
+mod common;
+
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{get, spawn_server};
+use rusty_server::access;
+use rusty_server::http::parse_request;
+use rusty_server::server::Exchange;
+
+fn exchange(head: &[u8], status: u16) -> Exchange {
+    let request = parse_request(head).ok();
+    Exchange {
+        bytes_read: head.len(),
+        status,
+        path: request.as_ref().map_or("/unknown".to_string(), |request| request.path.clone()),
+        request,
+        response_headers: Vec::new(),
+        request_id: String::new(),
+    }
+}
+
+#[test]
+fn lines_are_in_the_combined_log_format() {
+    let exchange = exchange(
+        b"GET /version?x=1 HTTP/1.1\r\nHost: a\r\nReferer: http://a/\r\nUser-Agent: curl/8.5.0\r\n\r\n",
+        200,
+    );
+
+    assert_eq!(
+        access::line("127.0.0.1:52110", &exchange, 85, 1_760_539_382),
+        r#"127.0.0.1 - - [15/Oct/2025:14:43:02 +0000] "GET /version?x=1 HTTP/1.1" 200 85 "http://a/" "curl/8.5.0""#
+    );
+    assert!(access::line("::1:52110", &exchange, 85, 0).starts_with("::1 - - [01/Jan/1970:00:00:00 +0000] "));
+}
+
+#[test]
+fn missing_fields_are_dashes_and_quotes_are_escaped() {
+    let unparsed = exchange(b"BAD\r\n\r\n", 400);
+    assert_eq!(
+        access::line("10.0.0.1:1", &unparsed, 0, 0),
+        r#"10.0.0.1 - - [01/Jan/1970:00:00:00 +0000] "-" 400 - "-" "-""#
+    );
+
+    let quoted = exchange(b"GET / HTTP/1.1\r\nHost: a\r\nUser-Agent: a \"b\" \\c\r\n\r\n", 200);
+    assert!(access::line("10.0.0.1:1", &quoted, 1, 0).ends_with(r#" "-" "a \"b\" \\c""#));
+}
+
+#[test]
+fn requests_are_appended_to_the_access_log() {
+    let path = std::env::temp_dir().join(format!("rusty-server-access-{}.log", std::process::id()));
+    let _ = fs::remove_file(&path);
+    access::open(&path).unwrap();
+    let addr = spawn_server();
+
+    get(addr, "/version", &["User-Agent: test/1.0"]);
+    get(addr, "/missing", &[]);
+
+    // The line is written after the response, so wait for it.
+    let started = Instant::now();
+    let log = loop {
+        let log = fs::read_to_string(&path).unwrap_or_default();
+        if log.lines().filter(|line| line.contains("HTTP/1.1")).count() >= 2 {
+            break log;
+        }
+        assert!(started.elapsed() < Duration::from_secs(5), "log: {}", log);
+        thread::sleep(Duration::from_millis(20));
+    };
+    let version = log.lines().find(|line| line.contains("/version")).unwrap();
+    assert!(version.starts_with("127.0.0.1 - - ["), "{}", version);
+    assert!(version.contains(r#""GET /version HTTP/1.1" 200 "#), "{}", version);
+    assert!(version.ends_with(r#" "-" "test/1.0""#), "{}", version);
+    assert!(log.contains(r#""GET /missing HTTP/1.1" 404 "#), "{}", log);
+    let _ = fs::remove_file(&path);
+}
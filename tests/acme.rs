@@ -0,0 +1,277 @@
+#![cfg(feature = "acme")]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL;
+use base64::Engine;
+use ring::digest::{digest, SHA256};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use rusty_server::acme::Acme;
+use rusty_server::server::{serve_with, Config};
+use rusty_server::Router;
+use serde_json::{json, Value};
+
+/// Accepts any certificate: the fake authority inspects the challenge
+/// certificate rather than trusting it.
+#[derive(Debug)]
+struct AnyCertificate;
+
+impl ServerCertVerifier for AnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _: &CertificateDer<'_>,
+        _: &[CertificateDer<'_>],
+        _: &ServerName<'_>,
+        _: &[u8],
+        _: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _: &[u8],
+        _: &CertificateDer<'_>,
+        _: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _: &[u8],
+        _: &CertificateDer<'_>,
+        _: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        rustls::crypto::ring::default_provider()
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// The certificate `server` presents to a TLS-ALPN-01 validation of
+/// `localhost`.
+fn challenge_certificate(server: SocketAddr) -> Vec<u8> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AnyCertificate))
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"acme-tls/1".to_vec()];
+    let name = ServerName::try_from("localhost").unwrap();
+    let mut connection = rustls::ClientConnection::new(Arc::new(config), name).unwrap();
+    let mut stream = TcpStream::connect(server).unwrap();
+    while connection.is_handshaking() {
+        connection.complete_io(&mut stream).unwrap();
+    }
+    assert_eq!(connection.alpn_protocol(), Some(&b"acme-tls/1"[..]));
+    connection.peer_certificates().unwrap()[0].to_vec()
+}
+
+/// What the fake authority has seen and decided.
+#[derive(Default)]
+struct Authority {
+    url: String,
+    server: Option<SocketAddr>,
+    nonces: u32,
+    jwk: String,
+    validated: bool,
+    issued: String,
+}
+
+impl Authority {
+    /// Checks the JWS `body` sent to `path` and returns its payload, or
+    /// `None` for an empty one.
+    fn open(&mut self, path: &str, body: &[u8]) -> Option<Value> {
+        let jws: Value = serde_json::from_slice(body).unwrap();
+        let decode = |field: &str| BASE64URL.decode(jws[field].as_str().unwrap()).unwrap();
+        let protected: Value = serde_json::from_slice(&decode("protected")).unwrap();
+        assert_eq!(protected["alg"], "ES256");
+        assert_eq!(protected["url"], format!("{}{}", self.url, path));
+        assert_eq!(protected["nonce"], format!("nonce-{}", self.nonces));
+        assert_eq!(decode("signature").len(), 64);
+        if path == "/account" {
+            self.jwk = protected["jwk"].to_string();
+        } else {
+            assert_eq!(protected["kid"], format!("{}/account/1", self.url));
+        }
+        let payload = decode("payload");
+        (!payload.is_empty()).then(|| serde_json::from_slice(&payload).unwrap())
+    }
+
+    /// The status, extra header field, and body answering `method` `path`.
+    fn answer(&mut self, method: &str, path: &str, body: &[u8]) -> (u16, String, String) {
+        let url = self.url.clone();
+        if method == "GET" {
+            assert_eq!(path, "/directory");
+            let directory = json!({
+                "newNonce": format!("{}/nonce", url),
+                "newAccount": format!("{}/account", url),
+                "newOrder": format!("{}/order", url),
+            });
+            return (200, String::new(), directory.to_string());
+        }
+        if method == "HEAD" {
+            return (200, String::new(), String::new());
+        }
+        let payload = self.open(path, body);
+        match path {
+            "/account" => {
+                assert_eq!(payload.unwrap()["termsOfServiceAgreed"], true);
+                (
+                    201,
+                    format!("Location: {}/account/1\r\n", url),
+                    "{}".to_string(),
+                )
+            }
+            "/order" => {
+                let identifiers = &payload.unwrap()["identifiers"];
+                assert_eq!(identifiers, &json!([{"type": "dns", "value": "localhost"}]));
+                let order = json!({
+                    "status": "pending",
+                    "authorizations": [format!("{}/authz/1", url)],
+                    "finalize": format!("{}/finalize/1", url),
+                });
+                (
+                    201,
+                    format!("Location: {}/order/1\r\n", url),
+                    order.to_string(),
+                )
+            }
+            "/authz/1" => {
+                assert!(payload.is_none());
+                let authorization = json!({
+                    "status": if self.validated { "valid" } else { "pending" },
+                    "identifier": {"type": "dns", "value": "localhost"},
+                    "challenges": [
+                        {"type": "http-01", "url": format!("{}/chall/0", url), "token": "web"},
+                        {"type": "tls-alpn-01", "url": format!("{}/chall/1", url), "token": "tok"},
+                    ],
+                });
+                (200, String::new(), authorization.to_string())
+            }
+            "/chall/1" => {
+                assert_eq!(payload.unwrap(), json!({}));
+                let thumbprint = BASE64URL.encode(digest(&SHA256, self.jwk.as_bytes()));
+                let expected = digest(&SHA256, format!("tok.{}", thumbprint).as_bytes());
+                let certificate = challenge_certificate(self.server.unwrap());
+                self.validated = certificate
+                    .windows(32)
+                    .any(|window| window == expected.as_ref());
+                (200, String::new(), "{}".to_string())
+            }
+            "/finalize/1" => {
+                assert!(payload.unwrap()["csr"].is_string());
+                let order = json!({"status": "processing"});
+                (200, String::new(), order.to_string())
+            }
+            "/order/1" => {
+                let order = json!({
+                    "status": "valid",
+                    "certificate": format!("{}/cert/1", url),
+                });
+                (200, String::new(), order.to_string())
+            }
+            "/cert/1" => (200, String::new(), self.issued.clone()),
+            _ => panic!("unexpected request to {}", path),
+        }
+    }
+}
+
+/// A certificate authority answering the requests of one order, and what it
+/// has seen.
+fn fake_authority() -> Arc<Mutex<Authority>> {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut params = rcgen::CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+    params.not_after = rcgen::date_time_ymd(2031, 5, 17);
+    let key = rcgen::KeyPair::generate().unwrap();
+    let authority = Arc::new(Mutex::new(Authority {
+        url: format!("http://{}", listener.local_addr().unwrap()),
+        issued: params.self_signed(&key).unwrap().pem(),
+        ..Authority::default()
+    }));
+    let shared = Arc::clone(&authority);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let authority = Arc::clone(&shared);
+            let mut reader = BufReader::new(stream.unwrap());
+            thread::spawn(move || loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                    return;
+                }
+                let mut parts = line.split(' ');
+                let (method, path) = (parts.next().unwrap(), parts.next().unwrap());
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    match line.trim_end().split_once(": ") {
+                        Some(("Content-Length", value)) => length = value.parse().unwrap(),
+                        Some(_) => {}
+                        None => break,
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+
+                let mut authority = authority.lock().unwrap();
+                let (status, header, answer) = authority.answer(method, path, &body);
+                authority.nonces += 1;
+                let length = if method == "HEAD" { 0 } else { answer.len() };
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 {} X\r\nReplay-Nonce: nonce-{}\r\n{}Content-Length: {}\r\n\r\n{}",
+                    status,
+                    authority.nonces,
+                    header,
+                    length,
+                    answer
+                )
+                .unwrap();
+            });
+        }
+    });
+    authority
+}
+
+#[test]
+fn certificates_are_obtained_over_tls_alpn_and_stored() {
+    let authority = fake_authority();
+    let dir = std::env::temp_dir().join(format!("rusty-server-acme-{}", std::process::id()));
+    let directory = format!("{}/directory", authority.lock().unwrap().url);
+    let acme = Arc::new(Acme::new(["localhost"], &dir).directory(&directory));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    authority.lock().unwrap().server = Some(listener.local_addr().unwrap());
+    let mut config = Config::default();
+    config.tls = Some(acme.acceptor());
+    thread::spawn(move || serve_with(listener, Router::default(), config));
+    assert!(acme.renewal_due());
+
+    let expires = acme.obtain().unwrap();
+
+    assert!(authority.lock().unwrap().validated);
+    // 2031-05-17T00:00:00Z
+    assert_eq!(expires, 1_936_742_400);
+    assert_eq!(acme.expires(), Some(expires));
+    assert!(!acme.renewal_due());
+    let stored = std::fs::read_to_string(dir.join("localhost.crt")).unwrap();
+    assert_eq!(stored, authority.lock().unwrap().issued);
+    assert!(dir.join("localhost.key").exists());
+    assert!(dir.join("account.key").exists());
+
+    let reloaded = Acme::new(["localhost"], &dir).directory(&directory);
+    assert_eq!(reloaded.load().unwrap(), Some(expires));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
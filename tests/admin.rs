@@ -0,0 +1,106 @@
+mod common;
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::thread;
+use std::time::Duration;
+
+use common::{connect, get};
+use rusty_server::{serve_with, Router};
+use serde_json::Value;
+
+fn spawn_admin_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut router = Router::default();
+    router.admin();
+    thread::spawn(move || serve_with(listener, router, Default::default()));
+    addr
+}
+
+fn get_json(addr: SocketAddr, path: &str) -> Value {
+    let response = get(addr, path, &[]);
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header("Content-Type"), Some("application/json"));
+    serde_json::from_str(&response.body).unwrap()
+}
+
+#[test]
+fn routes_are_listed_with_their_kind() {
+    let addr = spawn_admin_server();
+
+    let routes = get_json(addr, "/admin/routes");
+    let routes = routes.as_array().unwrap();
+    assert!(
+        routes.contains(&serde_json::json!({"method": "GET", "path": "/version", "kind": "route"}))
+    );
+    assert!(
+        routes.contains(&serde_json::json!({"method": "GET", "path": "/events", "kind": "events"}))
+    );
+    assert!(routes.iter().any(|route| route["path"] == "/admin/stats"));
+}
+
+#[test]
+fn open_connections_are_listed_until_they_close() {
+    let addr = spawn_admin_server();
+    let idle = connect(addr);
+    let peer = idle.local_addr().unwrap().to_string();
+    let listed = |addr| {
+        get_json(addr, "/admin/connections")
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|connection| connection["peer"] == peer.as_str())
+    };
+
+    // The server accepts the connection on its own thread.
+    let mut tries = 0;
+    while !listed(addr) {
+        tries += 1;
+        assert!(tries < 50, "idle connection not listed");
+        thread::sleep(Duration::from_millis(20));
+    }
+    drop(idle);
+    while listed(addr) {
+        tries += 1;
+        assert!(tries < 100, "closed connection still listed");
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    let stats = get_json(addr, "/admin/stats");
+    assert!(stats["connections_total"].as_u64().unwrap() >= 2);
+    assert!(stats["connections_active"].as_u64().unwrap() >= 1);
+    assert!(stats["connections_idle"].is_u64());
+}
+
+#[test]
+fn kept_alive_connections_are_listed_as_idle() {
+    let addr = spawn_admin_server();
+    let mut kept = connect(addr);
+    let peer = kept.local_addr().unwrap().to_string();
+    kept.write_all(b"GET /version HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+    let mut reader = BufReader::new(&kept);
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    assert!(line.starts_with("HTTP/1.1 200"), "{}", line);
+
+    // The connection is marked idle just after its response is sent.
+    let mut tries = 0;
+    let connection = loop {
+        let connections = get_json(addr, "/admin/connections");
+        let connection = connections
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|connection| connection["peer"] == peer.as_str())
+            .cloned();
+        if let Some(connection) = connection.filter(|connection| connection["requests"] == 1) {
+            break connection;
+        }
+        tries += 1;
+        assert!(tries < 50, "kept-alive connection not listed as idle");
+        thread::sleep(Duration::from_millis(20));
+    };
+    assert!(connection["idle_secs"].is_u64(), "{}", connection);
+    assert!(get_json(addr, "/admin/stats")["connections_idle"].as_u64().unwrap() >= 1);
+}
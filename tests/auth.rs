@@ -0,0 +1,150 @@
+#![cfg(feature = "auth")]
+
+use rusty_server::auth::{BasicAuth, BearerAuth, HtpasswdError};
+use rusty_server::http::parse_request;
+use rusty_server::{Request, Router};
+
+/// `ops:secret`, Base64-encoded.
+const OPS: &str = "b3BzOnNlY3JldA==";
+/// `ops:wrong`.
+const OPS_WRONG: &str = "b3BzOndyb25n";
+/// `scraper:hunter2`.
+const SCRAPER: &str = "c2NyYXBlcjpodW50ZXIy";
+
+fn request(path: &str, credentials: Option<&str>) -> Request {
+    let authorization = credentials
+        .map(|credentials| format!("Authorization: Basic {}\r\n", credentials))
+        .unwrap_or_default();
+    let head = format!(
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nAccept: text/plain\r\n{}\r\n",
+        path, authorization
+    );
+    parse_request(head.as_bytes()).unwrap()
+}
+
+fn router(auth: BasicAuth) -> Router {
+    let mut router = Router::default();
+    router.middleware(auth);
+    router
+}
+
+#[test]
+fn protected_paths_ask_for_credentials() {
+    let router = router(BasicAuth::new("status").protect("/metrics").user("ops", "secret"));
+
+    let response = router.handle(&request("/metrics", None));
+    assert_eq!(response.status, 401);
+    assert_eq!(
+        response.header_value("WWW-Authenticate"),
+        Some("Basic realm=\"status\", charset=\"UTF-8\"")
+    );
+    assert_eq!(router.handle(&request("/metrics", Some(OPS_WRONG))).status, 401);
+    assert_eq!(router.handle(&request("/metrics", Some(SCRAPER))).status, 401);
+
+    let response = router.handle(&request("/metrics", Some(OPS)));
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header_value("WWW-Authenticate"), None);
+
+    // Other paths need nothing.
+    assert_eq!(router.handle(&request("/healthz", None)).status, 200);
+}
+
+#[test]
+fn prefixes_cover_the_paths_below_them_only() {
+    let auth = BasicAuth::new("admin").protect("/admin/").user("ops", "secret");
+    assert!(auth.protects(&request("/admin", None)));
+    assert!(auth.protects(&request("/admin/stats", None)));
+    assert!(!auth.protects(&request("/administrator", None)));
+    assert!(!auth.protects(&request("/version", None)));
+}
+
+#[test]
+fn htpasswd_files_hold_plain_and_sha_passwords() {
+    let auth = BasicAuth::new("status")
+        .protect("/metrics")
+        .htpasswd("# scrapers\nops:secret\n\nscraper:{SHA}87u9ZqY9S/F0eUBXjsPQEDUw4h0=\n")
+        .unwrap();
+    assert!(auth.authorized(&request("/metrics", Some(OPS))));
+    assert!(auth.authorized(&request("/metrics", Some(SCRAPER))));
+    assert!(!auth.authorized(&request("/metrics", Some(OPS_WRONG))));
+
+    let dir = std::env::temp_dir().join(format!("rusty-server-htpasswd-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(".htpasswd");
+    std::fs::write(&path, "ops:secret\n").unwrap();
+    let auth = BasicAuth::new("status").htpasswd_file(&path).unwrap();
+    assert!(auth.authorized(&request("/metrics", Some(OPS))));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unusable_htpasswd_lines_are_errors() {
+    let auth = BasicAuth::new("status");
+    assert!(matches!(
+        auth.clone().htpasswd("ops:secret\nno password here\n"),
+        Err(HtpasswdError::Malformed(2))
+    ));
+    let bcrypt = "ops:$2y$05$c4WoMPo3SXsafkva.HHa6uXQZWr7oboPiC2bT/r7q1BB8I2s0BRqC";
+    match auth.clone().htpasswd(bcrypt) {
+        Err(HtpasswdError::UnsupportedHash(user)) => assert_eq!(user, "ops"),
+        other => panic!("expected an unsupported hash, got {:?}", other.map(|_| ())),
+    }
+    assert!(matches!(
+        auth.htpasswd_file("/nonexistent/.htpasswd"),
+        Err(HtpasswdError::Io(_))
+    ));
+}
+
+#[test]
+fn other_schemes_and_garbage_are_refused() {
+    let auth = BasicAuth::new("status").protect("/").user("ops", "secret");
+    let head = "GET / HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer b3BzOnNlY3JldA==\r\n\r\n";
+    assert!(!auth.authorized(&parse_request(head.as_bytes()).unwrap()));
+    assert!(!auth.authorized(&request("/", Some("not base64!"))));
+    assert!(!auth.authorized(&request("/", None)));
+    // The scheme is case-insensitive.
+    let head = "GET / HTTP/1.1\r\nHost: localhost\r\nAuthorization: basic b3BzOnNlY3JldA==\r\n\r\n";
+    assert!(auth.authorized(&parse_request(head.as_bytes()).unwrap()));
+}
+
+fn bearer(path: &str, authorization: &str) -> Request {
+    let head = format!(
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nAccept: text/plain\r\nAuthorization: {}\r\n\r\n",
+        path, authorization
+    );
+    parse_request(head.as_bytes()).unwrap()
+}
+
+#[test]
+fn bearer_tokens_let_scrapers_in() {
+    let auth = BearerAuth::new()
+        .protect("/metrics")
+        .token("prometheus-token")
+        .token(" grafana-token ")
+        .token("");
+    let mut router = Router::default();
+    router.middleware(auth);
+
+    let response = router.handle(&request("/metrics", None));
+    assert_eq!(response.status, 401);
+    assert_eq!(response.header_value("WWW-Authenticate"), Some("Bearer"));
+    let response = router.handle(&bearer("/metrics", "Bearer guessed-token"));
+    assert_eq!(response.status, 401);
+    assert_eq!(response.header_value("WWW-Authenticate"), Some("Bearer error=\"invalid_token\""));
+    assert_eq!(router.handle(&bearer("/metrics", "Bearer ")).status, 401);
+    assert_eq!(router.handle(&bearer("/metrics", "Basic b3BzOnNlY3JldA==")).status, 401);
+
+    assert_eq!(router.handle(&bearer("/metrics", "Bearer prometheus-token")).status, 200);
+    assert_eq!(router.handle(&bearer("/metrics", "bearer grafana-token")).status, 200);
+    assert_eq!(router.handle(&request("/healthz", None)).status, 200);
+}
+
+#[test]
+fn each_scheme_protects_its_own_prefixes() {
+    let mut router = Router::default();
+    router.middleware(BasicAuth::new("admin").protect("/admin").user("ops", "secret"));
+    router.middleware(BearerAuth::new().protect("/metrics").token("scraper"));
+    assert_eq!(router.handle(&bearer("/metrics", "Bearer scraper")).status, 200);
+    assert_eq!(router.handle(&bearer("/admin", "Bearer scraper")).status, 401);
+    assert_eq!(router.handle(&request("/metrics", Some(OPS))).status, 401);
+}
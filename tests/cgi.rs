@@ -0,0 +1,130 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use common::MemoryStream;
+use rusty_server::cgi::Cgi;
+use rusty_server::{serve_connection, Config, Router};
+
+/// Test scripts as `(name, source, executable)`.
+const SCRIPTS: &[(&str, &str, bool)] = &[
+    (
+        "echo.sh",
+        "#!/bin/sh\n\
+         printf 'Status: 201 Created\\r\\nContent-Type: text/plain\\r\\n\\r\\n'\n\
+         echo \"$REQUEST_METHOD $SCRIPT_NAME $PATH_INFO $QUERY_STRING $CONTENT_LENGTH $HTTP_X_CUSTOM\"\n\
+         cat\n",
+        true,
+    ),
+    ("spin.sh", "#!/bin/sh\nwhile :; do :; done\n", true),
+    ("quiet.sh", "#!/bin/sh\nexit 1\n", true),
+    ("data.txt", "not a script", false),
+];
+
+/// The cgi-bin directory holding [`SCRIPTS`]. It is written once, before any
+/// test runs a script: a file still open for writing while another thread
+/// forks could not be executed ("text file busy").
+fn cgi_bin() -> &'static Path {
+    static DIR: OnceLock<PathBuf> = OnceLock::new();
+    DIR.get_or_init(|| {
+        let dir = std::env::temp_dir().join(format!("rusty-server-cgi-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (name, source, executable) in SCRIPTS {
+            let path = dir.join(name);
+            fs::write(&path, source).unwrap();
+            let mode = if *executable { 0o755 } else { 0o644 };
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+        }
+        dir
+    })
+}
+
+fn serve(cgi: Cgi, request: &[u8]) -> (u16, common::Response) {
+    let mut router = Router::new();
+    router.stream("/cgi-bin", move |request, connection| {
+        cgi.serve(request, connection)
+    });
+    let mut stream = MemoryStream::new(request);
+    let exchange = serve_connection(&mut stream, &router, &Config::default()).unwrap();
+    (exchange.status, stream.response())
+}
+
+#[test]
+fn runs_script_with_cgi_environment_and_body() {
+    let (status, response) = serve(
+        Cgi::new("/cgi-bin", cgi_bin()),
+        b"POST /cgi-bin/echo.sh/extra/path?q=1 HTTP/1.1\r\nHost: a\r\n\
+          X-Custom: yes\r\nContent-Length: 5\r\n\r\nhello",
+    );
+
+    assert_eq!(status, 201);
+    assert_eq!(response.header("Content-Type"), Some("text/plain"));
+    assert_eq!(
+        response.body,
+        "POST /cgi-bin/echo.sh /extra/path q=1 5 yes\nhello"
+    );
+}
+
+#[test]
+fn runaway_script_is_killed_with_504() {
+    let (status, _) = serve(
+        Cgi::new("/cgi-bin", cgi_bin()).timeout(Duration::from_millis(200)),
+        b"GET /cgi-bin/spin.sh HTTP/1.1\r\nHost: a\r\n\r\n",
+    );
+
+    assert_eq!(status, 504);
+}
+
+#[test]
+fn script_without_response_head_gets_500() {
+    let (status, _) = serve(
+        Cgi::new("/cgi-bin", cgi_bin()),
+        b"GET /cgi-bin/quiet.sh HTTP/1.1\r\nHost: a\r\n\r\n",
+    );
+
+    assert_eq!(status, 500);
+}
+
+#[test]
+fn requests_over_the_concurrency_cap_get_503() {
+    let (status, response) = serve(
+        Cgi::new("/cgi-bin", cgi_bin()).max_concurrent(0),
+        b"GET /cgi-bin/quiet.sh HTTP/1.1\r\nHost: a\r\n\r\n",
+    );
+
+    assert_eq!(status, 503);
+    assert_eq!(response.header("Retry-After"), Some("1"));
+}
+
+#[test]
+fn only_executable_files_in_the_directory_run() {
+    let cases: [(&[u8], u16); 3] = [
+        (b"GET /cgi-bin/data.txt HTTP/1.1\r\nHost: a\r\n\r\n", 403),
+        (b"GET /cgi-bin/missing.sh HTTP/1.1\r\nHost: a\r\n\r\n", 404),
+        (b"GET /cgi-bin/ HTTP/1.1\r\nHost: a\r\n\r\n", 404),
+    ];
+
+    for (request, expected) in cases {
+        let (status, _) = serve(Cgi::new("/cgi-bin", cgi_bin()), request);
+        assert_eq!(status, expected, "{}", String::from_utf8_lossy(request));
+    }
+}
+
+#[test]
+fn sample_script_runs_from_a_relative_directory() {
+    let (status, response) = serve(
+        Cgi::new("/cgi-bin", "cgi-bin"),
+        b"GET /cgi-bin/hello.sh/x?q=1 HTTP/1.1\r\nHost: a\r\nUser-Agent: tester\r\n\r\n",
+    );
+
+    assert_eq!(status, 200);
+    assert!(response.body.contains("GET /cgi-bin/hello.sh/x?q=1"));
+    assert!(response.body.contains("tester"));
+}
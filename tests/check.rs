@@ -0,0 +1,63 @@
+mod common;
+
+use std::net::TcpListener;
+
+use common::spawn_server;
+use rusty_server::check::Check;
+
+#[test]
+fn reachable_endpoints_pass() {
+    let addr = spawn_server();
+
+    let probes = Check::new(format!("http://{}/", addr))
+        .endpoints(["/version", "version?format=json"])
+        .run();
+
+    assert_eq!(probes.len(), 2);
+    assert!(probes.iter().all(|probe| probe.passed()), "{:?}", probes);
+    assert_eq!(probes[0].status, Some(200));
+    assert_eq!(probes[1].endpoint, "version?format=json");
+}
+
+#[test]
+fn unexpected_statuses_fail() {
+    let addr = spawn_server();
+
+    let probes = Check::new(format!("http://{}", addr))
+        .endpoints(["/version", "/missing"])
+        .status(404)
+        .run();
+
+    assert_eq!(probes[0].failure.as_deref(), Some("expected status 404"));
+    assert!(probes[1].passed());
+}
+
+#[test]
+fn content_types_are_compared_without_parameters() {
+    let addr = spawn_server();
+    let url = format!("http://{}", addr);
+    let content_type = Check::new(&url).run()[0].content_type.clone().unwrap();
+    let media_type = content_type.split(';').next().unwrap().to_uppercase();
+
+    let matching = Check::new(&url).content_type(format!("{}; charset=utf-8", media_type));
+    let other = Check::new(&url).content_type("application/x-other");
+
+    assert!(matching.run()[0].passed());
+    assert_eq!(
+        other.run()[0].failure.as_deref(),
+        Some("expected application/x-other")
+    );
+}
+
+#[test]
+fn unreachable_servers_fail() {
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let probes = Check::new(format!("http://{}", addr)).run();
+
+    assert!(!probes[0].passed());
+    assert_eq!(probes[0].status, None);
+}
@@ -0,0 +1,276 @@
+mod common;
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use common::spawn_server;
+use rusty_server::client::{Client, ClientError};
+
+/// Reads one request head and its `Content-Length` body; `None` once the
+/// client has closed the connection.
+fn read_request<R: BufRead>(reader: &mut R) -> Option<(String, Vec<u8>)> {
+    let mut head = String::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        head.push_str(&line);
+        if line == "\r\n" {
+            break;
+        }
+    }
+    let length = head
+        .lines()
+        .find_map(|line| line.strip_prefix("Content-Length: "))
+        .map_or(0, |length| length.parse().unwrap());
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).unwrap();
+    Some((head, body))
+}
+
+/// A server answering each request with the next of `responses`, closing
+/// the connection after the responses listed in `close_after`. Reports each
+/// request head, along with the number of the connection it came on.
+fn fake_server(
+    responses: &'static [&'static str],
+    close_after: &'static [usize],
+) -> (SocketAddr, Receiver<(usize, String, Vec<u8>)>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (sender, requests) = mpsc::channel();
+    thread::spawn(move || {
+        let mut answered = 0;
+        for (connection, stream) in listener.incoming().enumerate() {
+            let mut reader = BufReader::new(stream.unwrap());
+            while let Some((head, body)) = read_request(&mut reader) {
+                reader
+                    .get_mut()
+                    .write_all(responses[answered].as_bytes())
+                    .unwrap();
+                sender.send((connection, head, body)).unwrap();
+                answered += 1;
+                if answered == responses.len() {
+                    return;
+                }
+                if close_after.contains(&answered) {
+                    break;
+                }
+            }
+        }
+    });
+    (addr, requests)
+}
+
+#[test]
+fn gets_from_the_server() {
+    let addr = spawn_server();
+
+    let response = Client::new()
+        .get(&format!("http://{}/version", addr))
+        .unwrap();
+
+    assert_eq!(response.status, 200);
+    assert!(String::from_utf8(response.body)
+        .unwrap()
+        .contains("Version"));
+}
+
+#[test]
+fn connections_are_reused() {
+    let (addr, requests) = fake_server(
+        &[
+            "HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\none",
+            "HTTP/1.1 201 Created\r\nContent-Length: 3\r\n\r\ntwo",
+        ],
+        &[],
+    );
+    let client = Client::new().user_agent("probe/1");
+
+    let first = client.get(&format!("http://{}/a", addr)).unwrap();
+    let second = client
+        .post(
+            &format!("http://{}/b?x=1", addr),
+            &[("Content-Type", "text/plain")],
+            b"payload",
+        )
+        .unwrap();
+
+    assert_eq!((first.status, first.body), (200, b"one".to_vec()));
+    assert_eq!((second.status, second.body), (201, b"two".to_vec()));
+    let (connection, head, _) = requests.recv().unwrap();
+    assert_eq!(connection, 0);
+    assert!(head.starts_with("GET /a HTTP/1.1\r\n"));
+    assert!(head.contains(&format!("Host: {}\r\n", addr)));
+    assert!(head.contains("User-Agent: probe/1\r\n"));
+    let (connection, head, body) = requests.recv().unwrap();
+    assert_eq!(connection, 0, "second request reuses the connection");
+    assert!(head.starts_with("POST /b?x=1 HTTP/1.1\r\n"));
+    assert!(head.contains("Content-Type: text/plain\r\n"));
+    assert_eq!(body, b"payload");
+}
+
+#[test]
+fn closed_pooled_connections_are_replaced() {
+    let (addr, requests) = fake_server(
+        &[
+            "HTTP/1.1 204 No Content\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok",
+        ],
+        &[1],
+    );
+    let client = Client::new();
+    let url = format!("http://{}/", addr);
+
+    assert_eq!(client.get(&url).unwrap().status, 204);
+    let (connection, _, _) = requests.recv().unwrap();
+    assert_eq!(connection, 0);
+    // Let the server's close reach the client.
+    thread::sleep(Duration::from_millis(50));
+    let response = client.get(&url).unwrap();
+
+    assert_eq!((response.status, response.body), (200, b"ok".to_vec()));
+    let (connection, _, _) = requests.recv().unwrap();
+    assert_eq!(connection, 1);
+}
+
+#[test]
+fn chunked_bodies_are_decoded() {
+    let (addr, _requests) = fake_server(
+        &["HTTP/1.1 100 Continue\r\n\r\n\
+           HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nX-Kind: chunks\r\n\r\n\
+           5;ext=1\r\nhello\r\n7\r\n, world\r\n0\r\nTrailer: yes\r\n\r\n"],
+        &[],
+    );
+
+    let response = Client::new().get(&format!("http://{}/", addr)).unwrap();
+
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, b"hello, world");
+    assert_eq!(response.header_value("X-Kind"), Some("chunks"));
+    assert_eq!(response.header_value("Transfer-Encoding"), None);
+}
+
+#[test]
+fn bodies_without_length_run_to_close() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        read_request(&mut reader).unwrap();
+        reader
+            .get_mut()
+            .write_all(b"HTTP/1.0 200 OK\r\n\r\nuntil the end")
+            .unwrap();
+    });
+
+    let response = Client::new().get(&format!("http://{}/", addr)).unwrap();
+
+    assert_eq!(response.body, b"until the end");
+}
+
+#[test]
+fn silent_servers_time_out() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = Client::new().timeout(Duration::from_millis(100));
+
+    let error = client.get(&format!("http://{}/", addr)).unwrap_err();
+
+    assert!(matches!(error, ClientError::Timeout(_)), "{:?}", error);
+    drop(listener);
+}
+
+#[test]
+fn invalid_requests_are_refused_before_sending() {
+    let client = Client::new();
+
+    assert!(matches!(
+        client.get("ftp://example.com/"),
+        Err(ClientError::UnsupportedScheme(_))
+    ));
+    assert!(matches!(
+        client.get("http://user@example.com/"),
+        Err(ClientError::InvalidUrl(_))
+    ));
+    assert!(matches!(
+        client.get("http://example.com/a b"),
+        Err(ClientError::InvalidUrl(_))
+    ));
+    assert!(matches!(
+        client.post("http://example.com/", &[("Host", "elsewhere")], b""),
+        Err(ClientError::InvalidRequest(_))
+    ));
+    assert!(matches!(
+        client.post("http://example.com/", &[("X-A", "1\r\nX-B: 2")], b""),
+        Err(ClientError::InvalidRequest(_))
+    ));
+}
+
+#[cfg(feature = "tls")]
+mod tls {
+    use std::sync::Arc;
+
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+
+    use super::*;
+
+    /// A TLS server for `localhost` answering one request, and the DER of its
+    /// self-signed certificate.
+    fn tls_server() -> (SocketAddr, Vec<u8>) {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let der = certified.cert.der().to_vec();
+        let key =
+            PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der()));
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = Arc::new(
+            rustls::ServerConfig::builder_with_provider(provider)
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_no_client_auth()
+                .with_single_cert(vec![CertificateDer::from(der.clone())], key)
+                .unwrap(),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let connection = rustls::ServerConnection::new(Arc::clone(&config)).unwrap();
+                let mut tls = rustls::StreamOwned::new(connection, stream.unwrap());
+                let mut reader = BufReader::new(&mut tls);
+                if read_request(&mut reader).is_some() {
+                    tls.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nsecure")
+                        .unwrap();
+                }
+            }
+        });
+        (addr, der)
+    }
+
+    #[test]
+    fn https_with_a_trusted_certificate() {
+        let (addr, der) = tls_server();
+        let client = Client::new().root_certificate(der).unwrap();
+
+        let response = client
+            .get(&format!("https://localhost:{}/", addr.port()))
+            .unwrap();
+
+        assert_eq!(response.body, b"secure");
+    }
+
+    #[test]
+    fn https_with_an_untrusted_certificate_fails() {
+        let (addr, _) = tls_server();
+
+        let error = Client::new()
+            .get(&format!("https://localhost:{}/", addr.port()))
+            .unwrap_err();
+
+        assert!(matches!(error, ClientError::Tls(_)), "{:?}", error);
+    }
+}
@@ -0,0 +1,131 @@
+//! Shared harness for the integration tests: runs the real server on an
+//! ephemeral port and talks raw HTTP/1.1 to it.
+
+#![allow(dead_code)]
+
+use std::io::{self, Cursor, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+/// Upper bound on any single socket operation so a misbehaving server fails
+/// the test instead of hanging the whole suite.
+pub const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Starts the server on `127.0.0.1:0` in a background thread and returns the
+/// address it actually bound to.
+pub fn spawn_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind ephemeral port");
+    let addr = listener.local_addr().expect("local addr");
+    thread::spawn(move || {
+        let _ = rusty_server::serve(listener);
+    });
+    addr
+}
+
+/// In-memory stand-in for a socket: reads come from a fixed request buffer
+/// and everything written is captured for inspection.
+pub struct MemoryStream {
+    input: Cursor<Vec<u8>>,
+    pub output: Vec<u8>,
+}
+
+impl MemoryStream {
+    pub fn new(request: &[u8]) -> Self {
+        MemoryStream {
+            input: Cursor::new(request.to_vec()),
+            output: Vec::new(),
+        }
+    }
+
+    pub fn response(&self) -> Response {
+        parse_response(&self.output)
+    }
+}
+
+impl Read for MemoryStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for MemoryStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Opens a client connection with read and write timeouts applied.
+pub fn connect(addr: SocketAddr) -> TcpStream {
+    let stream = TcpStream::connect_timeout(&addr, IO_TIMEOUT).expect("connect");
+    stream.set_read_timeout(Some(IO_TIMEOUT)).unwrap();
+    stream.set_write_timeout(Some(IO_TIMEOUT)).unwrap();
+    stream
+}
+
+/// A response as seen on the wire, split into its parts.
+#[derive(Debug)]
+pub struct Response {
+    pub status_line: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Response {
+    pub fn status(&self) -> u16 {
+        self.status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse().ok())
+            .expect("status code")
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Sends `raw` as-is and reads until the server closes the connection,
+/// which it does once it has answered, as no further request can follow.
+pub fn send_raw(addr: SocketAddr, raw: &[u8]) -> Response {
+    let mut stream = connect(addr);
+    stream.write_all(raw).expect("write request");
+    stream.shutdown(Shutdown::Write).expect("shut down writes");
+    let mut bytes = Vec::new();
+    stream.read_to_end(&mut bytes).expect("read response");
+    parse_response(&bytes)
+}
+
+/// Sends a bodiless request for `path` with the given extra header lines.
+pub fn get(addr: SocketAddr, path: &str, headers: &[&str]) -> Response {
+    let mut raw = format!("GET {} HTTP/1.1\r\nHost: {}\r\n", path, addr);
+    for header in headers {
+        raw.push_str(header);
+        raw.push_str("\r\n");
+    }
+    raw.push_str("\r\n");
+    send_raw(addr, raw.as_bytes())
+}
+
+pub fn parse_response(bytes: &[u8]) -> Response {
+    let text = String::from_utf8_lossy(bytes);
+    let (head, body) = text.split_once("\r\n\r\n").expect("header terminator");
+    let mut lines = head.split("\r\n");
+    let status_line = lines.next().unwrap_or_default().to_string();
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect();
+    Response {
+        status_line,
+        headers,
+        body: body.to_string(),
+    }
+}
@@ -0,0 +1,134 @@
+#![cfg(feature = "compression")]
+
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+use rusty_server::compress::{negotiate, Compress, Encoding};
+use rusty_server::http::parse_request;
+use rusty_server::{Request, Response, Router};
+
+const PAGE: &str = "<p>The quick brown fox jumps over the lazy dog.</p>\n";
+
+fn request(path: &str, headers: &str) -> Request {
+    let head = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n{}\r\n", path, headers);
+    parse_request(head.as_bytes()).unwrap()
+}
+
+fn router(compress: Compress) -> Router {
+    let mut router = Router::new();
+    router.get("/page", |_| Response::html(200, PAGE.repeat(100)));
+    router.get("/small", |_| Response::html(200, PAGE.repeat(10)));
+    router.get("/tagged", |_| Response::html(200, PAGE.repeat(100)).header("ETag", "\"v1\""));
+    router.get("/image", |_| Response::with_body(200, "image/png", PAGE.repeat(100)));
+    router.middleware(compress);
+    router
+}
+
+fn gunzip(body: &[u8]) -> String {
+    let mut text = String::new();
+    GzDecoder::new(body).read_to_string(&mut text).unwrap();
+    text
+}
+
+#[test]
+fn large_text_bodies_are_gzipped_for_clients_accepting_it() {
+    let router = router(Compress::new());
+
+    let response = router.handle(&request("/page", "Accept-Encoding: deflate, gzip;q=0.8\r\n"));
+    assert_eq!(response.header_value("Content-Encoding"), Some("gzip"));
+    assert_eq!(response.header_value("Vary"), Some("Accept-Encoding"));
+    assert!(response.body.len() < PAGE.len() * 10);
+    assert_eq!(gunzip(&response.body), PAGE.repeat(100));
+
+    // The tag names the compressed bytes, so a client holding them gets a 304.
+    let etag = response.header_value("ETag").unwrap();
+    let conditional = format!("Accept-Encoding: gzip\r\nIf-None-Match: {}\r\n", etag);
+    let again = router.handle(&request("/page", &conditional));
+    assert_eq!(again.status, 304);
+
+    let plain = router.handle(&request("/page", ""));
+    assert_eq!(plain.header_value("Content-Encoding"), None);
+    assert_eq!(plain.header_value("Vary"), Some("Accept-Encoding"));
+    assert_eq!(plain.body, PAGE.repeat(100).as_bytes());
+    assert_ne!(plain.header_value("ETag"), Some(etag));
+}
+
+#[test]
+fn small_and_already_compressed_bodies_are_sent_as_they_are() {
+    let router = router(Compress::new());
+    for path in ["/small", "/image"] {
+        let response = router.handle(&request(path, "Accept-Encoding: gzip\r\n"));
+        assert_eq!(response.header_value("Content-Encoding"), None, "{}", path);
+        assert_eq!(response.header_value("Vary"), None, "{}", path);
+    }
+
+    let router = self::router(Compress::new().min_size(256));
+    let response = router.handle(&request("/small", "Accept-Encoding: gzip\r\n"));
+    assert_eq!(response.header_value("Content-Encoding"), Some("gzip"));
+}
+
+#[test]
+fn error_pages_are_compressed_too() {
+    let router = router(Compress::new().min_size(64));
+    let response = router.handle(&request("/missing", "Accept-Encoding: gzip\r\n"));
+    assert_eq!(response.status, 404);
+    assert_eq!(response.header_value("Content-Encoding"), Some("gzip"));
+    assert!(gunzip(&response.body).contains("/missing"));
+}
+
+#[test]
+fn accept_encoding_is_read_with_its_weights() {
+    let gzip = Some(Encoding::Gzip);
+    for (header, chosen) in [
+        ("Accept-Encoding: gzip\r\n", gzip),
+        ("Accept-Encoding: deflate, GZIP\r\n", gzip),
+        ("Accept-Encoding: x-gzip\r\n", gzip),
+        ("Accept-Encoding: *\r\n", gzip),
+        ("Accept-Encoding: gzip;q=0\r\n", None),
+        ("Accept-Encoding: *, gzip;q=0.0\r\n", None),
+        ("Accept-Encoding: identity\r\n", None),
+        ("", None),
+    ] {
+        let request = request("/page", header);
+        assert_eq!(negotiate(&request, &[Encoding::Gzip]), chosen, "{:?}", header);
+    }
+}
+
+#[test]
+fn strong_tags_of_compressed_bodies_are_made_weak() {
+    let router = router(Compress::new());
+    let compressed = router.handle(&request("/tagged", "Accept-Encoding: gzip\r\n"));
+    assert_eq!(compressed.header_value("ETag"), Some("W/\"v1\""));
+    let plain = router.handle(&request("/tagged", ""));
+    assert_eq!(plain.header_value("ETag"), Some("\"v1\""));
+}
+
+#[cfg(feature = "brotli")]
+#[test]
+fn brotli_is_preferred_when_accepted() {
+    let router = router(Compress::new());
+    let response = router.handle(&request("/page", "Accept-Encoding: gzip, br\r\n"));
+    assert_eq!(response.header_value("Content-Encoding"), Some("br"));
+    let mut text = String::new();
+    brotli::Decompressor::new(response.body.as_slice(), 4096)
+        .read_to_string(&mut text)
+        .unwrap();
+    assert_eq!(text, PAGE.repeat(100));
+
+    // The client's weights come before the server's preference.
+    let response = router.handle(&request("/page", "Accept-Encoding: br;q=0.5, gzip\r\n"));
+    assert_eq!(response.header_value("Content-Encoding"), Some("gzip"));
+    let router = self::router(Compress::new().encodings(&[Encoding::Gzip]));
+    let response = router.handle(&request("/page", "Accept-Encoding: br\r\n"));
+    assert_eq!(response.header_value("Content-Encoding"), None);
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn zstd_bodies_decode_to_the_original() {
+    let router = router(Compress::new());
+    let response = router.handle(&request("/page", "Accept-Encoding: zstd, gzip;q=0.9\r\n"));
+    assert_eq!(response.header_value("Content-Encoding"), Some("zstd"));
+    let body = zstd::decode_all(response.body.as_slice()).unwrap();
+    assert_eq!(body, PAGE.repeat(100).as_bytes());
+}
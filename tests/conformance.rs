@@ -0,0 +1,149 @@
+//! Request-syntax conformance corpus (RFC 9112 / RFC 7230).
+//!
+//! Each case is a raw byte sequence and the exact status code the server must
+//! answer it with. Add a case here whenever the parser learns something new.
+
+mod common;
+
+use common::MemoryStream;
+use rusty_server::handle_connection;
+
+struct Case {
+    name: &'static str,
+    raw: Vec<u8>,
+    status: u16,
+}
+
+fn case(name: &'static str, raw: &[u8], status: u16) -> Case {
+    Case {
+        name,
+        raw: raw.to_vec(),
+        status,
+    }
+}
+
+fn corpus() -> Vec<Case> {
+    let long_target = format!("GET /{} HTTP/1.1\r\nHost: a\r\n\r\n", "a".repeat(9000));
+    let long_header = format!("GET /version HTTP/1.1\r\nHost: a\r\nCookie: {}\r\n\r\n", "c".repeat(9000));
+    let large_cookie = format!("GET /version HTTP/1.1\r\nHost: a\r\nCookie: {}\r\n\r\n", "c".repeat(4096));
+
+    vec![
+        // Valid requests.
+        case("minimal 1.1", b"GET /version HTTP/1.1\r\nHost: a\r\n\r\n", 200),
+        case("1.0 without Host", b"GET /version HTTP/1.0\r\n\r\n", 200),
+        case("later 1.x minor version", b"GET /version HTTP/1.9\r\nHost: a\r\n\r\n", 200),
+        case("no space after colon", b"GET /version HTTP/1.1\r\nHost:a\r\n\r\n", 200),
+        case("trailing whitespace in value", b"GET /version HTTP/1.1\r\nHost: a \t\r\n\r\n", 200),
+        case("lowercase header name", b"GET /version HTTP/1.1\r\nhost: a\r\n\r\n", 200),
+        case("absolute-form target", b"GET http://a/version HTTP/1.1\r\nHost: a\r\n\r\n", 200),
+        case("query string", b"GET /version?verbose=1 HTTP/1.1\r\nHost: a\r\n\r\n", 200),
+        case(
+            "repeated identical Content-Length",
+            b"GET /version HTTP/1.1\r\nHost: a\r\nContent-Length: 0\r\nContent-Length: 0\r\n\r\n",
+            200,
+        ),
+        case("percent-encoded path", b"GET /%76ersion HTTP/1.1\r\nHost: a\r\n\r\n", 200),
+        case("unknown path", b"GET /nope HTTP/1.1\r\nHost: a\r\n\r\n", 404),
+        case("large cookie", large_cookie.as_bytes(), 200),
+        // Request line.
+        case("empty request", b"", 400),
+        case("missing final CRLF", b"GET /version HTTP/1.1\r\nHost: a\r\n", 400),
+        case("bare LF line endings", b"GET /version HTTP/1.1\nHost: a\n\n", 400),
+        case("bare CR", b"GET /version HTTP/1.1\rHost: a\r\n\r\n", 400),
+        case("double space", b"GET  /version HTTP/1.1\r\nHost: a\r\n\r\n", 400),
+        case("missing version", b"GET /version\r\nHost: a\r\n\r\n", 400),
+        case("invalid method token", b"G(T /version HTTP/1.1\r\nHost: a\r\n\r\n", 400),
+        case("invalid target character", b"GET /<script> HTTP/1.1\r\nHost: a\r\n\r\n", 400),
+        case("relative target", b"GET version HTTP/1.1\r\nHost: a\r\n\r\n", 400),
+        case("truncated percent escape", b"GET /version%2 HTTP/1.1\r\nHost: a\r\n\r\n", 400),
+        case("non-hex percent escape", b"GET /%zzversion HTTP/1.1\r\nHost: a\r\n\r\n", 400),
+        case("percent-encoded invalid UTF-8", b"GET /%ff HTTP/1.1\r\nHost: a\r\n\r\n", 400),
+        case("garbage version", b"GET /version HTTP/one\r\nHost: a\r\n\r\n", 400),
+        case("HTTP/2.0 on the wire", b"GET /version HTTP/2.0\r\nHost: a\r\n\r\n", 505),
+        case("oversized request line", long_target.as_bytes(), 414),
+        // Header section.
+        case("missing Host on 1.1", b"GET /version HTTP/1.1\r\n\r\n", 400),
+        case("duplicate Host", b"GET /version HTTP/1.1\r\nHost: a\r\nHost: b\r\n\r\n", 400),
+        case("folded header", b"GET /version HTTP/1.1\r\nHost: a\r\nX-Long: one\r\n two\r\n\r\n", 400),
+        case("whitespace before colon", b"GET /version HTTP/1.1\r\nHost : a\r\n\r\n", 400),
+        case("header without colon", b"GET /version HTTP/1.1\r\nHost a\r\n\r\n", 400),
+        case("control byte in value", b"GET /version HTTP/1.1\r\nHost: a\x00b\r\n\r\n", 400),
+        case("oversized header", long_header.as_bytes(), 431),
+        // Message framing.
+        case(
+            "conflicting Content-Length",
+            b"GET /version HTTP/1.1\r\nHost: a\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\n",
+            400,
+        ),
+        case(
+            "conflicting Content-Length list",
+            b"GET /version HTTP/1.1\r\nHost: a\r\nContent-Length: 3, 4\r\n\r\n",
+            400,
+        ),
+        case("signed Content-Length", b"GET /version HTTP/1.1\r\nHost: a\r\nContent-Length: +1\r\n\r\n", 400),
+        case(
+            "Content-Length with Transfer-Encoding",
+            b"GET /version HTTP/1.1\r\nHost: a\r\nContent-Length: 1\r\nTransfer-Encoding: chunked\r\n\r\n",
+            400,
+        ),
+        case(
+            "body shorter than Content-Length",
+            b"GET /version HTTP/1.1\r\nHost: a\r\nContent-Length: 10\r\n\r\nshort",
+            400,
+        ),
+        case(
+            "body longer than Content-Length",
+            b"GET /version HTTP/1.1\r\nHost: a\r\nContent-Length: 2\r\n\r\nlonger",
+            200,
+        ),
+        case(
+            "chunked body",
+            b"GET /version HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n",
+            200,
+        ),
+        case(
+            "chunked body with extensions and trailers",
+            b"GET /version HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: Chunked\r\n\r\n5;name=x\r\nhello\r\n0\r\nX-Sum: 1\r\n\r\n",
+            200,
+        ),
+        case(
+            "malformed chunk size",
+            b"GET /version HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\nhello\r\n0\r\n\r\n",
+            400,
+        ),
+        case(
+            "chunk longer than its size",
+            b"GET /version HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nhello\r\n0\r\n\r\n",
+            400,
+        ),
+        case(
+            "incomplete chunked body",
+            b"GET /version HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhel",
+            400,
+        ),
+        case(
+            "chunked over another coding",
+            b"GET /version HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: gzip, chunked\r\n\r\n",
+            501,
+        ),
+        case(
+            "unsupported Transfer-Encoding",
+            b"GET /version HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: gzip\r\n\r\n",
+            501,
+        ),
+    ]
+}
+
+#[test]
+fn corpus_statuses() {
+    let mut failures = Vec::new();
+    for case in corpus() {
+        let mut stream = MemoryStream::new(&case.raw);
+        handle_connection(&mut stream).unwrap();
+        let status = stream.response().status();
+        if status != case.status {
+            failures.push(format!("{}: expected {}, got {}", case.name, case.status, status));
+        }
+    }
+    assert!(failures.is_empty(), "conformance failures:\n{}", failures.join("\n"));
+}
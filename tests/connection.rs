@@ -0,0 +1,408 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+
+use common::MemoryStream;
+use rusty_server::http::Response;
+use rusty_server::{handle_connection, serve_connection, Config, Middleware, Request, Router};
+
+#[test]
+fn reports_bytes_status_and_path() {
+    let request = b"GET /version HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    let mut stream = MemoryStream::new(request);
+
+    let (bytes, status, path) = handle_connection(&mut stream).unwrap();
+
+    assert_eq!(bytes, request.len());
+    assert_eq!(status, "200 OK");
+    assert_eq!(path, "/version");
+    assert_eq!(stream.response().status(), 200);
+}
+
+#[test]
+fn unknown_path_is_reported_as_404() {
+    let mut stream = MemoryStream::new(b"GET /missing HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+    let (_, status, path) = handle_connection(&mut stream).unwrap();
+
+    assert_eq!(status, "404 Not Found");
+    assert_eq!(path, "/missing");
+    assert!(stream.response().body.contains("/missing"));
+}
+
+#[test]
+fn malformed_request_line_is_reported_as_400() {
+    let mut stream = MemoryStream::new(b"\x00\x01garbage\r\n\r\n");
+
+    let (_, status, path) = handle_connection(&mut stream).unwrap();
+
+    assert_eq!(status, "400 Bad Request");
+    assert_eq!(path, "/unknown");
+    assert_eq!(stream.response().status(), 400);
+}
+
+#[test]
+fn json_variant_is_selected_by_accept_header() {
+    let mut stream = MemoryStream::new(
+        b"GET /version HTTP/1.1\r\nHost: localhost\r\nAccept: application/json\r\n\r\n",
+    );
+
+    handle_connection(&mut stream).unwrap();
+
+    let body = stream.response().body;
+    assert!(body.trim_start().starts_with('{'));
+    assert!(body.contains(r#""rust_version""#));
+}
+
+#[test]
+fn serve_connection_uses_the_given_router() {
+    let mut router = Router::new();
+    router.get("/hello", |_| Response::text(200, "hi"));
+    let mut stream = MemoryStream::new(b"GET /hello HTTP/1.1\r\nHost: a\r\n\r\n");
+
+    let exchange = serve_connection(&mut stream, &router, &Config::default()).unwrap();
+
+    assert_eq!(exchange.status, 200);
+    assert_eq!(exchange.path, "/hello");
+    assert_eq!(stream.response().body, "hi");
+}
+
+#[test]
+fn serve_connection_honours_max_head_size() {
+    let mut config = Config::default();
+    config.max_head_size = 32;
+    let mut stream =
+        MemoryStream::new(b"GET /version HTTP/1.1\r\nHost: a\r\nX-Padding: aaaaaaaa\r\n\r\n");
+
+    let exchange = serve_connection(&mut stream, &Router::default(), &config).unwrap();
+
+    assert_eq!(exchange.status, 431);
+    assert_eq!(exchange.bytes_read, 32);
+}
+
+#[test]
+fn routed_handlers_get_the_whole_body() {
+    let mut router = Router::new();
+    router.route("POST", "/echo", |request| Response::text(200, request.body.clone()));
+    // Longer than the head buffer, so most of it is read after the head.
+    let body = "b".repeat(4096);
+    let request = format!(
+        "POST /echo HTTP/1.1\r\nHost: a\r\nContent-Length: {}\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    let mut stream = MemoryStream::new(request.as_bytes());
+
+    serve_connection(&mut stream, &router, &Config::default()).unwrap();
+
+    assert_eq!(stream.response().body, body);
+}
+
+#[test]
+fn post_routes_get_the_form_body() {
+    let mut router = Router::new();
+    router.post("/notes", |request| {
+        Response::text(201, format!("saved {} bytes", request.body.len()))
+    });
+    let mut stream = MemoryStream::new(
+        b"POST /notes HTTP/1.1\r\nHost: a\r\nContent-Length: 11\r\n\r\nhello world",
+    );
+
+    let exchange = serve_connection(&mut stream, &router, &Config::default()).unwrap();
+
+    assert_eq!(exchange.status, 201);
+    assert_eq!(stream.response().body, "saved 11 bytes");
+}
+
+#[test]
+fn post_without_content_length_is_answered_with_411() {
+    let mut router = Router::new();
+    router.post("/notes", |_| panic!("the body length was unknown"));
+    let mut stream = MemoryStream::new(b"POST /notes HTTP/1.1\r\nHost: a\r\n\r\nhello");
+
+    let exchange = serve_connection(&mut stream, &router, &Config::default()).unwrap();
+
+    assert_eq!(exchange.status, 411);
+    assert_eq!(exchange.path, "/notes");
+}
+
+#[test]
+fn chunked_bodies_are_decoded_for_routes() {
+    let mut router = Router::new();
+    router.post("/echo", |request| {
+        assert_eq!(request.header("Transfer-Encoding"), None);
+        assert_eq!(request.content_length(), 11);
+        Response::text(200, request.body.clone())
+    });
+    let mut stream = MemoryStream::new(
+        b"POST /echo HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n\
+          6;ext=1\r\nhello \r\n5\r\nworld\r\n0\r\nX-Checksum: 42\r\n\r\n",
+    );
+
+    let exchange = serve_connection(&mut stream, &router, &Config::default()).unwrap();
+
+    assert_eq!(exchange.status, 200);
+    assert_eq!(stream.response().body, "hello world");
+}
+
+#[test]
+fn chunked_bodies_are_decoded_for_streaming_handlers() {
+    let mut router = Router::new();
+    router.stream("/upload", |_, connection| {
+        let mut body = String::new();
+        connection.read_to_string(&mut body)?;
+        connection.send_head(&Response::new(200))?;
+        connection.write_all(body.to_uppercase().as_bytes())?;
+        Ok(200)
+    });
+    let mut stream = MemoryStream::new(
+        b"POST /upload HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\n",
+    );
+
+    serve_connection(&mut stream, &router, &Config::default()).unwrap();
+
+    assert_eq!(stream.response().body, "ABC");
+}
+
+#[test]
+fn chunked_bodies_honour_max_body_size() {
+    let mut router = Router::new();
+    router.post("/echo", |_| panic!("the body was too large to route"));
+    let mut config = Config::default();
+    config.max_body_size = 4;
+    let mut stream = MemoryStream::new(
+        b"POST /echo HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n3\r\ndef\r\n0\r\n\r\n",
+    );
+
+    let exchange = serve_connection(&mut stream, &router, &config).unwrap();
+
+    assert_eq!(exchange.status, 413);
+}
+
+#[test]
+fn huge_chunk_sizes_are_too_large_rather_than_overflowing() {
+    let mut router = Router::new();
+    router.post("/echo", |_| panic!("the body was too large to route"));
+    let mut stream = MemoryStream::new(
+        b"POST /echo HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n1\r\na\r\nffffffffffffffff\r\n",
+    );
+
+    let exchange = serve_connection(&mut stream, &router, &Config::default()).unwrap();
+
+    assert_eq!(exchange.status, 413);
+}
+
+#[test]
+fn serve_connection_honours_max_body_size() {
+    let mut router = Router::new();
+    router.route("POST", "/echo", |_| panic!("the body was too large to route"));
+    let mut config = Config::default();
+    config.max_body_size = 4;
+    let mut stream =
+        MemoryStream::new(b"POST /echo HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\n\r\nhello");
+
+    let exchange = serve_connection(&mut stream, &router, &config).unwrap();
+
+    assert_eq!(exchange.status, 413);
+    assert_eq!(exchange.path, "/echo");
+}
+
+#[test]
+fn fallbacks_answer_unrouted_requests_in_order() {
+    let mut router = Router::new();
+    router.get("/routed", |_| Response::text(200, "route"));
+    router.fallback(|request| {
+        request
+            .path
+            .starts_with("/dyn/")
+            .then(|| Response::text(200, "first"))
+    });
+    router.fallback(|_| Some(Response::text(200, "second")));
+    router.fallback(|_| panic!("an earlier fallback answered"));
+
+    for (path, body) in [("/routed", "route"), ("/dyn/x", "first"), ("/other", "second")] {
+        let request = format!("GET {} HTTP/1.1\r\nHost: a\r\n\r\n", path);
+        let mut stream = MemoryStream::new(request.as_bytes());
+        serve_connection(&mut stream, &router, &Config::default()).unwrap();
+        assert_eq!(stream.response().body, body);
+    }
+}
+
+#[test]
+fn guards_turn_requests_away_before_routes() {
+    let mut router = Router::new();
+    router.get("/admin/stats", |_| Response::text(200, "stats"));
+    router.get("/administrator", |_| Response::text(200, "other"));
+    router.guard("/admin", |request| {
+        (request.header("X-Token") != Some("secret")).then(|| Response::text(403, "refused"))
+    });
+
+    for (path, token, body) in [
+        ("/admin/stats", "", "refused"),
+        ("/admin/stats", "X-Token: secret\r\n", "stats"),
+        ("/administrator", "", "other"),
+    ] {
+        let request = format!("GET {} HTTP/1.1\r\nHost: a\r\n{}\r\n", path, token);
+        let mut stream = MemoryStream::new(request.as_bytes());
+        serve_connection(&mut stream, &router, &Config::default()).unwrap();
+        assert_eq!(stream.response().body, body);
+    }
+}
+
+#[test]
+fn route_patterns_capture_path_parameters() {
+    let mut router = Router::new();
+    router.get("/kv/all", |_| Response::text(200, "every key"));
+    router.get("/kv/:key", |request| {
+        Response::text(200, format!("key {}", request.param("key").unwrap()))
+    });
+    router.get("/status/:code/:reason", |request| {
+        let code = request.param("code").unwrap().parse().unwrap_or(400);
+        Response::text(code, request.param("reason").unwrap().to_string())
+    });
+    router.get("/static/*file", |request| {
+        Response::text(200, format!("file {:?}", request.param("file").unwrap()))
+    });
+
+    for (path, status, body) in [
+        ("/kv/all", 200, "every key"),
+        ("/kv/a%20b", 200, "key a b"),
+        ("/kv/a/b", 404, ""),
+        ("/kv/", 404, ""),
+        ("/status/418/teapot", 418, "teapot"),
+        ("/static/css/site.css", 200, "file \"css/site.css\""),
+        ("/static/", 200, "file \"\""),
+        ("/static", 200, "file \"\""),
+        ("/statics/a", 404, ""),
+    ] {
+        let request = format!("GET {} HTTP/1.1\r\nHost: a\r\n\r\n", path);
+        let mut stream = MemoryStream::new(request.as_bytes());
+        let exchange = serve_connection(&mut stream, &router, &Config::default()).unwrap();
+        assert_eq!(exchange.status, status, "{}", path);
+        if status != 404 {
+            assert_eq!(stream.response().body, body, "{}", path);
+        }
+    }
+}
+
+#[test]
+fn other_methods_on_a_routed_path_get_405_with_allow() {
+    let mut router = Router::default();
+    router.route("DELETE", "/kv/:key", |_| Response::new(204));
+    router.route("PUT", "/kv/:key", |_| Response::new(201));
+
+    for (request, status, allow) in [
+        ("POST /version", 405, Some("GET")),
+        ("GET /kv/a", 405, Some("DELETE, PUT")),
+        ("DELETE /kv/a", 204, None),
+        ("POST /missing", 404, None),
+    ] {
+        let request = format!("{} HTTP/1.1\r\nHost: a\r\n\r\n", request);
+        let mut stream = MemoryStream::new(request.as_bytes());
+        let exchange = serve_connection(&mut stream, &router, &Config::default()).unwrap();
+        assert_eq!(exchange.status, status, "{}", request);
+        assert_eq!(stream.response().header("Allow"), allow, "{}", request);
+    }
+}
+
+/// Middleware recording its hooks in a shared log, and turning away requests
+/// without `X-Token` when `required`.
+struct Recording {
+    name: &'static str,
+    log: Arc<Mutex<Vec<String>>>,
+    required: bool,
+}
+
+impl Middleware for Recording {
+    fn before(&self, request: &Request) -> Option<Response> {
+        self.log.lock().unwrap().push(format!("{} before", self.name));
+        (self.required && request.header("X-Token").is_none())
+            .then(|| Response::text(401, "token required"))
+    }
+
+    fn after(&self, _: &Request, response: &mut Response) {
+        self.log.lock().unwrap().push(format!("{} after", self.name));
+        response.headers.push(("X-Seen-By".to_string(), self.name.to_string()));
+    }
+}
+
+#[test]
+fn middleware_runs_around_routes_in_order() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let mut router = Router::new();
+    let route_log = Arc::clone(&log);
+    router.get("/hello", move |_| {
+        route_log.lock().unwrap().push("route".to_string());
+        Response::text(200, "hi")
+    });
+    for (name, required) in [("outer", false), ("auth", true), ("inner", false)] {
+        let log = Arc::clone(&log);
+        router.middleware(Recording { name, log, required });
+    }
+    let serve = |token: &str| {
+        let request = format!("GET /hello HTTP/1.1\r\nHost: a\r\n{}\r\n", token);
+        let mut stream = MemoryStream::new(request.as_bytes());
+        serve_connection(&mut stream, &router, &Config::default()).unwrap();
+        stream.response()
+    };
+
+    let response = serve("X-Token: t\r\n");
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        *log.lock().unwrap(),
+        [
+            "outer before",
+            "auth before",
+            "inner before",
+            "route",
+            "inner after",
+            "auth after",
+            "outer after"
+        ]
+    );
+    assert_eq!(response.header("X-Seen-By"), Some("inner"));
+
+    log.lock().unwrap().clear();
+    let response = serve("");
+
+    assert_eq!(response.status(), 401);
+    assert_eq!(
+        *log.lock().unwrap(),
+        ["outer before", "auth before", "auth after", "outer after"]
+    );
+}
+
+#[test]
+fn requests_get_an_id_or_keep_their_own() {
+    let mut router = Router::new();
+    router.get("/id", |request| Response::text(200, request.id.clone()));
+    let serve = |head: &[u8]| {
+        let mut stream = MemoryStream::new(head);
+        let exchange = serve_connection(&mut stream, &router, &Config::default()).unwrap();
+        (exchange, stream.response())
+    };
+
+    let (exchange, response) = serve(b"GET /id HTTP/1.1\r\nHost: a\r\n\r\n");
+    let id = response.header("X-Request-Id").unwrap().to_string();
+    assert_eq!(id.len(), 32);
+    assert_eq!(response.body, id);
+    assert_eq!(exchange.request_id, id);
+    let (_, again) = serve(b"GET /id HTTP/1.1\r\nHost: a\r\n\r\n");
+    assert_ne!(again.header("X-Request-Id"), Some(id.as_str()));
+
+    let (exchange, response) = serve(b"GET /id HTTP/1.1\r\nHost: a\r\nX-Request-Id: trace-42\r\n\r\n");
+    assert_eq!(response.header("X-Request-Id"), Some("trace-42"));
+    assert_eq!(response.body, "trace-42");
+    assert_eq!(exchange.request_id, "trace-42");
+
+    // Spaces could split a log line, so such IDs are replaced.
+    let (_, response) = serve(b"GET /id HTTP/1.1\r\nHost: a\r\nX-Request-Id: a b\r\n\r\n");
+    assert_eq!(response.header("X-Request-Id").unwrap().len(), 32);
+
+    // Requests that cannot be parsed still get one.
+    let (exchange, response) = serve(b"NOT A REQUEST\r\n\r\n");
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.header("X-Request-Id"), Some(exchange.request_id.as_str()));
+}
@@ -0,0 +1,388 @@
+mod common;
+
+use std::net::{SocketAddr, TcpListener};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::get;
+use rusty_server::console::{
+    take_verbosity, ConflictingVerbosity, Format, Theme, UnknownFormat, UnknownTheme, Verbosity,
+};
+use serde_json::Value;
+
+#[test]
+fn formats_are_parsed_by_name() {
+    assert_eq!("table".parse(), Ok(Format::Table));
+    assert_eq!("ndjson".parse(), Ok(Format::Ndjson));
+    assert_eq!("json".parse(), Ok(Format::Ndjson));
+    #[cfg(feature = "tracing")]
+    assert_eq!("pretty".parse(), Ok(Format::Pretty));
+    assert_eq!(
+        "xml".parse::<Format>(),
+        Err(UnknownFormat("xml".to_string()))
+    );
+}
+
+#[test]
+fn themes_are_parsed_by_name() {
+    assert_eq!("heavy".parse(), Ok(Theme::Heavy));
+    assert_eq!("basic".parse(), Ok(Theme::Basic));
+    assert_eq!("none".parse(), Ok(Theme::None));
+    assert_eq!("plain".parse(), Ok(Theme::Plain));
+    assert_eq!(
+        "double".parse::<Theme>(),
+        Err(UnknownTheme("double".to_string()))
+    );
+}
+
+#[test]
+fn verbosity_flags_are_taken_from_the_arguments() {
+    let taken = |args: &[&str]| {
+        let mut args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        take_verbosity(&mut args).map(|verbosity| (verbosity, args))
+    };
+
+    assert_eq!(
+        taken(&["--tui"]),
+        Ok((Verbosity::Normal, vec!["--tui".to_string()]))
+    );
+    assert_eq!(taken(&["-q"]), Ok((Verbosity::Quiet, vec![])));
+    assert_eq!(taken(&["--verbose"]), Ok((Verbosity::Verbose, vec![])));
+    assert_eq!(taken(&["-v", "-v"]), Ok((Verbosity::VeryVerbose, vec![])));
+    assert_eq!(taken(&["-vv"]), Ok((Verbosity::VeryVerbose, vec![])));
+    assert_eq!(taken(&["-q", "-v"]), Err(ConflictingVerbosity));
+}
+
+#[cfg(feature = "console")]
+#[test]
+fn outcomes_are_colored_by_status_class() {
+    use rusty_server::console;
+
+    const GREEN: &str = "\x1b[32m";
+    const YELLOW: &str = "\x1b[33m";
+    const RED: &str = "\x1b[31m";
+    console::set_color(true);
+    let colored = |value: &str| console::render_table(&[("Response", value.to_string())]);
+
+    assert!(colored("✓ 200 OK (78 bytes)").contains(GREEN));
+    assert!(colored("✓ 404 Not Found (78 bytes)").contains(YELLOW));
+    assert!(colored("✓ 503 Service Unavailable (78 bytes)").contains(RED));
+    assert!(colored("✗ Failed: timed out").contains(RED));
+    assert!(!colored("Server Started").contains('\x1b'));
+    let grid = console::render_grid(
+        &["Endpoint", "Status"],
+        &[vec!["/500".to_string(), "500".to_string()]],
+    );
+    assert!(!grid.contains(&format!("{}/500", RED)));
+    assert!(grid.contains(&format!("{}500", RED)));
+}
+
+/// Runs the binary with `args` and NDJSON output, requests `paths` from it,
+/// waits for `linger`, and returns what it printed.
+fn ndjson_output(args: &[&str], paths: &[&str], linger: Duration) -> (SocketAddr, Vec<Value>) {
+    let addr: SocketAddr = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    };
+    let mut server = Command::new(env!("CARGO_BIN_EXE_rusty-server"))
+        .args(["--log-format", "ndjson"])
+        .args(args)
+        .env("LISTEN_ADDRESS", addr.to_string())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let started = Instant::now();
+    while std::net::TcpStream::connect(addr).is_err() {
+        assert!(
+            started.elapsed() < Duration::from_secs(5),
+            "server did not start"
+        );
+        thread::sleep(Duration::from_millis(20));
+    }
+    for path in paths {
+        get(addr, path, &[]);
+    }
+    thread::sleep(linger);
+    server.kill().unwrap();
+    let output = server.wait_with_output().unwrap();
+
+    let lines = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    (addr, lines)
+}
+
+#[test]
+fn ndjson_output_is_one_object_per_line() {
+    // Long enough for the server to report the connection.
+    let (addr, lines) = ndjson_output(&[], &["/version"], Duration::from_millis(200));
+
+    assert_eq!(lines[0]["status"], "Server Started");
+    assert_eq!(lines[0]["address"], format!("http://{}", addr));
+    let request = lines
+        .iter()
+        .find(|line| line["path"] == "/version")
+        .unwrap();
+    assert_eq!(request["status"], 200);
+    assert_eq!(request["method"], "GET");
+    assert_eq!(request["request_id"].as_str().unwrap().len(), 32);
+    assert!(request["elapsed_ms"].as_f64().is_some());
+    assert!(request.get("target").is_none());
+    assert!(request["time"].as_u64().unwrap() > 0);
+}
+
+#[test]
+fn summaries_replace_connection_reports() {
+    let paths = ["/version", "/version", "/missing"];
+    let (_, lines) = ndjson_output(&["--summary", "0.5"], &paths, Duration::from_millis(1200));
+
+    assert!(lines.iter().all(|line| line.get("peer").is_none()));
+    let summaries: Vec<&Value> = lines
+        .iter()
+        .filter(|line| line.get("requests").is_some())
+        .collect();
+    // The harness's readiness probe is a connection too, answered with 400.
+    let count = |field: &str, key: &str| -> u64 {
+        summaries
+            .iter()
+            .filter_map(|summary| summary[field][key].as_u64())
+            .sum()
+    };
+    let count_total = |field: &str| -> u64 {
+        summaries
+            .iter()
+            .filter_map(|summary| summary[field].as_u64())
+            .sum()
+    };
+    assert_eq!(count("statuses", "200"), 2);
+    assert_eq!(count("statuses", "404"), 1);
+    assert_eq!(count("classes", "2xx"), 2);
+    assert_eq!(count("classes", "4xx"), 2);
+    assert_eq!(count("methods", "GET"), 3);
+    assert_eq!(count("top_paths", "/version"), 2);
+    let summary = summaries[0];
+    assert_eq!(summary["interval_secs"], 0.5);
+    assert!(summary["bytes_read"].as_u64().unwrap() > 0);
+    assert!(summary["bytes_sent"].as_u64().unwrap() > 0);
+    assert!(summary["mean_latency_ms"].as_f64().is_some());
+    assert_eq!(count_total("errors"), 0);
+}
+
+#[test]
+fn debugging_keeps_connection_reports_under_summaries() {
+    let (_, lines) = ndjson_output(
+        &["--summary", "0.5", "--debug"],
+        &["/version"],
+        Duration::from_millis(1200),
+    );
+
+    assert!(lines.iter().any(|line| line["path"] == "/version"));
+    assert!(lines.iter().any(|line| line["top_paths"]["/version"] == 1));
+}
+
+#[test]
+fn quiet_drops_connection_reports_but_not_summaries() {
+    let (_, lines) = ndjson_output(
+        &["-q", "--summary", "0.5"],
+        &["/version"],
+        Duration::from_millis(1200),
+    );
+
+    assert_eq!(lines[0]["status"], "Server Started");
+    assert!(lines.iter().all(|line| line.get("peer").is_none()));
+    assert!(lines.iter().any(|line| line["top_paths"]["/version"] == 1));
+}
+
+#[test]
+fn verbose_reports_carry_request_and_response_details() {
+    let (_, lines) = ndjson_output(&["-v"], &["/version"], Duration::from_millis(200));
+    let request = lines
+        .iter()
+        .find(|line| line["path"] == "/version")
+        .unwrap();
+    assert_eq!(request["method"], "GET");
+    assert_eq!(request["target"], "/version");
+    assert!(request["content_type"]
+        .as_str()
+        .unwrap()
+        .starts_with("text/html"));
+    assert!(request["elapsed_ms"].as_f64().is_some());
+    assert!(request.get("request_headers").is_none());
+
+    let (_, lines) = ndjson_output(&["-vv"], &["/version"], Duration::from_millis(200));
+    let request = lines
+        .iter()
+        .find(|line| line["path"] == "/version")
+        .unwrap();
+    assert!(request["request_headers"].is_object());
+    assert!(request["response_headers"]["Content-Type"].is_string());
+}
+
+#[cfg(feature = "console")]
+#[test]
+fn themes_change_the_borders() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty-server"))
+        .args(["--theme", "heavy", "check", "--url", "http://127.0.0.1:1"])
+        .output()
+        .unwrap();
+
+    let table = String::from_utf8(output.stdout).unwrap();
+    assert!(table.starts_with('┏'), "{}", table);
+    // Not a terminal, so no colors.
+    assert!(!table.contains('\x1b'));
+}
+
+#[cfg(feature = "console")]
+#[test]
+fn tables_can_be_ascii_or_plain_text() {
+    let check = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_rusty-server"))
+            .args(args)
+            .args(["check", "--url", "http://127.0.0.1:1"])
+            .output()
+            .unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let basic = check(&["--table-theme", "basic"]);
+    assert!(basic.starts_with('+'), "{}", basic);
+    // Only the result's ✗ is not ASCII.
+    assert!(basic.lines().next().unwrap().is_ascii(), "{}", basic);
+
+    let plain = check(&["--no-table"]);
+    assert!(plain.starts_with("Endpoint"), "{}", plain);
+    assert!(plain.lines().nth(1).unwrap().starts_with("/version"), "{}", plain);
+    assert!(!plain.contains(['+', '│', '╭']), "{}", plain);
+}
+
+#[test]
+fn output_is_mirrored_to_a_file_for_the_day() {
+    let dir = std::env::temp_dir().join(format!("rusty-server-logs-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty-server"))
+        .arg("--log-dir")
+        .arg(&dir)
+        .args(["check", "--url", "http://127.0.0.1:1"])
+        .output()
+        .unwrap();
+
+    let files: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(files.len(), 1);
+    let name = files[0].file_name().unwrap().to_str().unwrap();
+    assert!(
+        name.starts_with("rusty-server-20") && name.ends_with(".log"),
+        "{}",
+        name
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with(&format!("Log file: {}", files[0].display())));
+    assert_eq!(std::fs::read_to_string(&files[0]).unwrap(), stdout);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "console")]
+#[test]
+fn tables_wrap_to_the_width() {
+    const PATH: &str = "/a/long/path/that/does/not/fit/in/a/narrow/table";
+    let table = |columns: &str| {
+        let output = Command::new(env!("CARGO_BIN_EXE_rusty-server"))
+            .args(["check", "--url", "http://127.0.0.1:1", "--endpoints", PATH])
+            .env("COLUMNS", columns)
+            .output()
+            .unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let wide = table("200");
+    assert!(wide.contains(PATH), "{}", wide);
+    assert!(wide.lines().any(|line| line.chars().count() > 80));
+    let narrow = table("60");
+    assert!(!narrow.contains(PATH), "{}", narrow);
+    assert!(narrow.contains("/a/long/path"));
+    assert!(
+        narrow.lines().all(|line| line.chars().count() <= 60),
+        "{}",
+        narrow
+    );
+}
+
+#[cfg(feature = "console")]
+#[test]
+fn the_table_width_can_be_fixed() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty-server"))
+        .args(["--table-width", "60", "check", "--url", "http://127.0.0.1:1"])
+        .env("COLUMNS", "200")
+        .output()
+        .unwrap();
+
+    let table = String::from_utf8(output.stdout).unwrap();
+    assert!(table.lines().all(|line| line.chars().count() <= 60), "{}", table);
+    assert!(table.lines().any(|line| line.chars().count() > 50), "{}", table);
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn tracing_output_has_connection_spans_and_respects_rust_log() {
+    let run = |rust_log: &str| {
+        let addr: SocketAddr = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap()
+        };
+        let mut server = Command::new(env!("CARGO_BIN_EXE_rusty-server"))
+            .args(["--log-format", "tracing"])
+            .env("LISTEN_ADDRESS", addr.to_string())
+            .env("RUST_LOG", rust_log)
+            .env("NO_COLOR", "1")
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let started = Instant::now();
+        while std::net::TcpStream::connect(addr).is_err() {
+            assert!(started.elapsed() < Duration::from_secs(5), "server did not start");
+            thread::sleep(Duration::from_millis(20));
+        }
+        get(addr, "/version", &[]);
+        thread::sleep(Duration::from_millis(200));
+        server.kill().unwrap();
+        String::from_utf8(server.wait_with_output().unwrap().stdout).unwrap()
+    };
+
+    let info = run("info");
+    let served = info.lines().find(|line| line.contains("/version")).unwrap();
+    assert!(served.contains("connection{peer=\"127.0.0.1:"), "{}", served);
+    assert!(served.contains("path=\"/version\" request_id="), "{}", served);
+    assert!(served.contains(" status=200 "), "{}", served);
+    assert!(!info.contains("request_headers"));
+
+    assert!(run("debug").contains("request_headers"));
+    assert!(!run("warn").contains("served"));
+}
+
+#[test]
+fn unknown_formats_are_usage_errors() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty-server"))
+        .arg("--log-format=xml")
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(64));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown log format `xml`"));
+}
+
+#[test]
+fn prefixed_variables_take_precedence() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rusty-server"))
+        .env("LOG_FORMAT", "table")
+        .env("SERVER_LOG_FORMAT", "xml")
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(64));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown log format `xml`"));
+}
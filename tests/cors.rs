@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use rusty_server::cors::Cors;
+use rusty_server::http::parse_request;
+use rusty_server::{Request, Response, Router};
+
+fn request(method: &str, headers: &str) -> Request {
+    let head = format!(
+        "{} /version HTTP/1.1\r\nHost: localhost\r\nAccept: application/json\r\n{}\r\n",
+        method, headers
+    );
+    parse_request(head.as_bytes()).unwrap()
+}
+
+fn router(cors: Cors) -> Router {
+    let mut router = Router::default();
+    router.middleware(cors);
+    router
+}
+
+fn header<'a>(response: &'a Response, name: &str) -> Option<&'a str> {
+    response.header_value(name)
+}
+
+#[test]
+fn any_origin_gets_a_wildcard() {
+    let router = router(Cors::new());
+    let response = router.handle(&request("GET", "Origin: http://localhost:5173\r\n"));
+    assert_eq!(response.status, 200);
+    assert_eq!(header(&response, "Access-Control-Allow-Origin"), Some("*"));
+    assert_eq!(header(&response, "Access-Control-Allow-Credentials"), None);
+
+    // Same-origin and non-browser requests are left alone.
+    let response = router.handle(&request("GET", ""));
+    assert_eq!(header(&response, "Access-Control-Allow-Origin"), None);
+}
+
+#[test]
+fn only_listed_origins_are_allowed() {
+    let cors = Cors::new()
+        .allow_origin("https://app.example.com/")
+        .allow_origin("http://localhost:5173")
+        .expose_headers(&["X-Request-Id"]);
+    let router = router(cors);
+
+    let response = router.handle(&request("GET", "Origin: http://localhost:5173\r\n"));
+    assert_eq!(header(&response, "Access-Control-Allow-Origin"), Some("http://localhost:5173"));
+    assert_eq!(header(&response, "Access-Control-Expose-Headers"), Some("X-Request-Id"));
+    assert_eq!(header(&response, "Vary"), Some("Accept, Origin"));
+    let response = router.handle(&request("GET", "Origin: https://app.example.com\r\n"));
+    assert_eq!(header(&response, "Access-Control-Allow-Origin"), Some("https://app.example.com"));
+
+    let refused = router.handle(&request("GET", "Origin: https://evil.example\r\n"));
+    assert_eq!(refused.status, 200);
+    assert_eq!(header(&refused, "Access-Control-Allow-Origin"), None);
+    assert_eq!(header(&refused, "Vary"), Some("Accept, Origin"));
+}
+
+#[test]
+fn preflight_requests_are_answered_without_reaching_the_routes() {
+    let cors = Cors::new()
+        .allow_origin("http://localhost:5173")
+        .allow_methods(&["get", "post"])
+        .allow_credentials(true)
+        .max_age(Duration::from_secs(600));
+    let router = router(cors);
+    let preflight = "Origin: http://localhost:5173\r\nAccess-Control-Request-Method: POST\r\nAccess-Control-Request-Headers: content-type, x-token\r\n";
+
+    let response = router.handle(&request("OPTIONS", preflight));
+    assert_eq!(response.status, 204);
+    assert!(response.body.is_empty());
+    assert_eq!(header(&response, "Access-Control-Allow-Origin"), Some("http://localhost:5173"));
+    assert_eq!(header(&response, "Access-Control-Allow-Methods"), Some("GET, POST"));
+    assert_eq!(header(&response, "Access-Control-Allow-Headers"), Some("content-type, x-token"));
+    assert_eq!(header(&response, "Access-Control-Allow-Credentials"), Some("true"));
+    assert_eq!(header(&response, "Access-Control-Max-Age"), Some("600"));
+    assert_eq!(
+        header(&response, "Vary"),
+        Some("Origin, Access-Control-Request-Method, Access-Control-Request-Headers")
+    );
+
+    // Credentials are refused when any origin is allowed, rather than
+    // granted to every origin that asks.
+    let router = self::router(Cors::new().allow_credentials(true).allow_headers(&["Content-Type"]));
+    let response = router.handle(&request("OPTIONS", preflight));
+    assert_eq!(header(&response, "Access-Control-Allow-Origin"), Some("*"));
+    assert_eq!(header(&response, "Access-Control-Allow-Credentials"), None);
+    assert_eq!(header(&response, "Access-Control-Allow-Headers"), Some("Content-Type"));
+    let router = self::router(
+        Cors::new().allow_origin("http://localhost:5173").allow_origin("*").allow_credentials(true),
+    );
+    let response = router.handle(&request("GET", "Origin: http://localhost:5173\r\n"));
+    assert_eq!(header(&response, "Access-Control-Allow-Origin"), Some("*"));
+    assert_eq!(header(&response, "Access-Control-Allow-Credentials"), None);
+
+    // OPTIONS without a preflight's headers, or from an origin not allowed,
+    // is routed as usual.
+    let router = self::router(Cors::new().allow_origin("https://app.example.com"));
+    assert_eq!(router.handle(&request("OPTIONS", preflight)).status, 405);
+    assert_eq!(router.handle(&request("OPTIONS", "Origin: https://app.example.com\r\n")).status, 405);
+}
@@ -0,0 +1,44 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener};
+use std::thread;
+
+use common::{connect, MemoryStream};
+use rusty_server::echo::{echo, hex_dump};
+
+#[test]
+fn hex_dump_shows_offsets_bytes_and_text() {
+    assert_eq!(
+        hex_dump(b"GET / HTTP/1.1\r\n\x00", 0x20),
+        "00000020  47 45 54 20 2f 20 48 54  |GET / HT|\n\
+         00000028  54 50 2f 31 2e 31 0d 0a  |TP/1.1..|\n\
+         00000030  00                       |.|"
+    );
+    assert_eq!(hex_dump(b"", 0), "");
+}
+
+#[test]
+fn echo_writes_back_what_it_reads() {
+    let mut stream = MemoryStream::new(b"not even HTTP\xff");
+
+    let total = echo(&mut stream, "test").unwrap();
+
+    assert_eq!(total, 14);
+    assert_eq!(stream.output, b"not even HTTP\xff");
+}
+
+#[test]
+fn serve_echoes_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || rusty_server::echo::serve(listener));
+    let mut stream = connect(addr);
+
+    stream.write_all(b"ping\r\n").unwrap();
+    stream.shutdown(Shutdown::Write).unwrap();
+    let mut echoed = Vec::new();
+    stream.read_to_end(&mut echoed).unwrap();
+
+    assert_eq!(echoed, b"ping\r\n");
+}
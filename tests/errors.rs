@@ -0,0 +1,34 @@
+use std::io;
+use std::process::ExitCode;
+
+use rusty_server::http::ParseError;
+use rusty_server::ServerError;
+
+#[test]
+fn parse_errors_keep_their_status() {
+    let e = ServerError::from(ParseError::UriTooLong);
+    assert_eq!(e.status(), (414, "URI Too Long"));
+    assert_eq!(e.to_string(), ParseError::UriTooLong.to_string());
+}
+
+#[test]
+fn socket_timeouts_become_timeout_errors() {
+    for kind in [io::ErrorKind::TimedOut, io::ErrorKind::WouldBlock] {
+        let e = ServerError::from(io::Error::from(kind));
+        assert!(matches!(e, ServerError::Timeout(_)));
+        assert_eq!(e.status().0, 408);
+    }
+    let e = ServerError::from(io::Error::from(io::ErrorKind::ConnectionReset));
+    assert!(matches!(e, ServerError::Io(_)));
+    assert_eq!(e.status().0, 500);
+}
+
+#[test]
+fn bind_failures_exit_with_ex_unavailable() {
+    let e = ServerError::Bind {
+        addr: "127.0.0.1:3000".to_string(),
+        source: io::Error::from(io::ErrorKind::AddrInUse),
+    };
+    assert_eq!(e.exit_code(), ExitCode::from(69));
+    assert!(e.to_string().starts_with("cannot bind 127.0.0.1:3000"));
+}
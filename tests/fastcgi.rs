@@ -0,0 +1,220 @@
+mod common;
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use common::MemoryStream;
+use rusty_server::fastcgi::FastCgi;
+use rusty_server::http::parse_request;
+use rusty_server::{serve_connection, Config, Router};
+
+/// What the fake backend received.
+struct Received {
+    params: HashMap<String, String>,
+    stdin: Vec<u8>,
+}
+
+fn read_record(stream: &mut TcpStream) -> (u8, Vec<u8>) {
+    let mut header = [0; 8];
+    stream.read_exact(&mut header).unwrap();
+    assert_eq!(header[0], 1, "FastCGI version");
+    assert_eq!(u16::from_be_bytes([header[2], header[3]]), 1, "request id");
+    let length = u16::from_be_bytes([header[4], header[5]]) as usize;
+    let mut content = vec![0; length + header[6] as usize];
+    stream.read_exact(&mut content).unwrap();
+    content.truncate(length);
+    (header[1], content)
+}
+
+fn write_record(stream: &mut TcpStream, kind: u8, content: &[u8]) {
+    let length = (content.len() as u16).to_be_bytes();
+    stream
+        .write_all(&[1, kind, 0, 1, length[0], length[1], 0, 0])
+        .unwrap();
+    stream.write_all(content).unwrap();
+}
+
+fn read_length(bytes: &[u8], at: &mut usize) -> usize {
+    if bytes[*at] < 0x80 {
+        *at += 1;
+        bytes[*at - 1] as usize
+    } else {
+        let length = u32::from_be_bytes(bytes[*at..*at + 4].try_into().unwrap()) & 0x7fff_ffff;
+        *at += 4;
+        length as usize
+    }
+}
+
+/// A one-shot FastCGI responder that answers with `stdout`, split into the
+/// given records, and reports what it received.
+fn fake_backend(stdout: &'static [&'static [u8]]) -> (SocketAddr, JoinHandle<Received>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let (kind, begin) = read_record(&mut stream);
+        assert_eq!(
+            (kind, &begin[..2]),
+            (1, &[0, 1][..]),
+            "BEGIN_REQUEST as responder"
+        );
+
+        let mut encoded = Vec::new();
+        loop {
+            let (kind, content) = read_record(&mut stream);
+            assert_eq!(kind, 4, "PARAMS");
+            if content.is_empty() {
+                break;
+            }
+            encoded.extend(content);
+        }
+        let mut params = HashMap::new();
+        let mut at = 0;
+        while at < encoded.len() {
+            let name_len = read_length(&encoded, &mut at);
+            let value_len = read_length(&encoded, &mut at);
+            let name = String::from_utf8(encoded[at..at + name_len].to_vec()).unwrap();
+            at += name_len;
+            let value = String::from_utf8(encoded[at..at + value_len].to_vec()).unwrap();
+            at += value_len;
+            params.insert(name, value);
+        }
+
+        let mut stdin = Vec::new();
+        loop {
+            let (kind, content) = read_record(&mut stream);
+            assert_eq!(kind, 5, "STDIN");
+            if content.is_empty() {
+                break;
+            }
+            stdin.extend(content);
+        }
+
+        write_record(&mut stream, 7, b"a warning");
+        for chunk in stdout {
+            write_record(&mut stream, 6, chunk);
+        }
+        write_record(&mut stream, 6, b"");
+        write_record(&mut stream, 3, &[0; 8]);
+        Received { params, stdin }
+    });
+    (addr, handle)
+}
+
+fn router(php: FastCgi) -> Router {
+    let mut router = Router::new();
+    router.stream("/app", move |request, connection| {
+        php.serve(request, connection)
+    });
+    router
+}
+
+#[test]
+fn relays_request_and_streams_response() {
+    let (addr, backend) = fake_backend(&[
+        b"Status: 201 Created\r\nContent-Type: text/plain\r\nX-Pow",
+        b"ered-By: fake\r\n\r\nhello, ",
+        b"world",
+    ]);
+    let router = router(FastCgi::new(&addr.to_string(), "/srv/www"));
+    let mut stream = MemoryStream::new(
+        b"POST /app/form.php?a=1&b=2 HTTP/1.1\r\nHost: example.com:8080\r\n\
+          Content-Type: text/plain\r\nContent-Length: 4\r\nX-Custom: yes\r\n\r\nbody",
+    );
+
+    let exchange = serve_connection(&mut stream, &router, &Config::default()).unwrap();
+
+    assert_eq!(exchange.status, 201);
+    let response = stream.response();
+    assert_eq!(response.status(), 201);
+    assert_eq!(response.header("Content-Type"), Some("text/plain"));
+    assert_eq!(response.header("X-Powered-By"), Some("fake"));
+    assert_eq!(response.header("Content-Length"), None);
+    assert_eq!(response.body, "hello, world");
+
+    let received = backend.join().unwrap();
+    assert_eq!(received.stdin, b"body");
+    let param = |name: &str| received.params.get(name).map(String::as_str);
+    assert_eq!(param("REQUEST_METHOD"), Some("POST"));
+    assert_eq!(param("SCRIPT_NAME"), Some("/app/form.php"));
+    assert_eq!(param("SCRIPT_FILENAME"), Some("/srv/www/app/form.php"));
+    assert_eq!(param("QUERY_STRING"), Some("a=1&b=2"));
+    assert_eq!(param("REQUEST_URI"), Some("/app/form.php?a=1&b=2"));
+    assert_eq!(param("CONTENT_LENGTH"), Some("4"));
+    assert_eq!(param("CONTENT_TYPE"), Some("text/plain"));
+    assert_eq!(param("SERVER_NAME"), Some("example.com"));
+    assert_eq!(param("HTTP_X_CUSTOM"), Some("yes"));
+    assert_eq!(param("HTTP_CONTENT_LENGTH"), None);
+}
+
+#[test]
+fn large_bodies_are_streamed_past_the_head_buffer() {
+    let (addr, backend) = fake_backend(&[b"Content-Type: text/plain\n\nok"]);
+    let router = router(FastCgi::new(&addr.to_string(), "/srv/www"));
+    let body = vec![b'x'; 200_000];
+    let mut request = format!(
+        "PUT /app/upload.php HTTP/1.1\r\nHost: a\r\nContent-Length: {}\r\n\r\n",
+        body.len()
+    )
+    .into_bytes();
+    request.extend(&body);
+    let mut stream = MemoryStream::new(&request);
+
+    let exchange = serve_connection(&mut stream, &router, &Config::default()).unwrap();
+
+    assert_eq!(exchange.status, 200);
+    assert_eq!(stream.response().body, "ok");
+    assert_eq!(backend.join().unwrap().stdin, body);
+}
+
+#[test]
+fn location_without_status_redirects() {
+    let (addr, backend) = fake_backend(&[b"Location: /elsewhere\r\n\r\n"]);
+    let router = router(FastCgi::new(&addr.to_string(), "/srv/www"));
+    let mut stream = MemoryStream::new(b"GET /app/ HTTP/1.1\r\nHost: a\r\n\r\n");
+
+    let exchange = serve_connection(&mut stream, &router, &Config::default()).unwrap();
+
+    assert_eq!(exchange.status, 302);
+    assert_eq!(stream.response().header("Location"), Some("/elsewhere"));
+    backend.join().unwrap();
+}
+
+#[test]
+fn unreachable_backend_gets_502() {
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let router =
+        router(FastCgi::new(&addr.to_string(), "/srv/www").timeout(Duration::from_secs(1)));
+    let mut stream = MemoryStream::new(b"GET /app/index.php HTTP/1.1\r\nHost: a\r\n\r\n");
+
+    let exchange = serve_connection(&mut stream, &router, &Config::default()).unwrap();
+
+    assert_eq!(exchange.status, 502);
+}
+
+#[test]
+fn front_controller_receives_path_info() {
+    let php = FastCgi::new("127.0.0.1:9000", "/srv/www").front_controller("index.php");
+    let request = parse_request(b"GET /app/users/7 HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+
+    let params: HashMap<_, _> = php.params(&request).unwrap().into_iter().collect();
+
+    assert_eq!(params["SCRIPT_NAME"], "/index.php");
+    assert_eq!(params["SCRIPT_FILENAME"], "/srv/www/index.php");
+    assert_eq!(params["PATH_INFO"], "/app/users/7");
+}
+
+#[test]
+fn stream_prefix_covers_only_whole_segments() {
+    let router = router(FastCgi::new("127.0.0.1:9000", "/srv/www"));
+
+    assert!(router.stream_handler("/app").is_some());
+    assert!(router.stream_handler("/app/x.php").is_some());
+    assert!(router.stream_handler("/apple").is_none());
+}
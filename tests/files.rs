@@ -0,0 +1,267 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use rusty_server::files::{content_type, StaticFiles};
+use rusty_server::http::{http_date, parse_http_date, parse_request};
+use rusty_server::Router;
+
+/// A directory of its own for `test`, holding a small site, next to a
+/// secret file outside it.
+fn site(test: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!(
+        "rusty-server-files-{}-{}",
+        test,
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("public/docs")).unwrap();
+    fs::write(root.join("secret.txt"), "secret").unwrap();
+    fs::write(root.join("public/index.html"), "<h1>home</h1>").unwrap();
+    fs::write(root.join("public/site.css"), "body {}").unwrap();
+    fs::write(root.join("public/docs/index.html"), "<h1>docs</h1>").unwrap();
+    root
+}
+
+fn router(dir: &Path, prefix: &str) -> Router {
+    let files = StaticFiles::new(prefix, dir);
+    let mut router = Router::default();
+    router.fallback(move |request| files.serve(request));
+    router
+}
+
+fn get(router: &Router, path: &str) -> rusty_server::Response {
+    let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+    router.handle(&parse_request(request.as_bytes()).unwrap())
+}
+
+#[test]
+fn files_are_served_under_the_prefix_with_their_content_type() {
+    let root = site("serve");
+    let router = router(&root.join("public"), "/static");
+
+    let css = get(&router, "/static/site.css");
+    assert_eq!(css.status, 200);
+    assert_eq!(css.header_value("Content-Type"), Some("text/css; charset=utf-8"));
+    assert_eq!(css.body, b"body {}");
+
+    let docs = get(&router, "/static/docs/");
+    assert_eq!(docs.status, 200);
+    assert_eq!(docs.body, b"<h1>docs</h1>");
+    let redirect = get(&router, "/static/docs");
+    assert_eq!(redirect.status, 301);
+    assert_eq!(redirect.header_value("Location"), Some("/static/docs/"));
+
+    // Routes take precedence, and other paths are left alone.
+    assert_eq!(get(&router, "/version").status, 200);
+    assert_eq!(get(&router, "/staticsite.css").status, 404);
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn missing_files_and_paths_outside_the_directory_get_the_404_page() {
+    let root = site("outside");
+    let router = router(&root.join("public"), "/static");
+
+    for path in [
+        "/static/missing.css",
+        "/static/../secret.txt",
+        "/static/docs/../../secret.txt",
+        "/static/%2e%2e/secret.txt",
+    ] {
+        let response = get(&router, path);
+        assert_eq!(response.status, 404, "{}", path);
+        assert!(!response.body.starts_with(b"secret"), "{}", path);
+    }
+
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(root.join("secret.txt"), root.join("public/link.txt")).unwrap();
+        assert_eq!(get(&router, "/static/link.txt").status, 404);
+    }
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn a_root_prefix_serves_the_index_at_slash() {
+    let root = site("root");
+    let router = router(&root.join("public"), "/");
+
+    assert_eq!(get(&router, "/").body, b"<h1>home</h1>");
+    assert_eq!(get(&router, "/site.css").status, 200);
+    assert_eq!(get(&router, "/healthz").status, 200);
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn content_types_follow_the_extension() {
+    assert_eq!(content_type(Path::new("a/index.HTML")), "text/html; charset=utf-8");
+    assert_eq!(content_type(Path::new("app.js")), "text/javascript; charset=utf-8");
+    assert_eq!(content_type(Path::new("logo.svg")), "image/svg+xml");
+    assert_eq!(content_type(Path::new("archive.tar.xz")), "application/octet-stream");
+    assert_eq!(content_type(Path::new("README")), "application/octet-stream");
+}
+
+#[test]
+fn content_types_can_be_overridden_by_extension() {
+    let root = site("types");
+    fs::write(root.join("public/notes.MD"), "# notes").unwrap();
+    let files = StaticFiles::new("/static", root.join("public"))
+        .mime_type(".md", "text/markdown")
+        .mime_type("CSS", "text/x-custom");
+    let mut router = Router::new();
+    router.fallback(move |request| files.serve(request));
+
+    let notes = get(&router, "/static/notes.MD");
+    assert_eq!(notes.header_value("Content-Type"), Some("text/markdown"));
+    let css = get(&router, "/static/site.css");
+    assert_eq!(css.header_value("Content-Type"), Some("text/x-custom"));
+    let html = get(&router, "/static/index.html");
+    assert_eq!(html.header_value("Content-Type"), Some("text/html; charset=utf-8"));
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn single_page_apps_get_the_index_for_paths_without_a_file() {
+    let root = site("spa");
+    let files = StaticFiles::new("/", root.join("public")).spa(true);
+    let mut router = Router::default();
+    router.fallback(move |request| files.serve(request));
+
+    for path in ["/users/42", "/docs/missing", "/../secret.txt"] {
+        let response = get(&router, path);
+        assert_eq!(response.status, 200, "{}", path);
+        assert_eq!(response.body, b"<h1>home</h1>", "{}", path);
+    }
+    assert_eq!(get(&router, "/site.css").body, b"body {}");
+    assert_eq!(get(&router, "/docs/").body, b"<h1>docs</h1>");
+    assert_eq!(get(&router, "/version").status, 200);
+
+    let post = "POST /users/42 HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\n\r\n";
+    let response = router.handle(&parse_request(post.as_bytes()).unwrap());
+    assert_eq!(response.status, 404);
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn ranges_of_a_file_are_served_as_partial_content() {
+    let root = site("range");
+    fs::write(root.join("public/video.mp4"), "0123456789").unwrap();
+    let router = router(&root.join("public"), "/static");
+    let get_range = |range: &str| {
+        let request = format!(
+            "GET /static/video.mp4 HTTP/1.1\r\nHost: localhost\r\nRange: {}\r\n\r\n",
+            range
+        );
+        router.handle(&parse_request(request.as_bytes()).unwrap())
+    };
+
+    let whole = get(&router, "/static/video.mp4");
+    assert_eq!(whole.status, 200);
+    assert_eq!(whole.header_value("Accept-Ranges"), Some("bytes"));
+    assert!(whole.header_value("ETag").unwrap().ends_with("-a\""));
+
+    for (range, body, content_range) in [
+        ("bytes=2-5", "2345", "bytes 2-5/10"),
+        ("bytes=7-", "789", "bytes 7-9/10"),
+        ("bytes=-3", "789", "bytes 7-9/10"),
+        ("bytes=8-100", "89", "bytes 8-9/10"),
+        ("bytes=-20", "0123456789", "bytes 0-9/10"),
+    ] {
+        let partial = get_range(range);
+        assert_eq!(partial.status, 206, "{}", range);
+        assert_eq!(partial.body, body.as_bytes(), "{}", range);
+        assert_eq!(partial.header_value("Content-Range"), Some(content_range));
+        assert_eq!(partial.header_value("Content-Type"), Some("video/mp4"));
+        // Tagged by the whole file's size, not the part sent.
+        assert_eq!(partial.header_value("ETag"), whole.header_value("ETag"));
+    }
+
+    for range in ["bytes=10-", "bytes=-0"] {
+        let unsatisfiable = get_range(range);
+        assert_eq!(unsatisfiable.status, 416, "{}", range);
+        assert_eq!(unsatisfiable.header_value("Content-Range"), Some("bytes */10"));
+    }
+
+    // Ranges this does not handle get the whole file.
+    for range in ["bytes=0-1,4-5", "items=0-1", "bytes=5-2", "bytes=x-"] {
+        let whole = get_range(range);
+        assert_eq!(whole.status, 200, "{}", range);
+        assert_eq!(whole.body, b"0123456789", "{}", range);
+    }
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn files_are_tagged_by_size_and_modification_time() {
+    let root = site("etag");
+    let router = router(&root.join("public"), "/static");
+    let etag = get(&router, "/static/site.css").header_value("ETag").unwrap().to_string();
+
+    let conditional = |etag: &str, range: &str| {
+        let request = format!(
+            "GET /static/site.css HTTP/1.1\r\nHost: localhost\r\nIf-None-Match: {}\r\n{}\r\n",
+            etag, range
+        );
+        router.handle(&parse_request(request.as_bytes()).unwrap())
+    };
+    let unmodified = conditional(&etag, "");
+    assert_eq!(unmodified.status, 304);
+    assert!(unmodified.body.is_empty());
+    assert_eq!(conditional(&etag, "Range: bytes=0-1\r\n").status, 304);
+
+    fs::write(root.join("public/site.css"), "body { margin: 0 }").unwrap();
+    let changed = conditional(&etag, "");
+    assert_eq!(changed.status, 200);
+    assert_ne!(changed.header_value("ETag"), Some(etag.as_str()));
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn files_are_not_modified_since_their_last_modified_time() {
+    let root = site("modified");
+    let path = root.join("public/site.css");
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    fs::File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+    let router = router(&root.join("public"), "/static");
+
+    let response = get(&router, "/static/site.css");
+    assert_eq!(
+        response.header_value("Last-Modified"),
+        Some("Tue, 14 Nov 2023 22:13:20 GMT")
+    );
+
+    let since = |date: &str, extra: &str| {
+        let request = format!(
+            "GET /static/site.css HTTP/1.1\r\nHost: localhost\r\nIf-Modified-Since: {}\r\n{}\r\n",
+            date, extra
+        );
+        router.handle(&parse_request(request.as_bytes()).unwrap()).status
+    };
+    assert_eq!(since("Tue, 14 Nov 2023 22:13:20 GMT", ""), 304);
+    assert_eq!(since("Wed, 15 Nov 2023 00:00:00 GMT", ""), 304);
+    assert_eq!(since("Tue, 14 Nov 2023 22:13:19 GMT", ""), 200);
+    assert_eq!(since("yesterday", ""), 200);
+    // If-None-Match takes precedence.
+    assert_eq!(since("Wed, 15 Nov 2023 00:00:00 GMT", "If-None-Match: \"other\"\r\n"), 200);
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn http_dates_round_trip() {
+    assert_eq!(http_date(0), "Thu, 01 Jan 1970 00:00:00 GMT");
+    assert_eq!(http_date(784_111_777), "Sun, 06 Nov 1994 08:49:37 GMT");
+    assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(784_111_777));
+    assert_eq!(parse_http_date("Thu, 29 Feb 2024 12:00:00 GMT"), Some(1_709_208_000));
+    for time in [0, 951_782_400, 1_709_208_000, 4_102_444_799] {
+        assert_eq!(parse_http_date(&http_date(time)), Some(time));
+    }
+    for invalid in [
+        "Sunday, 06-Nov-94 08:49:37 GMT",
+        "Sun Nov  6 08:49:37 1994",
+        "Sun, 06 Nov 1994 08:49:37 UTC",
+        "Sun, 06 Nov 1994 25:49:37 GMT",
+    ] {
+        assert_eq!(parse_http_date(invalid), None, "{}", invalid);
+    }
+}
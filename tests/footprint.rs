@@ -0,0 +1,51 @@
+//! Memory budget for serving a burst of requests.
+//!
+//! The release numbers are documented in the README ("Small-footprint build");
+//! this test guards the part that holds in any build: serving a thousand
+//! requests must not grow the process beyond a small, fixed budget.
+
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use common::{get, spawn_server};
+
+/// Allowed resident-set growth across the burst.
+const GROWTH_BUDGET_KIB: u64 = 2 * 1024;
+
+/// Allowed peak resident set of the whole test process, harness included.
+const PEAK_BUDGET_KIB: u64 = 16 * 1024;
+
+fn status_kib(field: &str) -> u64 {
+    let status = std::fs::read_to_string("/proc/self/status").unwrap();
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix(field))
+        .and_then(|rest| rest.trim().trim_end_matches("kB").trim().parse().ok())
+        .unwrap_or_else(|| panic!("{} missing from /proc/self/status", field))
+}
+
+#[test]
+fn thousand_requests_fit_in_rss_budget() {
+    let addr = spawn_server();
+    // Let allocator arenas and lazily initialised state settle first.
+    for _ in 0..20 {
+        get(addr, "/version", &[]);
+    }
+    let before = status_kib("VmRSS:");
+
+    for i in 0..1000 {
+        let path = if i % 2 == 0 { "/version" } else { "/missing" };
+        assert!(get(addr, path, &[]).status() < 500);
+    }
+
+    let after = status_kib("VmRSS:");
+    let peak = status_kib("VmHWM:");
+    println!("rss before={} KiB after={} KiB peak={} KiB", before, after, peak);
+    assert!(
+        after.saturating_sub(before) < GROWTH_BUDGET_KIB,
+        "RSS grew by {} KiB",
+        after - before
+    );
+    assert!(peak < PEAK_BUDGET_KIB, "peak RSS {} KiB", peak);
+}
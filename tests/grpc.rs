@@ -0,0 +1,65 @@
+#![cfg(feature = "grpc")]
+
+use std::net::{SocketAddr, TcpListener};
+use std::thread;
+
+use rusty_server::grpc::proto::stats_client::StatsClient;
+use rusty_server::grpc::proto::GetStatsRequest;
+use tonic::transport::Channel;
+use tonic_health::pb::health_check_response::ServingStatus;
+use tonic_health::pb::health_client::HealthClient;
+use tonic_health::pb::HealthCheckRequest;
+
+fn spawn_grpc() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind ephemeral port");
+    let addr = listener.local_addr().expect("local addr");
+    thread::spawn(move || {
+        let _ = rusty_server::grpc::serve(listener);
+    });
+    addr
+}
+
+#[tokio::test]
+async fn health_reports_serving() {
+    let addr = spawn_grpc();
+    let channel = Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HealthClient::new(channel);
+
+    for service in ["", "rusty_server.v1.Stats"] {
+        let response = client
+            .check(HealthCheckRequest {
+                service: service.to_string(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            response.into_inner().status(),
+            ServingStatus::Serving,
+            "{:?}",
+            service
+        );
+    }
+}
+
+#[tokio::test]
+async fn stats_match_the_http_counters() {
+    let addr = spawn_grpc();
+    let mut client = StatsClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap();
+
+    let stats = client
+        .get_stats(GetStatsRequest {})
+        .await
+        .unwrap()
+        .into_inner();
+
+    assert!(stats.time > 0);
+    // Nothing has connected to an HTTP listener in this test binary.
+    assert_eq!(stats.connections_total, 0);
+    assert_eq!(stats.connections_active, 0);
+}
@@ -0,0 +1,79 @@
+#![cfg(feature = "history")]
+
+mod common;
+
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{get, spawn_server};
+use rusty_server::history::{self, Record};
+use serde_json::Value;
+
+fn record(path: &str, status: Option<u16>) -> Record {
+    Record {
+        time: 1_760_486_400,
+        peer: "127.0.0.1:52110".to_string(),
+        method: "GET".to_string(),
+        path: path.to_string(),
+        status,
+        bytes_read: 78,
+        elapsed: Duration::from_micros(1500),
+    }
+}
+
+#[test]
+fn csv_quotes_fields_that_need_it() {
+    let csv = history::to_csv(&[record("/version", Some(200)), record("/a,\"b\"", None)]);
+
+    assert_eq!(
+        csv,
+        "time,peer,method,path,status,bytes_read,latency_ms\n\
+         1760486400,127.0.0.1:52110,GET,/version,200,78,1.500\n\
+         1760486400,127.0.0.1:52110,GET,\"/a,\"\"b\"\"\",,78,1.500\n"
+    );
+}
+
+#[test]
+fn json_has_a_null_status_for_failures() {
+    let json: Value = serde_json::from_str(&history::to_json(&[
+        record("/version", Some(200)),
+        record("/x", None),
+    ]))
+    .expect("valid JSON");
+
+    assert_eq!(json[0]["path"], "/version");
+    assert_eq!(json[0]["status"], 200);
+    assert_eq!(json[0]["latency_ms"], 1.5);
+    assert!(json[1]["status"].is_null());
+}
+
+#[test]
+fn served_requests_are_exported_to_both_files() {
+    let dir = std::env::temp_dir().join(format!("rusty-server-history-{}", std::process::id()));
+    history::start(&dir);
+    let addr = spawn_server();
+
+    assert_eq!(get(addr, "/version", &[]).status(), 200);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !history::records()
+        .iter()
+        .any(|record| record.path == "/version")
+    {
+        assert!(Instant::now() < deadline, "request was never recorded");
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let export = history::export().expect("export");
+    let csv = fs::read_to_string(&export.csv).expect("CSV file");
+    assert!(csv.lines().any(|line| line.contains(",GET,/version,200,")));
+    let json: Value = serde_json::from_str(&fs::read_to_string(&export.json).expect("JSON file"))
+        .expect("valid JSON");
+    assert!(json
+        .as_array()
+        .expect("array")
+        .iter()
+        .any(|record| record["path"] == "/version" && record["method"] == "GET"));
+
+    fs::remove_dir_all(&dir).expect("clean up");
+}
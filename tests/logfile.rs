@@ -0,0 +1,84 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use rusty_server::logfile::{LogFile, Rotation, UnknownRotation};
+
+/// An empty directory of its own for `test`.
+fn dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "rusty-server-logfile-{}-{}",
+        test,
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn rotations_are_parsed_by_name_and_size() {
+    assert_eq!("never".parse(), Ok(Rotation::Never));
+    assert_eq!("daily".parse(), Ok(Rotation::Daily));
+    assert_eq!("hourly".parse(), Ok(Rotation::Hourly));
+    assert_eq!("4096".parse(), Ok(Rotation::Size(4096)));
+    assert_eq!("512K".parse(), Ok(Rotation::Size(512 << 10)));
+    assert_eq!("10M".parse(), Ok(Rotation::Size(10 << 20)));
+    assert_eq!("1g".parse(), Ok(Rotation::Size(1 << 30)));
+    for invalid in ["", "0", "M", "weekly", "-1K", "99999999999G"] {
+        assert_eq!(
+            invalid.parse::<Rotation>(),
+            Err(UnknownRotation(invalid.to_string()))
+        );
+    }
+}
+
+#[test]
+fn files_rotate_by_size_and_only_the_newest_are_kept() {
+    let dir = dir("size");
+    let path = dir.join("server.log");
+    let mut log = LogFile::open(&path, Rotation::Size(10), 2).unwrap();
+    for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+        log.write_all(line.as_bytes()).unwrap();
+    }
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "fourth\n");
+    assert_eq!(
+        fs::read_to_string(dir.join("server.log.1")).unwrap(),
+        "third\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("server.log.2")).unwrap(),
+        "second\n"
+    );
+    assert!(!dir.join("server.log.3").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn files_from_an_earlier_day_are_rotated_on_open() {
+    let dir = dir("daily");
+    let path = dir.join("server.log");
+    fs::create_dir_all(&dir).unwrap();
+    let mut old = File::create(&path).unwrap();
+    old.write_all(b"yesterday\n").unwrap();
+    old.set_modified(SystemTime::now() - Duration::from_secs(2 * 86_400))
+        .unwrap();
+    drop(old);
+
+    let mut log = LogFile::open(&path, Rotation::Daily, 7).unwrap();
+    log.write_all(b"today\n").unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "today\n");
+    assert_eq!(
+        fs::read_to_string(dir.join("server.log.1")).unwrap(),
+        "yesterday\n"
+    );
+
+    // Without rotation the old file is appended to.
+    drop(log);
+    let mut log = LogFile::open(&path, Rotation::Never, 7).unwrap();
+    log.write_all(b"again\n").unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "today\nagain\n");
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,20 @@
+#![cfg(feature = "mdns")]
+
+use rusty_server::mdns::{advertise, MdnsError};
+
+#[test]
+fn loopback_servers_are_not_advertised() {
+    let result = advertise("demo", "127.0.0.1:3000".parse().unwrap());
+
+    assert!(matches!(result, Err(MdnsError::Loopback(_))));
+}
+
+#[test]
+fn instance_names_are_qualified_with_the_service_type() {
+    let advertisement = advertise("Demo server (lab 3)", "0.0.0.0:3000".parse().unwrap()).unwrap();
+
+    assert_eq!(
+        advertisement.fullname(),
+        "Demo server (lab 3)._http._tcp.local."
+    );
+}
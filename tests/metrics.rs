@@ -0,0 +1,61 @@
+mod common;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{get, spawn_server};
+use rusty_server::metrics::{self, InvalidBuckets};
+
+#[test]
+fn requests_are_counted_and_timed_by_route() {
+    assert_eq!(
+        metrics::set_buckets(&[0.1, 0.1]),
+        Err(InvalidBuckets(vec![0.1, 0.1]))
+    );
+    assert!(metrics::set_buckets(&[f64::NAN]).is_err());
+    metrics::set_buckets(&[0.5, 30.0]).unwrap();
+    let addr = spawn_server();
+    get(addr, "/version", &[]);
+    get(addr, "/version", &[]);
+    get(addr, "/missing/1", &[]);
+
+    // Requests are timed just after their responses are sent.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let body = loop {
+        let body = get(addr, "/metrics", &[]).body;
+        if body.contains(r#"path="unmatched""#) || Instant::now() >= deadline {
+            break body;
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    assert!(body.contains("# TYPE rusty_server_http_request_duration_seconds histogram\n"));
+    let version = r#"path="/version",method="GET",status="200""#;
+    assert!(body.contains(&format!(
+        "rusty_server_http_request_duration_seconds_bucket{{{},le=\"30\"}} 2\n",
+        version
+    )));
+    assert!(body.contains(&format!(
+        "rusty_server_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} 2\n",
+        version
+    )));
+    assert!(body.contains(&format!(
+        "rusty_server_http_request_duration_seconds_count{{{}}} 2\n",
+        version
+    )));
+    assert!(body.contains(&format!(
+        "rusty_server_http_request_duration_seconds_sum{{{}}} ",
+        version
+    )));
+    assert!(body.contains(
+        r#"rusty_server_http_request_duration_seconds_count{path="unmatched",method="GET",status="404"} 1"#
+    ));
+    assert!(body.contains("# TYPE rusty_server_http_requests_total counter\n"));
+    assert!(body.contains(
+        r#"rusty_server_http_requests_total{path="/version",method="GET",class="2xx"} 2"#
+    ));
+    assert!(body.contains(
+        r#"rusty_server_http_requests_total{path="unmatched",method="GET",class="4xx"} 1"#
+    ));
+    assert!(!body.contains("/missing/1"), "{}", body);
+}
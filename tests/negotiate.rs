@@ -0,0 +1,93 @@
+use rusty_server::http::parse_request;
+use rusty_server::negotiate::{accept, preferred, Format, MediaRange};
+use rusty_server::{Request, Router};
+
+fn request(path: &str, accept: &str) -> Request {
+    let head = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n{}\r\n", path, accept);
+    parse_request(head.as_bytes()).unwrap()
+}
+
+#[test]
+fn media_ranges_are_read_with_their_weights() {
+    let request = request("/", "Accept: text/html, application/*;q=0.5;level=1, bogus, */*;q=0\r\n");
+    let range = |kind: &str, subtype: &str, q: f32| MediaRange {
+        kind: kind.to_string(),
+        subtype: subtype.to_string(),
+        q,
+    };
+    assert_eq!(
+        accept(&request),
+        [range("text", "html", 1.0), range("application", "*", 0.5), range("*", "*", 0.0)]
+    );
+    assert!(accept(&request)[1].matches("application/json; charset=utf-8"));
+}
+
+#[test]
+fn the_most_specific_matching_range_sets_the_weight() {
+    let offered = ["text/html", "application/json", "text/plain"];
+    for (header, chosen) in [
+        ("", Some("text/html")),
+        ("Accept: */*\r\n", Some("text/html")),
+        ("Accept: application/json\r\n", Some("application/json")),
+        ("Accept: text/html;q=0.8, application/json\r\n", Some("application/json")),
+        ("Accept: text/*;q=0.5, text/plain\r\n", Some("text/plain")),
+        ("Accept: */*;q=0.1, text/html;q=0\r\n", Some("application/json")),
+        ("Accept: TEXT/PLAIN\r\n", Some("text/plain")),
+        ("Accept: image/png\r\n", None),
+    ] {
+        assert_eq!(preferred(&request("/", header), &offered), chosen, "{:?}", header);
+    }
+}
+
+#[test]
+fn formats_fall_back_to_the_first_offered() {
+    let offered = [Format::Json, Format::Text];
+    assert_eq!(Format::negotiate(&request("/", "Accept: text/plain\r\n"), &offered), Format::Text);
+    assert_eq!(Format::negotiate(&request("/", "Accept: image/png\r\n"), &offered), Format::Json);
+}
+
+#[test]
+fn endpoints_and_error_pages_answer_in_the_preferred_format() {
+    let router = Router::default();
+
+    let version = router.handle(&request("/version", "Accept: text/plain\r\n"));
+    assert_eq!(version.header_value("Content-Type"), Some("text/plain; charset=utf-8"));
+    assert!(version.body.starts_with(b"version: "));
+    let healthz = router.handle(&request("/healthz", "Accept: text/plain, application/json;q=0.5\r\n"));
+    assert_eq!(healthz.body, b"ok\n");
+
+    let missing = router.handle(&request("/nope", "Accept: application/json\r\n"));
+    assert_eq!(missing.status, 404);
+    assert_eq!(
+        missing.body,
+        br#"{"status":404,"error":"Not Found","message":"No endpoint at /nope."}"#
+    );
+    let missing = router.handle(&request("/nope", "Accept: text/plain\r\n"));
+    assert_eq!(missing.header_value("Content-Type"), Some("text/plain; charset=utf-8"));
+    assert!(missing.body.starts_with(b"404 Not Found: /nope"));
+
+    let post = "POST /version HTTP/1.1\r\nHost: localhost\r\nAccept: application/json\r\nContent-Length: 0\r\n\r\n";
+    let refused = router.handle(&parse_request(post.as_bytes()).unwrap());
+    assert_eq!(refused.status, 405);
+    assert_eq!(refused.header_value("Content-Type"), Some("application/json"));
+}
+
+#[test]
+fn negotiated_responses_vary_by_accept() {
+    let router = Router::default();
+
+    for (path, accept) in [
+        ("/version", "Accept: application/json\r\n"),
+        ("/version", ""),
+        ("/healthz", "Accept: text/plain\r\n"),
+        ("/nope", "Accept: application/json\r\n"),
+        ("/nope", "Accept: text/html\r\n"),
+    ] {
+        let response = router.handle(&request(path, accept));
+        assert_eq!(response.header_value("Vary"), Some("Accept"), "{} {:?}", path, accept);
+    }
+    let refused = parse_request(b"DELETE /version HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let refused = router.handle(&refused);
+    assert_eq!(refused.status, 405);
+    assert_eq!(refused.header_value("Vary"), Some("Accept"));
+}
@@ -0,0 +1,64 @@
+use rusty_server::http::parse_request;
+use rusty_server::openapi::document;
+use rusty_server::{Response, Router};
+use serde_json::Value;
+
+fn get(router: &Router, path: &str) -> Response {
+    let head = format!("GET {} HTTP/1.1\r\nHost: x\r\n\r\n", path);
+    router.handle(&parse_request(head.as_bytes()).unwrap())
+}
+
+#[test]
+fn documents_list_every_route_by_method() {
+    let mut router = Router::new();
+    router.get("/items", |_| Response::new(200));
+    router.route("POST", "/items", |_| Response::new(201));
+    router.route("BREW", "/coffee", |_| Response::new(418));
+    router.events("/events", |_, _| {});
+
+    let document: Value = serde_json::from_str(&document(&router)).unwrap();
+
+    assert_eq!(document["openapi"], "3.0.3");
+    assert_eq!(document["info"]["version"], env!("CARGO_PKG_VERSION"));
+    let paths = document["paths"].as_object().unwrap();
+    assert_eq!(paths.len(), 2);
+    assert!(paths["/items"]["get"].is_object());
+    assert!(paths["/items"]["post"].is_object());
+    assert!(
+        paths["/events"]["get"]["responses"]["200"]["content"]["text/event-stream"].is_object()
+    );
+}
+
+#[test]
+fn route_patterns_are_documented_as_path_templates() {
+    let mut router = Router::new();
+    router.get("/kv/:key", |_| Response::new(200));
+    router.get("/static/*file", |_| Response::new(200));
+
+    let document: Value = serde_json::from_str(&document(&router)).unwrap();
+
+    let paths = document["paths"].as_object().unwrap();
+    let parameters = &paths["/kv/{key}"]["get"]["parameters"];
+    assert_eq!(parameters[0]["name"], "key");
+    assert_eq!(parameters[0]["in"], "path");
+    assert_eq!(parameters[0]["required"], true);
+    assert_eq!(paths["/static/{file}"]["get"]["parameters"][0]["name"], "file");
+    assert!(paths["/kv/{key}"]["get"]["responses"].is_object());
+}
+
+#[test]
+fn docs_are_only_served_when_asked_for() {
+    let mut router = Router::default();
+    assert_eq!(get(&router, "/openapi.json").status, 404);
+
+    router.docs();
+
+    let served = get(&router, "/openapi.json");
+    assert_eq!(served.status, 200);
+    let document: Value = serde_json::from_slice(&served.body).unwrap();
+    assert!(document["paths"]["/version"]["get"].is_object());
+    assert!(document["paths"]["/docs"]["get"].is_object());
+    let page = get(&router, "/docs");
+    assert_eq!(page.status, 200);
+    assert!(String::from_utf8_lossy(&page.body).contains("\"/openapi.json\""));
+}
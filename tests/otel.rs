@@ -0,0 +1,239 @@
+#![cfg(feature = "otel")]
+
+mod common;
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+
+use common::get;
+use rusty_server::otel::{self, traceparent, Otlp, Span, Value};
+use rusty_server::{Response, Server};
+use serde_json::Value as Json;
+
+const WAIT: Duration = Duration::from_secs(5);
+
+/// A collector accepting exports to `/v1/traces`, and the bodies it got.
+fn collector() -> (String, Receiver<Json>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/v1/traces", listener.local_addr().unwrap());
+    let (sender, exports) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut reader = BufReader::new(stream.unwrap());
+            let sender = sender.clone();
+            thread::spawn(move || loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                    return;
+                }
+                assert_eq!(line, "POST /v1/traces HTTP/1.1\r\n");
+                let mut length = 0;
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    match line.trim_end().split_once(": ") {
+                        Some(("Content-Type", value)) => assert_eq!(value, "application/json"),
+                        Some(("Content-Length", value)) => length = value.parse().unwrap(),
+                        Some(_) => {}
+                        None => break,
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{{}}"
+                )
+                .unwrap();
+                let _ = sender.send(serde_json::from_slice(&body).unwrap());
+            });
+        }
+    });
+    (url, exports)
+}
+
+/// The spans of an export.
+fn spans(export: &Json) -> Vec<&Json> {
+    export["resourceSpans"][0]["scopeSpans"][0]["spans"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .collect()
+}
+
+fn attribute<'a>(span: &'a Json, key: &str) -> Option<&'a Json> {
+    span["attributes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|attribute| attribute["key"] == key)
+        .map(|attribute| &attribute["value"])
+}
+
+#[test]
+fn traceparent_headers_are_parsed() {
+    assert_eq!(
+        traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"),
+        Some((0x4bf92f3577b34da6a3ce929d0e0e4736, 0x00f067aa0ba902b7, true))
+    );
+    assert_eq!(
+        traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00"),
+        Some((
+            0x4bf92f3577b34da6a3ce929d0e0e4736,
+            0x00f067aa0ba902b7,
+            false
+        ))
+    );
+    // Later versions may append fields.
+    assert!(traceparent("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra").is_some());
+
+    for invalid in [
+        "",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+        "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+        "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+        "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+    ] {
+        assert_eq!(traceparent(invalid), None, "{}", invalid);
+    }
+}
+
+#[test]
+fn spans_are_encoded_as_otlp_json() {
+    let span = Span {
+        trace_id: 0x4bf92f3577b34da6a3ce929d0e0e4736,
+        span_id: 0xff,
+        parent_span_id: Some(0x00f067aa0ba902b7),
+        name: "GET /status/:code".to_string(),
+        start: UNIX_EPOCH + Duration::from_nanos(1_000_000_001),
+        end: UNIX_EPOCH + Duration::from_nanos(1_000_500_001),
+        attributes: vec![
+            ("http.route", Value::String("/status/:code".to_string())),
+            ("http.response.status_code", Value::Int(503)),
+        ],
+        error: true,
+    };
+
+    let export: Json = serde_json::from_str(&otel::to_json("demo \"svc\"", &[span])).unwrap();
+
+    let resource = &export["resourceSpans"][0]["resource"]["attributes"][0];
+    assert_eq!(resource["key"], "service.name");
+    assert_eq!(resource["value"]["stringValue"], "demo \"svc\"");
+    let span = spans(&export)[0];
+    assert_eq!(span["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+    assert_eq!(span["spanId"], "00000000000000ff");
+    assert_eq!(span["parentSpanId"], "00f067aa0ba902b7");
+    assert_eq!(span["kind"], 2);
+    assert_eq!(span["startTimeUnixNano"], "1000000001");
+    assert_eq!(span["endTimeUnixNano"], "1000500001");
+    assert_eq!(
+        attribute(span, "http.route").unwrap()["stringValue"],
+        "/status/:code"
+    );
+    assert_eq!(
+        attribute(span, "http.response.status_code").unwrap()["intValue"],
+        "503"
+    );
+    assert_eq!(span["status"]["code"], 2);
+}
+
+#[test]
+fn requests_are_exported_as_server_spans() {
+    let (url, exports) = collector();
+    let exporter = Otlp::new(url)
+        .service_name("test")
+        .interval(Duration::from_secs(60))
+        .start();
+    assert!(otel::install(exporter));
+    let server = Server::builder()
+        .bind("127.0.0.1:0")
+        .get("/status/:code", |request| {
+            Response::text(request.param("code").unwrap().parse().unwrap(), "")
+        })
+        .get("/version", |_| Response::text(200, "1"))
+        .build()
+        .unwrap();
+    let addr = server.local_addrs()[0];
+    thread::spawn(move || server.run());
+
+    get(
+        addr,
+        "/status/503?x=1",
+        &["traceparent: 00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"],
+    );
+    get(addr, "/version", &["User-Agent: test/1.0"]);
+    get(
+        addr,
+        "/version",
+        &["traceparent: 00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00"],
+    );
+    // The spans are recorded after the responses are sent.
+    thread::sleep(Duration::from_millis(200));
+    assert!(otel::installed().unwrap().flush(WAIT));
+
+    let export = exports.recv_timeout(WAIT).unwrap();
+    assert_eq!(
+        export["resourceSpans"][0]["resource"]["attributes"][0]["value"]["stringValue"],
+        "test"
+    );
+    let spans = spans(&export);
+    // The unsampled request is left out.
+    assert_eq!(spans.len(), 2, "{:#}", export);
+
+    let status = spans
+        .iter()
+        .find(|span| span["name"] == "GET /status/:code")
+        .unwrap();
+    assert_eq!(status["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+    assert_eq!(status["parentSpanId"], "00f067aa0ba902b7");
+    assert_eq!(
+        attribute(status, "http.request.method").unwrap()["stringValue"],
+        "GET"
+    );
+    assert_eq!(
+        attribute(status, "http.route").unwrap()["stringValue"],
+        "/status/:code"
+    );
+    assert_eq!(
+        attribute(status, "url.path").unwrap()["stringValue"],
+        "/status/503"
+    );
+    assert_eq!(
+        attribute(status, "url.query").unwrap()["stringValue"],
+        "x=1"
+    );
+    assert_eq!(
+        attribute(status, "http.response.status_code").unwrap()["intValue"],
+        "503"
+    );
+    assert_eq!(
+        attribute(status, "error.type").unwrap()["stringValue"],
+        "503"
+    );
+    assert_eq!(
+        attribute(status, "client.address").unwrap()["stringValue"],
+        "127.0.0.1"
+    );
+    assert_eq!(status["status"]["code"], 2);
+
+    let version = spans
+        .iter()
+        .find(|span| span["name"] == "GET /version")
+        .unwrap();
+    assert!(version.get("parentSpanId").is_none());
+    assert_ne!(version["traceId"], status["traceId"]);
+    assert_eq!(
+        attribute(version, "user_agent.original").unwrap()["stringValue"],
+        "test/1.0"
+    );
+    assert_eq!(
+        attribute(version, "network.protocol.version").unwrap()["stringValue"],
+        "1.1"
+    );
+    assert!(version.get("status").is_none());
+}
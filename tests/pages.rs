@@ -0,0 +1,64 @@
+#![cfg(feature = "html")]
+
+use std::fs;
+
+use rusty_server::http::parse_request;
+use rusty_server::pages::{error, set_error_pages};
+use rusty_server::{Response, Router};
+
+fn get(router: &Router, path: &str, accept: &str) -> Response {
+    let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n{}\r\n", path, accept);
+    router.handle(&parse_request(request.as_bytes()).unwrap())
+}
+
+#[test]
+fn error_pages_from_a_directory_replace_the_built_in_ones() {
+    let dir = std::env::temp_dir().join(format!("rusty-server-pages-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("404.html"),
+        "<h1>{{ status }} {{ reason }}</h1><p>{{path}}</p>{{ endpoints }}",
+    )
+    .unwrap();
+    fs::write(dir.join("500.html"), "<h1>Sorry: {{ message }}</h1>").unwrap();
+    fs::write(dir.join("notes.txt"), "not a page").unwrap();
+    fs::write(dir.join("200.html"), "not an error").unwrap();
+    assert_eq!(set_error_pages(&dir).unwrap(), [404, 500]);
+    let router = Router::default();
+
+    let missing = get(&router, "/%3Cb%3E", "");
+    assert_eq!(missing.status, 404);
+    assert_eq!(missing.header_value("Content-Type"), Some("text/html"));
+    let body = String::from_utf8(missing.body).unwrap();
+    assert!(body.starts_with("<h1>404 Not Found</h1><p>/&lt;b&gt;</p><table"), "{}", body);
+    assert!(body.contains(r#"<td class="endpoint-path">/healthz</td>"#));
+    assert!(body.ends_with("</table>"));
+
+    // Other formats, and codes without a template, are as they were.
+    let json = get(&router, "/nope", "Accept: application/json\r\n");
+    assert_eq!(json.header_value("Content-Type"), Some("application/json"));
+    let failed = error(500, "Internal Server Error", "disk <full>");
+    assert_eq!(failed.body, b"<h1>Sorry: disk &lt;full&gt;</h1>");
+    assert!(String::from_utf8(error(400, "Bad Request", "no").body).unwrap().contains("400 - Bad Request"));
+
+    assert!(set_error_pages(&dir.join("missing")).is_err());
+    // Misspelled placeholders and unclosed ones are refused, keeping the
+    // pages loaded before.
+    let broken = dir.join("broken");
+    fs::create_dir(&broken).unwrap();
+    for template in ["<p>{{ mesage }}</p>", "<p>{{ message </p>"] {
+        fs::write(broken.join("500.html"), template).unwrap();
+        let e = set_error_pages(&broken).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+        assert!(e.to_string().contains("500.html"), "{}", e);
+    }
+    assert_eq!(error(500, "Internal Server Error", "x").body, b"<h1>Sorry: x</h1>");
+    // Loading another directory replaces the pages.
+    let empty = dir.join("empty");
+    fs::create_dir(&empty).unwrap();
+    assert!(set_error_pages(&empty).unwrap().is_empty());
+    let body = get(&router, "/nope", "").body;
+    assert!(String::from_utf8(body).unwrap().contains("Error: Path Not Found"));
+    fs::remove_dir_all(&dir).unwrap();
+}
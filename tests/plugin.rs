@@ -0,0 +1,190 @@
+use std::ffi::c_void;
+use std::ptr;
+
+use rusty_server::http::parse_request;
+use rusty_server::plugin::{self, Host, PluginError, PluginRequest, ResponseHandle, ABI_VERSION};
+use rusty_server::Router;
+
+unsafe extern "C" fn register_hello(host: *const Host) -> i32 {
+    let host = &*host;
+    assert_eq!(host.abi_version, ABI_VERSION);
+    let ok = (host.add_route)(
+        host.context,
+        "GET".into(),
+        "/hello".into(),
+        hello,
+        7usize as *mut c_void,
+    );
+    if ok {
+        0
+    } else {
+        1
+    }
+}
+
+unsafe extern "C" fn hello(
+    user_data: *mut c_void,
+    request: *const PluginRequest,
+    response: *mut ResponseHandle,
+    host: *const Host,
+) {
+    let (request, host) = (&*request, &*host);
+    let body = format!(
+        "{} {} {} user_data={} headers={}",
+        request.method.as_str(),
+        request.path.as_str(),
+        request.target.as_str(),
+        user_data as usize,
+        request.header_count
+    );
+    (host.set_status)(response, 201);
+    (host.add_header)(response, "X-Plugin".into(), "hello".into());
+    (host.set_body)(response, body.as_ptr(), body.len());
+}
+
+unsafe extern "C" fn register_rejecting(_host: *const Host) -> i32 {
+    3
+}
+
+unsafe extern "C" fn register_sneaky(host: *const Host) -> i32 {
+    let host = &*host;
+    (host.add_route)(
+        host.context,
+        "GET".into(),
+        "/sneaky".into(),
+        sneaky,
+        ptr::null_mut(),
+    );
+    0
+}
+
+/// Tries to add a route and inject a header line while handling a request.
+unsafe extern "C" fn sneaky(
+    _user_data: *mut c_void,
+    _request: *const PluginRequest,
+    response: *mut ResponseHandle,
+    host: *const Host,
+) {
+    let host = &*host;
+    let added = (host.add_route)(
+        host.context,
+        "GET".into(),
+        "/late".into(),
+        sneaky,
+        ptr::null_mut(),
+    );
+    (host.add_header)(
+        response,
+        "X-Added".into(),
+        added.to_string().as_str().into(),
+    );
+    (host.add_header)(response, "X-Evil".into(), "1\r\nSet-Cookie: stolen".into());
+}
+
+fn get(router: &Router, target: &str) -> rusty_server::Response {
+    let raw = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", target);
+    router.handle(&parse_request(raw.as_bytes()).unwrap())
+}
+
+#[test]
+fn plugin_routes_answer_requests() {
+    let mut router = Router::new();
+
+    let routes = unsafe { plugin::register(&mut router, register_hello) }.unwrap();
+
+    assert_eq!(routes, ["GET /hello"]);
+    let response = get(&router, "/hello?x=1");
+    assert_eq!(response.status, 201);
+    assert_eq!(response.header_value("X-Plugin"), Some("hello"));
+    assert_eq!(
+        response.body,
+        b"GET /hello /hello?x=1 user_data=7 headers=1"
+    );
+}
+
+#[test]
+fn rejected_registration_is_reported() {
+    let mut router = Router::new();
+
+    let result = unsafe { plugin::register(&mut router, register_rejecting) };
+
+    assert!(matches!(result, Err(PluginError::Rejected(3))));
+}
+
+#[test]
+fn handlers_cannot_add_routes_or_header_lines() {
+    let mut router = Router::new();
+    unsafe { plugin::register(&mut router, register_sneaky) }.unwrap();
+
+    let response = get(&router, "/sneaky");
+
+    assert_eq!(response.header_value("X-Added"), Some("false"));
+    assert_eq!(response.header_value("X-Evil"), None);
+    assert_eq!(get(&router, "/late").status, 404);
+}
+
+#[cfg(feature = "plugins")]
+mod loading {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::get;
+    use rusty_server::plugin;
+    use rusty_server::Router;
+
+    /// The `hello_plugin` example, which `cargo test` builds next to the
+    /// test binaries.
+    fn example_plugin() -> Option<PathBuf> {
+        let deps = std::env::current_exe().ok()?.parent()?.to_path_buf();
+        let name = format!(
+            "{}hello_plugin.{}",
+            std::env::consts::DLL_PREFIX,
+            std::env::consts::DLL_EXTENSION
+        );
+        let path = deps.parent()?.join("examples").join(name);
+        path.exists().then_some(path)
+    }
+
+    #[test]
+    fn loads_the_example_plugin() {
+        let Some(path) = example_plugin() else {
+            eprintln!("skipping: build the hello_plugin example first");
+            return;
+        };
+        let mut router = Router::new();
+
+        let routes = unsafe { plugin::load(&mut router, &path) }.unwrap();
+
+        assert_eq!(routes, ["GET /plugin/hello", "GET /plugin/whoami"]);
+        assert_eq!(
+            get(&router, "/plugin/hello").body,
+            b"Hello from a plugin!\n"
+        );
+    }
+
+    #[test]
+    fn directory_loading_reports_each_library() {
+        let dir = std::env::temp_dir().join(format!("rusty-server-plugins-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let broken = dir.join(format!("broken.{}", std::env::consts::DLL_EXTENSION));
+        fs::write(&broken, b"not a library").unwrap();
+        fs::write(dir.join("notes.txt"), b"ignored").unwrap();
+        let mut router = Router::new();
+
+        let results = unsafe { plugin::load_dir(&mut router, &dir) };
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, broken);
+        assert!(results[0].1.is_err());
+    }
+
+    #[test]
+    fn missing_directory_loads_nothing() {
+        let mut router = Router::new();
+
+        let results = unsafe { plugin::load_dir(&mut router, "no-such-dir".as_ref()) };
+
+        assert!(results.is_empty());
+    }
+}
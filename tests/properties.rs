@@ -0,0 +1,106 @@
+//! Property-based tests for percent-decoding, request parsing, and routing.
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+use rusty_server::http::{parse_request, percent_decode, percent_encode_path};
+use rusty_server::Router;
+
+fn method() -> impl Strategy<Value = String> {
+    prop_oneof![
+        Just("GET".to_string()),
+        Just("HEAD".to_string()),
+        Just("POST".to_string()),
+        "[A-Z]{1,8}",
+    ]
+}
+
+/// Arbitrary decoded paths, including non-ASCII and reserved characters.
+fn decoded_path() -> impl Strategy<Value = String> {
+    prop_oneof![
+        Just("/version".to_string()),
+        "\\PC{0,40}".prop_map(|s| format!("/{}", s)),
+    ]
+}
+
+fn query() -> impl Strategy<Value = String> {
+    "[A-Za-z0-9=&._~-]{0,24}"
+}
+
+fn header_name() -> impl Strategy<Value = String> {
+    "[A-Za-z0-9!#$%&'*+.^_`|~-]{1,16}".prop_filter("framing headers change the outcome", |name| {
+        !["host", "content-length", "transfer-encoding"]
+            .contains(&name.to_ascii_lowercase().as_str())
+    })
+}
+
+/// Visible ASCII with interior spaces, so values survive whitespace trimming.
+fn header_value() -> impl Strategy<Value = String> {
+    "[!-~]( ?[!-~]){0,15}"
+}
+
+fn request_bytes(method: &str, target: &str, headers: &[(String, String)]) -> Vec<u8> {
+    let mut raw = format!("{} {} HTTP/1.1\r\nHost: example\r\n", method, target);
+    for (name, value) in headers {
+        raw.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    raw.push_str("\r\n");
+    raw.into_bytes()
+}
+
+proptest! {
+    #[test]
+    fn percent_encoding_round_trips(s in "\\PC*") {
+        prop_assert_eq!(percent_decode(&percent_encode_path(&s)), Some(s));
+    }
+
+    #[test]
+    fn percent_decode_never_panics(s in "[%0-9A-Fa-fg-z/]*") {
+        let _ = percent_decode(&s);
+    }
+
+    #[test]
+    fn parser_decodes_encoded_paths(path in decoded_path()) {
+        let raw = request_bytes("GET", &percent_encode_path(&path), &[]);
+        let request = parse_request(&raw).unwrap();
+        prop_assert_eq!(request.path, path);
+    }
+
+    #[test]
+    fn routing_depends_only_on_method_and_decoded_path(method in method(), path in decoded_path()) {
+        let router = Router::default();
+        let raw = request_bytes(&method, &percent_encode_path(&path), &[]);
+        let request = parse_request(&raw).unwrap();
+        prop_assert_eq!(
+            router.handler(&request.method, &request.path).is_some(),
+            router.handler(&method, &path).is_some()
+        );
+        prop_assert_eq!(router.handle(&request).status, router.handle(&request).status);
+    }
+
+    #[test]
+    fn query_string_does_not_affect_routing(path in decoded_path(), query in query()) {
+        let router = Router::default();
+        let with_query = format!("{}?{}", percent_encode_path(&path), query);
+        let request = parse_request(&request_bytes("GET", &with_query, &[])).unwrap();
+        let plain = parse_request(&request_bytes("GET", &percent_encode_path(&path), &[])).unwrap();
+        prop_assert_eq!(&request.path, &path);
+        prop_assert_eq!(router.handle(&request).status, router.handle(&plain).status);
+    }
+
+    #[test]
+    fn only_get_version_has_a_builtin_route(method in method(), path in decoded_path()) {
+        let expected = method == "GET" && path == "/version";
+        prop_assert_eq!(Router::default().handler(&method, &path).is_some(), expected);
+    }
+
+    #[test]
+    fn header_sets_round_trip(headers in vec((header_name(), header_value()), 0..8)) {
+        let request = parse_request(&request_bytes("GET", "/", &headers)).unwrap();
+        prop_assert_eq!(&request.headers[1..], &headers[..]);
+        for (name, value) in &headers {
+            let first = headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).unwrap();
+            prop_assert_eq!(request.header(name), Some(first.1.as_str()));
+            prop_assert!(request.header_all(name).any(|v| v == value));
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Guards the public API against accidental breaking changes.
+//!
+//! `signatures_are_stable` pins every public item and function signature at
+//! compile time, so removing or changing one fails the build of this test.
+//! `matches_snapshot` compares the full API listing from
+//! [cargo-public-api](https://github.com/cargo-public-api/cargo-public-api)
+//! with `tests/public-api.txt`; it is skipped when the tool or a nightly
+//! toolchain is missing. After an intentional API change, regenerate the
+//! snapshot with `UPDATE_PUBLIC_API=1 cargo test --test public_api`.
+
+// Spelling signatures out in full is the point of this file.
+#![allow(clippy::type_complexity)]
+
+use std::io::Cursor;
+use std::net::TcpListener;
+use std::process::{Command, ExitCode};
+
+use rusty_server::http::{self, ParseError, Version};
+use rusty_server::router::{Handler, Router};
+use rusty_server::server::{self, Config, Exchange};
+use rusty_server::{Request, Response, ServerError};
+
+const SNAPSHOT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/public-api.txt");
+
+#[test]
+fn signatures_are_stable() {
+    // Functions.
+    let _: fn(TcpListener) -> Result<(), ServerError> = server::serve;
+    let _: fn(TcpListener, Router, Config) -> Result<(), ServerError> = server::serve_with;
+    let _: fn(Vec<TcpListener>, Router, Config) -> Result<(), ServerError> = server::serve_all;
+    let _: fn(Cursor<Vec<u8>>, &Router, &Config) -> Result<Exchange, ServerError> =
+        server::serve_connection::<Cursor<Vec<u8>>>;
+    let _: fn(Cursor<Vec<u8>>) -> Result<(usize, String, String), ServerError> =
+        server::handle_connection::<Cursor<Vec<u8>>>;
+    let _: fn(&[u8]) -> Result<Request, ParseError> = http::parse_request;
+    let _: fn(&[u8], usize) -> Result<Request, ParseError> = http::parse_request_with_limit;
+    let _: fn(&str) -> Option<String> = http::percent_decode;
+    let _: fn(&str) -> String = http::percent_encode_path;
+    let _: fn(u16) -> &'static str = http::reason_phrase;
+    let _: usize = http::MAX_HEAD_SIZE;
+
+    // Root re-exports point at the same items as their modules.
+    let _: fn(TcpListener) -> Result<(), ServerError> = rusty_server::serve;
+    let _: fn(TcpListener, Router, Config) -> Result<(), ServerError> = rusty_server::serve_with;
+    let _: fn(Vec<TcpListener>, Router, Config) -> Result<(), ServerError> = rusty_server::serve_all;
+    let _: Option<rusty_server::Router> = None::<Router>;
+    let _: Option<rusty_server::Config> = None::<Config>;
+    let _: Option<rusty_server::Exchange> = None::<Exchange>;
+    let _: Option<rusty_server::Handler> = None::<Handler>;
+
+    // Types and their public fields.
+    let request = http::parse_request(b"GET /a?b HTTP/1.1\r\nHost: h\r\n\r\n").unwrap();
+    let Request {
+        method: _,
+        target: _,
+        path: _,
+        version: Version::Http10 | Version::Http11,
+        headers: _,
+        body: _,
+        secure: _,
+        client_cn: _,
+        peer: _,
+        params: _,
+        id: _,
+    } = request;
+    let Response {
+        status: _,
+        headers: _,
+        body: _,
+    } = Response::new(200);
+    let Exchange {
+        bytes_read: _,
+        status: _,
+        path: _,
+        request: _,
+        response_headers: _,
+        request_id: _,
+    } = Exchange {
+        bytes_read: 0,
+        status: 200,
+        path: String::new(),
+        request: None,
+        response_headers: Vec::new(),
+        request_id: String::new(),
+    };
+    let _: usize = Config::default().max_head_size;
+
+    let error = ServerError::Timeout("reading");
+    let _: (u16, &'static str) = error.status();
+    let _: ExitCode = error.exit_code();
+    match error {
+        ServerError::Bind { addr: _, source: _ }
+        | ServerError::Parse(_)
+        | ServerError::Timeout(_)
+        | ServerError::Handler(_)
+        | ServerError::Tls(_)
+        | ServerError::Io(_) => {}
+    }
+}
+
+#[test]
+fn matches_snapshot() {
+    let output = Command::new("cargo")
+        .args(["+nightly", "public-api", "-ss", "--color", "never"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output();
+    let listing = match output {
+        Ok(output) if output.status.success() => String::from_utf8(output.stdout).unwrap(),
+        _ => {
+            eprintln!("cargo-public-api or nightly unavailable, skipping snapshot check");
+            return;
+        }
+    };
+
+    if std::env::var_os("UPDATE_PUBLIC_API").is_some() {
+        std::fs::write(SNAPSHOT, &listing).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(SNAPSHOT).unwrap_or_default();
+    assert!(
+        listing == expected,
+        "public API changed; review the difference and regenerate tests/public-api.txt:\n{}",
+        listing
+    );
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use std::net::{IpAddr, Ipv4Addr, TcpListener};
+use std::thread;
+
+use common::get;
+use rusty_server::http::parse_request;
+use rusty_server::ratelimit::{RateLimit, MAX_CLIENTS};
+use rusty_server::{serve_with, Router};
+
+const CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+const OTHER: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+
+#[test]
+fn clients_get_their_burst_then_wait() {
+    let limit = RateLimit::new(0.5).burst(3);
+    for _ in 0..3 {
+        assert_eq!(limit.acquire(CLIENT), Ok(()));
+    }
+    let wait = limit.acquire(CLIENT).unwrap_err();
+    assert!(wait.as_secs_f64() > 1.5 && wait.as_secs_f64() <= 2.0, "{:?}", wait);
+
+    // Each client has its own bucket.
+    assert_eq!(limit.acquire(OTHER), Ok(()));
+}
+
+#[test]
+fn the_burst_defaults_to_the_rate() {
+    let limit = RateLimit::new(1.5);
+    assert_eq!(limit.acquire(CLIENT), Ok(()));
+    assert_eq!(limit.acquire(CLIENT), Ok(()));
+    assert!(limit.acquire(CLIENT).is_err());
+
+    let limit = RateLimit::new(0.1).burst(0);
+    assert_eq!(limit.acquire(CLIENT), Ok(()));
+    assert!(limit.acquire(CLIENT).is_err());
+}
+
+#[test]
+fn requests_without_a_client_address_are_not_limited() {
+    let mut router = Router::default();
+    router.middleware(RateLimit::new(0.1).burst(1));
+    let request = parse_request(b"GET /healthz HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    for _ in 0..3 {
+        assert_eq!(router.handle(&request).status, 200);
+    }
+}
+
+#[test]
+fn throttled_requests_get_429_and_are_counted() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut router = Router::default();
+    router.middleware(RateLimit::new(0.2).burst(2));
+    thread::spawn(move || serve_with(listener, router, Default::default()));
+
+    assert_eq!(get(addr, "/healthz", &[]).status(), 200);
+    assert_eq!(get(addr, "/healthz", &[]).status(), 200);
+    let throttled = get(addr, "/healthz", &["Accept: text/plain"]);
+    assert_eq!(throttled.status(), 429);
+    assert_eq!(throttled.header("Retry-After"), Some("5"));
+    assert!(throttled.body.starts_with("429 Too Many Requests"), "{}", throttled.body);
+
+    // /metrics is behind the limit too, so read it through a router without one.
+    let metrics = Router::default();
+    let request = parse_request(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let body = String::from_utf8(metrics.handle(&request).body).unwrap();
+    let count = body
+        .lines()
+        .find_map(|line| line.strip_prefix("rusty_server_throttled_requests_total "))
+        .and_then(|count| count.parse::<u64>().ok())
+        .unwrap();
+    assert!(count >= 1, "{}", body);
+}
+
+#[test]
+fn ipv6_clients_share_a_bucket_per_64() {
+    let client = |address: &str| address.parse::<IpAddr>().unwrap();
+    let limit = RateLimit::new(0.1).burst(1);
+    assert_eq!(limit.acquire(client("2001:db8:0:1::1")), Ok(()));
+    assert!(limit.acquire(client("2001:db8:0:1:ffff::2")).is_err());
+    assert_eq!(limit.acquire(client("2001:db8:0:2::1")), Ok(()));
+
+    // IPv4-mapped addresses are their IPv4 address.
+    assert_eq!(limit.acquire(CLIENT), Ok(()));
+    assert!(limit.acquire(client("::ffff:192.0.2.1")).is_err());
+}
+
+#[test]
+fn the_oldest_buckets_make_way_past_the_cap() {
+    let limit = RateLimit::new(0.001).burst(1);
+    assert_eq!(limit.acquire(CLIENT), Ok(()));
+    for n in 0..MAX_CLIENTS as u32 {
+        assert_eq!(limit.acquire(IpAddr::V4(Ipv4Addr::from(0x0a00_0000 + n))), Ok(()));
+    }
+
+    // The first client's bucket was dropped for the last one, so it starts
+    // afresh; the newest are still empty.
+    assert_eq!(limit.acquire(CLIENT), Ok(()));
+    assert!(limit.acquire(IpAddr::V4(Ipv4Addr::from(0x0a00_0000 + MAX_CLIENTS as u32 - 1))).is_err());
+}
@@ -0,0 +1,209 @@
+#![cfg(feature = "registry")]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use rusty_server::registry::{Consul, Etcd, RegistryError, Service};
+use serde_json::Value;
+
+/// A request as the fake registry saw it.
+struct Seen {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Value,
+}
+
+impl Seen {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A registry answering every request with `answer(path)`, and the requests
+/// it got.
+fn fake_registry(answer: fn(&str) -> (u16, &'static str)) -> (String, Receiver<Seen>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (sender, seen) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let sender = sender.clone();
+            let mut reader = BufReader::new(stream.unwrap());
+            thread::spawn(move || loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                    return;
+                }
+                let mut parts = line.split(' ');
+                let (method, path) = (parts.next().unwrap(), parts.next().unwrap());
+                let mut headers = Vec::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    match line.trim_end().split_once(": ") {
+                        Some((name, value)) => headers.push((name.to_string(), value.to_string())),
+                        None => break,
+                    }
+                }
+                let length = headers
+                    .iter()
+                    .find(|(name, _)| name == "Content-Length")
+                    .map_or(0, |(_, value)| value.parse().unwrap());
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+
+                let (status, answer_body) = answer(path);
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 {} X\r\nContent-Length: {}\r\n\r\n{}",
+                    status,
+                    answer_body.len(),
+                    answer_body
+                )
+                .unwrap();
+                let _ = sender.send(Seen {
+                    method: method.to_string(),
+                    path: path.to_string(),
+                    headers,
+                    body: serde_json::from_slice(&body).unwrap_or(Value::Null),
+                });
+            });
+        }
+    });
+    (url, seen)
+}
+
+const WAIT: Duration = Duration::from_secs(5);
+
+fn addr() -> SocketAddr {
+    "192.0.2.10:3000".parse().unwrap()
+}
+
+#[test]
+fn services_describe_themselves() {
+    let service = Service::new("demo", addr());
+
+    assert_eq!(service.service_id(), "demo-192-0-2-10-3000");
+    assert_eq!(service.address(), addr());
+    let json: Value = serde_json::from_str(&service.to_json()).unwrap();
+    assert_eq!(json["health"], "http://192.0.2.10:3000/version");
+    assert_eq!(json["port"], 3000);
+}
+
+#[test]
+fn unspecified_addresses_are_replaced() {
+    let service = Service::new("demo", "0.0.0.0:3000".parse().unwrap());
+
+    assert!(!service.address().ip().is_unspecified());
+    assert_eq!(service.address().port(), 3000);
+}
+
+#[test]
+fn consul_registers_with_a_health_check_and_deregisters() {
+    let (url, seen) = fake_registry(|_| (200, ""));
+    let service = Service::new("demo", addr()).health_url("http://192.0.2.10:3000/ready");
+
+    let registration = Consul::new(format!("{}/", url))
+        .token("secret")
+        .register(&service)
+        .unwrap();
+
+    let register = seen.recv_timeout(WAIT).unwrap();
+    assert_eq!(register.method, "PUT");
+    assert_eq!(register.path, "/v1/agent/service/register");
+    assert_eq!(register.header("X-Consul-Token"), Some("secret"));
+    assert_eq!(register.body["ID"], "demo-192-0-2-10-3000");
+    assert_eq!(register.body["Name"], "demo");
+    assert_eq!(register.body["Address"], "192.0.2.10");
+    assert_eq!(register.body["Port"], 3000);
+    assert_eq!(
+        register.body["Check"]["HTTP"],
+        "http://192.0.2.10:3000/ready"
+    );
+
+    registration.deregister().unwrap();
+    let deregister = seen.recv_timeout(WAIT).unwrap();
+    assert_eq!(deregister.method, "PUT");
+    assert_eq!(
+        deregister.path,
+        "/v1/agent/service/deregister/demo-192-0-2-10-3000"
+    );
+}
+
+#[test]
+fn consul_errors_are_reported() {
+    let (url, _seen) = fake_registry(|_| (403, "Permission denied\n"));
+
+    let error = Consul::new(url)
+        .register(&Service::new("demo", addr()))
+        .err()
+        .unwrap();
+
+    assert!(
+        matches!(&error, RegistryError::Rejected { status: 403, body } if body == "Permission denied"),
+        "{:?}",
+        error
+    );
+}
+
+#[test]
+fn etcd_stores_the_service_under_a_kept_alive_lease() {
+    let (url, seen) = fake_registry(|path| match path {
+        "/v3/lease/grant" => (
+            200,
+            r#"{"header":{"cluster_id":"1"},"ID":"7587","TTL":"1"}"#,
+        ),
+        _ => (200, "{}"),
+    });
+
+    let registration = Etcd::new(url)
+        .prefix("/demo/")
+        .ttl(Duration::from_secs(1))
+        .register(&Service::new("demo", addr()))
+        .unwrap();
+
+    let grant = seen.recv_timeout(WAIT).unwrap();
+    assert_eq!(grant.path, "/v3/lease/grant");
+    assert_eq!(grant.body["TTL"], 1);
+    let put = seen.recv_timeout(WAIT).unwrap();
+    assert_eq!(put.path, "/v3/kv/put");
+    assert_eq!(put.body["lease"], "7587");
+    let decode =
+        |field: &Value| String::from_utf8(BASE64.decode(field.as_str().unwrap()).unwrap()).unwrap();
+    assert_eq!(decode(&put.body["key"]), "/demo/demo/demo-192-0-2-10-3000");
+    let value: Value = serde_json::from_str(&decode(&put.body["value"])).unwrap();
+    assert_eq!(value["address"], "192.0.2.10");
+
+    let keepalive = seen.recv_timeout(WAIT).unwrap();
+    assert_eq!(keepalive.path, "/v3/lease/keepalive");
+    assert_eq!(keepalive.body["ID"], "7587");
+
+    registration.deregister().unwrap();
+    let revoke = seen
+        .iter()
+        .find(|seen| seen.path != "/v3/lease/keepalive")
+        .unwrap();
+    assert_eq!(revoke.path, "/v3/lease/revoke");
+    assert_eq!(revoke.body["ID"], "7587");
+}
+
+#[test]
+fn etcd_lease_grants_need_an_id() {
+    let (url, _seen) = fake_registry(|_| (200, r#"{"error":"nope"}"#));
+
+    let error = Etcd::new(url)
+        .register(&Service::new("demo", addr()))
+        .err()
+        .unwrap();
+
+    assert!(matches!(error, RegistryError::InvalidResponse(_)));
+}
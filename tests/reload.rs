@@ -0,0 +1,140 @@
+mod common;
+
+use std::fs;
+use std::net::{SocketAddr, TcpListener};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{get, send_raw};
+use rusty_server::server::{self, Config};
+use rusty_server::Router;
+use serde_json::Value;
+
+#[test]
+fn reconfigured_limits_apply_to_running_listeners() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut router = Router::default();
+    router.post("/echo", |request| {
+        rusty_server::Response::text(200, request.body.clone())
+    });
+    thread::spawn(move || server::serve_with(listener, router, Config::default()));
+    let post = b"POST /echo HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nhello";
+    assert_eq!(send_raw(addr, post).status(), 200);
+
+    let mut config = Config::default();
+    config.max_body_size = 4;
+    server::reconfigure(config);
+
+    assert_eq!(send_raw(addr, post).status(), 413);
+}
+
+#[test]
+fn config_file_is_reloaded_on_request() {
+    let addr: SocketAddr = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    };
+    let file =
+        std::env::temp_dir().join(format!("rusty-server-reload-{}.conf", std::process::id()));
+    let settings = |keep_alive: u32| {
+        format!(
+            "# Written by the reload test\nLISTEN_ADDRESS={}\nADMIN_API=1\nKEEP_ALIVE_TIMEOUT={}\n",
+            addr, keep_alive
+        )
+    };
+    fs::write(&file, settings(5)).unwrap();
+    let mut server = Command::new(env!("CARGO_BIN_EXE_rusty-server"))
+        .args(["--log-format", "ndjson", "--quiet", "--config"])
+        .arg(&file)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let started = Instant::now();
+    while std::net::TcpStream::connect(addr).is_err() {
+        assert!(
+            started.elapsed() < Duration::from_secs(5),
+            "server did not start"
+        );
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    fs::write(&file, settings(10)).unwrap();
+    let response = send_raw(
+        addr,
+        b"POST /admin/reload HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\n\r\n",
+    );
+    let routes = get(addr, "/admin/routes", &[]);
+    server.kill().unwrap();
+    let output = server.wait_with_output().unwrap();
+    fs::remove_file(&file).unwrap();
+
+    assert_eq!(response.status(), 200);
+    let changes: Value = serde_json::from_str(&response.body).unwrap();
+    assert_eq!(
+        changes,
+        serde_json::json!([{"setting": "Keep-alive timeout", "old": "5s", "new": "10s"}])
+    );
+    assert!(routes.body.contains(r#""path":"/admin/reload""#));
+    let reloaded = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap())
+        .find(|line| line["status"] == "Config Reloaded")
+        .expect("a Config Reloaded report");
+    assert_eq!(reloaded["keep_alive_timeout"], "5s → 10s");
+}
+
+#[test]
+fn static_files_are_reloaded() {
+    let addr: SocketAddr = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    };
+    let id = std::process::id();
+    let file = std::env::temp_dir().join(format!("rusty-server-reload-static-{}.conf", id));
+    let dir = std::env::temp_dir().join(format!("rusty-server-reload-static-{}", id));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("hello.txt"), "hello\n").unwrap();
+    let settings = format!("LISTEN_ADDRESS={}\nADMIN_API=1\n", addr);
+    fs::write(&file, &settings).unwrap();
+    let mut server = Command::new(env!("CARGO_BIN_EXE_rusty-server"))
+        .args(["--log-format", "ndjson", "--quiet", "--config"])
+        .arg(&file)
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    let started = Instant::now();
+    while std::net::TcpStream::connect(addr).is_err() {
+        assert!(started.elapsed() < Duration::from_secs(5), "server did not start");
+        thread::sleep(Duration::from_millis(20));
+    }
+    let before = get(addr, "/files/hello.txt", &[]);
+
+    let reloaded = format!("{}STATIC_DIR={}\nSTATIC_PREFIX=/files\nSPA=1\n", settings, dir.display());
+    fs::write(&file, reloaded).unwrap();
+    let response = send_raw(
+        addr,
+        b"POST /admin/reload HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\n\r\n",
+    );
+    let after = get(addr, "/files/hello.txt", &[]);
+    server.kill().unwrap();
+    server.wait().unwrap();
+    fs::remove_file(&file).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(before.status(), 404);
+    assert_eq!(response.status(), 200);
+    let changes: Value = serde_json::from_str(&response.body).unwrap();
+    assert_eq!(
+        changes,
+        serde_json::json!([
+            {"setting": "Static dir", "old": "off", "new": dir.display().to_string()},
+            {"setting": "Static prefix", "old": "/static", "new": "/files"},
+            {"setting": "SPA", "old": "off", "new": "on"},
+        ])
+    );
+    assert_eq!(after.status(), 200);
+    assert_eq!(after.body, "hello\n");
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use std::io::{Cursor, Read};
+use std::net::TcpListener;
+use std::thread;
+
+use common::{connect, get};
+use rusty_server::console::{self, Verbosity};
+use rusty_server::repl::{self, Command, CommandError};
+
+#[test]
+fn commands_are_parsed_by_name() {
+    assert_eq!("stats".parse(), Ok(Command::Stats));
+    assert_eq!(" connections ".parse(), Ok(Command::Connections));
+    assert_eq!("loglevel".parse(), Ok(Command::LogLevel(None)));
+    assert_eq!(
+        "loglevel debug".parse(),
+        Ok(Command::LogLevel(Some(Verbosity::VeryVerbose)))
+    );
+    assert!(matches!(
+        "loglevel loud".parse::<Command>(),
+        Err(CommandError::LogLevel(_))
+    ));
+    assert_eq!(
+        "restart now".parse::<Command>(),
+        Err(CommandError::Unknown("restart now".to_string()))
+    );
+}
+
+#[test]
+fn commands_run_until_quit() {
+    repl::run(Cursor::new(
+        "stats\n\nbogus\nloglevel quiet\nquit\nloglevel verbose\n",
+    ));
+
+    assert_eq!(console::verbosity(), Verbosity::Quiet);
+    console::set_verbosity(Verbosity::Normal);
+}
+
+#[test]
+fn draining_closes_new_connections() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || rusty_server::serve(listener));
+    assert_eq!(get(addr, "/version", &[]).status(), 200);
+
+    // Nothing is open, so the drain finishes at once.
+    repl::run(Cursor::new("drain\nloglevel debug\n"));
+
+    assert_ne!(console::verbosity(), Verbosity::VeryVerbose);
+    let mut stream = connect(addr);
+    let mut bytes = Vec::new();
+    stream.read_to_end(&mut bytes).unwrap();
+    assert!(bytes.is_empty());
+}
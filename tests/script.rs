@@ -0,0 +1,170 @@
+#![cfg(feature = "scripting")]
+
+mod common;
+
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use common::MemoryStream;
+use rusty_server::http::parse_request;
+use rusty_server::script::{ScriptError, Scripts};
+use rusty_server::{serve_connection, Config, Router};
+
+/// A fresh, empty scripts directory for one test.
+fn script_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rusty-server-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes `source` to `path`, with an mtime that differs from any earlier
+/// write even on filesystems with coarse timestamps.
+fn write_script(path: &Path, source: &str, generation: u64) {
+    fs::write(path, source).unwrap();
+    let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000 + generation);
+    File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(mtime)
+        .unwrap();
+}
+
+fn get(scripts: &Scripts, target: &str) -> Option<rusty_server::Response> {
+    let head = format!(
+        "GET {} HTTP/1.1\r\nHost: a\r\nUser-Agent: tester\r\n\r\n",
+        target
+    );
+    scripts.handle(&parse_request(head.as_bytes()).unwrap())
+}
+
+#[test]
+fn scripts_register_routes_and_build_responses() {
+    let dir = script_dir("routes");
+    write_script(
+        &dir.join("a.rhai"),
+        r#"
+            route("GET", "/text", |req| response(201).header("X-Agent", req.headers["user-agent"]).text("made"));
+            route("GET", "/string", |req| `path=${req.path} query=${req.query}`);
+            route("GET", "/map", |req| #{ method: req.method });
+            route("GET", "/empty", |req| ());
+        "#,
+        0,
+    );
+    let scripts = Scripts::new(&dir);
+
+    let outcomes = scripts.reload();
+
+    assert_eq!(outcomes.len(), 1);
+    assert_eq!(
+        outcomes[0].1.as_ref().unwrap(),
+        &["GET /text", "GET /string", "GET /map", "GET /empty"]
+    );
+    let text = get(&scripts, "/text").unwrap();
+    assert_eq!(text.status, 201);
+    assert_eq!(text.header_value("X-Agent"), Some("tester"));
+    assert_eq!(
+        text.header_value("Content-Type"),
+        Some("text/plain; charset=utf-8")
+    );
+    assert_eq!(text.body, b"made");
+    assert_eq!(
+        get(&scripts, "/string?x=1").unwrap().body,
+        b"path=/string query=x=1"
+    );
+    let map = get(&scripts, "/map").unwrap();
+    assert_eq!(map.header_value("Content-Type"), Some("application/json"));
+    let json: serde_json::Value = serde_json::from_slice(&map.body).unwrap();
+    assert_eq!(json["method"], "GET");
+    assert_eq!(get(&scripts, "/empty").unwrap().status, 204);
+    assert!(get(&scripts, "/missing").is_none());
+}
+
+#[test]
+fn failing_handlers_get_a_500() {
+    let dir = script_dir("failing");
+    write_script(
+        &dir.join("a.rhai"),
+        r#"
+            route("GET", "/throw", |req| throw "boom");
+            route("GET", "/spin", |req| { loop {} });
+            route("GET", "/number", |req| 42);
+            route("GET", "/header", |req| response(200).header("X", "a\r\nB: c"));
+        "#,
+        0,
+    );
+    let scripts = Scripts::new(&dir);
+    scripts.reload();
+
+    for path in ["/throw", "/spin", "/number", "/header"] {
+        assert_eq!(get(&scripts, path).unwrap().status, 500, "{}", path);
+    }
+}
+
+#[test]
+fn reload_picks_up_changes_and_keeps_last_good_version() {
+    let dir = script_dir("reload");
+    let path = dir.join("a.rhai");
+    write_script(&path, r#"route("GET", "/v", |req| "one");"#, 0);
+    let scripts = Scripts::new(&dir);
+    scripts.reload();
+    assert_eq!(get(&scripts, "/v").unwrap().body, b"one");
+
+    assert!(
+        scripts.reload().is_empty(),
+        "unchanged files are not reloaded"
+    );
+
+    write_script(&path, r#"route("GET", "/v", |req| "two");"#, 1);
+    scripts.reload();
+    assert_eq!(get(&scripts, "/v").unwrap().body, b"two");
+
+    write_script(&path, "route(", 2);
+    let outcomes = scripts.reload();
+    assert!(matches!(outcomes[0].1, Err(ScriptError::Compile(_))));
+    assert_eq!(get(&scripts, "/v").unwrap().body, b"two");
+
+    fs::remove_file(&path).unwrap();
+    scripts.reload();
+    assert!(get(&scripts, "/v").is_none());
+    assert!(scripts.routes().is_empty());
+}
+
+#[test]
+fn invalid_routes_fail_the_script() {
+    let dir = script_dir("invalid");
+    write_script(
+        &dir.join("a.rhai"),
+        r#"route("GET", "relative", |req| "");"#,
+        0,
+    );
+    let scripts = Scripts::new(&dir);
+
+    let outcomes = scripts.reload();
+
+    assert!(matches!(outcomes[0].1, Err(ScriptError::Run(_))));
+    assert!(scripts.routes().is_empty());
+}
+
+#[test]
+fn scripts_serve_through_router_fallback() {
+    let dir = script_dir("fallback");
+    write_script(
+        &dir.join("a.rhai"),
+        r#"route("GET", "/hi", |req| "hi");"#,
+        0,
+    );
+    let scripts = std::sync::Arc::new(Scripts::new(&dir));
+    scripts.reload();
+    let mut router = Router::new();
+    let handler = std::sync::Arc::clone(&scripts);
+    router.fallback(move |request| handler.handle(request));
+    let mut stream = MemoryStream::new(b"GET /hi HTTP/1.1\r\nHost: a\r\n\r\n");
+
+    let exchange = serve_connection(&mut stream, &router, &Config::default()).unwrap();
+
+    assert_eq!(exchange.status, 200);
+    assert_eq!(stream.response().body, "hi");
+}
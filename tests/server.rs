@@ -0,0 +1,604 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::num::NonZeroUsize;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{connect, get, send_raw, spawn_server};
+use rusty_server::{serve_all, serve_with, Config, Response, Router, Server, ServerError, Shedding};
+
+#[test]
+fn version_returns_json_when_requested() {
+    let addr = spawn_server();
+    let response = get(addr, "/version", &["Accept: application/json"]);
+
+    assert_eq!(response.status_line, "HTTP/1.1 200 OK");
+    assert!(!response.body.contains("<html>"));
+    let version: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+    assert_eq!(version["version"], "0.1.0");
+    assert!(version["started_at"].as_u64().unwrap() > 0);
+    assert!(version["uptime_secs"].is_u64());
+}
+
+#[cfg(feature = "html")]
+#[test]
+fn version_returns_html_by_default() {
+    let addr = spawn_server();
+    let response = get(addr, "/version", &[]);
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header("Content-Type"), Some("text/html"));
+    assert!(response.body.contains("Server Version Information"));
+}
+
+#[cfg(not(feature = "html"))]
+#[test]
+fn version_returns_json_without_html_feature() {
+    let addr = spawn_server();
+    let response = get(addr, "/version", &["Accept: text/html"]);
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header("Content-Type"), Some("application/json"));
+}
+
+#[test]
+fn json_bodies_are_serialized_with_strings_escaped() {
+    #[derive(serde::Serialize)]
+    struct Quote<'a> {
+        text: &'a str,
+    }
+    let response = Response::json(200, &Quote { text: "say \"hi\"\n" });
+    assert_eq!(response.header_value("Content-Type"), Some("application/json"));
+    assert_eq!(response.body, br#"{"text":"say \"hi\"\n"}"#);
+
+    let map = std::collections::HashMap::from([((1, 2), "not a string key")]);
+    assert_eq!(Response::json(200, &map).status, 500);
+}
+
+#[test]
+fn healthz_reports_ok_with_uptime() {
+    let addr = spawn_server();
+    let response = get(addr, "/healthz", &[]);
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header("Content-Type"), Some("application/json"));
+    let health: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+    assert_eq!(health["status"], "ok");
+    assert!(health["uptime_secs"].is_u64());
+    assert!(health["started_at"].as_u64().unwrap() <= health["timestamp"].as_u64().unwrap());
+    assert!(health["timestamp"].as_u64().unwrap() > 0);
+}
+
+#[test]
+fn metrics_are_exposed_for_prometheus() {
+    let addr = spawn_server();
+    assert_eq!(get(addr, "/version", &[]).status(), 200);
+
+    // The /version connection is counted just after its response is sent.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let response = loop {
+        let response = get(addr, "/metrics", &[]);
+        if response.body.contains("rusty_server_responses_total{status=\"200\"}")
+            || Instant::now() >= deadline
+        {
+            break response;
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.header("Content-Type"),
+        Some("text/plain; version=0.0.4; charset=utf-8")
+    );
+    assert!(response.body.contains("# TYPE rusty_server_responses_total counter\n"));
+    assert!(response.body.contains("rusty_server_responses_total{status=\"200\"}"));
+    assert!(response.body.contains("# TYPE rusty_server_start_time_seconds gauge\n"));
+    let sent: u64 = response
+        .body
+        .lines()
+        .find_map(|line| line.strip_prefix("rusty_server_sent_bytes_total "))
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(sent > 0);
+}
+
+#[test]
+fn content_length_matches_body() {
+    let addr = spawn_server();
+    let response = get(addr, "/version", &[]);
+
+    let length: usize = response.header("Content-Length").unwrap().parse().unwrap();
+    assert_eq!(length, response.body.len());
+}
+
+#[test]
+fn responses_are_tagged_and_not_modified_when_the_client_has_them() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut router = Router::new();
+    router.get("/motd", |_| Response::text(200, "hello\n"));
+    thread::spawn(move || serve_with(listener, router, Config::default()));
+
+    let first = get(addr, "/motd", &[]);
+    let etag = first.header("ETag").unwrap().to_string();
+    assert!(etag.starts_with('"') && etag.ends_with('"'), "{}", etag);
+    assert_eq!(get(addr, "/motd", &[]).header("ETag"), Some(etag.as_str()));
+
+    let if_none_match = format!("If-None-Match: \"other\", W/{}", etag);
+    let unmodified = get(addr, "/motd", &[&if_none_match]);
+    assert_eq!(unmodified.status(), 304);
+    assert_eq!(unmodified.header("ETag"), Some(etag.as_str()));
+    assert_eq!(unmodified.header("Content-Length"), None);
+    assert!(unmodified.body.is_empty());
+
+    assert_eq!(get(addr, "/motd", &["If-None-Match: \"other\""]).status(), 200);
+    assert_eq!(get(addr, "/motd", &["If-None-Match: *"]).status(), 304);
+}
+
+#[test]
+fn repeat_version_polls_are_not_modified() {
+    let addr = spawn_server();
+    let json = "Accept: application/json";
+    let first = get(addr, "/version", &[json]);
+    let etag = first.header("ETag").unwrap().to_string();
+
+    // Past a second, so that the uptime and response time have moved on.
+    thread::sleep(Duration::from_millis(1100));
+    let if_none_match = format!("If-None-Match: {}", etag);
+    let unmodified = get(addr, "/version", &[json, &if_none_match]);
+    assert_eq!(unmodified.status(), 304);
+    assert!(unmodified.body.is_empty());
+
+    // Other formats are other representations, with tags of their own.
+    let text = get(addr, "/version", &["Accept: text/plain", &if_none_match]);
+    assert_eq!(text.status(), 200);
+    assert_ne!(text.header("ETag"), Some(etag.as_str()));
+}
+
+#[test]
+fn unknown_path_returns_404_page() {
+    let addr = spawn_server();
+    let response = get(addr, "/nope", &[]);
+
+    assert_eq!(response.status_line, "HTTP/1.1 404 NOT FOUND");
+    if cfg!(feature = "html") {
+        assert!(response.body.contains(r#"<div class="path-box">/nope</div>"#));
+    } else {
+        assert!(response.body.starts_with("404 Not Found: /nope"));
+    }
+}
+
+#[cfg(feature = "html")]
+#[test]
+fn pages_fill_in_every_placeholder_of_their_templates() {
+    let router = Router::default();
+    let page = |head: &str| {
+        let request = rusty_server::http::parse_request(head.as_bytes()).unwrap();
+        String::from_utf8(router.handle(&request).body).unwrap()
+    };
+
+    let version = page("GET /version HTTP/1.1\r\nHost: a\r\n\r\n");
+    assert!(version.contains(r#"<div class="value">0.1.0</div>"#));
+    assert!(version.contains("&#34;version&#34;: &#34;0.1.0&#34;"));
+    let missing = page("GET /%3Cb%3E HTTP/1.1\r\nHost: a\r\n\r\n");
+    assert!(missing.contains(r#"<div class="path-box">/&#60;b&#62;</div>"#));
+    let refused = page("DELETE /version HTTP/1.1\r\nHost: a\r\n\r\n");
+    assert!(refused.contains("<h1 class=\"error-title\">405 - Method Not Allowed</h1>"));
+    for body in [version, missing, refused] {
+        assert!(!body.contains("{{"), "{}", body);
+    }
+}
+
+#[test]
+fn non_get_request_returns_400() {
+    let addr = spawn_server();
+    let response = send_raw(addr, b"BREW /pot HTTP/1.1\r\n\r\n");
+
+    assert_eq!(response.status(), 400);
+    if cfg!(feature = "html") {
+        assert!(response.body.contains("400 - Bad Request"));
+    } else {
+        assert!(response.body.starts_with("400 Bad Request"));
+    }
+}
+
+#[test]
+fn keep_alive_connection_serves_requests_until_close() {
+    let addr = spawn_server();
+    let mut stream = connect(addr);
+    // Sent back to back, without waiting for the first response.
+    stream
+        .write_all(
+            b"GET /version HTTP/1.1\r\nHost: localhost\r\n\r\n\
+              GET /healthz HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        )
+        .unwrap();
+
+    let mut bytes = Vec::new();
+    stream.read_to_end(&mut bytes).unwrap();
+    let text = String::from_utf8_lossy(&bytes);
+    let second = text.rfind("HTTP/1.1 ").unwrap();
+    let first = common::parse_response(&bytes[..second]);
+    let last = common::parse_response(&bytes[second..]);
+    assert_eq!(first.status(), 200);
+    assert_eq!(first.header("Connection"), Some("keep-alive"));
+    assert_eq!(first.header("Keep-Alive"), Some("timeout=5, max=999"));
+    assert_eq!(last.status(), 200);
+    assert_eq!(last.header("Connection"), Some("close"));
+    assert!(last.body.contains(r#""status":"ok""#));
+}
+
+#[test]
+fn connections_are_closed_after_max_requests() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut config = Config::default();
+    config.max_requests = NonZeroUsize::new(2);
+    thread::spawn(move || serve_with(listener, Router::default(), config));
+
+    let mut stream = connect(addr);
+    stream
+        .write_all(
+            b"GET /healthz HTTP/1.1\r\nHost: localhost\r\n\r\n\
+              GET /healthz HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        )
+        .unwrap();
+
+    // The second request is the last, though the client did not ask to close.
+    let mut bytes = Vec::new();
+    stream.read_to_end(&mut bytes).unwrap();
+    let text = String::from_utf8_lossy(&bytes);
+    let second = text.rfind("HTTP/1.1 ").unwrap();
+    let first = common::parse_response(&bytes[..second]);
+    let last = common::parse_response(&bytes[second..]);
+    assert_eq!(first.header("Connection"), Some("keep-alive"));
+    assert_eq!(first.header("Keep-Alive"), Some("timeout=5, max=1"));
+    assert_eq!(last.status(), 200);
+    assert_eq!(last.header("Connection"), Some("close"));
+    assert_eq!(last.header("Keep-Alive"), None);
+}
+
+#[test]
+fn sub_second_keep_alive_timeouts_are_advertised_rounded_up() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut config = Config::default();
+    config.keep_alive_timeout = Some(Duration::from_millis(500));
+    config.max_requests = None;
+    thread::spawn(move || serve_with(listener, Router::default(), config));
+
+    let mut stream = connect(addr);
+    stream
+        .write_all(
+            b"GET /healthz HTTP/1.1\r\nHost: localhost\r\n\r\n\
+              GET /healthz HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        )
+        .unwrap();
+    let mut bytes = Vec::new();
+    stream.read_to_end(&mut bytes).unwrap();
+    let second = String::from_utf8_lossy(&bytes).rfind("HTTP/1.1 ").unwrap();
+    let first = common::parse_response(&bytes[..second]);
+    assert_eq!(first.header("Connection"), Some("keep-alive"));
+    assert_eq!(first.header("Keep-Alive"), Some("timeout=1"));
+}
+
+#[test]
+fn http_1_0_connection_is_closed_unless_kept_alive() {
+    let addr = spawn_server();
+    let mut stream = connect(addr);
+    stream.write_all(b"GET /version HTTP/1.0\r\n\r\n").unwrap();
+
+    // EOF follows the response at once, so this returns well before the
+    // client's read timeout.
+    let mut bytes = Vec::new();
+    stream.read_to_end(&mut bytes).unwrap();
+    assert_eq!(common::parse_response(&bytes).header("Connection"), Some("close"));
+}
+
+#[test]
+fn idle_connection_is_closed_after_the_keep_alive_timeout() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut config = Config::default();
+    config.keep_alive_timeout = Some(Duration::from_millis(100));
+    thread::spawn(move || serve_with(listener, Router::default(), config));
+    let mut stream = connect(addr);
+    stream
+        .write_all(b"GET /version HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+
+    let started = Instant::now();
+    let mut bytes = Vec::new();
+    stream.read_to_end(&mut bytes).unwrap();
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert_eq!(common::parse_response(&bytes).status(), 200);
+}
+
+#[test]
+fn slow_client_does_not_hold_up_the_other_workers() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut config = Config::default();
+    config.workers = NonZeroUsize::new(2);
+    thread::spawn(move || serve_with(listener, Router::default(), config));
+    let _slow = connect(addr);
+
+    for _ in 0..3 {
+        assert_eq!(get(addr, "/version", &[]).status(), 200);
+    }
+}
+
+#[test]
+fn connections_beyond_the_limit_are_shed() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut config = Config::default();
+    config.max_connections = NonZeroUsize::new(1);
+    thread::spawn(move || serve_with(listener, Router::default(), config));
+    let held = connect(addr);
+
+    // Answered without sending a request.
+    let mut bytes = Vec::new();
+    connect(addr).read_to_end(&mut bytes).unwrap();
+    let response = common::parse_response(&bytes);
+    assert_eq!(response.status(), 503);
+    assert_eq!(response.header("Retry-After"), Some("1"));
+    assert_eq!(response.header("Connection"), Some("close"));
+
+    // The slot is given back when the held connection closes; until the
+    // server sees that, requests may still be shed, and reset.
+    drop(held);
+    let served = || {
+        let mut stream = connect(addr);
+        let _ = stream.write_all(b"GET /healthz HTTP/1.1\r\nHost: h\r\nConnection: close\r\n\r\n");
+        let mut bytes = Vec::new();
+        let _ = stream.read_to_end(&mut bytes);
+        bytes.starts_with(b"HTTP/1.1 200")
+    };
+    let mut tries = 0;
+    while !served() {
+        tries += 1;
+        assert!(tries < 50, "slot not given back");
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    let request = rusty_server::http::parse_request(b"GET /metrics HTTP/1.1\r\nHost: h\r\n\r\n").unwrap();
+    let metrics = String::from_utf8(Router::default().handle(&request).body).unwrap();
+    let rejected = metrics
+        .lines()
+        .find_map(|line| line.strip_prefix("rusty_server_rejected_connections_total "))
+        .and_then(|count| count.parse::<u64>().ok())
+        .unwrap();
+    assert!(rejected >= 1);
+}
+
+#[test]
+fn slow_request_heads_get_408_and_are_closed() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut config = Config::default();
+    config.request_timeout = Some(Duration::from_millis(300));
+    thread::spawn(move || serve_with(listener, Router::default(), config));
+
+    // Each byte arrives well within the deadline, but the head does not.
+    // Writing stops once the answer comes, so that the close stays clean.
+    let started = Instant::now();
+    let mut stream = connect(addr);
+    stream.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+    let mut bytes = Vec::new();
+    for byte in b"GET /healthz HTTP/1.1\r\nHost: h\r\n" {
+        stream.write_all(&[*byte]).unwrap();
+        let mut chunk = [0; 1024];
+        if let Ok(n @ 1..) = stream.read(&mut chunk) {
+            bytes.extend_from_slice(&chunk[..n]);
+            break;
+        }
+    }
+    stream.set_read_timeout(None).unwrap();
+    stream.read_to_end(&mut bytes).unwrap();
+    assert!(started.elapsed() < Duration::from_secs(5));
+    let response = common::parse_response(&bytes);
+    assert_eq!(response.status(), 408);
+    assert_eq!(response.header("Connection"), Some("close"));
+
+    // Heads sent in time are answered as usual.
+    let mut stream = connect(addr);
+    stream.write_all(b"GET /healthz HTTP/1.1\r\nHost: h\r\nConnection: close\r\n\r\n").unwrap();
+    let mut bytes = Vec::new();
+    stream.read_to_end(&mut bytes).unwrap();
+    assert_eq!(common::parse_response(&bytes).status(), 200);
+}
+
+#[test]
+fn clients_that_stop_reading_are_aborted() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut config = Config::default();
+    config.write_timeout = Some(Duration::from_millis(200));
+    let mut router = Router::default();
+    // Far more than the socket buffers on both ends hold.
+    router.get("/large", |_| Response::text(200, vec![b'x'; 64 << 20]));
+    thread::spawn(move || serve_with(listener, router, config));
+
+    let mut stream = connect(addr);
+    stream.write_all(b"GET /large HTTP/1.1\r\nHost: h\r\n\r\n").unwrap();
+    let write_timeouts = || {
+        let request = rusty_server::http::parse_request(b"GET /metrics HTTP/1.1\r\nHost: h\r\n\r\n").unwrap();
+        let metrics = String::from_utf8(Router::default().handle(&request).body).unwrap();
+        metrics
+            .lines()
+            .find_map(|line| line.strip_prefix("rusty_server_write_timeouts_total "))
+            .and_then(|count| count.parse::<u64>().ok())
+            .unwrap()
+    };
+    let mut tries = 0;
+    while write_timeouts() == 0 {
+        tries += 1;
+        assert!(tries < 100, "the stalled write never timed out");
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    // The connection was aborted: reading now ends short of the body.
+    let mut bytes = Vec::new();
+    let _ = stream.read_to_end(&mut bytes);
+    assert!(bytes.len() < 64 << 20);
+}
+
+#[test]
+fn shed_connections_can_be_closed_unanswered() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut config = Config::default();
+    config.max_connections = NonZeroUsize::new(1);
+    config.shedding = Shedding::Close;
+    thread::spawn(move || serve_with(listener, Router::default(), config));
+    let _held = connect(addr);
+
+    let mut bytes = Vec::new();
+    let _ = connect(addr).read_to_end(&mut bytes);
+    assert!(bytes.is_empty());
+    assert_eq!("close".parse(), Ok(Shedding::Close));
+    assert_eq!("503".parse(), Ok(Shedding::Unavailable));
+    assert!("drop".parse::<Shedding>().is_err());
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn tokio_runtime_serves_the_same_routes() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut config = Config::default();
+    config.runtime = rusty_server::Runtime::Tokio;
+    config.workers = NonZeroUsize::new(2);
+    thread::spawn(move || serve_with(listener, Router::default(), config));
+    let _slow = connect(addr);
+
+    let response = get(addr, "/version", &["Accept: application/json"]);
+    assert_eq!(response.status_line, "HTTP/1.1 200 OK");
+    assert!(response.body.contains(r#""version":"0.1.0""#));
+    assert_eq!(get(addr, "/missing", &[]).status(), 404);
+}
+
+#[test]
+fn every_listener_is_served() {
+    let listeners = vec![
+        TcpListener::bind("127.0.0.1:0").unwrap(),
+        TcpListener::bind("127.0.0.1:0").unwrap(),
+    ];
+    let addrs: Vec<_> = listeners
+        .iter()
+        .map(|listener| listener.local_addr().unwrap())
+        .collect();
+    thread::spawn(move || serve_all(listeners, Router::default(), Config::default()));
+
+    for addr in addrs {
+        assert_eq!(get(addr, "/version", &[]).status(), 200);
+    }
+}
+
+#[test]
+fn request_head_sent_in_pieces_is_read_whole() {
+    let addr = spawn_server();
+    let mut stream = connect(addr);
+    let cookie = format!("Cookie: {}\r\n", "c".repeat(4096));
+    let pieces = [
+        "GET /version HTTP/1.1\r\nHost: local",
+        "host\r\nConnection: close\r\n",
+        &cookie,
+        "\r",
+        "\n",
+    ];
+    for piece in pieces {
+        stream.write_all(piece.as_bytes()).unwrap();
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    let mut bytes = Vec::new();
+    stream.read_to_end(&mut bytes).unwrap();
+    assert_eq!(common::parse_response(&bytes).status(), 200);
+}
+
+#[test]
+fn client_disconnect_without_request_does_not_stop_server() {
+    let addr = spawn_server();
+    drop(connect(addr));
+
+    let response = get(addr, "/version", &[]);
+    assert_eq!(response.status(), 200);
+}
+
+#[test]
+fn builder_serves_its_routes_on_its_addresses() {
+    let server = Server::builder()
+        .bind("127.0.0.1:0")
+        .bind("127.0.0.1:0")
+        .get("/hello/:name", |request| {
+            Response::text(200, format!("Hello, {}!", request.param("name").unwrap()))
+        })
+        .build()
+        .unwrap();
+    let addrs = server.local_addrs();
+    thread::spawn(move || server.run());
+
+    assert_eq!(addrs.len(), 2);
+    for addr in addrs {
+        let response = get(addr, "/hello/you", &[]);
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body, "Hello, you!");
+        assert_eq!(get(addr, "/version", &[]).status(), 404);
+    }
+}
+
+#[test]
+fn builder_reports_addresses_it_cannot_bind() {
+    let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = taken.local_addr().unwrap().to_string();
+
+    let error = Server::builder().bind(addr.as_str()).build().err().unwrap();
+
+    assert!(matches!(error, ServerError::Bind { addr: failed, .. } if failed == addr));
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn tokio_runtime_sheds_connections_beyond_the_limit() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut config = Config::default();
+    config.runtime = rusty_server::Runtime::Tokio;
+    config.max_connections = NonZeroUsize::new(1);
+    thread::spawn(move || serve_with(listener, Router::default(), config));
+    let _held = connect(addr);
+
+    let mut bytes = Vec::new();
+    connect(addr).read_to_end(&mut bytes).unwrap();
+    let response = common::parse_response(&bytes);
+    assert_eq!(response.status(), 503);
+    assert_eq!(response.header("Retry-After"), Some("1"));
+    assert_eq!(response.header("Connection"), Some("close"));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn connections_are_served_over_async_streams() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let request = b"GET /version HTTP/1.1\r\nHost: a\r\nAccept: application/json\r\n\r\n";
+    let (mut client, server) = tokio::io::duplex(64 * 1024);
+    client.write_all(request).await.unwrap();
+
+    let (bytes, status, path) = rusty_server::handle_connection_async(server).await.unwrap();
+
+    assert_eq!(bytes, request.len());
+    assert_eq!(status, "200 OK");
+    assert_eq!(path, "/version");
+    let mut response = Vec::new();
+    client.read_to_end(&mut response).await.unwrap();
+    let response = common::parse_response(&response);
+    assert_eq!(response.status(), 200);
+    assert!(response.body.contains(r#""version":"0.1.0""#));
+}
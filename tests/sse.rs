@@ -0,0 +1,104 @@
+mod common;
+
+use std::io::{BufRead, BufReader, Write};
+
+use common::{connect, spawn_server, MemoryStream};
+use rusty_server::sse::Event;
+use rusty_server::{serve_connection, Config, Router};
+
+#[test]
+fn event_fields_are_framed_line_by_line() {
+    let event = Event::new("first\nsecond\r\nthird")
+        .id("7")
+        .event("stats")
+        .retry(3000);
+
+    assert_eq!(
+        event.to_wire(),
+        "id: 7\nevent: stats\nretry: 3000\ndata: first\ndata: second\ndata: third\n\n"
+    );
+}
+
+#[test]
+fn line_breaks_cannot_inject_fields() {
+    let event = Event::new("x").id("1\nevent: spoofed").event("a\r\nb");
+
+    assert_eq!(
+        event.to_wire(),
+        "id: 1event: spoofed\nevent: ab\ndata: x\n\n"
+    );
+}
+
+#[test]
+fn handler_streams_after_event_stream_head() {
+    let mut router = Router::new();
+    router.events("/feed", |_, events| {
+        events.comment("hello").unwrap();
+        events.send(&Event::new("one").id("1")).unwrap();
+        events.send(&Event::new("two").id("2")).unwrap();
+    });
+    let mut stream = MemoryStream::new(b"GET /feed HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+    let exchange = serve_connection(&mut stream, &router, &Config::default()).unwrap();
+
+    assert_eq!(exchange.status, 200);
+    let response = stream.response();
+    assert_eq!(response.header("Content-Type"), Some("text/event-stream"));
+    assert_eq!(response.header("Cache-Control"), Some("no-cache"));
+    assert_eq!(response.header("Content-Length"), None);
+    assert_eq!(
+        response.body,
+        ": hello\n\nid: 1\ndata: one\n\nid: 2\ndata: two\n\n"
+    );
+}
+
+#[test]
+fn other_methods_are_routed_normally() {
+    let mut router = Router::new();
+    router.events("/feed", |_, _| panic!("not an event-stream request"));
+    let mut stream = MemoryStream::new(b"POST /feed HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+    let exchange = serve_connection(&mut stream, &router, &Config::default()).unwrap();
+
+    assert_eq!(exchange.status, 405);
+    assert_eq!(stream.response().header("Allow"), Some("GET"));
+}
+
+/// Reads lines from the `/events` stream until the first complete event.
+fn first_event(last_event_id: Option<&str>) -> Vec<String> {
+    let addr = spawn_server();
+    let mut stream = connect(addr);
+    let mut request = format!("GET /events HTTP/1.1\r\nHost: {}\r\n", addr);
+    if let Some(id) = last_event_id {
+        request.push_str(&format!("Last-Event-ID: {}\r\n", id));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).unwrap();
+
+    let mut lines = BufReader::new(stream).lines().map(|line| line.unwrap());
+    assert_eq!(lines.next().unwrap(), "HTTP/1.1 200 OK");
+    lines
+        .skip_while(|line| !line.starts_with("id:"))
+        .take_while(|line| !line.is_empty())
+        .collect()
+}
+
+#[test]
+fn events_endpoint_streams_stats() {
+    let event = first_event(None);
+
+    assert_eq!(event[0], "id: 0");
+    assert_eq!(event[1], "event: stats");
+    assert_eq!(event[2], "retry: 3000");
+    let data = event[3].strip_prefix("data: ").unwrap();
+    let stats: serde_json::Value = serde_json::from_str(data).unwrap();
+    assert!(stats["connections_active"].as_u64().unwrap() >= 1);
+    assert!(stats["uptime_secs"].is_u64());
+}
+
+#[test]
+fn events_resume_after_last_event_id() {
+    let event = first_event(Some("41"));
+
+    assert_eq!(event[0], "id: 42");
+}
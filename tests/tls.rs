@@ -0,0 +1,236 @@
+#![cfg(feature = "tls")]
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, KeyPair};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName};
+use rusty_server::client::Client;
+use rusty_server::server::{serve_with, Config};
+use rusty_server::tls::Acceptor;
+use rusty_server::{Response, Router};
+
+/// A certificate for `name`, self-signed, and its key.
+fn server_certificate(name: &str) -> (CertificateDer<'static>, PrivateKeyDer<'static>) {
+    let certified = rcgen::generate_simple_self_signed(vec![name.to_string()]).unwrap();
+    let key = PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der());
+    (certified.cert.der().clone(), PrivateKeyDer::Pkcs8(key))
+}
+
+/// Serves `router` over TLS with `acceptor`, returning its address.
+fn spawn_tls_server(acceptor: Acceptor, router: Router) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut config = Config::default();
+    config.tls = Some(acceptor);
+    thread::spawn(move || serve_with(listener, router, config));
+    addr
+}
+
+/// A client config trusting `certs` and nothing else.
+fn trusting(certs: &[&CertificateDer<'static>]) -> rustls::ClientConfig {
+    let mut roots = rustls::RootCertStore::empty();
+    for cert in certs {
+        roots.add((*cert).clone()).unwrap();
+    }
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth()
+}
+
+/// The certificate the server at `addr` presents to a client with `config`
+/// asking for `name`.
+fn presented(
+    addr: SocketAddr,
+    config: &rustls::ClientConfig,
+    name: &str,
+) -> CertificateDer<'static> {
+    let name = ServerName::try_from(name.to_string()).unwrap();
+    let mut connection = rustls::ClientConnection::new(Arc::new(config.clone()), name).unwrap();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    while connection.is_handshaking() {
+        connection.complete_io(&mut stream).unwrap();
+    }
+    connection.peer_certificates().unwrap()[0].clone()
+}
+
+#[test]
+fn requests_are_served_over_https() {
+    let (cert, key) = server_certificate("localhost");
+    let addr = spawn_tls_server(
+        Acceptor::from_der(vec![cert.clone()], key).unwrap(),
+        Router::default(),
+    );
+    let client = Client::new().root_certificate(cert.to_vec()).unwrap();
+
+    let url = format!("https://localhost:{}/version", addr.port());
+    let first = client
+        .request("GET", &url, &[("Accept", "application/json")], b"")
+        .unwrap();
+    let second = client.get(&url).unwrap();
+
+    assert_eq!(first.status, 200);
+    assert!(String::from_utf8(first.body)
+        .unwrap()
+        .contains(r#""scheme":"https""#));
+    assert_eq!(second.status, 200);
+}
+
+#[test]
+fn silent_clients_are_closed_before_the_handshake() {
+    let (cert, key) = server_certificate("localhost");
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut config = Config::default();
+    config.tls = Some(Acceptor::from_der(vec![cert], key).unwrap());
+    config.request_timeout = Some(Duration::from_millis(300));
+    thread::spawn(move || serve_with(listener, Router::default(), config));
+
+    // No ClientHello is ever sent.
+    let started = Instant::now();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    let mut bytes = Vec::new();
+    stream.read_to_end(&mut bytes).unwrap();
+    assert!(bytes.is_empty());
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn client_certificates_are_required_and_named() {
+    let ca_key = KeyPair::generate().unwrap();
+    let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca_params
+        .distinguished_name
+        .push(DnType::CommonName, "Test CA");
+    let ca = ca_params.self_signed(&ca_key).unwrap();
+    let client_key = KeyPair::generate().unwrap();
+    let mut client_params = CertificateParams::new(Vec::new()).unwrap();
+    client_params
+        .distinguished_name
+        .push(DnType::OrganizationName, "Ops");
+    client_params
+        .distinguished_name
+        .push(DnType::CommonName, "operator");
+    let client_cert = client_params.signed_by(&client_key, &ca, &ca_key).unwrap();
+
+    let (cert, key) = server_certificate("localhost");
+    let acceptor =
+        Acceptor::from_der_with_client_ca(vec![cert.clone()], key, vec![ca.der().clone()]).unwrap();
+    let mut router = Router::new();
+    router.get("/whoami", |request| {
+        Response::text(200, request.client_cn.clone().unwrap_or_default())
+    });
+    let addr = spawn_tls_server(acceptor, router);
+
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(cert).unwrap();
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots);
+    let anonymous = builder.clone().with_no_client_auth();
+    let authenticated = builder
+        .with_client_auth_cert(
+            vec![client_cert.der().clone()],
+            PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(client_key.serialize_der())),
+        )
+        .unwrap();
+    let whoami = |config: rustls::ClientConfig| {
+        let name = ServerName::try_from("localhost").unwrap();
+        let connection = rustls::ClientConnection::new(Arc::new(config), name).unwrap();
+        let mut tls = rustls::StreamOwned::new(connection, TcpStream::connect(addr).unwrap());
+        tls.write_all(b"GET /whoami HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")?;
+        let mut response = String::new();
+        tls.read_to_string(&mut response)?;
+        Ok::<_, std::io::Error>(response)
+    };
+
+    let response = whoami(authenticated).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(response.ends_with("\r\n\r\noperator"), "{}", response);
+    assert!(whoami(anonymous).is_err());
+}
+
+#[test]
+fn certificates_are_chosen_by_server_name() {
+    let (default_cert, default_key) = server_certificate("localhost");
+    let (first_cert, first_key) = server_certificate("first.test");
+    let (second_cert, second_key) = server_certificate("second.test");
+    let acceptor = Acceptor::from_der(vec![default_cert.clone()], default_key)
+        .unwrap()
+        .with_named_der("first.test", vec![first_cert.clone()], first_key)
+        .unwrap()
+        .with_named_der("Second.Test", vec![second_cert.clone()], second_key)
+        .unwrap();
+    let addr = spawn_tls_server(acceptor, Router::default());
+
+    let with_sni = trusting(&[&default_cert, &first_cert, &second_cert]);
+    let mut without_sni = with_sni.clone();
+    without_sni.enable_sni = false;
+
+    assert_eq!(presented(addr, &with_sni, "first.test"), first_cert);
+    assert_eq!(presented(addr, &with_sni, "second.test"), second_cert);
+    assert_eq!(presented(addr, &with_sni, "localhost"), default_cert);
+    assert_eq!(presented(addr, &without_sni, "localhost"), default_cert);
+}
+
+#[test]
+fn named_certificates_must_be_for_their_name() {
+    let (cert, key) = server_certificate("localhost");
+    let (other_cert, other_key) = server_certificate("other.test");
+    let acceptor = Acceptor::from_der(vec![cert], key).unwrap();
+
+    let error = acceptor
+        .with_named_der("first.test", vec![other_cert], other_key)
+        .unwrap_err();
+
+    assert!(error.to_string().contains("first.test"), "{}", error);
+}
+
+#[test]
+fn changed_certificate_files_are_reloaded() {
+    let dir = std::env::temp_dir().join(format!("rusty-server-tls-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (cert_file, key_file) = (dir.join("cert.pem"), dir.join("key.pem"));
+    let write = |modified: u64| {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        std::fs::write(&cert_file, certified.cert.pem()).unwrap();
+        std::fs::write(&key_file, certified.key_pair.serialize_pem()).unwrap();
+        // Coarse file system clocks could otherwise hide a quick rewrite.
+        let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(modified);
+        for file in [&cert_file, &key_file] {
+            let file = std::fs::File::options().write(true).open(file).unwrap();
+            file.set_modified(modified).unwrap();
+        }
+        certified.cert.der().clone()
+    };
+    let old = write(1_000_000);
+    let acceptor = Acceptor::from_pem_files(&cert_file, &key_file).unwrap();
+    let addr = spawn_tls_server(acceptor.clone(), Router::default());
+    assert!(acceptor.reload().is_empty());
+
+    let new = write(2_000_000);
+    let reloaded = acceptor.reload();
+
+    assert_eq!(reloaded.len(), 1);
+    assert_eq!(reloaded[0].0, cert_file);
+    assert!(reloaded[0].1.is_ok());
+    assert_eq!(presented(addr, &trusting(&[&old, &new]), "localhost"), new);
+
+    std::fs::write(&key_file, "not a key").unwrap();
+    let reloaded = acceptor.reload();
+
+    assert!(reloaded[0].1.is_err());
+    assert_eq!(presented(addr, &trusting(&[&new]), "localhost"), new);
+    assert!(acceptor.reload().is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
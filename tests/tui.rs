@@ -0,0 +1,110 @@
+#![cfg(feature = "tui")]
+
+use std::time::Duration;
+
+use ratatui::backend::TestBackend;
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::Terminal;
+use rusty_server::tui::{Activity, Dashboard};
+
+fn activity(path: &str, status: u16) -> Activity {
+    Activity {
+        time: 0,
+        peer: "127.0.0.1:50000".to_string(),
+        path: path.to_string(),
+        status: Some(status),
+        bytes_read: 78,
+        elapsed: Duration::from_millis(2),
+    }
+}
+
+fn press(dashboard: &mut Dashboard, code: KeyCode) -> bool {
+    dashboard.key(KeyEvent::new(code, KeyModifiers::NONE))
+}
+
+/// The dashboard drawn on a 100x30 screen, as text.
+fn screen(dashboard: &Dashboard) -> String {
+    let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+    terminal.draw(|frame| dashboard.draw(frame)).unwrap();
+    let buffer = terminal.backend().buffer();
+    buffer
+        .content()
+        .chunks(buffer.area.width as usize)
+        .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[test]
+fn recent_requests_can_be_filtered() {
+    let mut dashboard = Dashboard::new("127.0.0.1:3000");
+    dashboard.record(activity("/version", 200));
+    dashboard.record(activity("/api/items", 404));
+    dashboard.sample(1);
+
+    let shown = screen(&dashboard);
+    assert!(shown.contains("/version"), "{}", shown);
+    assert!(shown.contains("Requests/s: 2"), "{}", shown);
+
+    for code in [KeyCode::Char('/'), KeyCode::Char('a'), KeyCode::Char('p')] {
+        assert!(press(&mut dashboard, code));
+    }
+    press(&mut dashboard, KeyCode::Enter);
+    let filtered = screen(&dashboard);
+    assert!(filtered.contains("/api/items"), "{}", filtered);
+    // The top paths still count it; only the recent requests are filtered.
+    assert!(!filtered.contains("200    /version"), "{}", filtered);
+
+    press(&mut dashboard, KeyCode::Esc);
+    assert!(screen(&dashboard).contains("/version"));
+}
+
+#[test]
+fn statuses_and_top_paths_are_counted() {
+    let mut dashboard = Dashboard::new("127.0.0.1:3000");
+    for _ in 0..3 {
+        dashboard.record(activity("/version", 200));
+    }
+    dashboard.record(activity("/missing", 404));
+    dashboard.record(Activity {
+        status: None,
+        path: "connection reset".to_string(),
+        ..activity("", 0)
+    });
+
+    let shown = screen(&dashboard);
+    assert!(shown.contains("│200    ×3"), "{}", shown);
+    assert!(shown.contains("│404    ×1"), "{}", shown);
+    assert!(shown.contains("│✗      ×1"), "{}", shown);
+    let top = shown.find("×3       /version").expect("top path");
+    assert!(top < shown.find("×1       /missing").expect("second path"));
+    assert!(!shown.contains("×1       connection reset"), "{}", shown);
+    assert!(shown.contains("up 00:00:"), "{}", shown);
+}
+
+#[test]
+fn pausing_freezes_the_display() {
+    let mut dashboard = Dashboard::new("127.0.0.1:3000");
+    dashboard.record(activity("/version", 200));
+
+    press(&mut dashboard, KeyCode::Char('p'));
+    dashboard.record(activity("/later", 200));
+    let paused = screen(&dashboard);
+    assert!(paused.contains("1 requests  PAUSED"), "{}", paused);
+    assert!(!paused.contains("/later"));
+
+    press(&mut dashboard, KeyCode::Char('p'));
+    assert!(screen(&dashboard).contains("/later"));
+}
+
+#[test]
+fn q_and_ctrl_c_quit_unless_typing_a_filter() {
+    let mut dashboard = Dashboard::new("127.0.0.1:3000");
+
+    press(&mut dashboard, KeyCode::Char('/'));
+    assert!(press(&mut dashboard, KeyCode::Char('q')));
+    press(&mut dashboard, KeyCode::Enter);
+
+    assert!(!press(&mut dashboard, KeyCode::Char('q')));
+    assert!(!dashboard.key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)));
+}
@@ -0,0 +1,102 @@
+#![cfg(feature = "wasm")]
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+use rusty_server::http::parse_request;
+use rusty_server::plugin::wasm::{self, Limits};
+use rusty_server::plugin::PluginError;
+use rusty_server::Router;
+
+/// Builds the `wasm-plugins/hello` component, or returns `None` when the
+/// `wasm32-wasip2` target is not installed.
+fn hello_component() -> Option<&'static Path> {
+    static COMPONENT: OnceLock<Option<PathBuf>> = OnceLock::new();
+    COMPONENT
+        .get_or_init(|| {
+            let crate_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("wasm-plugins/hello");
+            let status = Command::new(env!("CARGO"))
+                .args(["build", "--release", "--target", "wasm32-wasip2"])
+                .current_dir(&crate_dir)
+                .status()
+                .ok()?;
+            status
+                .success()
+                .then(|| crate_dir.join("target/wasm32-wasip2/release/hello_wasm_plugin.wasm"))
+        })
+        .as_deref()
+}
+
+fn get(router: &Router, path: &str) -> rusty_server::Response {
+    let raw = format!(
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nUser-Agent: tester\r\n\r\n",
+        path
+    );
+    router.handle(&parse_request(raw.as_bytes()).unwrap())
+}
+
+fn router_with_hello(limits: Limits) -> Option<Router> {
+    let Some(component) = hello_component() else {
+        eprintln!("skipping: rustup target add wasm32-wasip2 to build the test plugin");
+        return None;
+    };
+    let mut router = Router::new();
+    let routes = wasm::load(&mut router, component, limits).unwrap();
+    assert_eq!(
+        routes,
+        ["GET /wasm/hello", "GET /wasm/spin", "GET /wasm/hog"]
+    );
+    Some(router)
+}
+
+#[test]
+fn component_handles_requests() {
+    let Some(router) = router_with_hello(Limits::default()) else {
+        return;
+    };
+
+    let response = get(&router, "/wasm/hello");
+
+    assert_eq!(response.status, 200);
+    assert_eq!(
+        response.header_value("Content-Type"),
+        Some("text/plain; charset=utf-8")
+    );
+    assert_eq!(response.body, b"Hello from WebAssembly, tester!\n");
+}
+
+#[test]
+fn runaway_loops_run_out_of_fuel() {
+    let mut limits = Limits::default();
+    limits.fuel = 5_000_000;
+    let Some(router) = router_with_hello(limits) else {
+        return;
+    };
+
+    assert_eq!(get(&router, "/wasm/spin").status, 500);
+    // Each request gets a fresh instance and a full tank.
+    assert_eq!(get(&router, "/wasm/hello").status, 200);
+}
+
+#[test]
+fn memory_growth_is_capped() {
+    let Some(router) = router_with_hello(Limits::default()) else {
+        return;
+    };
+
+    assert_eq!(get(&router, "/wasm/hog").status, 500);
+    assert_eq!(get(&router, "/wasm/hello").status, 200);
+}
+
+#[test]
+fn invalid_components_are_rejected() {
+    let path = std::env::temp_dir().join(format!("not-a-component-{}.wasm", std::process::id()));
+    std::fs::write(&path, b"\0asm garbage").unwrap();
+    let mut router = Router::new();
+
+    let result = wasm::load(&mut router, &path, Limits::default());
+
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(result, Err(PluginError::Wasm(_))));
+}
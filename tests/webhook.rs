@@ -0,0 +1,154 @@
+#![cfg(feature = "webhooks")]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use rusty_server::webhook::{signature, Event, Webhooks};
+
+/// A delivery as the receiver saw it.
+struct Delivery {
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Delivery {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A receiver answering successive deliveries with `statuses`, and the
+/// deliveries it got.
+fn receiver(statuses: &'static [u16]) -> (String, Receiver<Delivery>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let (sender, deliveries) = mpsc::channel();
+    thread::spawn(move || {
+        for status in statuses {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            assert_eq!(line, "POST /hook HTTP/1.1\r\n");
+            let mut headers = Vec::new();
+            loop {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                match line.trim_end().split_once(": ") {
+                    Some((name, value)) => headers.push((name.to_string(), value.to_string())),
+                    None => break,
+                }
+            }
+            let length: usize = headers
+                .iter()
+                .find(|(name, _)| name == "Content-Length")
+                .map(|(_, value)| value.parse().unwrap())
+                .unwrap();
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let mut stream = reader.into_inner();
+            write!(stream, "HTTP/1.1 {} X\r\nContent-Length: 0\r\n\r\n", status).unwrap();
+            sender
+                .send(Delivery {
+                    headers,
+                    body: String::from_utf8(body).unwrap(),
+                })
+                .unwrap();
+        }
+    });
+    (url, deliveries)
+}
+
+const WAIT: Duration = Duration::from_secs(5);
+
+#[test]
+fn events_are_posted_as_signed_json() {
+    let (url, deliveries) = receiver(&[204]);
+    let dispatcher = Webhooks::new([url]).secret("s3cret").start();
+
+    dispatcher.notify(Event::IpBanned {
+        ip: "192.0.2.7".parse().unwrap(),
+        reason: "too many \"requests\"".to_string(),
+    });
+
+    let delivery = deliveries.recv_timeout(WAIT).unwrap();
+    assert_eq!(delivery.header("Content-Type"), Some("application/json"));
+    assert_eq!(delivery.header("X-Webhook-Event"), Some("ip_banned"));
+    assert_eq!(
+        delivery.header("X-Webhook-Signature"),
+        Some(signature(b"s3cret", delivery.body.as_bytes()).as_str())
+    );
+    let json: serde_json::Value = serde_json::from_str(&delivery.body).unwrap();
+    assert_eq!(json["event"], "ip_banned");
+    assert_eq!(json["ip"], "192.0.2.7");
+    assert_eq!(json["reason"], "too many \"requests\"");
+    assert!(json["time"].is_u64());
+    assert!(dispatcher.flush(WAIT));
+}
+
+#[test]
+fn failed_deliveries_are_retried() {
+    let (url, deliveries) = receiver(&[500, 503, 200]);
+    let dispatcher = Webhooks::new([url])
+        .retries(3, Duration::from_millis(10))
+        .start();
+
+    dispatcher.notify(Event::Shutdown);
+
+    for _ in 0..3 {
+        let delivery = deliveries.recv_timeout(WAIT).unwrap();
+        assert_eq!(delivery.header("X-Webhook-Signature"), None);
+        assert_eq!(
+            delivery.body.split(',').next(),
+            Some(r#"{"event":"shutdown""#)
+        );
+    }
+    assert!(dispatcher.flush(WAIT));
+    assert!(deliveries.recv_timeout(Duration::from_millis(100)).is_err());
+}
+
+#[test]
+fn crossing_the_error_rate_is_reported_once() {
+    let (url, deliveries) = receiver(&[200, 200]);
+    let window = Duration::from_millis(50);
+    let dispatcher = Webhooks::new([url]).error_rate(0.5, window, 4).start();
+
+    for status in [500, 502, 200, 500] {
+        dispatcher.record_status(status);
+    }
+    thread::sleep(window);
+    // The first request of the next window closes the previous one.
+    dispatcher.record_status(200);
+
+    let json: serde_json::Value =
+        serde_json::from_str(&deliveries.recv_timeout(WAIT).unwrap().body).unwrap();
+    assert_eq!(json["event"], "error_rate");
+    assert_eq!(json["rate"], 0.75);
+    assert_eq!(json["requests"], 4);
+
+    // Still failing: no second report.
+    for status in [500, 500, 500] {
+        dispatcher.record_status(status);
+    }
+    thread::sleep(window);
+    dispatcher.record_status(200);
+    assert!(dispatcher.flush(WAIT));
+    assert!(deliveries.recv_timeout(Duration::from_millis(100)).is_err());
+}
+
+#[test]
+fn undeliverable_events_are_given_up_on() {
+    let dispatcher = Webhooks::new(["ftp://example.invalid/hook"])
+        .retries(1, Duration::from_millis(1))
+        .start();
+
+    dispatcher.notify(Event::Shutdown);
+
+    assert!(dispatcher.flush(WAIT));
+}
@@ -0,0 +1,256 @@
+#![cfg(feature = "websocket")]
+
+mod common;
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+use common::{connect, parse_response, spawn_server, MemoryStream};
+use rusty_server::websocket::{accept_key, Message};
+use rusty_server::{serve_connection, Config, Router};
+
+const KEY: &str = "dGhlIHNhbXBsZSBub25jZQ==";
+
+fn upgrade_request(path: &str, extra: &str) -> Vec<u8> {
+    format!(
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: keep-alive, Upgrade\r\n\
+         Sec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n{}\r\n",
+        path, KEY, extra
+    )
+    .into_bytes()
+}
+
+/// A masked client frame.
+fn frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mask = [0x12, 0x34, 0x56, 0x78];
+    let mut frame = vec![if fin { 0x80 } else { 0 } | opcode];
+    match payload.len() {
+        len @ 0..=125 => frame.push(0x80 | len as u8),
+        len => {
+            frame.push(0x80 | 126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(&mask);
+    frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    frame
+}
+
+/// Splits server output into the handshake head and the (opcode, payload)
+/// frames that followed it.
+fn split_output(output: &[u8]) -> (String, Vec<(u8, Vec<u8>)>) {
+    let end = output
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .expect("head")
+        + 4;
+    let head = String::from_utf8_lossy(&output[..end]).into_owned();
+    let mut frames = Vec::new();
+    let mut rest = &output[end..];
+    while !rest.is_empty() {
+        let (len, offset) = match rest[1] & 0x7f {
+            126 => (u16::from_be_bytes([rest[2], rest[3]]) as usize, 4),
+            len => (len as usize, 2),
+        };
+        assert_eq!(rest[1] & 0x80, 0, "server frames are unmasked");
+        frames.push((rest[0] & 0x0f, rest[offset..offset + len].to_vec()));
+        rest = &rest[offset + len..];
+    }
+    (head, frames)
+}
+
+fn session(input: &[u8]) -> (String, Vec<(u8, Vec<u8>)>) {
+    let mut stream = MemoryStream::new(input);
+    let exchange = serve_connection(&mut stream, &Router::default(), &Config::default()).unwrap();
+    assert_eq!(exchange.status, 101);
+    split_output(&stream.output)
+}
+
+#[test]
+fn accept_key_matches_rfc_example() {
+    assert_eq!(accept_key(KEY), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+}
+
+#[test]
+fn handshake_switches_protocols() {
+    let (head, _) = session(&upgrade_request("/ws/echo", ""));
+    let response = parse_response(head.as_bytes());
+
+    assert_eq!(response.status_line, "HTTP/1.1 101 SWITCHING PROTOCOLS");
+    assert_eq!(response.header("Upgrade"), Some("websocket"));
+    assert_eq!(
+        response.header("Sec-WebSocket-Accept"),
+        Some("s3pPLMBiTxaQ9kYGzzhZRbK+xOo=")
+    );
+    assert_eq!(response.header("Content-Length"), None);
+}
+
+#[test]
+fn echo_returns_text_and_binary() {
+    let mut input = upgrade_request("/ws/echo", "");
+    input.extend(frame(true, 0x1, b"hello"));
+    input.extend(frame(true, 0x2, &[0, 159, 146, 150]));
+    input.extend(frame(true, 0x1, &[b'x'; 300]));
+    input.extend(frame(true, 0x8, &1000u16.to_be_bytes()));
+
+    let (_, frames) = session(&input);
+
+    assert_eq!(
+        frames,
+        vec![
+            (0x1, b"hello".to_vec()),
+            (0x2, vec![0, 159, 146, 150]),
+            (0x1, vec![b'x'; 300]),
+            (0x8, 1000u16.to_be_bytes().to_vec()),
+        ]
+    );
+}
+
+#[test]
+fn pings_are_answered_with_pongs() {
+    let mut input = upgrade_request("/ws/echo", "");
+    input.extend(frame(true, 0x9, b"beat"));
+    input.extend(frame(true, 0x8, &1000u16.to_be_bytes()));
+
+    let (_, frames) = session(&input);
+
+    assert_eq!(frames[0], (0xA, b"beat".to_vec()));
+}
+
+#[test]
+fn fragments_are_reassembled_around_control_frames() {
+    let mut input = upgrade_request("/ws/echo", "");
+    input.extend(frame(false, 0x1, b"hel"));
+    input.extend(frame(true, 0x9, b""));
+    input.extend(frame(true, 0x0, b"lo"));
+
+    let (_, frames) = session(&input);
+
+    assert_eq!(frames[0], (0xA, Vec::new()));
+    assert_eq!(frames[1], (0x1, b"hello".to_vec()));
+}
+
+#[test]
+fn protocol_violations_close_with_their_codes() {
+    let unmasked = vec![0x81, 0x02, b'h', b'i'];
+    let cases: [(&str, Vec<u8>, u16); 4] = [
+        ("unmasked frame", unmasked, 1002),
+        ("continuation without start", frame(true, 0x0, b"x"), 1002),
+        ("fragmented ping", frame(false, 0x9, b""), 1002),
+        ("invalid UTF-8 text", frame(true, 0x1, &[0xff, 0xfe]), 1007),
+    ];
+    for (name, bytes, code) in cases {
+        let mut input = upgrade_request("/ws/echo", "");
+        input.extend(bytes);
+
+        let (_, frames) = session(&input);
+
+        assert_eq!(frames.last().unwrap().0, 0x8, "{}", name);
+        assert_eq!(
+            frames.last().unwrap().1[..2],
+            code.to_be_bytes(),
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn plain_request_to_websocket_path_gets_426() {
+    let mut stream = MemoryStream::new(b"GET /ws/echo HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+    serve_connection(&mut stream, &Router::default(), &Config::default()).unwrap();
+
+    let response = stream.response();
+    assert_eq!(response.status(), 426);
+    assert_eq!(response.header("Upgrade"), Some("websocket"));
+}
+
+#[test]
+fn unsupported_version_and_bad_key_are_rejected() {
+    let request = String::from_utf8(upgrade_request("/ws/echo", ""))
+        .unwrap()
+        .replace("Version: 13", "Version: 8");
+    let mut stream = MemoryStream::new(request.as_bytes());
+    serve_connection(&mut stream, &Router::default(), &Config::default()).unwrap();
+    assert_eq!(stream.response().status(), 426);
+    assert_eq!(
+        stream.response().header("Sec-WebSocket-Version"),
+        Some("13")
+    );
+
+    let request = String::from_utf8(upgrade_request("/ws/echo", ""))
+        .unwrap()
+        .replace(KEY, "short");
+    let mut stream = MemoryStream::new(request.as_bytes());
+    serve_connection(&mut stream, &Router::default(), &Config::default()).unwrap();
+    assert_eq!(stream.response().status(), 400);
+}
+
+#[test]
+fn custom_handler_sends_first() {
+    let mut router = Router::new();
+    router.websocket("/greet", |ws| {
+        ws.send(&Message::Text("welcome".to_string())).unwrap();
+    });
+    let mut stream = MemoryStream::new(&upgrade_request("/greet", ""));
+
+    serve_connection(&mut stream, &router, &Config::default()).unwrap();
+
+    let (_, frames) = split_output(&stream.output);
+    assert_eq!(frames[0], (0x1, b"welcome".to_vec()));
+    // Returning from the handler closes the session normally.
+    assert_eq!(frames[1], (0x8, 1000u16.to_be_bytes().to_vec()));
+}
+
+#[test]
+fn broadcast_needs_a_shareable_transport() {
+    let (_, frames) = session(&upgrade_request("/ws/chat", ""));
+
+    assert_eq!(frames[0].0, 0x8);
+    assert_eq!(frames[0].1[..2], 1011u16.to_be_bytes());
+}
+
+fn open(addr: std::net::SocketAddr, path: &str) -> TcpStream {
+    let mut stream = connect(addr);
+    stream.write_all(&upgrade_request(path, "")).unwrap();
+    let mut head = Vec::new();
+    let mut byte = [0];
+    while !head.ends_with(b"\r\n\r\n") {
+        stream.read_exact(&mut byte).unwrap();
+        head.push(byte[0]);
+    }
+    assert_eq!(parse_response(&head).status(), 101);
+    stream
+}
+
+fn read_frame(stream: &mut TcpStream) -> (u8, Vec<u8>) {
+    let mut header = [0; 2];
+    stream.read_exact(&mut header).unwrap();
+    let mut payload = vec![0; (header[1] & 0x7f) as usize];
+    stream.read_exact(&mut payload).unwrap();
+    (header[0] & 0x0f, payload)
+}
+
+#[test]
+fn chat_relays_messages_to_every_member() {
+    let addr = spawn_server();
+    let mut alice = open(addr, "/ws/chat");
+    let mut bob = open(addr, "/ws/chat");
+    // A round trip through bob's session proves it has joined the group.
+    bob.write_all(&frame(true, 0x9, b"")).unwrap();
+    assert_eq!(read_frame(&mut bob).0, 0xA);
+
+    alice.write_all(&frame(true, 0x1, b"hi all")).unwrap();
+
+    assert_eq!(read_frame(&mut alice), (0x1, b"hi all".to_vec()));
+    assert_eq!(read_frame(&mut bob), (0x1, b"hi all".to_vec()));
+}
+
+#[test]
+fn sessions_do_not_block_other_connections() {
+    let addr = spawn_server();
+    let _open = open(addr, "/ws/echo");
+
+    assert_eq!(common::get(addr, "/version", &[]).status(), 200);
+}
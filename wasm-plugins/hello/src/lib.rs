@@ -0,0 +1,63 @@
+//! A WebAssembly handler plugin, built as a WASI component:
+//!
+//! ```text
+//! cargo build --release --target wasm32-wasip2
+//! cp target/wasm32-wasip2/release/hello_wasm_plugin.wasm ../../plugins/
+//! ```
+//!
+//! Besides a greeting, it has two routes that misbehave on purpose, to show
+//! the sandbox limits at work.
+
+wit_bindgen::generate!({ path: "../../wit", world: "handler" });
+
+use rusty_server::plugin::types::Header;
+
+struct Plugin;
+
+impl Guest for Plugin {
+    fn routes() -> Vec<Route> {
+        ["/wasm/hello", "/wasm/spin", "/wasm/hog"]
+            .into_iter()
+            .map(|path| Route {
+                method: "GET".to_string(),
+                path: path.to_string(),
+            })
+            .collect()
+    }
+
+    fn handle(request: Request) -> Response {
+        match request.path.as_str() {
+            // Runs until it is out of fuel.
+            "/wasm/spin" => {
+                let mut n: u64 = 0;
+                loop {
+                    n = std::hint::black_box(n.wrapping_add(1));
+                }
+            }
+            // Grows memory until the limit stops it.
+            "/wasm/hog" => {
+                let mut blocks = Vec::new();
+                loop {
+                    blocks.push(std::hint::black_box(vec![1u8; 1 << 20]));
+                }
+            }
+            _ => {
+                let user_agent = request
+                    .headers
+                    .iter()
+                    .find(|header| header.name.eq_ignore_ascii_case("User-Agent"))
+                    .map_or("unknown", |header| header.value.as_str());
+                Response {
+                    status: 200,
+                    headers: vec![Header {
+                        name: "Content-Type".to_string(),
+                        value: "text/plain; charset=utf-8".to_string(),
+                    }],
+                    body: format!("Hello from WebAssembly, {}!\n", user_agent).into_bytes(),
+                }
+            }
+        }
+    }
+}
+
+export!(Plugin);